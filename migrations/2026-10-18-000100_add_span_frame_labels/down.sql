@@ -0,0 +1,42 @@
+PRAGMA legacy_alter_table = ON;
+
+CREATE TABLE coin_store_events_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  opt_diff_id INTEGER NULL REFERENCES coin_store_diffs(id),
+  ev_action TEXT CHECK(ev_action IN ('insert', 'update', 'delete', 'open', 'close', 'reopen')) NOT NULL,
+  span INTEGER NOT NULL,
+  frame INTEGER NOT NULL,
+  created_on_ts REAL NOT NULL,
+  ev_desc TEXT NOT NULL
+);
+
+INSERT INTO coin_store_events_new
+SELECT * FROM coin_store_events
+WHERE ev_action NOT IN ('name', 'tag');
+
+DROP TABLE coin_store_events;
+ALTER TABLE coin_store_events_new RENAME TO coin_store_events;
+
+PRAGMA legacy_alter_table = OFF;
+
+CREATE TRIGGER trg_update_coin_store_events_grouped
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped;
+  INSERT INTO coin_store_events_grouped
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_hist
+	AFTER INSERT ON coin_store_events
+BEGIN
+	DELETE FROM coin_store_hist;
+	INSERT INTO coin_store_hist
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist AS t1;
+END;
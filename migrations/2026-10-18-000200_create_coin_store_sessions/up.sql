@@ -0,0 +1,6 @@
+CREATE TABLE coin_store_sessions (
+  session TEXT NOT NULL PRIMARY KEY,
+  span INTEGER NOT NULL,
+  frame INTEGER NOT NULL,
+  updated_on_ts REAL NOT NULL
+);
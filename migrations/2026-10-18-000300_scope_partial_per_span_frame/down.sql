@@ -0,0 +1 @@
+DROP TRIGGER trg_update_coin_store_hist_partial_on_delete;
@@ -0,0 +1,12 @@
+-- The partial view is replaced one span frame at a time, and a frame may end up with every event toggled off. The
+-- partial hist then has to follow deletes as well as inserts.
+CREATE TRIGGER trg_update_coin_store_hist_partial_on_delete
+	AFTER DELETE ON coin_store_events_grouped_partial
+BEGIN
+	DELETE FROM coin_store_hist_partial;
+	INSERT INTO coin_store_hist_partial
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist_partial AS t1;
+END;
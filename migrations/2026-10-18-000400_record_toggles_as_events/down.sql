@@ -0,0 +1,69 @@
+DROP TRIGGER trg_update_coin_store_partial;
+DROP VIEW v_coin_store_events_grouped_partial;
+
+PRAGMA legacy_alter_table = ON;
+
+CREATE TABLE coin_store_events_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  opt_diff_id INTEGER NULL REFERENCES coin_store_diffs(id),
+  ev_action TEXT CHECK(ev_action IN ('insert', 'update', 'delete', 'open', 'close', 'reopen', 'name', 'tag')) NOT NULL,
+  span INTEGER NOT NULL,
+  frame INTEGER NOT NULL,
+  created_on_ts REAL NOT NULL,
+  ev_desc TEXT NOT NULL
+);
+
+-- The partial table keeps the projected selection, only the toggle events themselves are dropped
+INSERT INTO coin_store_events_new
+SELECT id, opt_diff_id, ev_action, span, frame, created_on_ts, ev_desc
+FROM coin_store_events
+WHERE ev_action NOT IN ('toggle', 'untoggle');
+
+DROP TABLE coin_store_events;
+ALTER TABLE coin_store_events_new RENAME TO coin_store_events;
+
+PRAGMA legacy_alter_table = OFF;
+
+CREATE TRIGGER trg_update_coin_store_events_grouped
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped;
+  INSERT INTO coin_store_events_grouped
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_hist
+	AFTER INSERT ON coin_store_events
+BEGIN
+	DELETE FROM coin_store_hist;
+	INSERT INTO coin_store_hist
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_hist_partial
+	AFTER INSERT ON coin_store_events_grouped_partial
+BEGIN
+	DELETE FROM coin_store_hist_partial;
+	INSERT INTO coin_store_hist_partial
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist_partial AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_hist_partial_on_delete
+	AFTER DELETE ON coin_store_events_grouped_partial
+BEGIN
+	DELETE FROM coin_store_hist_partial;
+	INSERT INTO coin_store_hist_partial
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist_partial AS t1;
+END;
@@ -0,0 +1,115 @@
+-- Toggles become events targeting another event in the same span frame. The partial view is then a projection of
+-- the toggle events instead of a table that is written to directly.
+PRAGMA legacy_alter_table = ON;
+
+CREATE TABLE coin_store_events_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  opt_diff_id INTEGER NULL REFERENCES coin_store_diffs(id),
+  ev_action TEXT CHECK(ev_action IN ('insert', 'update', 'delete', 'open', 'close', 'reopen', 'name', 'tag', 'toggle', 'untoggle')) NOT NULL,
+  span INTEGER NOT NULL,
+  frame INTEGER NOT NULL,
+  created_on_ts REAL NOT NULL,
+  ev_desc TEXT NOT NULL,
+  opt_target_ev_id INTEGER NULL REFERENCES coin_store_events(id)
+);
+
+INSERT INTO coin_store_events_new (id, opt_diff_id, ev_action, span, frame, created_on_ts, ev_desc, opt_target_ev_id)
+SELECT id, opt_diff_id, ev_action, span, frame, created_on_ts, ev_desc, NULL
+FROM coin_store_events;
+
+DROP TABLE coin_store_events;
+ALTER TABLE coin_store_events_new RENAME TO coin_store_events;
+
+PRAGMA legacy_alter_table = OFF;
+
+-- Carry over what was toggled off in the old partial table. A store that never set a partial view has an empty one,
+-- which means nothing was toggled off rather than everything
+INSERT INTO coin_store_events (opt_diff_id, ev_action, span, frame, created_on_ts, ev_desc, opt_target_ev_id)
+SELECT NULL, 'toggle', t1.grp_span, t1.grp_frame, CAST(strftime('%s', 'now') AS REAL) * 1000, 'migrated toggle', t1.ev_id
+FROM coin_store_events_grouped AS t1
+WHERE NOT EXISTS (
+  SELECT 1
+  FROM coin_store_events_grouped_partial AS t2
+  WHERE t2.grp_span = t1.grp_span AND t2.grp_frame = t1.grp_frame AND t2.ev_id = t1.ev_id
+)
+AND EXISTS (SELECT 1 FROM coin_store_events_grouped_partial);
+
+DROP TRIGGER trg_update_coin_store_hist_partial;
+DROP TRIGGER trg_update_coin_store_hist_partial_on_delete;
+
+-- An event is enabled in a span frame unless the latest toggle event for it there is a toggle rather than an untoggle
+CREATE VIEW v_coin_store_events_grouped_partial AS
+  WITH
+    latest_toggle AS (
+      SELECT
+        span, frame, opt_target_ev_id AS ev_id, ev_action,
+        ROW_NUMBER() OVER (PARTITION BY span, frame, opt_target_ev_id ORDER BY id DESC) AS rn
+      FROM coin_store_events
+      WHERE ev_action = 'toggle' OR ev_action = 'untoggle'
+    )
+  SELECT t1.*
+  FROM v_coin_store_events_grouped AS t1
+  WHERE NOT EXISTS (
+    SELECT 1
+    FROM latest_toggle AS t2
+    WHERE
+      t2.rn = 1 AND t2.ev_action = 'toggle' AND
+      t2.span = t1.grp_span AND t2.frame = t1.grp_frame AND t2.ev_id = t1.ev_id
+  )
+  ORDER BY
+    t1.created_on_ts
+;
+
+CREATE TRIGGER trg_update_coin_store_events_grouped
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped;
+  INSERT INTO coin_store_events_grouped
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_hist
+	AFTER INSERT ON coin_store_events
+BEGIN
+	DELETE FROM coin_store_hist;
+	INSERT INTO coin_store_hist
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_partial
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped_partial;
+  INSERT INTO coin_store_events_grouped_partial
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped_partial AS t1;
+
+  DELETE FROM coin_store_hist_partial;
+  INSERT INTO coin_store_hist_partial
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_hist_partial AS t1;
+END;
+
+DELETE FROM coin_store_events_grouped_partial;
+INSERT INTO coin_store_events_grouped_partial
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_events_grouped_partial AS t1;
+
+DELETE FROM coin_store_hist_partial;
+INSERT INTO coin_store_hist_partial
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_hist_partial AS t1;
@@ -0,0 +1,79 @@
+DROP TRIGGER trg_update_coin_store_hist;
+
+DROP VIEW v_coin_store_hist_snapshotted;
+DROP VIEW v_coin_store_hist;
+DROP VIEW v_coin_store_hist_partial;
+
+CREATE VIEW v_coin_store_hist AS
+  WITH
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id,
+        SUM(coins) AS coins
+      FROM v_coin_store_events_grouped
+      GROUP BY obj_id, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person,
+          ev_action AS obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY created_on_ts DESC) AS rn
+        FROM v_coin_store_events_grouped AS t1
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id;
+
+CREATE VIEW v_coin_store_hist_partial AS
+  WITH
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id,
+        SUM(coins) AS coins
+      FROM coin_store_events_grouped_partial
+      GROUP BY obj_id, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person,
+          ev_action AS obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY created_on_ts DESC) AS rn
+        FROM coin_store_events_grouped_partial AS t1
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id;
+
+CREATE TRIGGER trg_update_coin_store_hist
+	AFTER INSERT ON coin_store_events
+BEGIN
+	DELETE FROM coin_store_hist;
+	INSERT INTO coin_store_hist
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist AS t1;
+END;
+
+DROP TABLE coin_store_snapshot_hist;
+DROP TABLE coin_store_snapshots;
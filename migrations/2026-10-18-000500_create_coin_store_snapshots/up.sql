@@ -0,0 +1,154 @@
+-- A snapshot is the hist of a span frame as of its last event. The hist is then projected from the latest snapshot
+-- of each span frame plus the events after it, instead of from the full event log.
+CREATE TABLE coin_store_snapshots (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  span INTEGER NOT NULL,
+  frame INTEGER NOT NULL,
+  last_ev_id INTEGER NOT NULL,
+  created_on_ts REAL NOT NULL,
+  ev_desc TEXT NOT NULL
+);
+
+CREATE TABLE coin_store_snapshot_hist (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  snapshot_id INTEGER NOT NULL REFERENCES coin_store_snapshots(id),
+  grp_id INTEGER NOT NULL,
+  grp_span INTEGER NOT NULL,
+  grp_frame INTEGER NOT NULL,
+  obj_id INTEGER NOT NULL,
+  obj_state TEXT CHECK(obj_state IN ('insert', 'update', 'delete')) NOT NULL,
+  person TEXT NOT NULL,
+  coins INTEGER NOT NULL
+);
+
+-- Events made within the same timestamp are ordered by id, so the latest state of an object is the same however the
+-- hist is projected
+DROP VIEW v_coin_store_hist;
+DROP VIEW v_coin_store_hist_partial;
+
+CREATE VIEW v_coin_store_hist AS
+  WITH
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id,
+        SUM(coins) AS coins
+      FROM v_coin_store_events_grouped
+      GROUP BY obj_id, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person,
+          ev_action AS obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY created_on_ts DESC, ev_id DESC) AS rn
+        FROM v_coin_store_events_grouped AS t1
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id;
+
+CREATE VIEW v_coin_store_hist_partial AS
+  WITH
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id,
+        SUM(coins) AS coins
+      FROM coin_store_events_grouped_partial
+      GROUP BY obj_id, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person,
+          ev_action AS obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY created_on_ts DESC, ev_id DESC) AS rn
+        FROM coin_store_events_grouped_partial AS t1
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id;
+
+CREATE VIEW v_coin_store_hist_snapshotted AS
+  WITH
+    latest_snapshot AS (
+      SELECT t1.id, t1.span, t1.frame, t1.last_ev_id
+      FROM coin_store_snapshots AS t1
+      WHERE t1.id = (
+        SELECT MAX(t2.id)
+        FROM coin_store_snapshots AS t2
+        WHERE t2.span = t1.span AND t2.frame = t1.frame
+      )
+    ),
+    starting_point AS (
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.obj_state, t1.person, t1.coins,
+        0 AS is_tail, 0 AS ev_id, 0 AS created_on_ts
+      FROM coin_store_snapshot_hist AS t1
+      JOIN latest_snapshot AS t2
+        ON t1.snapshot_id = t2.id
+      UNION ALL
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.ev_action AS obj_state, t1.person, t1.coins,
+        1 AS is_tail, t1.ev_id, t1.created_on_ts
+      FROM v_coin_store_events_grouped AS t1
+      LEFT JOIN latest_snapshot AS t2
+        ON t1.grp_span = t2.span AND t1.grp_frame = t2.frame
+      WHERE t2.id IS NULL OR t1.ev_id > t2.last_ev_id
+    ),
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id,
+        SUM(coins) AS coins
+      FROM starting_point
+      GROUP BY obj_id, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person, obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY is_tail DESC, created_on_ts DESC, ev_id DESC) AS rn
+        FROM starting_point
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id;
+
+DROP TRIGGER trg_update_coin_store_hist;
+
+CREATE TRIGGER trg_update_coin_store_hist
+	AFTER INSERT ON coin_store_events
+BEGIN
+	DELETE FROM coin_store_hist;
+	INSERT INTO coin_store_hist
+	SELECT
+    row_number() over () as id,
+		t1.*
+	FROM v_coin_store_hist_snapshotted AS t1;
+END;
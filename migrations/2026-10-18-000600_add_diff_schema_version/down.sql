@@ -0,0 +1 @@
+ALTER TABLE coin_store_diffs DROP COLUMN schema_version;
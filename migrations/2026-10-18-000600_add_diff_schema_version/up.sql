@@ -0,0 +1,2 @@
+-- Every diff records the schema version its payload was written with. Diffs from before versioning are version 1.
+ALTER TABLE coin_store_diffs ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+DROP TABLE coin_store_chain_seals;
+ALTER TABLE coin_store_events DROP COLUMN opt_ev_hash;
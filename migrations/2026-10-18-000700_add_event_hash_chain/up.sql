@@ -0,0 +1,14 @@
+-- Each event stores a hash of its content chained with the previous event's hash. Existing events are hashed in id
+-- order by the library the next time an event is written.
+ALTER TABLE coin_store_events ADD COLUMN opt_ev_hash TEXT NULL;
+
+-- Events without a hash are only sealed once, and only the ones written before hashing existed. This records the last
+-- of them. The library sets it to 0 once it has sealed them, so that an event whose hash is removed later is reported
+-- as missing instead of being chained again.
+CREATE TABLE coin_store_chain_seals (
+  id INTEGER NOT NULL PRIMARY KEY CHECK(id = 1),
+  last_unsealed_ev_id INTEGER NOT NULL
+);
+
+INSERT INTO coin_store_chain_seals (id, last_unsealed_ev_id)
+SELECT 1, COALESCE(MAX(id), 0) FROM coin_store_events;
@@ -0,0 +1,218 @@
+-- Every diff moves an amount of one asset, such as credits, loyalty points or vouchers. Diffs written before assets
+-- existed are of the default asset, coin. Wallets are projected per object and asset, while the state and person of
+-- an object are shared by all of its assets.
+ALTER TABLE coin_store_diffs ADD COLUMN asset TEXT NOT NULL DEFAULT 'coin';
+ALTER TABLE coin_store_events_grouped ADD COLUMN asset TEXT NOT NULL DEFAULT 'coin';
+ALTER TABLE coin_store_events_grouped_partial ADD COLUMN asset TEXT NOT NULL DEFAULT 'coin';
+ALTER TABLE coin_store_hist ADD COLUMN asset TEXT NOT NULL DEFAULT 'coin';
+ALTER TABLE coin_store_hist_partial ADD COLUMN asset TEXT NOT NULL DEFAULT 'coin';
+ALTER TABLE coin_store_snapshot_hist ADD COLUMN asset TEXT NOT NULL DEFAULT 'coin';
+
+DROP VIEW v_coin_store_hist_snapshotted;
+DROP VIEW v_coin_store_hist_partial;
+DROP VIEW v_coin_store_hist;
+DROP VIEW v_coin_store_events_grouped_partial;
+DROP VIEW v_coin_store_events_grouped;
+
+CREATE VIEW v_coin_store_events_grouped AS
+WITH RECURSIVE duplicator(dup, ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset) AS (
+  SELECT 1, t1.id, t2.obj_id, t1.ev_action, t1.span, t1.frame, t1.created_on_ts, t2.person, t2.coins, t1.ev_desc, t2.asset
+  FROM coin_store_events AS t1
+  INNER JOIN coin_store_diffs AS t2
+    ON t1.opt_diff_id = t2.id
+  WHERE ev_action != 'open' AND ev_action != 'close' AND ev_action != 'reopen'
+  UNION
+  SELECT dup + 1, ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  FROM duplicator
+  WHERE
+    (dup + 1) <= (
+      SELECT COUNT(*)
+      FROM coin_store_events
+      WHERE ev_action = 'open'
+    )
+)
+SELECT t2.*, t1.*
+FROM duplicator AS t1
+JOIN
+  (
+    SELECT row_number() over () as grp_id, *
+    FROM (
+      SELECT u1.span AS grp_span, u1.frame AS grp_frame, u1.created_on_ts AS grp_created_on_ts
+      FROM coin_store_events AS u1
+      WHERE ev_action = 'open'
+      )
+  ) AS t2
+ON t1.dup = t2.grp_id
+WHERE
+  (t1.frame == t2.grp_frame AND t1.span == t2.grp_span) OR
+  (t1.span < t2.grp_span AND t1.created_on_ts < t2.grp_created_on_ts)
+ORDER BY
+  t1.created_on_ts
+;
+
+CREATE VIEW v_coin_store_events_grouped_partial AS
+  WITH
+    latest_toggle AS (
+      SELECT
+        span, frame, opt_target_ev_id AS ev_id, ev_action,
+        ROW_NUMBER() OVER (PARTITION BY span, frame, opt_target_ev_id ORDER BY id DESC) AS rn
+      FROM coin_store_events
+      WHERE ev_action = 'toggle' OR ev_action = 'untoggle'
+    )
+  SELECT t1.*
+  FROM v_coin_store_events_grouped AS t1
+  WHERE NOT EXISTS (
+    SELECT 1
+    FROM latest_toggle AS t2
+    WHERE
+      t2.rn = 1 AND t2.ev_action = 'toggle' AND
+      t2.span = t1.grp_span AND t2.frame = t1.grp_frame AND t2.ev_id = t1.ev_id
+  )
+  ORDER BY
+    t1.created_on_ts
+;
+
+CREATE VIEW v_coin_store_hist AS
+  WITH
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id, asset,
+        SUM(coins) AS coins
+      FROM v_coin_store_events_grouped
+      GROUP BY obj_id, asset, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person,
+          ev_action AS obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY created_on_ts DESC, ev_id DESC) AS rn
+        FROM v_coin_store_events_grouped AS t1
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins, a.asset
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id
+;
+
+CREATE VIEW v_coin_store_hist_partial AS
+  WITH
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id, asset,
+        SUM(coins) AS coins
+      FROM coin_store_events_grouped_partial
+      GROUP BY obj_id, asset, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person,
+          ev_action AS obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY created_on_ts DESC, ev_id DESC) AS rn
+        FROM coin_store_events_grouped_partial AS t1
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins, a.asset
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id
+;
+
+CREATE VIEW v_coin_store_hist_snapshotted AS
+  WITH
+    latest_snapshot AS (
+      SELECT t1.id, t1.span, t1.frame, t1.last_ev_id
+      FROM coin_store_snapshots AS t1
+      WHERE t1.id = (
+        SELECT MAX(t2.id)
+        FROM coin_store_snapshots AS t2
+        WHERE t2.span = t1.span AND t2.frame = t1.frame
+      )
+    ),
+    starting_point AS (
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.obj_state, t1.person, t1.coins, t1.asset,
+        0 AS is_tail, 0 AS ev_id, 0 AS created_on_ts
+      FROM coin_store_snapshot_hist AS t1
+      JOIN latest_snapshot AS t2
+        ON t1.snapshot_id = t2.id
+      UNION ALL
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.ev_action AS obj_state, t1.person, t1.coins, t1.asset,
+        1 AS is_tail, t1.ev_id, t1.created_on_ts
+      FROM v_coin_store_events_grouped AS t1
+      LEFT JOIN latest_snapshot AS t2
+        ON t1.grp_span = t2.span AND t1.grp_frame = t2.frame
+      WHERE t2.id IS NULL OR t1.ev_id > t2.last_ev_id
+    ),
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id, asset,
+        SUM(coins) AS coins
+      FROM starting_point
+      GROUP BY obj_id, asset, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person, obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY is_tail DESC, created_on_ts DESC, ev_id DESC) AS rn
+        FROM starting_point
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins, a.asset
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id
+;
+
+DELETE FROM coin_store_events_grouped;
+INSERT INTO coin_store_events_grouped
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_events_grouped AS t1;
+
+DELETE FROM coin_store_hist;
+INSERT INTO coin_store_hist
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_hist_snapshotted AS t1;
+
+DELETE FROM coin_store_events_grouped_partial;
+INSERT INTO coin_store_events_grouped_partial
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_events_grouped_partial AS t1;
+
+DELETE FROM coin_store_hist_partial;
+INSERT INTO coin_store_hist_partial
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_hist_partial AS t1;
@@ -0,0 +1,79 @@
+-- Rebuilds the events table to allow the rate action, and adds the exchange rate that a rate event sets or that an
+-- exchange posting was made at, e.g. `10 points = 1 coin`. Other events keep it null.
+PRAGMA legacy_alter_table = ON;
+
+CREATE TABLE coin_store_events_new (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  opt_diff_id INTEGER NULL REFERENCES coin_store_diffs(id),
+  ev_action TEXT CHECK(ev_action IN ('insert', 'update', 'delete', 'open', 'close', 'reopen', 'name', 'tag', 'toggle', 'untoggle', 'double_entry', 'rate')) NOT NULL,
+  span INTEGER NOT NULL,
+  frame INTEGER NOT NULL,
+  created_on_ts REAL NOT NULL,
+  ev_desc TEXT NOT NULL,
+  opt_target_ev_id INTEGER NULL REFERENCES coin_store_events(id),
+  opt_ev_hash TEXT NULL,
+  opt_tx_id INTEGER NULL,
+  opt_rate TEXT NULL
+);
+
+INSERT INTO coin_store_events_new (id, opt_diff_id, ev_action, span, frame, created_on_ts, ev_desc, opt_target_ev_id, opt_ev_hash, opt_tx_id)
+SELECT id, opt_diff_id, ev_action, span, frame, created_on_ts, ev_desc, opt_target_ev_id, opt_ev_hash, opt_tx_id
+FROM coin_store_events;
+
+DROP TABLE coin_store_events;
+ALTER TABLE coin_store_events_new RENAME TO coin_store_events;
+
+PRAGMA legacy_alter_table = OFF;
+
+CREATE TRIGGER trg_update_coin_store_events_grouped
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped;
+  INSERT INTO coin_store_events_grouped
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_hist
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_hist;
+  INSERT INTO coin_store_hist
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_hist_snapshotted AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_partial
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped_partial;
+  INSERT INTO coin_store_events_grouped_partial
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped_partial AS t1;
+
+  DELETE FROM coin_store_hist_partial;
+  INSERT INTO coin_store_hist_partial
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_hist_partial AS t1;
+END;
+
+-- Once a double_entry event is recorded, coins can only move in transactions. Events that open or close an account
+-- with 0 coins are still allowed on their own. That every transaction balances is checked by the library, since
+-- SQLite has no triggers at the end of a transaction.
+CREATE TRIGGER trg_check_coin_store_double_entry
+  BEFORE INSERT ON coin_store_events
+  WHEN
+    NEW.opt_tx_id IS NULL AND
+    EXISTS (SELECT 1 FROM coin_store_events WHERE ev_action = 'double_entry') AND
+    (SELECT coins FROM coin_store_diffs WHERE id = NEW.opt_diff_id) != 0
+BEGIN
+  SELECT RAISE(ABORT, 'Coins can only move in balanced transactions in double-entry mode');
+END;
@@ -0,0 +1,3 @@
+-- Income can carry the date its coins expire on, e.g. promotional coins. Coins that are not spent by then are written
+-- off by an expiration posting that points at the income. Other events keep it null.
+ALTER TABLE coin_store_events ADD COLUMN opt_expires_on TEXT NULL;
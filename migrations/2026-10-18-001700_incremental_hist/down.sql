@@ -0,0 +1,84 @@
+DROP TRIGGER trg_update_coin_store_hist_on_diff;
+DROP TRIGGER trg_update_coin_store_hist_on_open;
+
+DROP VIEW v_coin_store_hist_snapshotted;
+
+CREATE VIEW v_coin_store_hist_snapshotted AS
+  WITH
+    latest_snapshot AS (
+      SELECT t1.id, t1.span, t1.frame, t1.last_ev_id
+      FROM coin_store_snapshots AS t1
+      WHERE t1.id = (
+        SELECT MAX(t2.id)
+        FROM coin_store_snapshots AS t2
+        WHERE t2.span = t1.span AND t2.frame = t1.frame
+      )
+    ),
+    starting_point AS (
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.obj_state, t1.person, t1.coins, t1.asset,
+        0 AS is_tail, 0 AS ev_id, 0 AS created_on_ts
+      FROM coin_store_snapshot_hist AS t1
+      JOIN latest_snapshot AS t2
+        ON t1.snapshot_id = t2.id
+      UNION ALL
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.ev_action AS obj_state, t1.person, t1.coins, t1.asset,
+        1 AS is_tail, t1.ev_id, t1.created_on_ts
+      FROM v_coin_store_events_grouped AS t1
+      LEFT JOIN latest_snapshot AS t2
+        ON t1.grp_span = t2.span AND t1.grp_frame = t2.frame
+      WHERE t2.id IS NULL OR t1.ev_id > t2.last_ev_id
+    ),
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id, asset,
+        SUM(coins) AS coins
+      FROM starting_point
+      GROUP BY obj_id, asset, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person, obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY is_tail DESC, created_on_ts DESC, ev_id DESC) AS rn
+        FROM starting_point
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins, a.asset
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id
+;
+
+DROP VIEW v_coin_store_diff_events;
+DROP VIEW v_coin_store_span_frames;
+
+DROP INDEX idx_coin_store_hist_grp;
+DROP INDEX idx_coin_store_events_created_on_ts;
+DROP INDEX idx_coin_store_events_open;
+
+CREATE TRIGGER trg_update_coin_store_hist
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_hist;
+  INSERT INTO coin_store_hist
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_hist_snapshotted AS t1;
+END;
+
+DELETE FROM coin_store_hist;
+INSERT INTO coin_store_hist
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_hist_snapshotted AS t1;
@@ -0,0 +1,190 @@
+-- Keeps coin_store_hist up to date one event at a time instead of rebuilding it on every insert. An open event folds
+-- the events its span frame inherits into the new frame's rows, and an event with a diff adds its coins to the rows of
+-- every span frame that sees it. The snapshotted view, which full rebuilds still use, groups only the events after
+-- each span frame's latest snapshot.
+CREATE INDEX idx_coin_store_events_open ON coin_store_events(id) WHERE ev_action = 'open';
+CREATE INDEX idx_coin_store_events_created_on_ts ON coin_store_events(created_on_ts);
+CREATE INDEX idx_coin_store_hist_grp ON coin_store_hist(grp_id, obj_id, asset);
+
+-- Span frames in the order they were opened, numbered like the groups of v_coin_store_events_grouped
+CREATE VIEW v_coin_store_span_frames AS
+  SELECT
+    row_number() over (ORDER BY id) AS grp_id,
+    span AS grp_span, frame AS grp_frame, created_on_ts AS grp_created_on_ts
+  FROM coin_store_events
+  WHERE ev_action = 'open'
+;
+
+-- Events that carry a diff, with the diff
+CREATE VIEW v_coin_store_diff_events AS
+  SELECT
+    t1.id AS ev_id, t1.ev_action, t1.span, t1.frame, t1.created_on_ts,
+    t2.obj_id, t2.person, t2.coins, t2.asset
+  FROM coin_store_events AS t1
+  INNER JOIN coin_store_diffs AS t2
+    ON t1.opt_diff_id = t2.id
+  WHERE t1.ev_action NOT IN ('open', 'close', 'reopen')
+;
+
+DROP VIEW v_coin_store_hist_snapshotted;
+
+CREATE VIEW v_coin_store_hist_snapshotted AS
+  WITH
+    latest_snapshot AS (
+      SELECT t1.id, t1.span, t1.frame, t1.last_ev_id
+      FROM coin_store_snapshots AS t1
+      WHERE t1.id = (
+        SELECT MAX(t2.id)
+        FROM coin_store_snapshots AS t2
+        WHERE t2.span = t1.span AND t2.frame = t1.frame
+      )
+    ),
+    starting_point AS (
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.obj_state, t1.person, t1.coins, t1.asset,
+        0 AS is_tail, 0 AS ev_id, 0 AS created_on_ts
+      FROM coin_store_snapshot_hist AS t1
+      JOIN latest_snapshot AS t2
+        ON t1.snapshot_id = t2.id
+      UNION ALL
+      SELECT
+        t1.grp_id, t1.grp_span, t1.grp_frame, t3.obj_id, t3.ev_action AS obj_state, t3.person, t3.coins, t3.asset,
+        1 AS is_tail, t3.ev_id, t3.created_on_ts
+      FROM v_coin_store_span_frames AS t1
+      LEFT JOIN latest_snapshot AS t2
+        ON t1.grp_span = t2.span AND t1.grp_frame = t2.frame
+      JOIN v_coin_store_diff_events AS t3
+        ON t3.ev_id > COALESCE(t2.last_ev_id, 0) AND (
+          (t3.span = t1.grp_span AND t3.frame = t1.grp_frame) OR
+          (t3.span < t1.grp_span AND t3.created_on_ts < t1.grp_created_on_ts)
+        )
+    ),
+    aggr AS (
+      SELECT
+        grp_id, grp_span, grp_frame, obj_id, asset,
+        SUM(coins) AS coins
+      FROM starting_point
+      GROUP BY obj_id, asset, grp_id
+    ),
+    latest AS (
+      SELECT
+        grp_id, obj_id, obj_state, person
+      FROM (
+        SELECT
+          grp_id, obj_id, person, obj_state,
+          ROW_NUMBER() OVER (PARTITION BY grp_id, obj_id ORDER BY is_tail DESC, created_on_ts DESC, ev_id DESC) AS rn
+        FROM starting_point
+      )
+      WHERE
+        rn = 1
+    )
+  SELECT
+    a.grp_id, a.grp_span, a.grp_frame,
+    a.obj_id, l.obj_state, l.person, a.coins, a.asset
+  FROM aggr AS a
+  JOIN latest AS l
+    ON l.grp_id = a.grp_id AND l.obj_id = a.obj_id
+  ORDER BY a.grp_id
+;
+
+DROP TRIGGER trg_update_coin_store_hist;
+
+-- A new span frame starts with the events of lower spans made before it was opened
+CREATE TRIGGER trg_update_coin_store_hist_on_open
+  AFTER INSERT ON coin_store_events
+  WHEN NEW.ev_action = 'open'
+BEGIN
+  INSERT INTO coin_store_hist (grp_id, grp_span, grp_frame, obj_id, obj_state, person, coins, asset)
+  SELECT
+    (SELECT grp_id FROM v_coin_store_span_frames WHERE grp_span = NEW.span AND grp_frame = NEW.frame),
+    NEW.span, NEW.frame, a.obj_id, l.obj_state, l.person, a.coins, a.asset
+  FROM (
+    SELECT obj_id, asset, SUM(coins) AS coins
+    FROM v_coin_store_diff_events
+    WHERE span < NEW.span AND created_on_ts < NEW.created_on_ts
+    GROUP BY obj_id, asset
+  ) AS a
+  JOIN (
+    SELECT obj_id, obj_state, person
+    FROM (
+      SELECT
+        obj_id, person,
+        ev_action AS obj_state,
+        ROW_NUMBER() OVER (PARTITION BY obj_id ORDER BY created_on_ts DESC, ev_id DESC) AS rn
+      FROM v_coin_store_diff_events
+      WHERE span < NEW.span AND created_on_ts < NEW.created_on_ts
+    )
+    WHERE
+      rn = 1
+  ) AS l
+    ON l.obj_id = a.obj_id;
+END;
+
+-- The diff is seen by its own span frame and by the span frames of higher spans opened after it was made
+CREATE TRIGGER trg_update_coin_store_hist_on_diff
+  AFTER INSERT ON coin_store_events
+  WHEN NEW.opt_diff_id IS NOT NULL AND NEW.ev_action NOT IN ('open', 'close', 'reopen')
+BEGIN
+  UPDATE coin_store_hist
+  SET coins = coins + (SELECT coins FROM coin_store_diffs WHERE id = NEW.opt_diff_id)
+  WHERE
+    obj_id = (SELECT obj_id FROM coin_store_diffs WHERE id = NEW.opt_diff_id) AND
+    asset = (SELECT asset FROM coin_store_diffs WHERE id = NEW.opt_diff_id) AND
+    grp_id IN (
+      SELECT grp_id
+      FROM v_coin_store_span_frames
+      WHERE
+        (grp_span = NEW.span AND grp_frame = NEW.frame) OR
+        (NEW.span < grp_span AND NEW.created_on_ts < grp_created_on_ts)
+    );
+
+  INSERT INTO coin_store_hist (grp_id, grp_span, grp_frame, obj_id, obj_state, person, coins, asset)
+  SELECT
+    t1.grp_id, t1.grp_span, t1.grp_frame, t2.obj_id, NEW.ev_action, t2.person, t2.coins, t2.asset
+  FROM v_coin_store_span_frames AS t1, coin_store_diffs AS t2
+  WHERE
+    t2.id = NEW.opt_diff_id AND (
+      (t1.grp_span = NEW.span AND t1.grp_frame = NEW.frame) OR
+      (NEW.span < t1.grp_span AND NEW.created_on_ts < t1.grp_created_on_ts)
+    ) AND
+    NOT EXISTS (
+      SELECT 1
+      FROM coin_store_hist AS t3
+      WHERE t3.grp_id = t1.grp_id AND t3.obj_id = t2.obj_id AND t3.asset = t2.asset
+    );
+
+  -- The state and person of an object are those of its latest event, which an event written with an earlier
+  -- timestamp, such as an imported one, is not
+  UPDATE coin_store_hist
+  SET
+    obj_state = NEW.ev_action,
+    person = (SELECT person FROM coin_store_diffs WHERE id = NEW.opt_diff_id)
+  WHERE
+    obj_id = (SELECT obj_id FROM coin_store_diffs WHERE id = NEW.opt_diff_id) AND
+    grp_id IN (
+      SELECT t1.grp_id
+      FROM v_coin_store_span_frames AS t1
+      WHERE
+        (
+          (t1.grp_span = NEW.span AND t1.grp_frame = NEW.frame) OR
+          (NEW.span < t1.grp_span AND NEW.created_on_ts < t1.grp_created_on_ts)
+        ) AND
+        NOT EXISTS (
+          SELECT 1
+          FROM v_coin_store_diff_events AS t2
+          WHERE
+            t2.obj_id = (SELECT obj_id FROM coin_store_diffs WHERE id = NEW.opt_diff_id) AND
+            (t2.created_on_ts > NEW.created_on_ts OR (t2.created_on_ts = NEW.created_on_ts AND t2.ev_id > NEW.id)) AND (
+              (t2.span = t1.grp_span AND t2.frame = t1.grp_frame) OR
+              (t2.span < t1.grp_span AND t2.created_on_ts < t1.grp_created_on_ts)
+            )
+        )
+    );
+END;
+
+DELETE FROM coin_store_hist;
+INSERT INTO coin_store_hist
+SELECT
+  row_number() over () as id,
+  t1.*
+FROM v_coin_store_hist_snapshotted AS t1;
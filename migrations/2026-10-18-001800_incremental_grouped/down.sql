@@ -0,0 +1,38 @@
+DROP TRIGGER trg_update_coin_store_partial_on_toggle;
+DROP TRIGGER trg_update_coin_store_events_grouped_on_diff;
+DROP TRIGGER trg_update_coin_store_events_grouped_on_open;
+
+DROP INDEX idx_coin_store_hist_partial_grp;
+DROP INDEX idx_coin_store_events_grouped_partial_obj;
+DROP INDEX idx_coin_store_events_grouped_partial_grp;
+DROP INDEX idx_coin_store_events_grouped_grp;
+DROP INDEX idx_coin_store_events_grouped_ev;
+
+CREATE TRIGGER trg_update_coin_store_events_grouped
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped;
+  INSERT INTO coin_store_events_grouped
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped AS t1;
+END;
+
+CREATE TRIGGER trg_update_coin_store_partial
+  AFTER INSERT ON coin_store_events
+BEGIN
+  DELETE FROM coin_store_events_grouped_partial;
+  INSERT INTO coin_store_events_grouped_partial
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_events_grouped_partial AS t1;
+
+  DELETE FROM coin_store_hist_partial;
+  INSERT INTO coin_store_hist_partial
+  SELECT
+    row_number() over () as id,
+    t1.*
+  FROM v_coin_store_hist_partial AS t1;
+END;
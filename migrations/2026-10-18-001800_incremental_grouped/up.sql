@@ -0,0 +1,176 @@
+-- Keeps coin_store_events_grouped, coin_store_events_grouped_partial and coin_store_hist_partial up to date one event
+-- at a time, like coin_store_hist, instead of rebuilding them from the whole event log on every insert. An open event
+-- groups the events its span frame inherits under the new frame, an event with a diff is grouped under every span
+-- frame that sees it, and a toggle or untoggle takes the event it points at out of or back into the partial view of
+-- its span frame. Only the partial hist rows of the objects that changed are added up again.
+CREATE INDEX idx_coin_store_events_grouped_ev ON coin_store_events_grouped(ev_id);
+CREATE INDEX idx_coin_store_events_grouped_grp ON coin_store_events_grouped(grp_span, grp_frame, ev_id);
+CREATE INDEX idx_coin_store_events_grouped_partial_grp ON coin_store_events_grouped_partial(grp_span, grp_frame, ev_id);
+CREATE INDEX idx_coin_store_events_grouped_partial_obj ON coin_store_events_grouped_partial(grp_id, obj_id);
+CREATE INDEX idx_coin_store_hist_partial_grp ON coin_store_hist_partial(grp_id, obj_id);
+
+DROP TRIGGER trg_update_coin_store_events_grouped;
+DROP TRIGGER trg_update_coin_store_partial;
+
+-- A new span frame starts with the events of lower spans made before it was opened, none of them toggled off
+CREATE TRIGGER trg_update_coin_store_events_grouped_on_open
+  AFTER INSERT ON coin_store_events
+  WHEN NEW.ev_action = 'open'
+BEGIN
+  INSERT INTO coin_store_events_grouped (
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  )
+  SELECT
+    t1.grp_id, t1.grp_span, t1.grp_frame, t1.grp_created_on_ts, t1.grp_id,
+    t2.id, t3.obj_id, t2.ev_action, t2.span, t2.frame, t2.created_on_ts, t3.person, t3.coins, t2.ev_desc, t3.asset
+  FROM v_coin_store_span_frames AS t1, coin_store_events AS t2
+  INNER JOIN coin_store_diffs AS t3
+    ON t2.opt_diff_id = t3.id
+  WHERE
+    t1.grp_span = NEW.span AND t1.grp_frame = NEW.frame AND
+    t2.ev_action NOT IN ('open', 'close', 'reopen') AND
+    t2.span < NEW.span AND t2.created_on_ts < NEW.created_on_ts
+  ORDER BY t2.created_on_ts, t2.id;
+
+  INSERT INTO coin_store_events_grouped_partial (
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  )
+  SELECT
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  FROM coin_store_events_grouped
+  WHERE grp_span = NEW.span AND grp_frame = NEW.frame
+  ORDER BY id;
+
+  INSERT INTO coin_store_hist_partial (grp_id, grp_span, grp_frame, obj_id, obj_state, person, coins, asset)
+  SELECT a.grp_id, a.grp_span, a.grp_frame, a.obj_id, l.ev_action, l.person, a.coins, a.asset
+  FROM (
+    SELECT grp_id, grp_span, grp_frame, obj_id, asset, SUM(coins) AS coins
+    FROM coin_store_events_grouped_partial
+    WHERE grp_span = NEW.span AND grp_frame = NEW.frame
+    GROUP BY grp_id, obj_id, asset
+  ) AS a
+  INNER JOIN coin_store_events_grouped_partial AS l
+    ON l.id = (
+      SELECT t1.id
+      FROM coin_store_events_grouped_partial AS t1
+      WHERE t1.grp_id = a.grp_id AND t1.obj_id = a.obj_id
+      ORDER BY t1.created_on_ts DESC, t1.ev_id DESC
+      LIMIT 1
+    );
+END;
+
+-- The diff is seen by its own span frame and by the span frames of higher spans opened after it was made. It cannot
+-- have been toggled off yet, so it goes into their partial views as well.
+CREATE TRIGGER trg_update_coin_store_events_grouped_on_diff
+  AFTER INSERT ON coin_store_events
+  WHEN NEW.opt_diff_id IS NOT NULL AND NEW.ev_action NOT IN ('open', 'close', 'reopen')
+BEGIN
+  INSERT INTO coin_store_events_grouped (
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  )
+  SELECT
+    t1.grp_id, t1.grp_span, t1.grp_frame, t1.grp_created_on_ts, t1.grp_id,
+    NEW.id, t2.obj_id, NEW.ev_action, NEW.span, NEW.frame, NEW.created_on_ts, t2.person, t2.coins, NEW.ev_desc, t2.asset
+  FROM v_coin_store_span_frames AS t1, coin_store_diffs AS t2
+  WHERE
+    t2.id = NEW.opt_diff_id AND (
+      (t1.grp_span = NEW.span AND t1.grp_frame = NEW.frame) OR
+      (NEW.span < t1.grp_span AND NEW.created_on_ts < t1.grp_created_on_ts)
+    )
+  ORDER BY t1.grp_id;
+
+  INSERT INTO coin_store_events_grouped_partial (
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  )
+  SELECT
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  FROM coin_store_events_grouped
+  WHERE ev_id = NEW.id
+  ORDER BY id;
+
+  DELETE FROM coin_store_hist_partial
+  WHERE (grp_id, obj_id) IN (
+    SELECT grp_id, obj_id
+    FROM coin_store_events_grouped
+    WHERE ev_id = NEW.id
+  );
+
+  INSERT INTO coin_store_hist_partial (grp_id, grp_span, grp_frame, obj_id, obj_state, person, coins, asset)
+  SELECT a.grp_id, a.grp_span, a.grp_frame, a.obj_id, l.ev_action, l.person, a.coins, a.asset
+  FROM (
+    SELECT t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.asset, SUM(t1.coins) AS coins
+    FROM coin_store_events_grouped_partial AS t1
+    INNER JOIN coin_store_events_grouped AS t2
+      ON t2.ev_id = NEW.id AND t1.grp_id = t2.grp_id AND t1.obj_id = t2.obj_id
+    GROUP BY t1.grp_id, t1.obj_id, t1.asset
+  ) AS a
+  INNER JOIN coin_store_events_grouped_partial AS l
+    ON l.id = (
+      SELECT t1.id
+      FROM coin_store_events_grouped_partial AS t1
+      WHERE t1.grp_id = a.grp_id AND t1.obj_id = a.obj_id
+      ORDER BY t1.created_on_ts DESC, t1.ev_id DESC
+      LIMIT 1
+    );
+END;
+
+-- A toggle takes the event out of the partial view of its span frame, and an untoggle puts it back
+CREATE TRIGGER trg_update_coin_store_partial_on_toggle
+  AFTER INSERT ON coin_store_events
+  WHEN NEW.ev_action IN ('toggle', 'untoggle')
+BEGIN
+  DELETE FROM coin_store_events_grouped_partial
+  WHERE
+    NEW.ev_action = 'toggle' AND
+    grp_span = NEW.span AND grp_frame = NEW.frame AND ev_id = NEW.opt_target_ev_id;
+
+  INSERT INTO coin_store_events_grouped_partial (
+    grp_id, grp_span, grp_frame, grp_created_on_ts, dup,
+    ev_id, obj_id, ev_action, span, frame, created_on_ts, person, coins, ev_desc, asset
+  )
+  SELECT
+    t1.grp_id, t1.grp_span, t1.grp_frame, t1.grp_created_on_ts, t1.dup,
+    t1.ev_id, t1.obj_id, t1.ev_action, t1.span, t1.frame, t1.created_on_ts, t1.person, t1.coins, t1.ev_desc, t1.asset
+  FROM coin_store_events_grouped AS t1
+  WHERE
+    NEW.ev_action = 'untoggle' AND
+    t1.grp_span = NEW.span AND t1.grp_frame = NEW.frame AND t1.ev_id = NEW.opt_target_ev_id AND
+    NOT EXISTS (
+      SELECT 1
+      FROM coin_store_events_grouped_partial AS t2
+      WHERE t2.grp_span = NEW.span AND t2.grp_frame = NEW.frame AND t2.ev_id = NEW.opt_target_ev_id
+    );
+
+  DELETE FROM coin_store_hist_partial
+  WHERE (grp_id, obj_id) IN (
+    SELECT grp_id, obj_id
+    FROM coin_store_events_grouped
+    WHERE grp_span = NEW.span AND grp_frame = NEW.frame AND ev_id = NEW.opt_target_ev_id
+  );
+
+  INSERT INTO coin_store_hist_partial (grp_id, grp_span, grp_frame, obj_id, obj_state, person, coins, asset)
+  SELECT a.grp_id, a.grp_span, a.grp_frame, a.obj_id, l.ev_action, l.person, a.coins, a.asset
+  FROM (
+    SELECT t1.grp_id, t1.grp_span, t1.grp_frame, t1.obj_id, t1.asset, SUM(t1.coins) AS coins
+    FROM coin_store_events_grouped_partial AS t1
+    INNER JOIN coin_store_events_grouped AS t2
+      ON
+        t2.grp_span = NEW.span AND t2.grp_frame = NEW.frame AND t2.ev_id = NEW.opt_target_ev_id AND
+        t1.grp_id = t2.grp_id AND t1.obj_id = t2.obj_id
+    GROUP BY t1.grp_id, t1.obj_id, t1.asset
+  ) AS a
+  INNER JOIN coin_store_events_grouped_partial AS l
+    ON l.id = (
+      SELECT t1.id
+      FROM coin_store_events_grouped_partial AS t1
+      WHERE t1.grp_id = a.grp_id AND t1.obj_id = a.obj_id
+      ORDER BY t1.created_on_ts DESC, t1.ev_id DESC
+      LIMIT 1
+    );
+END;
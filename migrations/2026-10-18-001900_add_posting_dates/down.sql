@@ -0,0 +1 @@
+ALTER TABLE coin_store_events DROP COLUMN opt_made_on;
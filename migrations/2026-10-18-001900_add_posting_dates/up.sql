@@ -0,0 +1,4 @@
+-- A posting that takes coins from a user carries the date it was made on, since created_on_ts is too coarse to tell
+-- which day a posting near midnight belongs to, and the lots it can spend depend on it. Other events keep it null, and
+-- so do postings made before, which fall back to created_on_ts.
+ALTER TABLE coin_store_events ADD COLUMN opt_made_on TEXT NULL;
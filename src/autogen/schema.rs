@@ -1,4 +1,4 @@
-#[derive(diesel_derive_enum::DbEnum, Debug, strum::VariantArray, Clone)]
+#[derive(diesel_derive_enum::DbEnum, Debug, strum::VariantArray, Clone, PartialEq, Eq)]
 pub enum EventAction {
     Insert,
     Update,
@@ -6,6 +6,21 @@ pub enum EventAction {
     Open,
     Close,
     Reopen,
+    Name,
+    Tag,
+    Toggle,
+    Untoggle,
+    DoubleEntry,
+    Rate,
+    Scale,
+    Schedule,
+    Unschedule,
+    Hold,
+    Release,
+    Limit,
+    Unlimit,
+    CreditLine,
+    Iou,
 }
 
 #[derive(diesel_derive_enum::DbEnum, Debug, strum::VariantArray, Clone)]
@@ -27,12 +42,21 @@ impl From<ObjState> for EventAction {
 
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    coin_store_chain_seals (id) {
+        id -> Integer,
+        last_unsealed_ev_id -> Integer,
+    }
+}
+
 diesel::table! {
     coin_store_diffs (id) {
         id -> Integer,
         obj_id -> Integer,
         person -> Text,
-        coins -> Integer,
+        coins -> BigInt,
+        schema_version -> Integer,
+        asset -> Text,
     }
 }
 
@@ -45,6 +69,18 @@ diesel::table! {
         frame -> Integer,
         created_on_ts -> Float,
         ev_desc -> Text,
+        opt_target_ev_id -> Nullable<Integer>,
+        opt_ev_hash -> Nullable<Text>,
+        opt_tx_id -> Nullable<Integer>,
+        opt_rate -> Nullable<Text>,
+        opt_scale -> Nullable<Integer>,
+        opt_schedule -> Nullable<Text>,
+        opt_hold -> Nullable<Text>,
+        opt_expires_on -> Nullable<Text>,
+        opt_limit -> Nullable<Text>,
+        opt_credit_line -> Nullable<Text>,
+        opt_iou -> Nullable<Text>,
+        opt_made_on -> Nullable<Text>,
     }
 }
 
@@ -63,8 +99,9 @@ diesel::table! {
         frame -> Integer,
         created_on_ts -> Float,
         person -> Text,
-        coins -> Integer,
+        coins -> BigInt,
         ev_desc -> Text,
+        asset -> Text,
     }
 }
 
@@ -83,8 +120,9 @@ diesel::table! {
         frame -> Integer,
         created_on_ts -> Float,
         person -> Text,
-        coins -> Integer,
+        coins -> BigInt,
         ev_desc -> Text,
+        asset -> Text,
     }
 }
 
@@ -97,7 +135,8 @@ diesel::table! {
         obj_id -> Integer,
         obj_state -> crate::autogen::schema::ObjStateMapping,
         person -> Text,
-        coins -> Integer,
+        coins -> BigInt,
+        asset -> Text,
     }
 }
 
@@ -110,17 +149,58 @@ diesel::table! {
         obj_id -> Integer,
         obj_state -> crate::autogen::schema::ObjStateMapping,
         person -> Text,
-        coins -> Integer,
+        coins -> BigInt,
+        asset -> Text,
+    }
+}
+
+diesel::table! {
+    coin_store_sessions (session) {
+        session -> Text,
+        span -> Integer,
+        frame -> Integer,
+        updated_on_ts -> Float,
+    }
+}
+
+diesel::table! {
+    coin_store_snapshot_hist (id) {
+        id -> Integer,
+        snapshot_id -> Integer,
+        grp_id -> Integer,
+        grp_span -> Integer,
+        grp_frame -> Integer,
+        obj_id -> Integer,
+        obj_state -> crate::autogen::schema::ObjStateMapping,
+        person -> Text,
+        coins -> BigInt,
+        asset -> Text,
+    }
+}
+
+diesel::table! {
+    coin_store_snapshots (id) {
+        id -> Integer,
+        span -> Integer,
+        frame -> Integer,
+        last_ev_id -> Integer,
+        created_on_ts -> Float,
+        ev_desc -> Text,
     }
 }
 
 diesel::joinable!(coin_store_events -> coin_store_diffs (opt_diff_id));
+diesel::joinable!(coin_store_snapshot_hist -> coin_store_snapshots (snapshot_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    coin_store_chain_seals,
     coin_store_diffs,
     coin_store_events,
     coin_store_events_grouped,
     coin_store_events_grouped_partial,
     coin_store_hist,
     coin_store_hist_partial,
+    coin_store_sessions,
+    coin_store_snapshot_hist,
+    coin_store_snapshots,
 );
@@ -1,20 +1,42 @@
-use crc32fast::Hasher;
-use std::{hash::Hash, sync::Mutex};
+use std::{collections::HashMap, sync::Mutex};
 
 use credit_store_demo::{
     autogen::schema::ObjState,
-    db, drivers,
-    macros::diesel_hist_models::{CreateSpanFrameError, SpanFrame},
+    db,
+    db::double_entry::DoubleEntryError,
+    db::exchange::{self, ExchangeError},
+    drivers,
+    macros::diesel_hist_models::{
+        CreateSpanFrameError, LabelSpanFrameError, SpanFrame, SpanFrameInfo, VerifyEventChainError,
+    },
 };
-use deterministic_hash::DeterministicHasher;
 use diesel::{RunQueryDsl, SqliteConnection, query_dsl::methods::FilterDsl};
+use itertools::Itertools;
 use log::*;
 use shi::{cmd, error::ShiError, parent};
 use tap::prelude::*;
+use thiserror::Error;
 
 struct InternalShellState {
     conn: SqliteConnection,
+    session: String,
     cur_span_frame: SpanFrame,
+    /// How long holds last before they expire
+    hold_duration: db::models::HoldDuration,
+}
+
+impl InternalShellState {
+    /// Switches the current span frame and persists it for the session so it is restored on the next startup
+    fn switch_span_frame(&mut self, span_frame: SpanFrame) -> Result<(), ShiError> {
+        use credit_store_demo::db::models::*;
+
+        coin_store::set_session_span_frame(&mut self.conn, &self.session, &span_frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        self.cur_span_frame = span_frame;
+
+        Ok(())
+    }
 }
 
 struct _ExternalShellState {}
@@ -35,6 +57,7 @@ fn coin_store_add_user(
     _args: &[String],
 ) -> Result<String, ShiError> {
     use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::double_entry;
     use credit_store_demo::db::models::*;
     use diesel::prelude::*;
 
@@ -55,32 +78,27 @@ fn coin_store_add_user(
         return Ok("Error: User already exists".to_owned());
     }
 
-    let new_common = coin_store::NewCommon {
-        coins: 0,
+    // With 0 coins, the account needs no transaction, even in double-entry mode
+    let posting = double_entry::Posting {
+        obj_id: person.get_obj_id(),
+        obj_state: ObjState::Insert,
+        opt_target_ev_id: None,
+        ev_desc: "create user",
         person: &person,
+        coins: credit_store_demo::db::models::Amount::ZERO,
+        asset: &Asset::default(),
+        opt_rate: None,
+        opt_expires_on: None,
     };
 
-    let obj_id = {
-        let mut hasher = DeterministicHasher::new(Hasher::new());
-        person.hash(&mut hasher);
-
-        hasher.as_inner().clone().finalize()
-    };
-
-    let ev = coin_store::insert_event_for_obj(
+    double_entry::insert_posting(
         &mut mut_state.conn,
-        obj_id as i32,
         &mut_state.cur_span_frame,
-        ObjState::Insert,
-        "create user",
-        new_common,
+        None,
+        &posting,
     )
     .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    // Update partial for this new event
-    sync_coin_store_events_partial(&mut mut_state.conn, ev.id)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
-
     Ok("Created user".to_owned())
 }
 
@@ -89,6 +107,7 @@ fn coin_store_delete_user(
     _args: &[String],
 ) -> Result<String, ShiError> {
     use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::double_entry;
     use credit_store_demo::db::models::*;
     use diesel::prelude::*;
 
@@ -109,44 +128,39 @@ fn coin_store_delete_user(
         return Ok("Error: User does not exist".to_owned());
     }
 
-    let new_common = coin_store::NewCommon {
-        coins: 0,
+    // With 0 coins, the account needs no transaction, even in double-entry mode
+    let posting = double_entry::Posting {
+        obj_id: person.get_obj_id(),
+        obj_state: ObjState::Delete,
+        opt_target_ev_id: None,
+        ev_desc: "create user",
         person: &person,
+        coins: credit_store_demo::db::models::Amount::ZERO,
+        asset: &Asset::default(),
+        opt_rate: None,
+        opt_expires_on: None,
     };
 
-    let obj_id = {
-        let mut hasher = DeterministicHasher::new(Hasher::new());
-        person.hash(&mut hasher);
-
-        hasher.as_inner().clone().finalize()
-    };
-
-    let ev = coin_store::insert_event_for_obj(
+    double_entry::insert_posting(
         &mut mut_state.conn,
-        obj_id as i32,
         &mut_state.cur_span_frame,
-        ObjState::Delete,
-        "create user",
-        new_common,
+        None,
+        &posting,
     )
     .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    // Update partial for this new event
-    sync_coin_store_events_partial(&mut mut_state.conn, ev.id)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
-
     Ok("Deleted user".to_owned())
 }
 
-pub fn display_pretty_table(table_to_print: &[(String, String)]) -> String {
+pub fn display_pretty_table(table_to_print: &[(String, String, String)]) -> String {
     use tabled::{builder::Builder, settings::Style};
 
     let mut b = Builder::with_capacity(3, 0);
 
-    b.push_record(["person", "total_coins"]);
+    b.push_record(["person", "asset", "total_coins"]);
 
-    for (person, coins) in table_to_print {
-        b.push_record([person, coins]);
+    for (person, asset, coins) in table_to_print {
+        b.push_record([person, asset, coins]);
     }
 
     let mut table = b.build();
@@ -202,6 +216,35 @@ pub fn display_pretty_table_for_records_toggled(
     table.to_string()
 }
 
+/// The scale of the store, which amounts are parsed and displayed at
+fn get_scale(
+    conn: &mut SqliteConnection,
+) -> Result<credit_store_demo::db::models::Scale, ShiError> {
+    db::scale::get_scale(conn).map_err(|e| ShiError::General { msg: e.to_string() })
+}
+
+/// Coins of an asset at the scale of the store, e.g. `10.50 points`
+pub fn display_coins(
+    coins: credit_store_demo::db::models::Amount,
+    scale: credit_store_demo::db::models::Scale,
+    asset: &credit_store_demo::db::models::Asset,
+) -> String {
+    format!("{} {}", coins.to_scaled_string(scale), asset.to_inner())
+}
+
+/// Coins of an asset with the rate they were exchanged at, if any, e.g. `-10 points at 10 points = 1 coin`
+pub fn display_coins_at_rate(
+    coins: credit_store_demo::db::models::Amount,
+    scale: credit_store_demo::db::models::Scale,
+    asset: &credit_store_demo::db::models::Asset,
+    opt_rate: Option<&String>,
+) -> String {
+    match opt_rate {
+        Some(rate) => format!("{} at {rate}", display_coins(coins, scale, asset)),
+        None => display_coins(coins, scale, asset),
+    }
+}
+
 pub fn display_timestamp(timestamp: f32) -> String {
     use chrono::{DateTime, TimeZone, Utc};
 
@@ -235,21 +278,52 @@ fn coin_store_show_wallet(
                     .and(dsl::grp_frame.eq(mut_state.cur_span_frame.frame)),
             )
         })
+        .order((dsl::obj_id.asc(), dsl::asset.asc()))
         .select(coin_store::Hist::as_select())
         .get_results(&mut mut_state.conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
+    let scale = get_scale(&mut mut_state.conn)?;
+
     let table_to_print = objects
         .iter()
-        .map(|row| (row.person.to_inner(), format!("{}", row.coins)))
+        .map(|row| {
+            (
+                row.person.to_inner(),
+                row.asset.to_inner(),
+                row.coins.to_scaled_string(scale),
+            )
+        })
         .collect::<Vec<_>>();
 
-    Ok(format!(
+    let mut mut_out = format!(
         "(span: {}, frame: {})\n{}",
         mut_state.cur_span_frame.span,
         mut_state.cur_span_frame.frame,
         display_pretty_table(&table_to_print)
-    ))
+    );
+
+    let double_entry = credit_store_demo::db::double_entry::is_double_entry(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if double_entry {
+        let totals = objects
+            .iter()
+            .into_group_map_by(|row| row.asset.clone())
+            .into_iter()
+            .sorted_by_key(|(asset, _)| asset.clone())
+            .map(|(asset, rows)| {
+                Amount::checked_sum(rows.iter().map(|row| row.coins))
+                    .map(|total| display_coins(total, scale, &asset))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ShiError::General { msg: e.to_string() })?
+            .join(", ");
+
+        mut_out += &format!("\nDouble-entry: accounts add up to {totals}");
+    }
+
+    Ok(mut_out)
 }
 
 fn coin_store_show_partial_wallet(
@@ -273,9 +347,17 @@ fn coin_store_show_partial_wallet(
         .get_results(&mut mut_state.conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
+    let scale = get_scale(&mut mut_state.conn)?;
+
     let table_to_print = objects
         .iter()
-        .map(|row| (row.person.to_inner(), format!("{}", row.coins)))
+        .map(|row| {
+            (
+                row.person.to_inner(),
+                row.asset.to_inner(),
+                row.coins.to_scaled_string(scale),
+            )
+        })
         .collect::<Vec<_>>();
 
     Ok(format!(
@@ -303,17 +385,22 @@ fn coin_store_show_records(
                     .and(dsl::grp_frame.eq(mut_state.cur_span_frame.frame)),
             )
         })
+        .order((dsl::created_on_ts.asc(), dsl::ev_id.asc()))
         .select(coin_store::EventGrouped::as_select())
         .get_results(&mut mut_state.conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
+    let rates = exchange::get_rates_by_ev_id(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let scale = get_scale(&mut mut_state.conn)?;
+
     let table_to_print = objects
         .iter()
         .map(|row| {
             (
                 display_timestamp(row.created_on_ts),
                 row.person.to_inner(),
-                format!("{}", row.coins),
+                display_coins_at_rate(row.coins, scale, &row.asset, rates.get(&row.ev_id)),
                 row.ev_desc.clone(),
             )
         })
@@ -344,17 +431,22 @@ fn coin_store_show_partial_records(
                     .and(dsl::grp_frame.eq(mut_state.cur_span_frame.frame)),
             )
         })
+        .order((dsl::created_on_ts.asc(), dsl::ev_id.asc()))
         .select(coin_store::EventGroupedPartial::as_select())
         .get_results(&mut mut_state.conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
+    let rates = exchange::get_rates_by_ev_id(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let scale = get_scale(&mut mut_state.conn)?;
+
     let table_to_print = objects
         .iter()
         .map(|row| {
             (
                 display_timestamp(row.created_on_ts),
                 row.person.to_inner(),
-                format!("{}", row.coins),
+                display_coins_at_rate(row.coins, scale, &row.asset, rates.get(&row.ev_id)),
                 row.ev_desc.clone(),
             )
         })
@@ -368,137 +460,93 @@ fn coin_store_show_partial_records(
     ))
 }
 
-fn set_coin_store_events_partial_to_full(
+fn get_coin_store_events_grouped(
     conn: &mut SqliteConnection,
-) -> Result<(), diesel::result::Error> {
+    span_frame: &SpanFrame,
+) -> Result<Vec<credit_store_demo::db::models::coin_store::EventGrouped>, diesel::result::Error> {
     use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
     use credit_store_demo::db::models::*;
     use diesel::prelude::*;
 
-    let objects: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+    dsl::coin_store_events_grouped
+        .pipe(|tbl| {
+            FilterDsl::filter(
+                tbl,
+                dsl::grp_span
+                    .eq(span_frame.span)
+                    .and(dsl::grp_frame.eq(span_frame.frame)),
+            )
+        })
         .select(coin_store::EventGrouped::as_select())
-        .get_results(conn)?;
-
-    coin_store::set_events_grouped_partial(conn, &objects)?;
-
-    Ok(())
+        .get_results(conn)
 }
 
-fn sync_coin_store_events_partial(
+fn get_coin_store_events_grouped_partial(
     conn: &mut SqliteConnection,
-    new_event_id: i32,
-) -> Result<(), diesel::result::Error> {
-    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
-    use credit_store_demo::autogen::schema::coin_store_events_grouped_partial::dsl as dsl_p;
+    span_frame: &SpanFrame,
+) -> Result<
+    Vec<credit_store_demo::db::models::coin_store::EventGroupedPartial>,
+    diesel::result::Error,
+> {
+    use credit_store_demo::autogen::schema::coin_store_events_grouped_partial::dsl;
     use credit_store_demo::db::models::*;
     use diesel::prelude::*;
 
-    let objects_p: Vec<coin_store::EventGroupedPartial> = dsl_p::coin_store_events_grouped_partial
+    dsl::coin_store_events_grouped_partial
+        .pipe(|tbl| {
+            FilterDsl::filter(
+                tbl,
+                dsl::grp_span
+                    .eq(span_frame.span)
+                    .and(dsl::grp_frame.eq(span_frame.frame)),
+            )
+        })
         .select(coin_store::EventGroupedPartial::as_select())
-        .get_results(conn)?;
-
-    let objects: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
-        .select(coin_store::EventGrouped::as_select())
-        .get_results(conn)?;
-
-    let new_objects = objects
-        .into_iter()
-        .filter(|object| {
-            let in_partial = objects_p
-                .iter()
-                .any(|object_p| object_p.ev_id == object.ev_id);
+        .get_results(conn)
+}
 
-            let added_now = object.ev_id == new_event_id;
+fn display_coin_store_toggle_table(
+    objects: &[credit_store_demo::db::models::coin_store::EventGrouped],
+    objects_p: &[credit_store_demo::db::models::coin_store::EventGroupedPartial],
+    scale: credit_store_demo::db::models::Scale,
+) -> String {
+    let table_to_print = objects
+        .iter()
+        .map(|row| {
+            let in_partial = objects_p.iter().any(|object_p| object_p.ev_id == row.ev_id);
 
-            in_partial || added_now
+            (
+                format!("{}", row.ev_id),
+                format!("{}", in_partial),
+                display_timestamp(row.created_on_ts),
+                row.person.to_inner(),
+                display_coins(row.coins, scale, &row.asset),
+                row.ev_desc.clone(),
+            )
         })
         .collect::<Vec<_>>();
 
-    coin_store::set_events_grouped_partial(conn, &new_objects)?;
-
-    Ok(())
-}
-
-fn set_coin_store_events_partial_to_full_if_empty(
-    conn: &mut SqliteConnection,
-) -> Result<(), diesel::result::Error> {
-    use credit_store_demo::autogen::schema::coin_store_events_grouped_partial::dsl as dsl_p;
-    use credit_store_demo::db::models::*;
-    use diesel::prelude::*;
-
-    let objects_p: Vec<coin_store::EventGroupedPartial> = dsl_p::coin_store_events_grouped_partial
-        .select(coin_store::EventGroupedPartial::as_select())
-        .get_results(conn)?;
-
-    if !objects_p.is_empty() {
-        return Ok(());
-    }
-
-    set_coin_store_events_partial_to_full(conn)?;
-
-    Ok(())
+    display_pretty_table_for_records_toggled(&table_to_print)
 }
 
 fn coin_store_toggle_by_id(
     mut_state: &mut InternalShellState,
     _args: &[String],
 ) -> Result<String, ShiError> {
-    use credit_store_demo::autogen::schema::coin_store_diffs::dsl as dsl_d;
-    use credit_store_demo::autogen::schema::coin_store_events::dsl as dsl_e;
-    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
-    use credit_store_demo::autogen::schema::coin_store_events_grouped_partial::dsl as dsl_p;
     use credit_store_demo::db::models::*;
-    use diesel::prelude::*;
-
-    let objects_p: Vec<coin_store::EventGroupedPartial> = dsl_p::coin_store_events_grouped_partial
-        .select(coin_store::EventGroupedPartial::as_select())
-        .get_results(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let objects: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
-        .select(coin_store::EventGrouped::as_select())
-        .get_results(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let objects_p =
+        get_coin_store_events_grouped_partial(&mut mut_state.conn, &mut_state.cur_span_frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let events_joined: Vec<(coin_store::Event, coin_store::Diff)> = dsl_e::coin_store_events
-        .inner_join(dsl_d::coin_store_diffs)
-        .select((
-            coin_store::Event::as_select(),
-            coin_store::Diff::as_select(),
-        ))
-        .load::<(coin_store::Event, coin_store::Diff)>(&mut mut_state.conn)
+    let objects = get_coin_store_events_grouped(&mut mut_state.conn, &mut_state.cur_span_frame)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let events_joined_enabled: Vec<(String, &coin_store::Event, &coin_store::Diff)> = events_joined
-        .iter()
-        .map(|(event, diff)| {
-            let in_partial = objects_p.iter().any(|object_p| object_p.ev_id == event.id);
-
-            if in_partial {
-                ("true".to_owned(), event, diff)
-            } else {
-                ("false".to_owned(), event, diff)
-            }
-        })
-        .collect::<Vec<_>>();
-
-    let table_to_print = events_joined_enabled
-        .iter()
-        .map(|(en, row_ev, row_diff)| {
-            (
-                format!("{}", row_ev.id),
-                en.clone(),
-                display_timestamp(row_ev.created_on_ts),
-                row_diff.person.to_inner(),
-                format!("{}", row_diff.coins),
-                row_ev.ev_desc.clone(),
-            )
-        })
-        .collect::<Vec<_>>();
+    let scale = get_scale(&mut mut_state.conn)?;
 
     println!(
         "{}",
-        display_pretty_table_for_records_toggled(&table_to_print)
+        display_coin_store_toggle_table(&objects, &objects_p, scale)
     );
 
     let ev_id_toggled: u32 = match drivers::read_input_from_user_until_valid_or_quit(
@@ -515,14 +563,22 @@ fn coin_store_toggle_by_id(
                 .iter()
                 .any(|object_p| object_p.ev_id == object.ev_id);
 
-            let toggled_now = object.ev_id == (ev_id_toggled as i32);
+            let toggled_now = object.ev_id == ev_id_toggled as i32;
 
             in_partial ^ toggled_now
         })
         .collect::<Vec<_>>();
 
-    coin_store::set_events_grouped_partial(&mut mut_state.conn, &new_objects)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    coin_store::set_events_grouped_partial(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &new_objects,
+        &format!(
+            "toggle by id {ev_id_toggled} (session: {})",
+            mut_state.session
+        ),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
     Ok("Toggle applied".to_string())
 }
@@ -531,62 +587,20 @@ fn coin_store_undo_toggle_by_desc(
     mut_state: &mut InternalShellState,
     _args: &[String],
 ) -> Result<String, ShiError> {
-    use credit_store_demo::autogen::schema::coin_store_diffs::dsl as dsl_d;
-    use credit_store_demo::autogen::schema::coin_store_events::dsl as dsl_e;
-    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
-    use credit_store_demo::autogen::schema::coin_store_events_grouped_partial::dsl as dsl_p;
     use credit_store_demo::db::models::*;
-    use diesel::prelude::*;
-
-    let objects_p: Vec<coin_store::EventGroupedPartial> = dsl_p::coin_store_events_grouped_partial
-        .select(coin_store::EventGroupedPartial::as_select())
-        .get_results(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let objects: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
-        .select(coin_store::EventGrouped::as_select())
-        .get_results(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let objects_p =
+        get_coin_store_events_grouped_partial(&mut mut_state.conn, &mut_state.cur_span_frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let events_joined: Vec<(coin_store::Event, coin_store::Diff)> = dsl_e::coin_store_events
-        .inner_join(dsl_d::coin_store_diffs)
-        .select((
-            coin_store::Event::as_select(),
-            coin_store::Diff::as_select(),
-        ))
-        .load::<(coin_store::Event, coin_store::Diff)>(&mut mut_state.conn)
+    let objects = get_coin_store_events_grouped(&mut mut_state.conn, &mut_state.cur_span_frame)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let events_joined_enabled: Vec<(String, &coin_store::Event, &coin_store::Diff)> = events_joined
-        .iter()
-        .map(|(event, diff)| {
-            let in_partial = objects_p.iter().any(|object_p| object_p.ev_id == event.id);
-
-            if in_partial {
-                ("true".to_owned(), event, diff)
-            } else {
-                ("false".to_owned(), event, diff)
-            }
-        })
-        .collect::<Vec<_>>();
-
-    let table_to_print = events_joined_enabled
-        .iter()
-        .map(|(en, row_ev, row_diff)| {
-            (
-                format!("{}", row_ev.id),
-                en.clone(),
-                display_timestamp(row_ev.created_on_ts),
-                row_diff.person.to_inner(),
-                format!("{}", row_diff.coins),
-                row_ev.ev_desc.clone(),
-            )
-        })
-        .collect::<Vec<_>>();
+    let scale = get_scale(&mut mut_state.conn)?;
 
     println!(
         "{}",
-        display_pretty_table_for_records_toggled(&table_to_print)
+        display_coin_store_toggle_table(&objects, &objects_p, scale)
     );
 
     let desc_to_filter = match drivers::read_str_or_quit("Description substring") {
@@ -607,163 +621,292 @@ fn coin_store_undo_toggle_by_desc(
         })
         .collect::<Vec<_>>();
 
-    coin_store::set_events_grouped_partial(&mut mut_state.conn, &new_objects)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    coin_store::set_events_grouped_partial(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &new_objects,
+        &format!(
+            "toggle by description {desc_to_filter:?} (session: {})",
+            mut_state.session
+        ),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
     Ok("Toggle applied".to_string())
 }
 
-fn coin_store_span_push(
-    mut_state: &mut InternalShellState,
-    _args: &[String],
-) -> Result<String, ShiError> {
-    use credit_store_demo::db::models::*;
+pub fn display_pretty_table_for_toggle_preview(
+    table_to_print: &[(String, String, String)],
+) -> String {
+    use tabled::{builder::Builder, settings::Style};
 
-    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let mut b = Builder::with_capacity(3, 0);
 
-    let opt_latest_upper_span_frame = span_frames
-        .iter()
-        .filter(|sf| sf.span == mut_state.cur_span_frame.span + 1)
-        .max_by_key(|k| k.frame.abs());
+    b.push_record(["person", "total_coins_before", "total_coins_after"]);
 
-    match opt_latest_upper_span_frame {
-        Some(latest_upper_span_frame) => {
-            let sf = coin_store::create_span_frame(
-                &mut mut_state.conn,
-                mut_state.cur_span_frame.span + 1,
-                latest_upper_span_frame.frame + 1,
-                "push new spanframe",
-            )
-            .map_err(|e| ShiError::General { msg: e.to_string() })?;
-            mut_state.cur_span_frame = sf;
-        }
-        None => {
-            let sf = coin_store::create_span_frame(
-                &mut mut_state.conn,
-                mut_state.cur_span_frame.span + 1,
-                1,
-                "push new spanframe",
-            )
-            .map_err(|e| ShiError::General { msg: e.to_string() })?;
-            mut_state.cur_span_frame = sf;
-        }
+    for (person, coins_before, coins_after) in table_to_print {
+        b.push_record([person, coins_before, coins_after]);
     }
 
-    Ok(format!(
-        "Pushed frame (span: {}, frame: {})",
-        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame
-    ))
-}
+    let mut table = b.build();
 
-fn coin_store_span_pop(
-    mut_state: &mut InternalShellState,
-    _args: &[String],
-) -> Result<String, ShiError> {
-    use credit_store_demo::db::models::*;
+    table.with(Style::modern_rounded());
 
-    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    table.to_string()
+}
 
-    // Go to the latest lower span frame
-    if mut_state.cur_span_frame.span == 1 {
-        return Ok("This is the lowest span".to_owned());
-    }
+/// Total coins per person and asset
+type CoinsPerPerson = Vec<(
+    (String, credit_store_demo::db::models::Asset),
+    credit_store_demo::db::models::Amount,
+)>;
 
-    let opt_latest_lower_span_frame = span_frames
-        .iter()
-        .filter(|sf| sf.span == mut_state.cur_span_frame.span - 1)
-        .max_by_key(|k| k.frame.abs());
+/// Sums the coins per person and asset over the given enabled events, in the order they first appear
+fn get_coin_store_total_coins_per_person(
+    objects: &[&credit_store_demo::db::models::coin_store::EventGrouped],
+) -> Result<CoinsPerPerson, credit_store_demo::db::models::AmountError> {
+    let mut mut_out: CoinsPerPerson = vec![];
 
-    match opt_latest_lower_span_frame {
-        Some(latest_lower_span_frame) => {
-            mut_state.cur_span_frame = latest_lower_span_frame.clone();
-            Ok(format!(
-                "Popped to frame (span: {}, frame: {})",
-                latest_lower_span_frame.span, latest_lower_span_frame.frame
-            ))
+    for object in objects {
+        let key = (object.person.to_inner(), object.asset.clone());
+
+        match mut_out.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, coins)) => *coins = coins.checked_add(object.coins)?,
+            None => mut_out.push((key, object.coins)),
         }
-        None => Ok("Error: Could not find latest lower span frame".to_owned()),
     }
+
+    Ok(mut_out)
 }
 
-fn coin_store_switch(
+/// Toggles the events matching a filter expression, after previewing the partial wallet and asking for confirmation.
+/// See `credit_store_demo::db::filter` for the syntax.
+fn coin_store_toggle_where(
     mut_state: &mut InternalShellState,
-    _args: &[String],
+    args: &[String],
 ) -> Result<String, ShiError> {
-    use credit_store_demo::db::models::*;
-
-    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    use credit_store_demo::db::{filter::EventFilter, models::*};
 
-    let span: u32 = match drivers::read_input_from_user_until_valid_or_quit("span (u32)") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
+    let filter_str = if args.is_empty() {
+        match drivers::read_str_or_quit(
+            "Filter (e.g. person=alice coins=10..100 date=2025-01-01..2025-01-31 frame=1/1 action=update desc=^rent)",
+        ) {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        }
+    } else {
+        args.join(" ")
     };
 
-    let span_exists = span_frames.iter().any(|sf| sf.span == span as i32);
-
-    if !span_exists {
-        return Ok("Error: span does not exist".to_owned());
-    }
+    let scale = get_scale(&mut mut_state.conn)?;
 
-    let frame: u32 = match drivers::read_input_from_user_until_valid_or_quit("frame (u32)") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
+    let filter = match EventFilter::parse_scaled(&filter_str, scale) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
     };
 
-    let opt_sf = span_frames
+    let objects_p =
+        get_coin_store_events_grouped_partial(&mut mut_state.conn, &mut_state.cur_span_frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let objects = get_coin_store_events_grouped(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let matched_objects = objects
         .iter()
-        .find(|sf| sf.span == span as i32 && sf.frame == frame as i32);
+        .filter(|object| filter.matches(object))
+        .cloned()
+        .collect::<Vec<_>>();
 
-    match opt_sf {
-        Some(sf) => {
-            mut_state.cur_span_frame = sf.clone();
-            Ok(format!(
-                "Switched to frame (span: {}, frame: {})",
-                sf.span, sf.frame
-            ))
-        }
-        None => Ok("Error: No such span frame found".to_owned()),
+    if matched_objects.is_empty() {
+        return Ok("No events match the filter".to_owned());
     }
-}
 
-fn coin_store_soft_reset(
-    mut_state: &mut InternalShellState,
-    _args: &[String],
-) -> Result<String, ShiError> {
-    use credit_store_demo::db::models::*;
+    let enabled_ev_ids = objects_p
+        .iter()
+        .map(|object_p| object_p.ev_id)
+        .collect::<Vec<_>>();
 
-    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
+    let enabled_before = objects
+        .iter()
+        .filter(|object| enabled_ev_ids.contains(&object.ev_id))
+        .collect::<Vec<_>>();
+
+    let asked_for = objects
+        .iter()
+        .filter(|object| {
+            let in_partial = enabled_ev_ids.contains(&object.ev_id);
+            let toggled_now = filter.matches(object);
+
+            in_partial ^ toggled_now
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // Transactions are toggled as a whole, so the preview shows what becomes of their other postings too
+    let enabled_after = coin_store::get_events_grouped_partial_next(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &asked_for,
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let totals_before = get_coin_store_total_coins_per_person(&enabled_before)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let totals_after =
+        get_coin_store_total_coins_per_person(&enabled_after.iter().collect::<Vec<_>>())
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let opt_latest_sf_in_span = span_frames
+    let keys = totals_before
         .iter()
-        .filter(|sf| sf.span == mut_state.cur_span_frame.span)
-        .max_by_key(|k| k.frame.abs());
+        .chain(totals_after.iter())
+        .map(|(key, _)| key.clone())
+        .unique()
+        .collect::<Vec<_>>();
 
-    match opt_latest_sf_in_span {
-        Some(sf) => {
-            let new_sf = coin_store::create_span_frame(
-                &mut mut_state.conn,
-                sf.span,
-                sf.frame + 1,
-                "soft reset",
+    let total_for = |totals: &CoinsPerPerson,
+                     key: &(String, credit_store_demo::db::models::Asset)| {
+        totals
+            .iter()
+            .find(|(other, _)| other == key)
+            .map(|(_, coins)| display_coins(*coins, scale, &key.1))
+            .unwrap_or_else(|| "-".to_owned())
+    };
+
+    let table_to_print = keys
+        .iter()
+        .map(|key| {
+            (
+                key.0.clone(),
+                total_for(&totals_before, key),
+                total_for(&totals_after, key),
             )
+        })
+        .collect::<Vec<_>>();
+
+    println!(
+        "{}",
+        display_coin_store_toggle_table(&matched_objects, &objects_p, scale)
+    );
+
+    println!(
+        "{}",
+        display_pretty_table_for_toggle_preview(&table_to_print)
+    );
+
+    let confirm = match drivers::read_str_or_quit(&format!(
+        "Toggle {} events? (yes/any)",
+        matched_objects.len()
+    )) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if confirm != "yes" {
+        return Ok("Did nothing".to_owned());
+    }
+
+    coin_store::set_events_grouped_partial(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &enabled_after,
+        &format!(
+            "toggle where {filter_str:?} (session: {})",
+            mut_state.session
+        ),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok("Toggle applied".to_string())
+}
+
+/// Show the toggle events recorded for the current span frame
+fn coin_store_toggle_log(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let toggle_events =
+        coin_store::get_toggle_events(&mut mut_state.conn, &mut_state.cur_span_frame)
             .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-            mut_state.cur_span_frame = new_sf.clone();
+    let mut b = Builder::with_capacity(3, 0);
 
-            Ok(format!(
-                "Switched to frame (span: {}, frame: {})",
-                new_sf.span, new_sf.frame
-            ))
-        }
-        None => Ok("Error: Could not find latest span frame".to_owned()),
+    b.push_record(["id", "action", "target_id", "created_on", "description"]);
+
+    for event in toggle_events {
+        b.push_record([
+            format!("{}", event.id),
+            format!("{:?}", event.ev_action),
+            event
+                .opt_target_ev_id
+                .map(|target_ev_id| format!("{target_ev_id}"))
+                .unwrap_or_default(),
+            display_timestamp(event.created_on_ts),
+            event.ev_desc,
+        ]);
     }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
 }
 
-fn coin_store_hard_reset(
+/// The frame that a push from the given span frame would open, one past the latest frame at the upper span
+fn get_next_upper_span_frame(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<SpanFrame, diesel::result::Error> {
+    use credit_store_demo::db::models::*;
+
+    let span_frames = coin_store::get_created_span_frames(conn)?;
+
+    let opt_latest_upper_span_frame = span_frames
+        .iter()
+        .filter(|sf| sf.span == span_frame.span + 1)
+        .max_by_key(|k| k.frame.abs());
+
+    Ok(SpanFrame {
+        span: span_frame.span + 1,
+        frame: opt_latest_upper_span_frame
+            .map(|latest_upper_span_frame| latest_upper_span_frame.frame + 1)
+            .unwrap_or(1),
+    })
+}
+
+fn coin_store_span_push(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let next_span_frame = get_next_upper_span_frame(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let sf = coin_store::create_span_frame(
+        &mut mut_state.conn,
+        next_span_frame.span,
+        next_span_frame.frame,
+        Some(&mut_state.cur_span_frame),
+        "push new spanframe",
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    mut_state.switch_span_frame(sf)?;
+
+    Ok(format!(
+        "Pushed frame (span: {}, frame: {})",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame
+    ))
+}
+
+fn coin_store_span_pop(
     mut_state: &mut InternalShellState,
     _args: &[String],
 ) -> Result<String, ShiError> {
@@ -772,228 +915,2907 @@ fn coin_store_hard_reset(
     let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    let opt_latest_sf_in_span = span_frames
+    // Go to the latest lower span frame
+    if mut_state.cur_span_frame.span == 1 {
+        return Ok("This is the lowest span".to_owned());
+    }
+
+    let opt_latest_lower_span_frame = span_frames
         .iter()
-        .filter(|sf| sf.span == 1)
+        .filter(|sf| sf.span == mut_state.cur_span_frame.span - 1)
         .max_by_key(|k| k.frame.abs());
 
-    match opt_latest_sf_in_span {
-        Some(sf) => {
-            let new_sf = coin_store::create_span_frame(
-                &mut mut_state.conn,
-                sf.span,
-                sf.frame + 1,
-                "hard reset",
-            )
-            .map_err(|e| ShiError::General { msg: e.to_string() })?;
-
-            mut_state.cur_span_frame = new_sf.clone();
-
+    match opt_latest_lower_span_frame {
+        Some(latest_lower_span_frame) => {
+            mut_state.switch_span_frame(latest_lower_span_frame.clone())?;
             Ok(format!(
-                "Switched to frame (span: {}, frame: {})",
-                new_sf.span, new_sf.frame
+                "Popped to frame (span: {}, frame: {})",
+                latest_lower_span_frame.span, latest_lower_span_frame.frame
             ))
         }
-        None => Ok("Error: Could not find latest span frame".to_owned()),
+        None => Ok("Error: Could not find latest lower span frame".to_owned()),
     }
 }
 
-fn coin_store_actually_reset(
+/// Makes the partial view of the current span frame real. A frame is pushed on top of it, and for every disabled
+/// event a compensating event that targets it is written there. Any difference left over, such as from events the new
+/// frame did not inherit, is then reconciled against the partial hist so both views end up the same.
+fn coin_store_partial_commit(
     mut_state: &mut InternalShellState,
     _args: &[String],
 ) -> Result<String, ShiError> {
-    use credit_store_demo::autogen::schema::coin_store_diffs::dsl as dsl_d;
-    use credit_store_demo::autogen::schema::coin_store_events::dsl;
+    use credit_store_demo::autogen::schema::{coin_store_hist, coin_store_hist_partial};
+    use credit_store_demo::db::models::*;
+    use diesel::prelude::*;
 
-    let del_resp = match drivers::read_str_or_quit("Really delete everything? (yes/any)") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
-    };
+    let src_span_frame = mut_state.cur_span_frame.clone();
 
-    if del_resp == "yes" {
-        diesel::delete(dsl_d::coin_store_diffs)
-            .execute(&mut mut_state.conn)
-            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let objects_p = get_coin_store_events_grouped_partial(&mut mut_state.conn, &src_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-        diesel::delete(dsl::coin_store_events)
-            .execute(&mut mut_state.conn)
-            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let objects = get_coin_store_events_grouped(&mut mut_state.conn, &src_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-        set_coin_store_events_partial_to_full(&mut mut_state.conn)
-            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let disabled_objects = objects
+        .iter()
+        .filter(|object| {
+            !objects_p
+                .iter()
+                .any(|object_p| object_p.ev_id == object.ev_id)
+        })
+        .collect::<Vec<_>>();
 
-        let res_sf = get_or_create_init_span_frame(&mut mut_state.conn);
+    if disabled_objects.is_empty() {
+        return Ok(
+            "Nothing is toggled off, the partial view is the same as the full one".to_owned(),
+        );
+    }
 
-        match res_sf {
-            Ok(sf) => {
-                mut_state.cur_span_frame = sf.clone();
+    let target_hist = coin_store_hist_partial::dsl::coin_store_hist_partial
+        .pipe(|tbl| {
+            FilterDsl::filter(
+                tbl,
+                coin_store_hist_partial::dsl::grp_span
+                    .eq(src_span_frame.span)
+                    .and(coin_store_hist_partial::dsl::grp_frame.eq(src_span_frame.frame)),
+            )
+        })
+        .select(coin_store::HistPartial::as_select())
+        .get_results(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let target_state_for = |obj_id: i32| {
+        target_hist
+            .iter()
+            .find(|row| row.obj_id == obj_id)
+            .map(|row| row.obj_state.clone())
+            .unwrap_or(ObjState::Delete)
+    };
+
+    let new_span_frame = mut_state
+        .conn
+        .transaction::<_, CommitPartialError, _>(|conn| {
+            use credit_store_demo::db::double_entry::{self, Posting};
+
+            // In double-entry mode, everything written to the new frame is one transaction, which only balances if
+            // whole transactions were toggled
+            let opt_tx_id = if double_entry::is_double_entry(conn)? {
+                Some(double_entry::get_next_tx_id(conn)?)
+            } else {
+                None
+            };
+
+            let next_span_frame = get_next_upper_span_frame(conn, &src_span_frame)?;
+
+            let new_span_frame = coin_store::create_span_frame(
+                conn,
+                next_span_frame.span,
+                next_span_frame.frame,
+                Some(&src_span_frame),
+                &format!(
+                    "commit partial of (span: {}, frame: {})",
+                    src_span_frame.span, src_span_frame.frame
+                ),
+            )?;
+
+            for object in disabled_objects.iter() {
+                double_entry::insert_posting(
+                    conn,
+                    &new_span_frame,
+                    opt_tx_id,
+                    &Posting {
+                        obj_id: object.obj_id,
+                        obj_state: target_state_for(object.obj_id),
+                        opt_target_ev_id: Some(object.ev_id),
+                        ev_desc: &format!("compensate event {}: {}", object.ev_id, object.ev_desc),
+                        person: &object.person,
+                        coins: object.coins.checked_neg()?,
+                        asset: &object.asset,
+                        opt_rate: None,
+                        opt_expires_on: None,
+                    },
+                )?;
             }
-            Err(e) => {
-                return Ok(format!("Error: Failed to create spanframe: {}", e));
+
+            let actual_hist = coin_store_hist::dsl::coin_store_hist
+                .pipe(|tbl| {
+                    FilterDsl::filter(
+                        tbl,
+                        coin_store_hist::dsl::grp_span
+                            .eq(new_span_frame.span)
+                            .and(coin_store_hist::dsl::grp_frame.eq(new_span_frame.frame)),
+                    )
+                })
+                .select(coin_store::Hist::as_select())
+                .get_results(conn)?;
+
+            let keys = target_hist
+                .iter()
+                .map(|row| row.get_key())
+                .chain(actual_hist.iter().map(|row| row.get_key()))
+                .unique()
+                .collect::<Vec<_>>();
+
+            for key in keys.iter() {
+                let (obj_id, asset) = key;
+
+                let opt_target = target_hist.iter().find(|row| row.get_key() == *key);
+                let opt_actual = actual_hist.iter().find(|row| row.get_key() == *key);
+
+                let (obj_state, coins, person) = match (opt_target, opt_actual) {
+                    (Some(target), Some(actual)) => (
+                        target.obj_state.clone(),
+                        target.coins.checked_sub(actual.coins)?,
+                        &target.person,
+                    ),
+                    (Some(target), None) => {
+                        (target.obj_state.clone(), target.coins, &target.person)
+                    }
+                    (None, Some(actual)) => (
+                        ObjState::Delete,
+                        actual.coins.checked_neg()?,
+                        &actual.person,
+                    ),
+                    (None, None) => continue,
+                };
+
+                let actual_obj_state = opt_actual.map(|actual| format!("{:?}", actual.obj_state));
+
+                if coins.is_zero() && actual_obj_state == Some(format!("{obj_state:?}")) {
+                    continue;
+                }
+
+                double_entry::insert_posting(
+                    conn,
+                    &new_span_frame,
+                    opt_tx_id,
+                    &Posting {
+                        obj_id: *obj_id,
+                        obj_state,
+                        opt_target_ev_id: None,
+                        ev_desc: &format!(
+                            "reconcile with partial of (span: {}, frame: {})",
+                            src_span_frame.span, src_span_frame.frame
+                        ),
+                        person,
+                        coins,
+                        asset,
+                        opt_rate: None,
+                        opt_expires_on: None,
+                    },
+                )?;
             }
-        }
 
-        Ok("deleted everything".to_owned())
-    } else {
-        Ok("Did nothing".to_owned())
-    }
+            if let Some(tx_id) = opt_tx_id {
+                double_entry::check_transaction_balanced(conn, tx_id)?;
+            }
+
+            Ok(new_span_frame)
+        })
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    mut_state.switch_span_frame(new_span_frame)?;
+
+    Ok(format!(
+        "Committed {} disabled events of (span: {}, frame: {}) into frame (span: {}, frame: {})",
+        disabled_objects.len(),
+        src_span_frame.span,
+        src_span_frame.frame,
+        mut_state.cur_span_frame.span,
+        mut_state.cur_span_frame.frame
+    ))
 }
 
-fn coin_store_income(
+fn coin_store_snapshot_create(
     mut_state: &mut InternalShellState,
     _args: &[String],
 ) -> Result<String, ShiError> {
-    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
     use credit_store_demo::db::models::*;
-    use diesel::prelude::*;
 
-    let person: Person =
-        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
-            Some(item) => item,
-            None => return Ok("".to_owned()),
-        };
+    let snapshot = coin_store::create_span_frame_snapshot(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &format!("manual snapshot (session: {})", mut_state.session),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    // Check if the user already exists in the current spanframe
-    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
-        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
-        .select(coin_store::EventGrouped::as_select())
-        .get_results(&mut mut_state.conn)
+    Ok(format!(
+        "Created snapshot {} of (span: {}, frame: {}) up to event {}",
+        snapshot.id, snapshot.span, snapshot.frame, snapshot.last_ev_id
+    ))
+}
+
+fn coin_store_snapshot_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let snapshots = coin_store::get_snapshots(&mut mut_state.conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    if results.is_empty() {
-        return Ok("Error: User does not exist".to_owned());
-    }
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record([
+        "id",
+        "span/frame",
+        "last_ev_id",
+        "created_on",
+        "description",
+    ]);
+
+    for snapshot in snapshots {
+        b.push_record([
+            format!("{}", snapshot.id),
+            format!("{}/{}", snapshot.span, snapshot.frame),
+            format!("{}", snapshot.last_ev_id),
+            display_timestamp(snapshot.created_on_ts),
+            snapshot.ev_desc,
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!("{}", table))
+}
+
+/// Checks the current span frame's wallet, projected from its latest snapshot, against a replay of all events
+fn coin_store_snapshot_verify(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let mismatches =
+        coin_store::verify_span_frame_snapshot(&mut mut_state.conn, &mut_state.cur_span_frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    if mismatches.is_empty() {
+        return Ok(format!(
+            "(span: {}, frame: {}) matches a full replay",
+            mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame
+        ));
+    }
+
+    let display_hist = |opt_hist: &Option<coin_store::Hist>| {
+        opt_hist
+            .as_ref()
+            .map(|hist| {
+                format!(
+                    "{:?} {}",
+                    hist.obj_state,
+                    display_coins(hist.coins, scale, &hist.asset)
+                )
+            })
+            .unwrap_or_else(|| "-".to_owned())
+    };
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["person", "snapshotted", "replayed"]);
+
+    for mismatch in mismatches {
+        let person = mismatch
+            .opt_projected
+            .as_ref()
+            .or(mismatch.opt_replayed.as_ref())
+            .map(|hist| hist.person.to_inner())
+            .unwrap_or_default();
+
+        b.push_record([
+            person,
+            display_hist(&mismatch.opt_projected),
+            display_hist(&mismatch.opt_replayed),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "Error: (span: {}, frame: {}) differs from a full replay\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Recomputes the hash chain over all events to detect edits made to the database outside of the demo
+fn coin_store_verify(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    match coin_store::verify_event_chain(&mut mut_state.conn) {
+        Ok((event_count, opt_head_ev_hash)) => Ok(format!(
+            "Verified {event_count} events. Latest hash: {}",
+            opt_head_ev_hash.unwrap_or_else(|| "-".to_owned())
+        )),
+        Err(VerifyEventChainError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: Broken link in the event chain. {e}")),
+    }
+}
+
+/// Exports events, diffs, wallets or records as JSON Lines or CSV, to stdout or a file. See `docs/export.md`.
+fn coin_store_export(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::export::{self, ExportFormat, ExportKind};
+
+    let res_matches = clap::Command::new("coins export")
+        .no_binary_name(true)
+        .arg(
+            clap::Arg::new("kind")
+                .required(true)
+                .value_parser(["events", "diffs", "wallet", "records"]),
+        )
+        .arg(
+            clap::Arg::new("format")
+                .long("format")
+                .default_value("jsonl")
+                .value_parser(["jsonl", "csv"]),
+        )
+        .arg(
+            clap::Arg::new("frame")
+                .long("frame")
+                .help("Only export this span frame, as <span>/<frame> or a name or tag"),
+        )
+        .arg(
+            clap::Arg::new("out")
+                .long("out")
+                .help("File to write to instead of stdout"),
+        )
+        .try_get_matches_from(args);
+
+    let matches = match res_matches {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let get_arg = |id: &str| matches.get_one::<String>(id).cloned();
+
+    let kind_str = get_arg("kind").expect("kind is required");
+
+    let kind: ExportKind = kind_str
+        .parse()
+        .map_err(|e: export::ParseExportArgError| ShiError::General { msg: e.to_string() })?;
+
+    let format: ExportFormat = get_arg("format")
+        .expect("format has a default value")
+        .parse()
+        .map_err(|e: export::ParseExportArgError| ShiError::General { msg: e.to_string() })?;
+
+    let opt_span_frame = match get_arg("frame") {
+        Some(frame) => match find_span_frame(&mut mut_state.conn, &frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?
+        {
+            Some(span_frame) => Some(span_frame),
+            None => return Ok(format!("Error: No span frame matches {frame:?}")),
+        },
+        None => None,
+    };
+
+    match get_arg("out") {
+        Some(out_path) => {
+            let mut file = std::fs::File::create(&out_path)
+                .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+            let count = export::export(
+                &mut mut_state.conn,
+                kind,
+                format,
+                opt_span_frame.as_ref(),
+                &mut file,
+            )
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+            Ok(format!("Exported {count} {kind_str} to {out_path}"))
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+
+            export::export(
+                &mut mut_state.conn,
+                kind,
+                format,
+                opt_span_frame.as_ref(),
+                &mut stdout,
+            )
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+            Ok("".to_owned())
+        }
+    }
+}
+
+fn coin_store_ledger(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::ledger::{self, LedgerFormat};
+
+    let res_matches = clap::Command::new("coins ledger")
+        .no_binary_name(true)
+        .arg(
+            clap::Arg::new("format")
+                .long("format")
+                .default_value("ledger")
+                .value_parser(["ledger", "beancount"]),
+        )
+        .arg(clap::Arg::new("frame").long("frame").help(
+            "Span frame to export instead of the current one, as <span>/<frame> or a name or tag",
+        ))
+        .arg(
+            clap::Arg::new("out")
+                .long("out")
+                .help("File to write to instead of stdout"),
+        )
+        .try_get_matches_from(args);
+
+    let matches = match res_matches {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let get_arg = |id: &str| matches.get_one::<String>(id).cloned();
+
+    let format: LedgerFormat = get_arg("format")
+        .expect("format has a default value")
+        .parse()
+        .map_err(|e: ledger::ParseLedgerFormatError| ShiError::General { msg: e.to_string() })?;
+
+    let span_frame = match get_arg("frame") {
+        Some(frame) => match find_span_frame(&mut mut_state.conn, &frame)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?
+        {
+            Some(span_frame) => span_frame,
+            None => return Ok(format!("Error: No span frame matches {frame:?}")),
+        },
+        None => mut_state.cur_span_frame.clone(),
+    };
+
+    let (count, mismatches) = match get_arg("out") {
+        Some(out_path) => {
+            let mut file = std::fs::File::create(&out_path)
+                .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+            ledger::export_ledger(&mut mut_state.conn, format, &span_frame, &mut file)
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+
+            ledger::export_ledger(&mut mut_state.conn, format, &span_frame, &mut stdout)
+        }
+    }
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let mut mut_out = format!(
+        "Exported {count} transactions of (span: {}, frame: {})",
+        span_frame.span, span_frame.frame
+    );
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    if mismatches.is_empty() {
+        mut_out += "\nBalances match the wallet";
+    } else {
+        for mismatch in mismatches {
+            mut_out += &format!(
+                "\nError: {} has {} {} in the export but {} in the wallet",
+                mismatch.person,
+                mismatch.exported_coins.to_scaled_string(scale),
+                mismatch.asset,
+                mismatch.hist_coins.to_scaled_string(scale)
+            );
+        }
+    }
+
+    Ok(mut_out)
+}
+
+fn coin_store_import(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::import;
+
+    let res_matches = clap::Command::new("coins import")
+        .no_binary_name(true)
+        .arg(
+            clap::Arg::new("file")
+                .required(true)
+                .help("JSON Lines file written by coins export events"),
+        )
+        .arg(
+            clap::Arg::new("dry-run")
+                .long("dry-run")
+                .action(clap::ArgAction::SetTrue)
+                .help("Only show what would be imported"),
+        )
+        .try_get_matches_from(args);
+
+    let matches = match res_matches {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let file_path = matches.get_one::<String>("file").expect("file is required");
+
+    let dry_run = matches.get_flag("dry-run");
+
+    let file = match std::fs::File::open(file_path) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: Failed to open {file_path}: {e}")),
+    };
+
+    let res_summary = import::read_exported_events(std::io::BufReader::new(file))
+        .and_then(|events| import::import_events(&mut mut_state.conn, &events, dry_run));
+
+    let summary = match res_summary {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: Nothing was imported. {e}")),
+    };
+
+    let display_span_frames = |span_frames: &[SpanFrame]| {
+        span_frames
+            .iter()
+            .map(|sf| format!("{}/{}", sf.span, sf.frame))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut mut_out = format!(
+        "{} {} events",
+        if dry_run { "Would import" } else { "Imported" },
+        summary.event_count
+    );
+
+    for (ev_action, count) in summary.action_counts.iter() {
+        mut_out += &format!("\n  {ev_action}: {count}");
+    }
+
+    if !summary.opened_span_frames.is_empty() {
+        mut_out += &format!(
+            "\nNew span frames: {}",
+            display_span_frames(&summary.opened_span_frames)
+        );
+    }
+
+    if !summary.existing_span_frames.is_empty() {
+        mut_out += &format!(
+            "\nAppended to existing span frames: {}",
+            display_span_frames(&summary.existing_span_frames)
+        );
+    }
+
+    Ok(mut_out)
+}
+
+/// Switch to a span frame by name or tag if one is given, otherwise asks for the span and frame
+fn coin_store_switch(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    if let Some(label) = args.first() {
+        let opt_sf = coin_store::find_span_frame_by_label(&mut mut_state.conn, label)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        return match opt_sf {
+            Some(sf) => {
+                mut_state.switch_span_frame(sf.clone())?;
+                Ok(format!(
+                    "Switched to frame {label} (span: {}, frame: {})",
+                    sf.span, sf.frame
+                ))
+            }
+            None => Ok(format!("Error: No span frame named or tagged {label}")),
+        };
+    }
+
+    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let span: u32 = match drivers::read_input_from_user_until_valid_or_quit("span (u32)") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let span_exists = span_frames.iter().any(|sf| sf.span == span as i32);
+
+    if !span_exists {
+        return Ok("Error: span does not exist".to_owned());
+    }
+
+    let frame: u32 = match drivers::read_input_from_user_until_valid_or_quit("frame (u32)") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let opt_sf = span_frames
+        .iter()
+        .find(|sf| sf.span == span as i32 && sf.frame == frame as i32);
+
+    match opt_sf {
+        Some(sf) => {
+            mut_state.switch_span_frame(sf.clone())?;
+            Ok(format!(
+                "Switched to frame (span: {}, frame: {})",
+                sf.span, sf.frame
+            ))
+        }
+        None => Ok("Error: No such span frame found".to_owned()),
+    }
+}
+
+fn coin_store_name(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let name = match drivers::arg_or_read_str_or_quit(args, 0, "Name for the current span frame") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    match coin_store::name_span_frame(&mut mut_state.conn, &mut_state.cur_span_frame, &name) {
+        Ok(()) => Ok(format!(
+            "Named frame (span: {}, frame: {}) {name}",
+            mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame
+        )),
+        Err(LabelSpanFrameError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+fn coin_store_tag(mut_state: &mut InternalShellState, args: &[String]) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let tag = match drivers::arg_or_read_str_or_quit(args, 0, "Tag for the current span frame") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    match coin_store::tag_span_frame(&mut mut_state.conn, &mut_state.cur_span_frame, &tag) {
+        Ok(()) => Ok(format!(
+            "Tagged frame (span: {}, frame: {}) {tag}",
+            mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame
+        )),
+        Err(LabelSpanFrameError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+fn coin_store_soft_reset(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let opt_latest_sf_in_span = span_frames
+        .iter()
+        .filter(|sf| sf.span == mut_state.cur_span_frame.span)
+        .max_by_key(|k| k.frame.abs());
+
+    // The new frame is a sibling of the current one, so it branches off the same parent
+    let opt_parent = coin_store::get_span_frame_infos(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?
+        .into_iter()
+        .find(|info| {
+            info.span_frame.span == mut_state.cur_span_frame.span
+                && info.span_frame.frame == mut_state.cur_span_frame.frame
+        })
+        .and_then(|info| info.opt_parent);
+
+    match opt_latest_sf_in_span {
+        Some(sf) => {
+            let new_sf = coin_store::create_span_frame(
+                &mut mut_state.conn,
+                sf.span,
+                sf.frame + 1,
+                opt_parent.as_ref(),
+                "soft reset",
+            )
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+            mut_state.switch_span_frame(new_sf.clone())?;
+
+            Ok(format!(
+                "Switched to frame (span: {}, frame: {})",
+                new_sf.span, new_sf.frame
+            ))
+        }
+        None => Ok("Error: Could not find latest span frame".to_owned()),
+    }
+}
+
+fn coin_store_hard_reset(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let opt_latest_sf_in_span = span_frames
+        .iter()
+        .filter(|sf| sf.span == 1)
+        .max_by_key(|k| k.frame.abs());
+
+    match opt_latest_sf_in_span {
+        Some(sf) => {
+            let new_sf = coin_store::create_span_frame(
+                &mut mut_state.conn,
+                sf.span,
+                sf.frame + 1,
+                None,
+                "hard reset",
+            )
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+            mut_state.switch_span_frame(new_sf.clone())?;
+
+            Ok(format!(
+                "Switched to frame (span: {}, frame: {})",
+                new_sf.span, new_sf.frame
+            ))
+        }
+        None => Ok("Error: Could not find latest span frame".to_owned()),
+    }
+}
+
+fn coin_store_actually_reset(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::autogen::schema::coin_store_diffs::dsl as dsl_d;
+    use credit_store_demo::autogen::schema::coin_store_events::dsl;
+    use credit_store_demo::autogen::schema::coin_store_snapshot_hist::dsl as dsl_sh;
+    use credit_store_demo::autogen::schema::coin_store_snapshots::dsl as dsl_s;
+
+    let del_resp = match drivers::read_str_or_quit("Really delete everything? (yes/any)") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if del_resp == "yes" {
+        diesel::delete(dsl_sh::coin_store_snapshot_hist)
+            .execute(&mut mut_state.conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        diesel::delete(dsl_s::coin_store_snapshots)
+            .execute(&mut mut_state.conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        diesel::delete(dsl_d::coin_store_diffs)
+            .execute(&mut mut_state.conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        diesel::delete(dsl::coin_store_events)
+            .execute(&mut mut_state.conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        let res_sf = get_or_create_init_span_frame(&mut mut_state.conn);
+
+        match res_sf {
+            Ok(sf) => {
+                mut_state.switch_span_frame(sf.clone())?;
+            }
+            Err(e) => {
+                return Ok(format!("Error: Failed to create spanframe: {}", e));
+            }
+        }
+
+        Ok("deleted everything".to_owned())
+    } else {
+        Ok("Did nothing".to_owned())
+    }
+}
+
+fn coin_store_income(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::models::*;
+    use diesel::prelude::*;
+
+    let (args, opt_expires_on) = match args {
+        [rest @ .., flag, date] if flag == "--expires-on" => match date.parse::<NaiveDate>() {
+            Ok(item) => (rest, Some(item)),
+            Err(e) => return Ok(format!("Error: Invalid date {date:?}: {e}")),
+        },
+        _ => (args, None),
+    };
+
+    if let Some(expires_on) = opt_expires_on
+        && expires_on <= Utc::now().date_naive()
+    {
+        return Ok(format!(
+            "Error: Coins expiring on {expires_on} would already be expired"
+        ));
+    }
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    // Check if the user already exists in the current spanframe
+    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if results.is_empty() {
+        return Ok("Error: User does not exist".to_owned());
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins to add (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if !coins.is_positive() {
+        return Ok("Error: Coins must be positive".to_owned());
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if let Some(expires_on) = opt_expires_on {
+        db::expiry::insert_expiring_income(
+            &mut mut_state.conn,
+            &mut_state.cur_span_frame,
+            &person,
+            coins,
+            &asset,
+            expires_on,
+            &desc,
+        )
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        return Ok(format!("Added income for user, expiring on {expires_on}"));
+    }
+
+    credit_store_demo::db::double_entry::insert_coins_for_person(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &person,
+        coins,
+        &asset,
+        &desc,
+        None,
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok("Added income for user".to_owned())
+}
+
+/// The asset passed as the first argument of a command, or the default one
+fn get_asset_arg(
+    args: &[String],
+) -> Result<credit_store_demo::db::models::Asset, credit_store_demo::db::models::AssetFromStrError>
+{
+    args.first()
+        .map_or_else(|| Ok(Default::default()), |arg| arg.parse())
+}
+
+/// The error to show when coins could not be held, with the coins at the scale of the store
+fn get_hold_error_msg(
+    mut_state: &mut InternalShellState,
+    e: credit_store_demo::db::hold::HoldError,
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::hold::HoldError;
+
+    match e {
+        HoldError::InsufficientFunds {
+            person,
+            asset,
+            available,
+            ..
+        } => {
+            let scale = get_scale(&mut mut_state.conn)?;
+
+            Ok(format!(
+                "Error: {person} only has {} {asset} available",
+                available.to_scaled_string(scale)
+            ))
+        }
+        HoldError::LimitError(e) => get_limit_error_msg(mut_state, e),
+        HoldError::DieselError(e) => Err(ShiError::General { msg: e.to_string() }),
+        e => Ok(format!("Error: {e}")),
+    }
+}
+
+/// The error to show when a limit refused to spend coins, with the coins at the scale of the store
+fn get_limit_error_msg(
+    mut_state: &mut InternalShellState,
+    e: credit_store_demo::db::limit::LimitError,
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::limit::LimitError;
+
+    match e {
+        LimitError::LimitExceeded {
+            person,
+            asset,
+            limit_ev_id,
+            remaining,
+            resets_on,
+            ..
+        } => {
+            let scale = get_scale(&mut mut_state.conn)?;
+
+            Ok(format!(
+                "Error: {person} can only spend {} {asset} more until {resets_on} under limit {limit_ev_id}",
+                remaining.to_scaled_string(scale)
+            ))
+        }
+        LimitError::DieselError(e) => Err(ShiError::General { msg: e.to_string() }),
+        e => Ok(format!("Error: {e}")),
+    }
+}
+
+/// The error to show when coins could not be spent, with the coins at the scale of the store
+fn get_spend_error_msg(
+    mut_state: &mut InternalShellState,
+    e: credit_store_demo::db::spend::SpendError,
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::spend::SpendError;
+
+    match e {
+        SpendError::HoldError(e) => get_hold_error_msg(mut_state, e),
+        SpendError::LimitError(e) => get_limit_error_msg(mut_state, e),
+        SpendError::DieselError(e) => Err(ShiError::General { msg: e.to_string() }),
+        e => Ok(format!("Error: {e}")),
+    }
+}
+
+fn coin_store_expense(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::models::*;
+    use credit_store_demo::db::spend;
+    use diesel::prelude::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    // Check if the user already exists in the current spanframe
+    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if results.is_empty() {
+        return Ok("Error: User does not exist".to_owned());
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins to add (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if !coins.is_positive() {
+        return Ok("Error: Coins must be positive".to_owned());
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let expense = spend::Expense {
+        person: &person,
+        coins,
+        asset: &asset,
+        ev_desc: &desc,
+        opt_target_ev_id: None,
+    };
+
+    if let Err(e) = spend::insert_expense(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &expense,
+        Utc::now(),
+    ) {
+        return get_spend_error_msg(mut_state, e);
+    }
+
+    Ok("Added income for user".to_owned())
+}
+
+fn coin_store_transfer(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::double_entry::Posting;
+    use credit_store_demo::db::models::*;
+    use credit_store_demo::db::spend;
+    use diesel::prelude::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let from_person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("from person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    let to_person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("to person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    if from_person == to_person {
+        return Ok("Error: Cannot transfer to the same user".to_owned());
+    }
+
+    for person in [&from_person, &to_person] {
+        // Check if the user already exists in the current spanframe
+        let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+            .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(person)))
+            .select(coin_store::EventGrouped::as_select())
+            .get_results(&mut mut_state.conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        if results.is_empty() {
+            return Ok(format!("Error: User {} does not exist", person.to_inner()));
+        }
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins to transfer (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if !coins.is_positive() {
+        return Ok("Error: Coins must be positive".to_owned());
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let postings = [
+        Posting {
+            obj_id: from_person.get_obj_id(),
+            obj_state: ObjState::Update,
+            opt_target_ev_id: None,
+            ev_desc: &desc,
+            person: &from_person,
+            coins: coins
+                .checked_neg()
+                .map_err(|e| ShiError::General { msg: e.to_string() })?,
+            asset: &asset,
+            opt_rate: None,
+            opt_expires_on: None,
+        },
+        Posting {
+            obj_id: to_person.get_obj_id(),
+            obj_state: ObjState::Update,
+            opt_target_ev_id: None,
+            ev_desc: &desc,
+            person: &to_person,
+            coins,
+            asset: &asset,
+            opt_rate: None,
+            opt_expires_on: None,
+        },
+    ];
+
+    let tx_id = match spend::insert_spending_transaction(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &postings,
+        Utc::now(),
+    ) {
+        Ok(item) => item,
+        Err(e) => return get_spend_error_msg(mut_state, e),
+    };
+
+    Ok(format!("Transferred coins in transaction {tx_id}"))
+}
+
+/// Divides a purchase of the asset given as argument that one user paid between several users, who each pay their
+/// share back to the payer in one transaction
+fn coin_store_split(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::models::*;
+    use credit_store_demo::db::split::{self, SplitError, SplitMode, SplitOrder};
+    use diesel::prelude::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let payer: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("payer (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    let participants: Vec<Person> = match drivers::read_input_from_user_until_parsed_or_quit(
+        "participants separated by spaces, the payer included if they take part (NOT admin!)",
+        |input| {
+            input
+                .split_whitespace()
+                .map(str::parse::<Person>)
+                .collect::<Result<Vec<_>, _>>()
+        },
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    for person in std::iter::once(&payer).chain(participants.iter()) {
+        // Check if the user already exists in the current spanframe
+        let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+            .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(person)))
+            .select(coin_store::EventGrouped::as_select())
+            .get_results(&mut mut_state.conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+        if results.is_empty() {
+            return Ok(format!("Error: User {} does not exist", person.to_inner()));
+        }
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let total = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("total coins to split (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let mode_name = match drivers::read_input_from_user_until_parsed_or_quit(
+        "split mode (equal, weights or exact)",
+        |input| match input.trim() {
+            mode_name @ ("equal" | "weights" | "exact") => Ok(mode_name.to_owned()),
+            _ => Err(()),
+        },
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let opt_mode = match mode_name.as_str() {
+        "weights" => drivers::read_input_from_user_until_parsed_or_quit(
+            "weights in the order of the participants, e.g. 2 1 1",
+            |input| {
+                input
+                    .split_whitespace()
+                    .map(str::parse::<u32>)
+                    .collect::<Result<Vec<_>, _>>()
+            },
+        )
+        .map(SplitMode::Weights),
+        "exact" => drivers::read_input_from_user_until_parsed_or_quit(
+            &format!("coins in the order of the participants (up to {scale} decimals)"),
+            |input| {
+                input
+                    .split_whitespace()
+                    .map(|coins| Amount::parse_scaled(coins, scale))
+                    .collect::<Result<Vec<_>, _>>()
+            },
+        )
+        .map(SplitMode::Exact),
+        _ => Some(SplitMode::Equal),
+    };
+
+    let Some(mode) = opt_mode else {
+        return Ok("".to_owned());
+    };
+
+    // Shares that do not add up are refused before asking for a description
+    if let Err(e) = split::get_shares(total, &participants, &mode) {
+        return Ok(format!("Error: {e}"));
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let order = SplitOrder {
+        payer: &payer,
+        total,
+        asset: &asset,
+        participants: &participants,
+        mode: &mode,
+        ev_desc: &desc,
+    };
+
+    let split = match split::insert_split(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &order,
+        Utc::now(),
+    ) {
+        Ok(item) => item,
+        Err(SplitError::DieselError(e)) => return Err(ShiError::General { msg: e.to_string() }),
+        Err(SplitError::SpendError(e)) => return get_spend_error_msg(mut_state, e),
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["person", "share"]);
+
+    for share in split.shares {
+        b.push_record([
+            share.person.to_inner(),
+            display_coins(share.coins, scale, &asset),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "Split {} paid by {} in transaction {}\n{}",
+        display_coins(total, scale, &asset),
+        payer.to_inner(),
+        split.tx_id,
+        table
+    ))
+}
+
+/// Records a rate between two assets in the current span frame, e.g. `10 points = 1 coin`
+fn coin_store_rate_set(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let rate: Rate =
+        match drivers::read_input_from_user_until_valid_or_quit("rate (e.g. 10 points = 1 coin)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    exchange::set_rate(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &rate,
+        &format!("set rate (session: {})", mut_state.session),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok(format!("Rate set to {rate}"))
+}
+
+fn coin_store_rate_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let rates = exchange::get_rates(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["rate", "set_in", "created_on"]);
+
+    for (event, rate) in rates {
+        b.push_record([
+            rate.to_string(),
+            format!("(span: {}, frame: {})", event.span, event.frame),
+            display_timestamp(event.created_on_ts),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Sets a limit on what a user, or every user, may spend of the asset given as argument in a window
+fn coin_store_limit_set(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let opt_person = match drivers::read_input_from_user_until_parsed_or_quit(
+        "person, or * for each user on their own (NOT admin!)",
+        |input| match input.trim() {
+            "*" => Ok(None),
+            person => person.parse::<Person>().map(Some),
+        },
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("most coins to spend per window (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if coins.is_negative() {
+        return Ok("Error: Coins must not be negative".to_owned());
+    }
+
+    let window: LimitWindow =
+        match drivers::read_input_from_user_until_valid_or_quit("window (day, week or month)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    let limit = Limit {
+        opt_person,
+        coins,
+        asset,
+        window,
+    };
+
+    let event = db::limit::set_limit(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &limit,
+        &format!("set limit (session: {})", mut_state.session),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok(format!(
+        "Limit {}: {}",
+        event.id,
+        display_limit(&limit, scale)
+    ))
+}
+
+/// A limit with its coins at the scale of the store, e.g. `* 5.00 coin per day`
+fn display_limit(
+    limit: &credit_store_demo::db::models::Limit,
+    scale: credit_store_demo::db::models::Scale,
+) -> String {
+    format!(
+        "{} {} per {}",
+        limit
+            .opt_person
+            .as_ref()
+            .map_or_else(|| "*".to_owned(), |person| person.to_inner()),
+        display_coins(limit.coins, scale, &limit.asset),
+        limit.window
+    )
+}
+
+fn coin_store_limit_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let limits = db::limit::get_limits(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["id", "limit", "state", "set_in", "description"]);
+
+    for info in limits {
+        let state = match info.opt_ended_by {
+            Some(ev_id) => format!("ended by event {ev_id}"),
+            None => "active".to_owned(),
+        };
+
+        b.push_record([
+            format!("{}", info.event.id),
+            display_limit(&info.limit, scale),
+            state,
+            format!("(span: {}, frame: {})", info.event.span, info.event.frame),
+            info.event.ev_desc,
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Lifts the limit with the id given as argument
+fn coin_store_limit_lift(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::limit::LimitError;
+
+    let Some(Ok(limit_ev_id)) = args.first().map(|arg| arg.parse::<i32>()) else {
+        return Ok("Error: Expected the id of the limit to lift".to_owned());
+    };
+
+    match db::limit::lift_limit(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        limit_ev_id,
+        &format!("lift limit {limit_ev_id} (session: {})", mut_state.session),
+    ) {
+        Ok(_) => Ok(format!("Limit {limit_ev_id} lifted")),
+        Err(LimitError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+/// Sets how far below 0 a user's balance of the asset given as argument may go
+fn coin_store_credit_set(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins the user may go below 0, or 0 to take it back (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if coins.is_negative() {
+        return Ok("Error: Coins must not be negative".to_owned());
+    }
+
+    let credit_line = CreditLine {
+        person,
+        coins,
+        asset,
+    };
+
+    let event = db::credit::set_credit_line(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &credit_line,
+        &format!("set credit line (session: {})", mut_state.session),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok(format!(
+        "Credit line {}: {} {}",
+        event.id,
+        credit_line.person.to_inner(),
+        display_coins(credit_line.coins, scale, &credit_line.asset)
+    ))
+}
+
+fn coin_store_credit_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let credit_lines = db::credit::get_credit_lines(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["person", "credit_line"]);
+
+    for credit_line in credit_lines {
+        b.push_record([
+            credit_line.person.to_inner(),
+            display_coins(credit_line.coins, scale, &credit_line.asset),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Records that one user owes another coins of the asset given as argument, without moving any
+fn coin_store_iou_add(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let debtor: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("debtor (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    let creditor: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("creditor (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    if debtor == creditor {
+        return Ok("Error: A user cannot owe themselves".to_owned());
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins owed (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if !coins.is_positive() {
+        return Ok("Error: Coins must be positive".to_owned());
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let iou = Iou {
+        debtor,
+        creditor,
+        coins,
+        asset,
+    };
+
+    let event = db::credit::record_iou(&mut mut_state.conn, &mut_state.cur_span_frame, &iou, &desc)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok(format!("IOU {}: {}", event.id, display_iou(&iou, scale)))
+}
+
+/// An IOU with its coins at the scale of the store, e.g. `bob owes alice 5.00 coin`
+fn display_iou(
+    iou: &credit_store_demo::db::models::Iou,
+    scale: credit_store_demo::db::models::Scale,
+) -> String {
+    format!(
+        "{} owes {} {}",
+        iou.debtor.to_inner(),
+        iou.creditor.to_inner(),
+        display_coins(iou.coins, scale, &iou.asset)
+    )
+}
+
+fn coin_store_iou_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let ious = db::credit::get_ious(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record([
+        "id",
+        "iou",
+        "repaid",
+        "outstanding",
+        "set_in",
+        "description",
+    ]);
+
+    for info in ious {
+        b.push_record([
+            format!("{}", info.event.id),
+            display_iou(&info.iou, scale),
+            info.repaid.to_scaled_string(scale),
+            info.outstanding.to_scaled_string(scale),
+            format!("(span: {}, frame: {})", info.event.span, info.event.frame),
+            info.event.ev_desc,
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Repays what is outstanding of the IOU with the id given as argument, or only some of it
+fn coin_store_iou_repay(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::db::credit::CreditError;
+    use credit_store_demo::db::models::*;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let (iou_ev_id, opt_coins) = match args {
+        [id] => (id, None),
+        [id, coins] => match Amount::parse_scaled(coins, scale) {
+            Ok(item) => (id, Some(item)),
+            Err(e) => return Ok(format!("Error: {e}")),
+        },
+        _ => {
+            return Ok(
+                "Error: Expected the id of the IOU to repay and optionally the coins".to_owned(),
+            );
+        }
+    };
+
+    let Ok(iou_ev_id) = iou_ev_id.parse::<i32>() else {
+        return Ok(format!("Error: Invalid IOU id {iou_ev_id:?}"));
+    };
+
+    match db::credit::repay_iou(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        iou_ev_id,
+        opt_coins,
+        Utc::now(),
+        &format!("repay IOU {iou_ev_id} (session: {})", mut_state.session),
+    ) {
+        Ok(coins) => Ok(format!(
+            "Repaid {} of IOU {iou_ev_id}",
+            coins.to_scaled_string(scale)
+        )),
+        Err(CreditError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(CreditError::SpendError(e)) => get_spend_error_msg(mut_state, *e),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+/// Exchanges coins of the first asset given as argument for the second one at the rate of the current span frame
+fn coin_store_exchange(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::models::*;
+    use diesel::prelude::*;
+
+    let [from_asset, to_asset] = args else {
+        return Ok(
+            "Error: Expected the asset to exchange from and the one to exchange to".to_owned(),
+        );
+    };
+
+    let (from_asset, to_asset): (Asset, Asset) = match (from_asset.parse(), to_asset.parse()) {
+        (Ok(from_asset), Ok(to_asset)) => (from_asset, to_asset),
+        (Err(e), _) | (_, Err(e)) => return Ok(format!("Error: {e}")),
+    };
+
+    let person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    // Check if the user already exists in the current spanframe
+    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if results.is_empty() {
+        return Ok("Error: User does not exist".to_owned());
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins to exchange (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if !coins.is_positive() {
+        return Ok("Error: Coins must be positive".to_owned());
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let order = exchange::ExchangeOrder {
+        obj_id: person.get_obj_id(),
+        person: &person,
+        coins,
+        from_asset: &from_asset,
+        to_asset: &to_asset,
+        ev_desc: &desc,
+    };
+
+    match exchange::insert_exchange(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &order,
+        Person::exchange().get_obj_id(),
+        Utc::now(),
+    ) {
+        Ok(out) => Ok(format!(
+            "Exchanged {} for {} at {} in transaction {}",
+            display_coins(coins, scale, &from_asset),
+            display_coins(out.to_coins, scale, &to_asset),
+            out.rate,
+            out.tx_id
+        )),
+        Err(ExchangeError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(ExchangeError::SpendError(e)) => get_spend_error_msg(mut_state, e),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+/// Sets up a schedule that credits or charges a user, of the asset given as argument or coin. Its occurrences are
+/// written in the current span frame once they are due.
+fn coin_store_schedule_add(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::models::*;
+    use diesel::prelude::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    // Check if the user already exists in the current spanframe
+    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if results.is_empty() {
+        return Ok("Error: User does not exist".to_owned());
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins per occurrence, negative to charge (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if coins.is_zero() {
+        return Ok("Error: Coins must not be 0".to_owned());
+    }
+
+    let every: Recurrence = match drivers::read_input_from_user_until_valid_or_quit(
+        "how often (e.g. month, 2 weeks or 10 days)",
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let start: chrono::NaiveDate =
+        match drivers::read_input_from_user_until_valid_or_quit("first due date (YYYY-MM-DD)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let schedule = Schedule {
+        person,
+        coins,
+        asset,
+        every,
+        start,
+    };
+
+    let event = db::schedule::add_schedule(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &schedule,
+        &desc,
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    Ok(format!(
+        "Schedule {}: {} {} every {} from {}",
+        event.id,
+        schedule.person.to_inner(),
+        display_coins(schedule.coins, scale, &schedule.asset),
+        schedule.every,
+        schedule.start
+    ))
+}
+
+fn coin_store_schedule_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let schedules = db::schedule::get_schedules(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record([
+        "id",
+        "person",
+        "coins",
+        "every",
+        "from",
+        "written",
+        "next_due",
+        "made_in",
+        "description",
+    ]);
+
+    for info in schedules {
+        let next_due = if info.cancelled {
+            "cancelled".to_owned()
+        } else {
+            info.next_due_date()
+                .map_or_else(|| "-".to_owned(), |due_date| due_date.to_string())
+        };
+
+        b.push_record([
+            format!("{}", info.event.id),
+            info.schedule.person.to_inner(),
+            display_coins(info.schedule.coins, scale, &info.schedule.asset),
+            info.schedule.every.to_string(),
+            info.schedule.start.to_string(),
+            format!("{}", info.occurrence_count),
+            next_due,
+            format!("(span: {}, frame: {})", info.event.span, info.event.frame),
+            info.event.ev_desc,
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(table.to_string())
+}
+
+/// Cancels the schedule with the id given as argument
+fn coin_store_schedule_cancel(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::schedule::ScheduleError;
+
+    let Some(Ok(schedule_ev_id)) = args.first().map(|arg| arg.parse::<i32>()) else {
+        return Ok("Error: Expected the id of the schedule to cancel".to_owned());
+    };
+
+    match db::schedule::cancel_schedule(
+        &mut mut_state.conn,
+        schedule_ev_id,
+        &format!("cancel schedule (session: {})", mut_state.session),
+    ) {
+        Ok(_) => Ok(format!("Schedule {schedule_ev_id} cancelled")),
+        Err(ScheduleError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+/// Writes the occurrences of the current span frame's schedules that are due up to the date given with `--until`, or
+/// today
+fn coin_store_schedule_run(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::db::schedule::ScheduleError;
+    use tabled::{builder::Builder, settings::Style};
+
+    let until = match args {
+        [] => Utc::now().date_naive(),
+        [flag, date] if flag == "--until" => match date.parse::<NaiveDate>() {
+            Ok(item) => item,
+            Err(e) => return Ok(format!("Error: Invalid date {date:?}: {e}")),
+        },
+        _ => return Ok("Error: Expected nothing or --until <YYYY-MM-DD>".to_owned()),
+    };
+
+    let run = match db::schedule::run_schedules(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        until,
+        Utc::now(),
+    ) {
+        Ok(item) => item,
+        Err(ScheduleError::DieselError(e)) => {
+            return Err(ShiError::General { msg: e.to_string() });
+        }
+        Err(ScheduleError::SpendError(e)) => return get_spend_error_msg(mut_state, e),
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    if run.written.is_empty() && run.refused.is_empty() {
+        return Ok(format!("Nothing is due by {until}"));
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["schedule", "due", "person", "coins", "written_in"]);
+
+    for occurrence in run.written.iter() {
+        b.push_record([
+            format!("{}", occurrence.schedule_ev_id),
+            occurrence.due_date.to_string(),
+            occurrence.person.to_inner(),
+            display_coins(occurrence.coins, scale, &occurrence.asset),
+            format!(
+                "(span: {}, frame: {})",
+                occurrence.span_frame.span, occurrence.span_frame.frame
+            ),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    let mut mut_out = format!(
+        "Wrote {} occurrences due by {until}\n{}",
+        run.written.len(),
+        table
+    );
+
+    // The rest of a schedule waits for its refused occurrence, so it is written on a later run
+    for refused in run.refused {
+        let msg = get_spend_error_msg(mut_state, refused.error)?;
+
+        mut_out.push_str(&format!(
+            "\nSkipped schedule {} from {}: {}",
+            refused.occurrence.schedule_ev_id,
+            refused.occurrence.due_date,
+            msg.trim_start_matches("Error: ")
+        ));
+    }
+
+    Ok(mut_out)
+}
+
+/// Projects each user's balance up to the date given with `--until` from the schedules of the current span frame,
+/// without writing anything
+fn coin_store_forecast(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::db::forecast::{self, ForecastError};
+    use tabled::{builder::Builder, settings::Style};
+
+    let until = match args {
+        [flag, date] if flag == "--until" => match date.parse::<NaiveDate>() {
+            Ok(item) => item,
+            Err(e) => return Ok(format!("Error: Invalid date {date:?}: {e}")),
+        },
+        _ => return Ok("Error: Expected --until <YYYY-MM-DD>".to_owned()),
+    };
+
+    let forecast = match forecast::forecast(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        until,
+        Utc::now(),
+    ) {
+        Ok(item) => item,
+        Err(ForecastError::DieselError(e)) => {
+            return Err(ShiError::General { msg: e.to_string() });
+        }
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    if forecast.projected.is_empty() && forecast.refused.is_empty() {
+        return Ok(format!("Nothing is due by {until}"));
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["date", "person", "coins"]);
+
+    for (person, asset, coins) in forecast.balances.iter() {
+        b.push_record([
+            "now".to_owned(),
+            person.to_inner(),
+            display_coins(*coins, scale, asset),
+        ]);
+    }
+
+    for projected in forecast.projected.iter() {
+        b.push_record([
+            projected.date.to_string(),
+            projected.person.to_inner(),
+            display_coins(projected.coins, scale, &projected.asset),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    let mut mut_out = format!(
+        "Forecast of (span: {}, frame: {}) until {until}\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    );
+
+    for (person, asset, date) in forecast.get_first_negative_dates() {
+        mut_out += &format!(
+            "\n{} would drop below 0 {} on {date}",
+            person.to_inner(),
+            asset.to_inner()
+        );
+    }
+
+    for refused in forecast.refused {
+        let msg = get_spend_error_msg(mut_state, refused.error)?;
+
+        mut_out.push_str(&format!(
+            "\nSchedule {} would be skipped from {}: {}",
+            refused.occurrence.schedule_ev_id,
+            refused.occurrence.due_date,
+            msg.trim_start_matches("Error: ")
+        ));
+    }
+
+    Ok(mut_out)
+}
+
+/// Holds coins of a user for a later charge, of the asset given as argument or coin, until the hold expires
+fn coin_store_hold_place(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::models::*;
+    use diesel::prelude::*;
+
+    let asset = match get_asset_arg(args) {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
+    };
+
+    let person: Person =
+        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
+            Some(item) => item,
+            None => return Ok("".to_owned()),
+        };
+
+    // Check if the user already exists in the current spanframe
+    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if results.is_empty() {
+        return Ok("Error: User does not exist".to_owned());
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let coins = match drivers::read_input_from_user_until_parsed_or_quit(
+        &format!("coins to hold (up to {scale} decimals)"),
+        |input| Amount::parse_scaled(input, scale),
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if !coins.is_positive() {
+        return Ok("Error: Coins must be positive".to_owned());
+    }
+
+    let desc = match drivers::read_str_or_quit("Description") {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    let now = Utc::now();
+
+    let hold = Hold {
+        person,
+        coins,
+        asset,
+        expires_on: now + mut_state.hold_duration.to_inner(),
+    };
+
+    match db::hold::place_hold(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &hold,
+        now,
+        &desc,
+    ) {
+        Ok(event) => Ok(format!(
+            "Hold {}: {} of {} until {}",
+            event.id,
+            display_coins(hold.coins, scale, &hold.asset),
+            hold.person.to_inner(),
+            hold.expires_on.format("%Y-%m-%d %H:%M:%S UTC")
+        )),
+        Err(e) => get_hold_error_msg(mut_state, e),
+    }
+}
+
+fn coin_store_hold_list(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let holds = db::hold::get_holds(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let now = Utc::now();
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record([
+        "id",
+        "person",
+        "coins",
+        "expires_on",
+        "state",
+        "description",
+    ]);
+
+    for info in holds {
+        let state = match info.opt_settled_by {
+            Some(ev_id) => format!("settled by event {ev_id}"),
+            None if info.is_active(now) => "active".to_owned(),
+            None => "expired".to_owned(),
+        };
+
+        b.push_record([
+            format!("{}", info.event.id),
+            info.hold.person.to_inner(),
+            display_coins(info.hold.coins, scale, &info.hold.asset),
+            info.hold
+                .expires_on
+                .format("%Y-%m-%d %H:%M:%S UTC")
+                .to_string(),
+            state,
+            info.event.ev_desc,
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Charges the coins of the hold with the id given as argument, or only the coins given after it
+fn coin_store_hold_capture(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::db::models::*;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let (hold_ev_id, opt_coins) = match args {
+        [id] => (id, None),
+        [id, coins] => match Amount::parse_scaled(coins, scale) {
+            Ok(item) => (id, Some(item)),
+            Err(e) => return Ok(format!("Error: {e}")),
+        },
+        _ => {
+            return Ok(
+                "Error: Expected the id of the hold to capture and optionally the coins".to_owned(),
+            );
+        }
+    };
+
+    let Ok(hold_ev_id) = hold_ev_id.parse::<i32>() else {
+        return Ok(format!("Error: Invalid hold id {hold_ev_id:?}"));
+    };
+
+    match db::hold::capture_hold(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        hold_ev_id,
+        opt_coins,
+        Utc::now(),
+        &format!("capture hold {hold_ev_id} (session: {})", mut_state.session),
+    ) {
+        Ok(coins) => Ok(format!(
+            "Captured {} of hold {hold_ev_id}",
+            coins.to_scaled_string(scale)
+        )),
+        Err(e) => get_hold_error_msg(mut_state, e),
+    }
+}
+
+/// Releases the hold with the id given as argument without charging anything
+fn coin_store_hold_release(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use credit_store_demo::db::hold::HoldError;
+
+    let Some(Ok(hold_ev_id)) = args.first().map(|arg| arg.parse::<i32>()) else {
+        return Ok("Error: Expected the id of the hold to release".to_owned());
+    };
+
+    match db::hold::release_hold(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        hold_ev_id,
+        Utc::now(),
+        &format!("release hold {hold_ev_id} (session: {})", mut_state.session),
+    ) {
+        Ok(_) => Ok(format!("Hold {hold_ev_id} released")),
+        Err(HoldError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+/// Shows each user's balance in the current span frame with their credit line, what is held or expired and what is
+/// left to spend
+fn coin_store_show_available(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let balances = db::hold::get_available_balances(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        Utc::now(),
+    )
+    .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record([
+        "person",
+        "asset",
+        "balance",
+        "credit_line",
+        "held",
+        "expired",
+        "available",
+    ]);
+
+    for balance in balances {
+        b.push_record([
+            balance.person.to_inner(),
+            balance.asset.to_inner(),
+            balance.balance.to_scaled_string(scale),
+            balance.credit_line.to_scaled_string(scale),
+            balance.held.to_scaled_string(scale),
+            balance.expired.to_scaled_string(scale),
+            balance.available.to_scaled_string(scale),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Shows what each user still owes each of their counterparties in the current span frame
+fn coin_store_show_debts(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let debts = db::credit::get_debts(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["debtor", "creditor", "outstanding"]);
+
+    for debt in debts {
+        b.push_record([
+            debt.debtor.to_inner(),
+            debt.creditor.to_inner(),
+            display_coins(debt.outstanding, scale, &debt.asset),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Shows what each user is owed and owes over all their counterparties in the current span frame
+fn coin_store_show_positions(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use tabled::{builder::Builder, settings::Style};
+
+    let positions = db::credit::get_net_positions(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["person", "asset", "owed", "owing", "net"]);
+
+    for position in positions {
+        b.push_record([
+            position.person.to_inner(),
+            position.asset.to_inner(),
+            position.owed.to_scaled_string(scale),
+            position.owing.to_scaled_string(scale),
+            position.net.to_scaled_string(scale),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Shows the coins of each user in the current span frame that they can spend today and those that expired
+fn coin_store_expiry_show(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let today = Utc::now().date_naive();
+
+    let balances =
+        db::expiry::get_expiry_balances(&mut mut_state.conn, &mut_state.cur_span_frame, today)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let lots = db::expiry::get_lots(&mut mut_state.conn, &mut_state.cur_span_frame)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["person", "asset", "spendable", "expired", "next_expiry"]);
+
+    for balance in balances {
+        let opt_next_expiry = lots
+            .iter()
+            .filter(|lot| lot.person == balance.person && lot.asset == balance.asset)
+            .filter(|lot| !lot.is_expired(today))
+            .filter_map(|lot| lot.opt_expires_on)
+            .min();
+
+        b.push_record([
+            balance.person.to_inner(),
+            balance.asset.to_inner(),
+            balance.spendable.to_scaled_string(scale),
+            balance.expired.to_scaled_string(scale),
+            opt_next_expiry.map_or_else(|| "-".to_owned(), |date| date.to_string()),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "(span: {}, frame: {})\n{}",
+        mut_state.cur_span_frame.span, mut_state.cur_span_frame.frame, table
+    ))
+}
+
+/// Writes off the coins in the current span frame that expired by today
+fn coin_store_expiry_sweep(
+    mut_state: &mut InternalShellState,
+    _args: &[String],
+) -> Result<String, ShiError> {
+    use chrono::prelude::*;
+    use tabled::{builder::Builder, settings::Style};
+
+    let today = Utc::now().date_naive();
+
+    let swept = db::expiry::sweep_expired(&mut mut_state.conn, &mut_state.cur_span_frame, today)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    if swept.is_empty() {
+        return Ok(format!("Nothing expired by {today}"));
+    }
+
+    let scale = get_scale(&mut mut_state.conn)?;
+
+    let mut b = Builder::with_capacity(3, 0);
+
+    b.push_record(["income", "person", "coins", "expired_on"]);
+
+    for lot in swept.iter() {
+        b.push_record([
+            format!("{}", lot.ev_id),
+            lot.person.to_inner(),
+            display_coins(lot.coins, scale, &lot.asset),
+            lot.opt_expires_on
+                .map_or_else(|| "-".to_owned(), |date| date.to_string()),
+        ]);
+    }
+
+    let mut table = b.build();
+
+    table.with(Style::modern_rounded());
+
+    Ok(format!(
+        "Expired coins of {} incomes\n{}",
+        swept.len(),
+        table
+    ))
+}
 
-    let coins: u32 = match drivers::read_input_from_user_until_valid_or_quit("coins to add (u32)") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
-    };
+/// Shows the number of decimals of the store's amounts, or sets it to the one given as argument
+fn coin_store_scale(
+    mut_state: &mut InternalShellState,
+    args: &[String],
+) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+    use credit_store_demo::db::scale::{self, ScaleError};
 
-    let desc = match drivers::read_str_or_quit("Description") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
-    };
+    let Some(arg) = args.first() else {
+        let scale = get_scale(&mut mut_state.conn)?;
 
-    let new_common = coin_store::NewCommon {
-        coins: coins as i32,
-        person: &person,
+        return Ok(format!(
+            "Amounts have {scale} decimals, e.g. {}",
+            Amount::new(1234567).to_scaled_string(scale)
+        ));
     };
 
-    let obj_id = {
-        let mut hasher = DeterministicHasher::new(Hasher::new());
-        person.hash(&mut hasher);
-
-        hasher.as_inner().clone().finalize()
+    let scale: Scale = match arg.parse() {
+        Ok(item) => item,
+        Err(e) => return Ok(format!("Error: {e}")),
     };
 
-    let ev = coin_store::insert_event_for_obj(
+    match scale::set_scale(
         &mut mut_state.conn,
-        obj_id as i32,
         &mut_state.cur_span_frame,
-        ObjState::Update,
-        &desc,
-        new_common,
-    )
-    .map_err(|e| ShiError::General { msg: e.to_string() })?;
-
-    // Update partial for this new event
-    sync_coin_store_events_partial(&mut mut_state.conn, ev.id)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
-
-    Ok("Added income for user".to_owned())
+        scale,
+        &format!("set scale (session: {})", mut_state.session),
+    ) {
+        Ok(_) => Ok(format!("Amounts have {scale} decimals from now on")),
+        Err(ScaleError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
 }
 
-fn coin_store_expense(
+fn coin_store_double_entry(
     mut_state: &mut InternalShellState,
     _args: &[String],
 ) -> Result<String, ShiError> {
-    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+    use credit_store_demo::db::double_entry;
+
+    let confirm = match drivers::read_str_or_quit(
+        "Switch this store to double-entry mode for good? (yes/any)",
+    ) {
+        Some(item) => item,
+        None => return Ok("".to_owned()),
+    };
+
+    if confirm != "yes" {
+        return Ok("Did nothing".to_owned());
+    }
+
+    match double_entry::enable_double_entry(
+        &mut mut_state.conn,
+        &mut_state.cur_span_frame,
+        &format!("enable double-entry (session: {})", mut_state.session),
+    ) {
+        Ok(()) => Ok(
+            "Double-entry mode enabled. Income is issued from @issuance and expenses are paid into @expenses"
+                .to_owned(),
+        ),
+        Err(DoubleEntryError::DieselError(e)) => Err(ShiError::General { msg: e.to_string() }),
+        Err(e) => Ok(format!("Error: {e}")),
+    }
+}
+
+/// Total coins of each span frame, including the ones without any, displayed at the scale of the store
+fn get_coin_store_total_coins_per_span_frame(
+    conn: &mut SqliteConnection,
+) -> Result<HashMap<(i32, i32), String>, ShiError> {
+    use credit_store_demo::autogen::schema::coin_store_hist::dsl;
     use credit_store_demo::db::models::*;
     use diesel::prelude::*;
 
-    let person: Person =
-        match drivers::read_input_from_user_until_valid_or_quit("person (NOT admin!)") {
-            Some(item) => item,
-            None => return Ok("".to_owned()),
-        };
-
-    // Check if the user already exists in the current spanframe
-    let results: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
-        .pipe(|tbl| FilterDsl::filter(tbl, dsl::person.eq(&person)))
-        .select(coin_store::EventGrouped::as_select())
-        .get_results(&mut mut_state.conn)
+    let objects = dsl::coin_store_hist
+        .select(coin_store::Hist::as_select())
+        .get_results(conn)
         .map_err(|e| ShiError::General { msg: e.to_string() })?;
 
-    if results.is_empty() {
-        return Ok("Error: User does not exist".to_owned());
-    }
+    let mut mut_total_coins_per_span_frame: HashMap<(i32, i32), Amount> =
+        coin_store::get_span_frame_infos(conn)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?
+            .into_iter()
+            .map(|info| ((info.span_frame.span, info.span_frame.frame), Amount::ZERO))
+            .collect();
 
-    let coins: u32 = match drivers::read_input_from_user_until_valid_or_quit("coins to add (u32)") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
-    };
+    for row in objects.iter() {
+        let total = mut_total_coins_per_span_frame
+            .entry((row.grp_span, row.grp_frame))
+            .or_default();
 
-    let desc = match drivers::read_str_or_quit("Description") {
-        Some(item) => item,
-        None => return Ok("".to_owned()),
-    };
+        *total = total
+            .checked_add(row.coins)
+            .map_err(|e| ShiError::General { msg: e.to_string() })?;
+    }
 
-    let new_common = coin_store::NewCommon {
-        coins: -(coins as i32),
-        person: &person,
-    };
+    let scale = get_scale(conn)?;
 
-    let obj_id = {
-        let mut hasher = DeterministicHasher::new(Hasher::new());
-        person.hash(&mut hasher);
+    Ok(mut_total_coins_per_span_frame
+        .into_iter()
+        .map(|(key, total)| (key, total.to_scaled_string(scale)))
+        .collect())
+}
 
-        hasher.as_inner().clone().finalize()
-    };
+fn display_span_frame_labels(info: &SpanFrameInfo) -> String {
+    info.names
+        .iter()
+        .map(|name| format!(" [name: {name}]"))
+        .chain(info.tags.iter().map(|tag| format!(" [tag: {tag}]")))
+        .collect::<String>()
+}
 
-    let ev = coin_store::insert_event_for_obj(
-        &mut mut_state.conn,
-        obj_id as i32,
-        &mut_state.cur_span_frame,
-        ObjState::Update,
-        &desc,
-        new_common,
+fn display_span_frame_info(
+    info: &SpanFrameInfo,
+    cur_span_frame: &SpanFrame,
+    total_coins_per_span_frame: &HashMap<(i32, i32), String>,
+) -> String {
+    let is_cur = info.span_frame.span == cur_span_frame.span
+        && info.span_frame.frame == cur_span_frame.frame;
+
+    let total_coins = total_coins_per_span_frame
+        .get(&(info.span_frame.span, info.span_frame.frame))
+        .map_or("-", String::as_str);
+
+    format!(
+        "{}(span: {}, frame: {}){} \"{}\" created: {} events: {} coins: {}{}",
+        if is_cur { "==> " } else { "" },
+        info.span_frame.span,
+        info.span_frame.frame,
+        display_span_frame_labels(info),
+        info.ev_desc,
+        display_timestamp(info.created_on_ts),
+        info.event_count,
+        total_coins,
+        if info.closed { " [closed]" } else { "" },
     )
-    .map_err(|e| ShiError::General { msg: e.to_string() })?;
-
-    // Update partial for this new event
-    sync_coin_store_events_partial(&mut mut_state.conn, ev.id)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+}
 
-    Ok("Added income for user".to_owned())
+fn is_span_frame_child_of(info: &SpanFrameInfo, opt_parent: Option<&SpanFrame>) -> bool {
+    match (&info.opt_parent, opt_parent) {
+        (None, None) => true,
+        (Some(parent), Some(other)) => parent.span == other.span && parent.frame == other.frame,
+        _ => false,
+    }
 }
 
-fn coin_store_ls(mut_state: &mut InternalShellState, _args: &[String]) -> Result<String, ShiError> {
-    use credit_store_demo::db::models::*;
+pub fn display_span_frame_tree(
+    infos: &[SpanFrameInfo],
+    cur_span_frame: &SpanFrame,
+    total_coins_per_span_frame: &HashMap<(i32, i32), String>,
+) -> String {
+    fn push_subtree(
+        mut_output: &mut String,
+        infos: &[SpanFrameInfo],
+        info: &SpanFrameInfo,
+        prefix: &str,
+        opt_is_last: Option<bool>,
+        cur_span_frame: &SpanFrame,
+        total_coins_per_span_frame: &HashMap<(i32, i32), String>,
+    ) {
+        let (branch, child_prefix) = match opt_is_last {
+            None => ("", prefix.to_owned()),
+            Some(true) => ("└── ", format!("{prefix}    ")),
+            Some(false) => ("├── ", format!("{prefix}│   ")),
+        };
 
-    let span_frames = coin_store::get_created_span_frames(&mut mut_state.conn)
-        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+        *mut_output += &format!(
+            "{prefix}{branch}{}\n",
+            display_span_frame_info(info, cur_span_frame, total_coins_per_span_frame)
+        );
+
+        let children = infos
+            .iter()
+            .filter(|child| is_span_frame_child_of(child, Some(&info.span_frame)))
+            .collect::<Vec<_>>();
+
+        for (i, child) in children.iter().enumerate() {
+            push_subtree(
+                mut_output,
+                infos,
+                child,
+                &child_prefix,
+                Some(i + 1 == children.len()),
+                cur_span_frame,
+                total_coins_per_span_frame,
+            );
+        }
+    }
 
-    let output = {
-        let mut mut_output = "".to_owned();
-
-        for span_frame in span_frames {
-            if span_frame.span == mut_state.cur_span_frame.span
-                && span_frame.frame == mut_state.cur_span_frame.frame
-            {
-                mut_output += &format!(
-                    "==> (span: {}, frame: {})\n",
-                    span_frame.span, span_frame.frame
-                );
-            } else {
-                mut_output +=
-                    &format!("(span: {}, frame: {})\n", span_frame.span, span_frame.frame);
-            }
+    let mut mut_output = "".to_owned();
+
+    for root in infos
+        .iter()
+        .filter(|info| is_span_frame_child_of(info, None))
+    {
+        push_subtree(
+            &mut mut_output,
+            infos,
+            root,
+            "",
+            None,
+            cur_span_frame,
+            total_coins_per_span_frame,
+        );
+    }
+
+    mut_output
+}
+
+pub fn display_span_frame_dot(
+    infos: &[SpanFrameInfo],
+    cur_span_frame: &SpanFrame,
+    total_coins_per_span_frame: &HashMap<(i32, i32), String>,
+) -> String {
+    let node_name = |sf: &SpanFrame| format!("\"{}_{}\"", sf.span, sf.frame);
+
+    let mut mut_output = "digraph span_frames {\n".to_owned();
+
+    for info in infos {
+        let is_cur = info.span_frame.span == cur_span_frame.span
+            && info.span_frame.frame == cur_span_frame.frame;
+
+        let total_coins = total_coins_per_span_frame
+            .get(&(info.span_frame.span, info.span_frame.frame))
+            .map_or("-", String::as_str);
+
+        let label = format!(
+            "(span: {}, frame: {}){}\\n{}\\n{}\\nevents: {} coins: {}{}",
+            info.span_frame.span,
+            info.span_frame.frame,
+            display_span_frame_labels(info).replace('"', "\\\""),
+            info.ev_desc.replace('"', "\\\""),
+            display_timestamp(info.created_on_ts),
+            info.event_count,
+            total_coins,
+            if info.closed { "\\n[closed]" } else { "" },
+        );
+
+        mut_output += &format!(
+            "    {} [label=\"{}\"{}];\n",
+            node_name(&info.span_frame),
+            label,
+            if is_cur { ", style=bold" } else { "" },
+        );
+    }
+
+    for info in infos {
+        if let Some(parent) = &info.opt_parent {
+            mut_output += &format!(
+                "    {} -> {};\n",
+                node_name(parent),
+                node_name(&info.span_frame)
+            );
         }
+    }
 
-        mut_output
-    };
+    mut_output += "}\n";
+
+    mut_output
+}
 
-    Ok(output)
+/// List the span frame tree. Pass `dot` to get a Graphviz export of the same graph instead.
+fn coin_store_ls(mut_state: &mut InternalShellState, args: &[String]) -> Result<String, ShiError> {
+    use credit_store_demo::db::models::*;
+
+    let infos = coin_store::get_span_frame_infos(&mut mut_state.conn)
+        .map_err(|e| ShiError::General { msg: e.to_string() })?;
+
+    let total_coins_per_span_frame =
+        get_coin_store_total_coins_per_span_frame(&mut mut_state.conn)?;
+
+    match args.first().map(|arg| arg.as_str()) {
+        None => Ok(display_span_frame_tree(
+            &infos,
+            &mut_state.cur_span_frame,
+            &total_coins_per_span_frame,
+        )),
+        Some("dot") => Ok(display_span_frame_dot(
+            &infos,
+            &mut_state.cur_span_frame,
+            &total_coins_per_span_frame,
+        )),
+        Some(arg) => Ok(format!(
+            "Error: Unknown argument {arg}. Expected nothing or dot"
+        )),
+    }
 }
 
 pub fn get_or_create_init_span_frame(
@@ -1015,7 +3837,84 @@ pub fn get_or_create_init_span_frame(
 
     match opt_first_spanframe {
         Some(first_spanframe) => Ok((*first_spanframe).clone()),
-        None => Ok(coin_store::create_span_frame(conn, 1, 1, "First Frame!")?),
+        None => Ok(coin_store::create_span_frame(
+            conn,
+            1,
+            1,
+            None,
+            "First Frame!",
+        )?),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CommitPartialError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Failed to create frame: {0}")]
+    CreateSpanFrameError(#[from] CreateSpanFrameError),
+
+    #[error("{0}. Toggle whole transactions to commit them in double-entry mode")]
+    DoubleEntryError(#[from] DoubleEntryError),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] credit_store_demo::db::models::AmountError),
+}
+
+#[derive(Error, Debug)]
+pub enum GetStartSpanFrameError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Failed to create first frame: {0}")]
+    CreateSpanFrameError(#[from] CreateSpanFrameError),
+
+    #[error("No span frame matches {0:?}. Expected <span>/<frame> or a name or tag")]
+    SpanFrameNotFound(String),
+}
+
+/// Picks the span frame to start in. The `--frame` override wins over the frame persisted for the session, and new
+/// sessions start at the first frame.
+pub fn get_start_span_frame(
+    conn: &mut SqliteConnection,
+    session: &str,
+    opt_frame_override: Option<&str>,
+) -> Result<SpanFrame, GetStartSpanFrameError> {
+    use credit_store_demo::db::models::*;
+
+    let init_span_frame = get_or_create_init_span_frame(conn)?;
+
+    let Some(frame_override) = opt_frame_override else {
+        let opt_session_span_frame = coin_store::get_session_span_frame(conn, session)?;
+
+        return Ok(opt_session_span_frame.unwrap_or(init_span_frame));
+    };
+
+    find_span_frame(conn, frame_override)?
+        .ok_or_else(|| GetStartSpanFrameError::SpanFrameNotFound(frame_override.to_owned()))
+}
+
+/// Finds a created span frame given as `<span>/<frame>` or by a name or tag
+pub fn find_span_frame(
+    conn: &mut SqliteConnection,
+    span_frame_str: &str,
+) -> Result<Option<SpanFrame>, diesel::result::Error> {
+    use credit_store_demo::db::models::*;
+
+    match span_frame_str.split_once('/') {
+        Some((span, frame)) => {
+            let (Ok(span), Ok(frame)) = (span.parse::<i32>(), frame.parse::<i32>()) else {
+                return Ok(None);
+            };
+
+            let span_frames = coin_store::get_created_span_frames(conn)?;
+
+            Ok(span_frames
+                .into_iter()
+                .find(|sf| sf.span == span && sf.frame == frame))
+        }
+        None => coin_store::find_span_frame_by_label(conn, span_frame_str),
     }
 }
 
@@ -1024,18 +3923,68 @@ fn main() {
 
     drivers::logging::init_logging_with_level(log::LevelFilter::Trace);
 
+    let matches = clap::command!()
+        .arg(
+            clap::Arg::new("session")
+                .long("session")
+                .default_value("default")
+                .help("Session whose current span frame is restored on startup and persisted on change"),
+        )
+        .arg(
+            clap::Arg::new("frame")
+                .long("frame")
+                .help("Start in this span frame instead, as <span>/<frame> or a name or tag"),
+        )
+        .arg(
+            clap::Arg::new("hold-duration")
+                .long("hold-duration")
+                .default_value("15 minutes")
+                .value_parser(|s: &str| s.parse::<db::models::HoldDuration>())
+                .help("How long holds last before they expire, e.g. 15 minutes, 2 hours or 7 days"),
+        )
+        .get_matches();
+
+    let session = matches
+        .get_one::<String>("session")
+        .expect("session has a default value")
+        .clone();
+
+    let hold_duration = *matches
+        .get_one::<db::models::HoldDuration>("hold-duration")
+        .expect("hold-duration has a default value");
+
     let mut conn = db::loader::establish_connection().expect("Failed to initialize Sqlite db");
 
-    let cur_span_frame =
-        get_or_create_init_span_frame(&mut conn).expect("Failed to create first frame");
+    let sealed_count = db::models::coin_store::seal_event_chain(&mut conn)
+        .expect("Failed to hash events written before hashing");
+
+    if sealed_count > 0 {
+        info!("Hashed {sealed_count} events into the event chain");
+    }
+
+    let cur_span_frame = get_start_span_frame(
+        &mut conn,
+        &session,
+        matches
+            .get_one::<String>("frame")
+            .map(|frame| frame.as_str()),
+    )
+    .expect("Failed to get start span frame");
+
+    db::models::coin_store::set_session_span_frame(&mut conn, &session, &cur_span_frame)
+        .expect("Failed to persist session span frame");
 
-    set_coin_store_events_partial_to_full_if_empty(&mut conn)
-        .expect("Failed to set coin_store_events_partial to full");
+    info!(
+        "Session {session} starting at (span: {}, frame: {})",
+        cur_span_frame.span, cur_span_frame.frame
+    );
 
     let shell_join = drivers::shell::spawn_shell_loop_thread(
-        || InternalShellState {
+        move || InternalShellState {
             conn,
+            session,
             cur_span_frame,
+            hold_duration,
         },
         || {
             vec![
@@ -1060,14 +4009,165 @@ fn main() {
                     ),
                     cmd!(
                         "income",
-                        "Add income coins for a user in current span/frame",
+                        "Add income coins for a user in current span/frame, of the asset given as argument or coin [--expires-on <YYYY-MM-DD>]",
                         coin_store_income,
                     ),
                     cmd!(
                         "expense",
-                        "Add an expense order for a user in current span/frame",
+                        "Add an expense order for a user in current span/frame, of the asset given as argument or coin",
                         coin_store_expense,
                     ),
+                    cmd!(
+                        "transfer",
+                        "Move coins of the asset given as argument or coin from one user to another in one balanced transaction",
+                        coin_store_transfer,
+                    ),
+                    cmd!(
+                        "split",
+                        "Divide a purchase of the asset given as argument or coin that one user paid between several users equally, by weights or by exact amounts",
+                        coin_store_split,
+                    ),
+                    cmd!(
+                        "exchange",
+                        "Exchange coins of one asset for another at the rate of the current span/frame <from> <to>",
+                        coin_store_exchange,
+                    ),
+                    parent!(
+                        "rate",
+                        cmd!(
+                            "set",
+                            "Set the rate between two assets in the current span/frame, e.g. 10 points = 1 coin",
+                            coin_store_rate_set,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the rates in use in the current span/frame",
+                            coin_store_rate_list,
+                        ),
+                    ),
+                    parent!(
+                        "schedule",
+                        cmd!(
+                            "add",
+                            "Set up recurring income or expenses for a user in the current span/frame, of the asset given as argument or coin",
+                            coin_store_schedule_add,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the schedules with what they wrote so far and when they are next due",
+                            coin_store_schedule_list,
+                        ),
+                        cmd!(
+                            "cancel",
+                            "Stop a schedule from writing any more occurrences <id>",
+                            coin_store_schedule_cancel,
+                        ),
+                        cmd!(
+                            "run",
+                            "Write the occurrences of this frame's schedules that are due and not written yet [--until <YYYY-MM-DD>]",
+                            coin_store_schedule_run,
+                        ),
+                    ),
+                    cmd!(
+                        "forecast",
+                        "Project each user's balance from the schedules of the current span/frame, without writing anything --until <YYYY-MM-DD>",
+                        coin_store_forecast,
+                    ),
+                    parent!(
+                        "hold",
+                        cmd!(
+                            "place",
+                            "Hold coins of a user for a later charge in the current span/frame, of the asset given as argument or coin",
+                            coin_store_hold_place,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the holds of the current span/frame with whether they are active, settled or expired",
+                            coin_store_hold_list,
+                        ),
+                        cmd!(
+                            "capture",
+                            "Charge the coins of a hold, or only some of them <id> [coins]",
+                            coin_store_hold_capture,
+                        ),
+                        cmd!(
+                            "release",
+                            "Let go of a hold without charging anything <id>",
+                            coin_store_hold_release,
+                        ),
+                    ),
+                    parent!(
+                        "limit",
+                        cmd!(
+                            "set",
+                            "Limit what a user, or each user on their own with *, may spend per day, week or month of the asset given as argument or coin",
+                            coin_store_limit_set,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the limits of the current span/frame with whether they are active",
+                            coin_store_limit_list,
+                        ),
+                        cmd!(
+                            "lift",
+                            "Lift a limit so that it no longer restricts spending <id>",
+                            coin_store_limit_lift,
+                        ),
+                    ),
+                    parent!(
+                        "credit",
+                        cmd!(
+                            "set",
+                            "Let a user's balance of the asset given as argument or coin go below 0 down to a credit line",
+                            coin_store_credit_set,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the credit lines of the current span/frame",
+                            coin_store_credit_list,
+                        ),
+                    ),
+                    parent!(
+                        "iou",
+                        cmd!(
+                            "add",
+                            "Record that one user owes another coins of the asset given as argument or coin, without moving any",
+                            coin_store_iou_add,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the IOUs of the current span/frame with what was repaid and is outstanding",
+                            coin_store_iou_list,
+                        ),
+                        cmd!(
+                            "repay",
+                            "Move coins from the debtor to the creditor of an IOU, all that is outstanding or only some <id> [coins]",
+                            coin_store_iou_repay,
+                        ),
+                    ),
+                    parent!(
+                        "expiry",
+                        cmd!(
+                            "show",
+                            "Show the coins of all users in current span/frame that are spendable today and those that expired",
+                            coin_store_expiry_show,
+                        ),
+                        cmd!(
+                            "sweep",
+                            "Write off the coins in the current span/frame that expired by today",
+                            coin_store_expiry_sweep,
+                        ),
+                    ),
+                    cmd!(
+                        "scale",
+                        "Show the number of decimals of amounts, or set it before any coins have moved <decimals>",
+                        coin_store_scale,
+                    ),
+                    cmd!(
+                        "double-entry",
+                        "Switch the store to double-entry mode, where coins only move in balanced transactions",
+                        coin_store_double_entry,
+                    ),
                     parent!(
                         "show",
                         cmd!(
@@ -1080,6 +4180,21 @@ fn main() {
                             "Show the current span/frame records of transactions made",
                             coin_store_show_records,
                         ),
+                        cmd!(
+                            "available",
+                            "Show the balance of all users in current span/frame with their credit line and what is held and available",
+                            coin_store_show_available,
+                        ),
+                        cmd!(
+                            "debts",
+                            "Show what each user still owes each of their counterparties in current span/frame",
+                            coin_store_show_debts,
+                        ),
+                        cmd!(
+                            "positions",
+                            "Show what each user is owed, owes and their net position in current span/frame",
+                            coin_store_show_positions,
+                        ),
                         parent!(
                             "partial",
                             cmd!(
@@ -1094,11 +4209,19 @@ fn main() {
                             ),
                         )
                     ),
+                    parent!(
+                        "partial",
+                        cmd!(
+                            "commit",
+                            "Push a frame where the partial view of the current span/frame becomes the actual one",
+                            coin_store_partial_commit,
+                        ),
+                    ),
                     parent!(
                         "toggle",
                         cmd!(
                             "id",
-                            "Toggles whether a transaction is enabled by id",
+                            "Toggles whether an event is enabled by id, together with the rest of its transaction",
                             coin_store_toggle_by_id,
                         ),
                         cmd!(
@@ -1106,10 +4229,20 @@ fn main() {
                             "Toggles whether a transaction is enabled by description substring",
                             coin_store_undo_toggle_by_desc,
                         ),
+                        cmd!(
+                            "where",
+                            "Toggles transactions matching a filter such as person=alice coins=10..100, with a preview",
+                            coin_store_toggle_where,
+                        ),
+                        cmd!(
+                            "log",
+                            "Show the toggle events recorded in the current span/frame",
+                            coin_store_toggle_log,
+                        ),
                     ),
                     cmd!(
                         "ls",
-                        "List the span/frame tree and the user's curent position within it",
+                        "List the span/frame tree and the user's curent position within it (pass dot for Graphviz)",
                         coin_store_ls,
                     ),
                     parent!(
@@ -1125,7 +4258,59 @@ fn main() {
                             coin_store_span_pop,
                         ),
                     ),
-                    cmd!("switch", "Switch to a given span frame", coin_store_switch,),
+                    cmd!(
+                        "verify",
+                        "Check that no event was edited or removed outside of the demo",
+                        coin_store_verify,
+                    ),
+                    cmd!(
+                        "export",
+                        "Export events|diffs|wallet|records [--format jsonl|csv] [--frame <sf>] [--out <file>]",
+                        coin_store_export,
+                    ),
+                    cmd!(
+                        "import",
+                        "Import events exported as JSON Lines, all or nothing <file> [--dry-run]",
+                        coin_store_import,
+                    ),
+                    cmd!(
+                        "ledger",
+                        "Export the current span/frame as ledger or beancount postings and check the balances [--format ledger|beancount] [--frame <sf>] [--out <file>]",
+                        coin_store_ledger,
+                    ),
+                    parent!(
+                        "snapshot",
+                        cmd!(
+                            "create",
+                            "Snapshot the wallet of the current span/frame so reads start from it",
+                            coin_store_snapshot_create,
+                        ),
+                        cmd!(
+                            "list",
+                            "List the snapshots of all span/frames",
+                            coin_store_snapshot_list,
+                        ),
+                        cmd!(
+                            "verify",
+                            "Check the snapshotted wallet of the current span/frame against a full replay",
+                            coin_store_snapshot_verify,
+                        ),
+                    ),
+                    cmd!(
+                        "switch",
+                        "Switch to a given span frame, or to the one with the given name or tag",
+                        coin_store_switch,
+                    ),
+                    cmd!(
+                        "name",
+                        "Name the current span frame. Names move over if reused",
+                        coin_store_name,
+                    ),
+                    cmd!(
+                        "tag",
+                        "Tag the current span frame. Tags cannot be moved",
+                        coin_store_tag,
+                    ),
                     parent!(
                         "reset",
                         cmd!(
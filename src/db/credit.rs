@@ -0,0 +1,382 @@
+//! Credit between users: credit lines set by `credit_line` events, and debts recorded by `iou` events.
+//!
+//! A credit line, such as 200 coin for alice, lets the user's balance of the asset go down to -200 instead of 0, and a
+//! later one for the same user and asset replaces it. An IOU, such as bob owing alice 50 coin, only records the debt,
+//! no coins move. It is repaid, in full or in parts, by transfers from the debtor to the creditor whose postings point at
+//! it, so what is still outstanding is read from the records of the span frame. Credit lines and IOUs are read as
+//! `SpanFrameInfo::sees` decides.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::{EventAction, ObjState};
+use crate::db::double_entry::Posting;
+use crate::db::models::{
+    Amount, AmountError, Asset, CreditLine, Iou, Payload, PayloadError, Person, coin_store,
+};
+use crate::db::spend::{self, SpendError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum CreditError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Payload Error: {0}")]
+    PayloadError(#[from] PayloadError),
+
+    // Boxed, since what is available to spend depends on credit lines
+    #[error("Spend Error: {0}")]
+    SpendError(#[from] Box<SpendError>),
+
+    #[error("Span frame (span: {}, frame: {}) does not exist", .0.span, .0.frame)]
+    UnknownSpanFrame(SpanFrame),
+
+    #[error("Coins to repay must be positive")]
+    NotPositive,
+
+    #[error("Event {0} is not an IOU seen in this span frame")]
+    UnknownIou(i32),
+
+    #[error("IOU {0} is already repaid")]
+    AlreadyRepaid(i32),
+
+    #[error("Cannot repay {coins} of IOU {ev_id}, of which only {outstanding} is outstanding")]
+    ExceedsOutstanding {
+        ev_id: i32,
+        outstanding: Amount,
+        coins: Amount,
+    },
+}
+
+/// An IOU with what is left of it
+#[derive(Debug)]
+pub struct IouInfo {
+    pub event: coin_store::Event,
+    pub iou: Iou,
+    pub repaid: Amount,
+    pub outstanding: Amount,
+}
+
+/// What one user still owes another in an asset, over all their IOUs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Debt {
+    pub debtor: Person,
+    pub creditor: Person,
+    pub asset: Asset,
+    pub outstanding: Amount,
+}
+
+/// What a user is owed and owes in an asset, over all their counterparties
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetPosition {
+    pub person: Person,
+    pub asset: Asset,
+    pub owed: Amount,
+    pub owing: Amount,
+    /// What the user is owed less what they owe
+    pub net: Amount,
+}
+
+/// The events of the action that the span frame sees, in id order
+fn get_seen_events(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    ev_action: EventAction,
+) -> Result<Vec<coin_store::Event>, CreditError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let info = coin_store::get_span_frame_info(conn, span_frame)?
+        .ok_or_else(|| CreditError::UnknownSpanFrame(span_frame.clone()))?;
+
+    let events: Vec<coin_store::Event> = dsl::coin_store_events
+        .filter(dsl::ev_action.eq(ev_action))
+        .order(dsl::id.asc())
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    Ok(events
+        .into_iter()
+        .filter(|event| info.sees(event.span, event.frame, event.created_on_ts))
+        .collect())
+}
+
+fn insert_credit_event(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    ev_action: EventAction,
+    opt_credit_line: Option<String>,
+    opt_iou: Option<String>,
+    ev_desc: &str,
+) -> Result<coin_store::Event, diesel::result::Error> {
+    let new_event = coin_store::NewEvent {
+        opt_credit_line,
+        opt_iou,
+        ..coin_store::NewEvent::new(ev_action, span_frame, ev_desc)
+    };
+
+    coin_store::insert_recorded_event(conn, new_event, None)
+}
+
+/// Records the credit line in the span frame, replacing any earlier one of the user in the asset
+pub fn set_credit_line(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    credit_line: &CreditLine,
+    ev_desc: &str,
+) -> Result<coin_store::Event, CreditError> {
+    Ok(insert_credit_event(
+        conn,
+        span_frame,
+        EventAction::CreditLine,
+        Some(credit_line.to_payload()?),
+        None,
+        ev_desc,
+    )?)
+}
+
+/// The credit line of every user in every asset that the span frame sees, leaving out those set back to 0, ordered
+/// by name and asset
+pub fn get_credit_lines(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<CreditLine>, CreditError> {
+    let mut mut_out: Vec<CreditLine> = vec![];
+
+    for event in get_seen_events(conn, span_frame, EventAction::CreditLine)? {
+        let credit_line =
+            CreditLine::from_payload(event.opt_credit_line.as_deref().unwrap_or_default())?;
+
+        mut_out
+            .retain(|other| other.person != credit_line.person || other.asset != credit_line.asset);
+        mut_out.push(credit_line);
+    }
+
+    mut_out.retain(|credit_line| credit_line.coins.is_positive());
+    mut_out.sort_by_key(|credit_line| (credit_line.person.to_inner(), credit_line.asset.clone()));
+
+    Ok(mut_out)
+}
+
+/// Records that the debtor owes the creditor the coins, without moving any
+pub fn record_iou(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    iou: &Iou,
+    ev_desc: &str,
+) -> Result<coin_store::Event, CreditError> {
+    Ok(insert_credit_event(
+        conn,
+        span_frame,
+        EventAction::Iou,
+        None,
+        Some(iou.to_payload()?),
+        ev_desc,
+    )?)
+}
+
+/// Every IOU the span frame sees in id order with what was repaid of it there, repaid ones included
+pub fn get_ious(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<IouInfo>, CreditError> {
+    use crate::autogen::schema::coin_store_events::dsl as events_dsl;
+    use crate::autogen::schema::coin_store_events_grouped::dsl;
+
+    let events = get_seen_events(conn, span_frame, EventAction::Iou)?;
+
+    let iou_ev_ids = events.iter().map(|event| event.id).collect::<Vec<_>>();
+
+    // Postings that point at an IOU are its repayments
+    let repayments: Vec<(i32, Option<i32>)> = events_dsl::coin_store_events
+        .filter(events_dsl::opt_target_ev_id.eq_any(&iou_ev_ids))
+        .select((events_dsl::id, events_dsl::opt_target_ev_id))
+        .get_results(conn)?;
+
+    let records: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .filter(dsl::ev_id.eq_any(repayments.iter().map(|(ev_id, _)| *ev_id)))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(conn)?;
+
+    events
+        .into_iter()
+        .map(|event| {
+            let iou = Iou::from_payload(event.opt_iou.as_deref().unwrap_or_default())?;
+
+            let repaid = Amount::checked_sum(
+                records
+                    .iter()
+                    .filter(|record| {
+                        repayments.iter().any(|(ev_id, opt_target_ev_id)| {
+                            *ev_id == record.ev_id && *opt_target_ev_id == Some(event.id)
+                        })
+                    })
+                    .filter(|record| record.person == iou.creditor)
+                    .map(|record| record.coins),
+            )?;
+
+            Ok(IouInfo {
+                outstanding: iou.coins.checked_sub(repaid)?,
+                repaid,
+                iou,
+                event,
+            })
+        })
+        .collect()
+}
+
+/// Moves the coins from the debtor to the creditor of the IOU in one transaction that points at it, or only part of
+/// what is outstanding, if the debtor has them available at `now`. Returns the coins that were repaid.
+pub fn repay_iou(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    iou_ev_id: i32,
+    opt_coins: Option<Amount>,
+    now: DateTime<Utc>,
+    ev_desc: &str,
+) -> Result<Amount, CreditError> {
+    conn.transaction(|conn| {
+        let info = get_ious(conn, span_frame)?
+            .into_iter()
+            .find(|info| info.event.id == iou_ev_id)
+            .ok_or(CreditError::UnknownIou(iou_ev_id))?;
+
+        if !info.outstanding.is_positive() {
+            return Err(CreditError::AlreadyRepaid(iou_ev_id));
+        }
+
+        let coins = opt_coins.unwrap_or(info.outstanding);
+
+        if !coins.is_positive() {
+            return Err(CreditError::NotPositive);
+        }
+
+        if coins > info.outstanding {
+            return Err(CreditError::ExceedsOutstanding {
+                ev_id: iou_ev_id,
+                outstanding: info.outstanding,
+                coins,
+            });
+        }
+
+        let postings = [
+            Posting {
+                obj_id: info.iou.debtor.get_obj_id(),
+                obj_state: ObjState::Update,
+                opt_target_ev_id: Some(iou_ev_id),
+                ev_desc,
+                person: &info.iou.debtor,
+                coins: coins.checked_neg()?,
+                asset: &info.iou.asset,
+                opt_rate: None,
+                opt_expires_on: None,
+            },
+            Posting {
+                obj_id: info.iou.creditor.get_obj_id(),
+                obj_state: ObjState::Update,
+                opt_target_ev_id: Some(iou_ev_id),
+                ev_desc,
+                person: &info.iou.creditor,
+                coins,
+                asset: &info.iou.asset,
+                opt_rate: None,
+                opt_expires_on: None,
+            },
+        ];
+
+        spend::insert_spending_transaction(conn, span_frame, &postings, now).map_err(Box::new)?;
+
+        Ok(coins)
+    })
+}
+
+/// What every user still owes each of their counterparties in every asset in the span frame, ordered by debtor,
+/// creditor and asset
+pub fn get_debts(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<Debt>, CreditError> {
+    let mut mut_out: Vec<Debt> = vec![];
+
+    for info in get_ious(conn, span_frame)? {
+        if !info.outstanding.is_positive() {
+            continue;
+        }
+
+        match mut_out.iter_mut().find(|debt| {
+            debt.debtor == info.iou.debtor
+                && debt.creditor == info.iou.creditor
+                && debt.asset == info.iou.asset
+        }) {
+            Some(debt) => debt.outstanding = debt.outstanding.checked_add(info.outstanding)?,
+            None => mut_out.push(Debt {
+                debtor: info.iou.debtor,
+                creditor: info.iou.creditor,
+                asset: info.iou.asset,
+                outstanding: info.outstanding,
+            }),
+        }
+    }
+
+    mut_out.sort_by_key(|debt| {
+        (
+            debt.debtor.to_inner(),
+            debt.creditor.to_inner(),
+            debt.asset.clone(),
+        )
+    });
+
+    Ok(mut_out)
+}
+
+/// What every user with outstanding debts is owed and owes in every asset in the span frame, ordered by name and
+/// asset
+pub fn get_net_positions(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<NetPosition>, CreditError> {
+    let mut mut_out: Vec<NetPosition> = vec![];
+
+    for debt in get_debts(conn, span_frame)? {
+        for (person, is_owed) in [(debt.creditor, true), (debt.debtor, false)] {
+            let position = match mut_out
+                .iter()
+                .position(|position| position.person == person && position.asset == debt.asset)
+            {
+                Some(index) => &mut mut_out[index],
+                None => {
+                    mut_out.push(NetPosition {
+                        person,
+                        asset: debt.asset.clone(),
+                        owed: Amount::ZERO,
+                        owing: Amount::ZERO,
+                        net: Amount::ZERO,
+                    });
+
+                    mut_out.last_mut().expect("just pushed")
+                }
+            };
+
+            if is_owed {
+                position.owed = position.owed.checked_add(debt.outstanding)?;
+            } else {
+                position.owing = position.owing.checked_add(debt.outstanding)?;
+            }
+
+            position.net = position.owed.checked_sub(position.owing)?;
+        }
+    }
+
+    mut_out.sort_by_key(|position| (position.person.to_inner(), position.asset.clone()));
+
+    Ok(mut_out)
+}
@@ -0,0 +1,317 @@
+//! Optional double-entry bookkeeping for the coin store.
+//!
+//! Once enabled, coins only move in transactions: postings to two or more accounts that share a transaction id and
+//! add up to 0 in every asset. Income is issued from the `@issuance` system account and expenses are paid into `@expenses`, so the
+//! accounts of every span frame, system accounts included, add up to 0. Enabling is only possible before any coins
+//! have moved, and cannot be undone.
+//!
+//! Coins can also be moved in transactions outside of double-entry mode, e.g. to transfer them between users.
+
+use chrono::{NaiveDate, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::{EventAction, ObjState};
+use crate::db::models::{Amount, AmountError, Asset, Person, Rate, coin_store};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum DoubleEntryError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Double-entry mode is already enabled")]
+    AlreadyEnabled,
+
+    #[error("Coins have already moved, so double-entry mode can no longer be enabled")]
+    CoinsAlreadyMoved,
+
+    #[error("A transaction needs at least two postings")]
+    TooFewPostings,
+
+    #[error(
+        "Transaction {tx_id} is unbalanced: its postings of {asset} add up to {total} instead of 0"
+    )]
+    Unbalanced {
+        tx_id: i32,
+        asset: String,
+        total: Amount,
+    },
+}
+
+/// One side of a transaction
+#[derive(Debug, Clone)]
+pub struct Posting<'a> {
+    pub obj_id: i32,
+    pub obj_state: ObjState,
+    pub opt_target_ev_id: Option<i32>,
+    pub ev_desc: &'a str,
+    pub person: &'a Person,
+    pub coins: Amount,
+    pub asset: &'a Asset,
+    /// The rate an exchange posting is made at
+    pub opt_rate: Option<&'a Rate>,
+    /// The date the coins of an income posting expire on
+    pub opt_expires_on: Option<NaiveDate>,
+}
+
+pub fn is_double_entry(conn: &mut SqliteConnection) -> Result<bool, diesel::result::Error> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    diesel::select(diesel::dsl::exists(
+        dsl::coin_store_events.filter(dsl::ev_action.eq(EventAction::DoubleEntry)),
+    ))
+    .get_result(conn)
+}
+
+/// Records that the store is in double-entry mode from now on
+pub fn enable_double_entry(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    ev_desc: &str,
+) -> Result<(), DoubleEntryError> {
+    use crate::autogen::schema::coin_store_diffs::dsl;
+
+    conn.transaction(|conn| {
+        if is_double_entry(conn)? {
+            return Err(DoubleEntryError::AlreadyEnabled);
+        }
+
+        let coins_moved = diesel::select(diesel::dsl::exists(
+            dsl::coin_store_diffs.filter(dsl::coins.ne(0)),
+        ))
+        .get_result::<bool>(conn)?;
+
+        if coins_moved {
+            return Err(DoubleEntryError::CoinsAlreadyMoved);
+        }
+
+        let new_event = coin_store::NewEvent::new(EventAction::DoubleEntry, span_frame, ev_desc);
+
+        coin_store::insert_recorded_event(conn, new_event, None)?;
+
+        Ok(())
+    })
+}
+
+/// Transaction ids are allocated in increasing order, independently of event ids
+pub fn get_next_tx_id(conn: &mut SqliteConnection) -> Result<i32, diesel::result::Error> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let opt_max_tx_id: Option<i32> = dsl::coin_store_events
+        .select(diesel::dsl::max(dsl::opt_tx_id))
+        .get_result(conn)?;
+
+    Ok(opt_max_tx_id.unwrap_or_default() + 1)
+}
+
+/// Writes a single posting, in a transaction if `opt_tx_id` is given. Outside of one, only postings of 0 coins, which
+/// add or remove an account, are allowed in double-entry mode. Prefer `insert_transaction`, which also checks that the
+/// postings balance. A posting that takes coins carries the date it is made on, which decides the lots it spends.
+pub fn insert_posting(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    opt_tx_id: Option<i32>,
+    posting: &Posting,
+) -> Result<coin_store::Event, diesel::result::Error> {
+    let now = Utc::now();
+
+    let new_event = coin_store::NewEvent {
+        created_on_ts: now.timestamp_millis() as f32,
+        opt_target_ev_id: posting.opt_target_ev_id,
+        opt_tx_id,
+        opt_rate: posting.opt_rate.map(ToString::to_string),
+        opt_expires_on: posting.opt_expires_on.map(|date| date.to_string()),
+        opt_made_on: posting
+            .coins
+            .is_negative()
+            .then(|| now.date_naive().to_string()),
+        ..coin_store::NewEvent::new(
+            posting.obj_state.clone().into(),
+            span_frame,
+            posting.ev_desc,
+        )
+    };
+
+    coin_store::insert_recorded_event(
+        conn,
+        new_event,
+        Some((
+            posting.obj_id,
+            coin_store::NewCommon {
+                coins: posting.coins,
+                person: posting.person,
+                asset: posting.asset,
+            },
+        )),
+    )
+}
+
+/// Checks that the postings of the transaction add up to 0 in every asset
+pub fn check_transaction_balanced(
+    conn: &mut SqliteConnection,
+    tx_id: i32,
+) -> Result<(), DoubleEntryError> {
+    use crate::autogen::schema::{coin_store_diffs, coin_store_events};
+
+    let postings: Vec<(Asset, Amount)> = coin_store_events::table
+        .inner_join(
+            coin_store_diffs::table
+                .on(coin_store_events::opt_diff_id.eq(coin_store_diffs::id.nullable())),
+        )
+        .filter(coin_store_events::opt_tx_id.eq(tx_id))
+        .select((coin_store_diffs::asset, coin_store_diffs::coins))
+        .get_results(conn)?;
+
+    // Summed here rather than in SQL, so that an overflow is an error
+    let mut mut_totals: Vec<(Asset, Amount)> = vec![];
+
+    for (asset, coins) in postings {
+        match mut_totals.iter_mut().find(|(other, _)| *other == asset) {
+            Some((_, total)) => *total = total.checked_add(coins)?,
+            None => mut_totals.push((asset, coins)),
+        }
+    }
+
+    match mut_totals.into_iter().find(|(_, total)| !total.is_zero()) {
+        None => Ok(()),
+        Some((asset, total)) => Err(DoubleEntryError::Unbalanced {
+            tx_id,
+            asset: asset.to_inner(),
+            total,
+        }),
+    }
+}
+
+/// Writes the postings as one transaction, all or nothing. Returns its transaction id.
+pub fn insert_transaction(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    postings: &[Posting],
+) -> Result<i32, DoubleEntryError> {
+    if postings.len() < 2 {
+        return Err(DoubleEntryError::TooFewPostings);
+    }
+
+    conn.transaction(|conn| {
+        let tx_id = get_next_tx_id(conn)?;
+
+        for posting in postings {
+            insert_posting(conn, span_frame, Some(tx_id), posting)?;
+        }
+
+        check_transaction_balanced(conn, tx_id)?;
+
+        Ok(tx_id)
+    })
+}
+
+/// Gives coins of an asset to or takes them from a user. In double-entry mode, they are issued from `@issuance` or paid
+/// into `@expenses` in the same transaction. Only the user's posting points at `opt_target_ev_id`.
+pub fn insert_coins_for_person(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    coins: Amount,
+    asset: &Asset,
+    ev_desc: &str,
+    opt_target_ev_id: Option<i32>,
+) -> Result<(), DoubleEntryError> {
+    let posting = Posting {
+        obj_id: person.get_obj_id(),
+        obj_state: ObjState::Update,
+        opt_target_ev_id,
+        ev_desc,
+        person,
+        coins,
+        asset,
+        opt_rate: None,
+        opt_expires_on: None,
+    };
+
+    insert_posting_for_person(conn, span_frame, posting)
+}
+
+/// Writes the posting of a user. In double-entry mode, a system account takes the other side in the same transaction,
+/// like for `insert_coins_for_person`.
+pub fn insert_posting_for_person(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    posting: Posting,
+) -> Result<(), DoubleEntryError> {
+    use crate::autogen::schema::coin_store_diffs::dsl;
+
+    if !is_double_entry(conn)? {
+        insert_posting(conn, span_frame, None, &posting)?;
+
+        return Ok(());
+    }
+
+    let system_account = if posting.coins.is_positive() {
+        Person::issuance()
+    } else {
+        Person::expenses()
+    };
+
+    let system_obj_id = system_account.get_obj_id();
+
+    // System accounts are not added like users, so their first posting inserts them
+    let system_obj_exists = diesel::select(diesel::dsl::exists(
+        dsl::coin_store_diffs.filter(dsl::obj_id.eq(system_obj_id)),
+    ))
+    .get_result::<bool>(conn)?;
+
+    let system_posting = Posting {
+        obj_id: system_obj_id,
+        obj_state: if system_obj_exists {
+            ObjState::Update
+        } else {
+            ObjState::Insert
+        },
+        opt_target_ev_id: None,
+        person: &system_account,
+        coins: posting.coins.checked_neg()?,
+        opt_expires_on: None,
+        ..posting.clone()
+    };
+
+    insert_transaction(conn, span_frame, &[posting, system_posting])?;
+
+    Ok(())
+}
+
+/// The balance of every account in every asset in the span frame, system accounts included, ordered by name and asset
+pub fn get_account_balances(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<(Person, Asset, Amount)>, DoubleEntryError> {
+    use crate::autogen::schema::coin_store_hist::dsl;
+
+    let hist = dsl::coin_store_hist
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .select(coin_store::Hist::as_select())
+        .get_results(conn)?;
+
+    let mut mut_out: Vec<(Person, Asset, Amount)> = vec![];
+
+    for row in hist {
+        match mut_out
+            .iter_mut()
+            .find(|(person, asset, _)| *person == row.person && *asset == row.asset)
+        {
+            Some((_, _, coins)) => *coins = coins.checked_add(row.coins)?,
+            None => mut_out.push((row.person, row.asset, row.coins)),
+        }
+    }
+
+    mut_out.sort_by_key(|(person, asset, _)| (person.to_inner(), asset.clone()));
+
+    Ok(mut_out)
+}
@@ -0,0 +1,251 @@
+//! Exchange between assets at rates that are recorded as events.
+//!
+//! A rate such as `10 points = 1 coin` is set in a span frame by a `rate` event. A span frame sees the rates set in
+//! it and the ones it inherits from lower spans, the same way it sees any other event, so a branch can try out
+//! different rates. The latest rate between two assets is the one in use, in both directions.
+//!
+//! An exchange debits one asset and credits another for the same user in one transaction. The `@exchange` system
+//! account takes the other side of both postings, so that the transaction balances in each asset. The user's
+//! postings record the rate they were made at.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::{EventAction, ObjState};
+use crate::db::double_entry::Posting;
+use crate::db::models::{Amount, AmountError, Asset, Person, Rate, RateFromStrError, coin_store};
+use crate::db::spend::{self, SpendError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum ExchangeError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Spend Error: {0}")]
+    SpendError(#[from] SpendError),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Stored rate is invalid: {0}")]
+    InvalidRate(#[from] RateFromStrError),
+
+    #[error("Only a positive amount can be exchanged")]
+    NotPositive,
+
+    #[error("Cannot exchange {0} for itself")]
+    SameAsset(String),
+
+    #[error("No rate between {from_asset} and {to_asset} is set in the span frame")]
+    MissingRate {
+        from_asset: String,
+        to_asset: String,
+    },
+
+    #[error("{coins} {from_asset} is not worth a whole number of {to_asset} at {rate}")]
+    InexactAmount {
+        coins: Amount,
+        from_asset: String,
+        to_asset: String,
+        rate: Rate,
+    },
+}
+
+/// Exchange of some of a user's coins of one asset for another
+#[derive(Debug, Clone)]
+pub struct ExchangeOrder<'a> {
+    pub obj_id: i32,
+    pub person: &'a Person,
+    pub coins: Amount,
+    pub from_asset: &'a Asset,
+    pub to_asset: &'a Asset,
+    pub ev_desc: &'a str,
+}
+
+/// What an exchange wrote
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub tx_id: i32,
+    pub rate: Rate,
+    pub to_coins: Amount,
+}
+
+/// Records the rate in the span frame. It replaces any earlier rate between the same two assets from there on.
+pub fn set_rate(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    rate: &Rate,
+    ev_desc: &str,
+) -> Result<coin_store::Event, diesel::result::Error> {
+    let new_event = coin_store::NewEvent {
+        opt_rate: Some(rate.to_string()),
+        ..coin_store::NewEvent::new(EventAction::Rate, span_frame, ev_desc)
+    };
+
+    coin_store::insert_recorded_event(conn, new_event, None)
+}
+
+/// The rates in use in the span frame with the events that set them, in the order they were set
+pub fn get_rates(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<(coin_store::Event, Rate)>, ExchangeError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let Some(info) = coin_store::get_span_frame_info(conn, span_frame)? else {
+        return Ok(vec![]);
+    };
+
+    let events: Vec<coin_store::Event> = dsl::coin_store_events
+        .filter(dsl::ev_action.eq(EventAction::Rate))
+        .order(dsl::id.asc())
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    let mut mut_out: Vec<(coin_store::Event, Rate)> = vec![];
+
+    for event in events {
+        if !info.sees(event.span, event.frame, event.created_on_ts) {
+            continue;
+        }
+
+        let rate: Rate = event.opt_rate.as_deref().unwrap_or_default().parse()?;
+
+        mut_out.retain(|(_, other)| !other.is_between(&rate.from_asset, &rate.to_asset));
+        mut_out.push((event, rate));
+    }
+
+    Ok(mut_out)
+}
+
+/// The rate in use between the two assets in the span frame
+pub fn get_rate(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    asset: &Asset,
+    other: &Asset,
+) -> Result<Option<Rate>, ExchangeError> {
+    Ok(get_rates(conn, span_frame)?
+        .into_iter()
+        .map(|(_, rate)| rate)
+        .find(|rate| rate.is_between(asset, other)))
+}
+
+/// The rate that each exchange posting was made at, as recorded, by event id
+pub fn get_rates_by_ev_id(
+    conn: &mut SqliteConnection,
+) -> Result<HashMap<i32, String>, diesel::result::Error> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let rates: Vec<(i32, Option<String>)> = dsl::coin_store_events
+        .filter(dsl::opt_rate.is_not_null())
+        .filter(dsl::ev_action.ne(EventAction::Rate))
+        .select((dsl::id, dsl::opt_rate))
+        .get_results(conn)?;
+
+    Ok(rates
+        .into_iter()
+        .filter_map(|(ev_id, opt_rate)| opt_rate.map(|rate| (ev_id, rate)))
+        .collect())
+}
+
+/// Exchanges the coins at the rate in use in the span frame, all or nothing, if the user has them available at `now`.
+/// `exchange_obj_id` is the object of the `@exchange` system account, which is inserted by its first exchange.
+pub fn insert_exchange(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    order: &ExchangeOrder,
+    exchange_obj_id: i32,
+    now: DateTime<Utc>,
+) -> Result<Exchange, ExchangeError> {
+    use crate::autogen::schema::coin_store_diffs::dsl;
+
+    if !order.coins.is_positive() {
+        return Err(ExchangeError::NotPositive);
+    }
+
+    if order.from_asset == order.to_asset {
+        return Err(ExchangeError::SameAsset(order.from_asset.to_inner()));
+    }
+
+    let rate = get_rate(conn, span_frame, order.from_asset, order.to_asset)?.ok_or_else(|| {
+        ExchangeError::MissingRate {
+            from_asset: order.from_asset.to_inner(),
+            to_asset: order.to_asset.to_inner(),
+        }
+    })?;
+
+    let to_coins = rate.convert(order.coins, order.from_asset).ok_or_else(|| {
+        ExchangeError::InexactAmount {
+            coins: order.coins,
+            from_asset: order.from_asset.to_inner(),
+            to_asset: order.to_asset.to_inner(),
+            rate: rate.clone(),
+        }
+    })?;
+
+    conn.transaction(|conn| {
+        let exchange_obj_exists = diesel::select(diesel::dsl::exists(
+            dsl::coin_store_diffs.filter(dsl::obj_id.eq(exchange_obj_id)),
+        ))
+        .get_result::<bool>(conn)?;
+
+        let exchange = Person::exchange();
+
+        let debit = Posting {
+            obj_id: order.obj_id,
+            obj_state: ObjState::Update,
+            opt_target_ev_id: None,
+            ev_desc: order.ev_desc,
+            person: order.person,
+            coins: order.coins.checked_neg()?,
+            asset: order.from_asset,
+            opt_rate: Some(&rate),
+            opt_expires_on: None,
+        };
+
+        let credit = Posting {
+            coins: to_coins,
+            asset: order.to_asset,
+            ..debit.clone()
+        };
+
+        // The first posting of the system account inserts it
+        let exchange_debit = Posting {
+            obj_id: exchange_obj_id,
+            obj_state: if exchange_obj_exists {
+                ObjState::Update
+            } else {
+                ObjState::Insert
+            },
+            person: &exchange,
+            coins: order.coins,
+            opt_rate: None,
+            ..debit.clone()
+        };
+
+        let exchange_credit = Posting {
+            obj_state: ObjState::Update,
+            coins: to_coins.checked_neg()?,
+            asset: order.to_asset,
+            ..exchange_debit.clone()
+        };
+
+        let tx_id = spend::insert_spending_transaction(
+            conn,
+            span_frame,
+            &[debit, credit, exchange_debit, exchange_credit],
+            now,
+        )?;
+
+        Ok(Exchange {
+            tx_id,
+            rate: rate.clone(),
+            to_coins,
+        })
+    })
+}
@@ -0,0 +1,327 @@
+//! Income whose coins expire, e.g. promotional coins.
+//!
+//! An income posting can carry the date its coins expire on. The coins of each income form a lot, and expenses use up
+//! the oldest lots that have not expired yet first. Income without an expiry date forms a lot that never expires. The
+//! lots are folded from the records of the span frame, so nothing but the expiry date of income and the date that each
+//! posting taking coins was made on has to be stored.
+//!
+//! Coins of expired lots stay in the balance until the lots are swept, which writes an expiration posting for the rest
+//! of every expired lot, pointing at its income. A negative posting that points at an income, like an expiration or a
+//! compensating event of a partial commit, takes its coins from that lot rather than the oldest one.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate};
+use diesel::prelude::*;
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::autogen::schema::ObjState;
+use crate::db::double_entry::{self, DoubleEntryError, Posting};
+use crate::db::models::{Amount, AmountError, Asset, Person, coin_store};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum ExpiryError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Double-entry Error: {0}")]
+    DoubleEntryError(#[from] DoubleEntryError),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Event {ev_id} has an invalid expiry date {expires_on:?}")]
+    InvalidExpiry { ev_id: i32, expires_on: String },
+
+    #[error("Event {ev_id} has an invalid date {made_on:?} it was made on")]
+    InvalidMadeOn { ev_id: i32, made_on: String },
+
+    #[error("Coins of expiring income must be positive")]
+    NotPositive,
+}
+
+/// Coins of an income that are not used up yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lot {
+    /// The income event the lot came from
+    pub ev_id: i32,
+    pub person: Person,
+    pub asset: Asset,
+    pub coins: Amount,
+    pub opt_expires_on: Option<NaiveDate>,
+}
+
+impl Lot {
+    /// Coins can no longer be spent from the day they expire on
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.opt_expires_on
+            .is_some_and(|expires_on| expires_on <= today)
+    }
+}
+
+/// A user's coins in an asset, split into those they can spend and those that expired but are not swept yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiryBalance {
+    pub person: Person,
+    pub asset: Asset,
+    pub spendable: Amount,
+    pub expired: Amount,
+}
+
+/// The lots of a user in an asset, and the coins spent beyond them
+#[derive(Debug, Default)]
+struct Account {
+    lots: Vec<Lot>,
+    overdrawn: Amount,
+}
+
+/// The accounts of each user and asset, in the order they first appear
+type AccountsPerPerson = Vec<((Person, Asset), Account)>;
+
+/// Gives coins to a user that expire on the given date. In double-entry mode, they are issued from `@issuance`.
+pub fn insert_expiring_income(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    coins: Amount,
+    asset: &Asset,
+    expires_on: NaiveDate,
+    ev_desc: &str,
+) -> Result<(), ExpiryError> {
+    if !coins.is_positive() {
+        return Err(ExpiryError::NotPositive);
+    }
+
+    let posting = Posting {
+        obj_id: person.get_obj_id(),
+        obj_state: ObjState::Update,
+        opt_target_ev_id: None,
+        ev_desc,
+        person,
+        coins,
+        asset,
+        opt_rate: None,
+        opt_expires_on: Some(expires_on),
+    };
+
+    Ok(double_entry::insert_posting_for_person(
+        conn, span_frame, posting,
+    )?)
+}
+
+/// Folds the records of the span frame, in the order the wallet applies them, into accounts of lots. Each expense is
+/// spent from the lots that had not expired on the day it was made.
+fn get_accounts(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<AccountsPerPerson, ExpiryError> {
+    use crate::autogen::schema::coin_store_events::dsl as events_dsl;
+    use crate::autogen::schema::coin_store_events_grouped::dsl;
+
+    let records: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .order((dsl::created_on_ts.asc(), dsl::ev_id.asc()))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(conn)?;
+
+    let ev_ids = records
+        .iter()
+        .map(|record| record.ev_id)
+        .collect::<Vec<_>>();
+
+    let event_columns: HashMap<i32, (Option<i32>, Option<String>, Option<String>)> =
+        events_dsl::coin_store_events
+            .filter(events_dsl::id.eq_any(&ev_ids))
+            .select((
+                events_dsl::id,
+                events_dsl::opt_target_ev_id,
+                events_dsl::opt_expires_on,
+                events_dsl::opt_made_on,
+            ))
+            .get_results::<(i32, Option<i32>, Option<String>, Option<String>)>(conn)?
+            .into_iter()
+            .map(|(ev_id, opt_target_ev_id, opt_expires_on, opt_made_on)| {
+                (ev_id, (opt_target_ev_id, opt_expires_on, opt_made_on))
+            })
+            .collect();
+
+    let mut mut_accounts: AccountsPerPerson = vec![];
+
+    for record in records {
+        if record.coins.is_zero() || record.person.is_system_account() {
+            continue;
+        }
+
+        let (opt_target_ev_id, opt_expires_on, opt_made_on) = event_columns
+            .get(&record.ev_id)
+            .cloned()
+            .unwrap_or_default();
+
+        let opt_expires_on = opt_expires_on
+            .map(|expires_on| {
+                expires_on
+                    .parse::<NaiveDate>()
+                    .map_err(|_| ExpiryError::InvalidExpiry {
+                        ev_id: record.ev_id,
+                        expires_on,
+                    })
+            })
+            .transpose()?;
+
+        let key = (record.person.clone(), record.asset.clone());
+
+        let account = match mut_accounts.iter_mut().position(|(other, _)| *other == key) {
+            Some(index) => &mut mut_accounts[index].1,
+            None => {
+                mut_accounts.push((key, Account::default()));
+                &mut mut_accounts.last_mut().expect("just pushed").1
+            }
+        };
+
+        if record.coins.is_positive() {
+            // Coins spent beyond the lots are made up for first
+            let made_up = record.coins.min(account.overdrawn);
+            account.overdrawn = account.overdrawn.checked_sub(made_up)?;
+
+            let coins = record.coins.checked_sub(made_up)?;
+
+            if coins.is_positive() {
+                account.lots.push(Lot {
+                    ev_id: record.ev_id,
+                    person: record.person,
+                    asset: record.asset,
+                    coins,
+                    opt_expires_on,
+                });
+            }
+
+            continue;
+        }
+
+        let mut mut_rest = record.coins.checked_neg()?;
+
+        if let Some(lot) = account
+            .lots
+            .iter_mut()
+            .find(|lot| Some(lot.ev_id) == opt_target_ev_id)
+        {
+            let taken = mut_rest.min(lot.coins);
+
+            lot.coins = lot.coins.checked_sub(taken)?;
+            mut_rest = mut_rest.checked_sub(taken)?;
+        }
+
+        // Postings written before the date was stored only have the timestamp, which is off by up to a minute
+        let made_on = match opt_made_on {
+            Some(made_on) => {
+                made_on
+                    .parse::<NaiveDate>()
+                    .map_err(|_| ExpiryError::InvalidMadeOn {
+                        ev_id: record.ev_id,
+                        made_on,
+                    })?
+            }
+            None => DateTime::from_timestamp_millis(record.created_on_ts as i64)
+                .unwrap_or_default()
+                .date_naive(),
+        };
+
+        for lot in account
+            .lots
+            .iter_mut()
+            .filter(|lot| !lot.is_expired(made_on))
+        {
+            let taken = mut_rest.min(lot.coins);
+
+            lot.coins = lot.coins.checked_sub(taken)?;
+            mut_rest = mut_rest.checked_sub(taken)?;
+        }
+
+        account.overdrawn = account.overdrawn.checked_add(mut_rest)?;
+        account.lots.retain(|lot| lot.coins.is_positive());
+    }
+
+    Ok(mut_accounts)
+}
+
+/// The lots of every user in the span frame that are not used up, oldest first
+pub fn get_lots(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<Lot>, ExpiryError> {
+    Ok(get_accounts(conn, span_frame)?
+        .into_iter()
+        .flat_map(|(_, account)| account.lots)
+        .sorted_by_key(|lot| lot.ev_id)
+        .collect())
+}
+
+/// The spendable and expired coins of every user in every asset in the span frame on `today`, ordered by name and
+/// asset
+pub fn get_expiry_balances(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    today: NaiveDate,
+) -> Result<Vec<ExpiryBalance>, ExpiryError> {
+    let mut mut_out = get_accounts(conn, span_frame)?
+        .into_iter()
+        .map(|((person, asset), account)| {
+            let (expired, spendable): (Vec<_>, Vec<_>) =
+                account.lots.iter().partition(|lot| lot.is_expired(today));
+
+            Ok(ExpiryBalance {
+                person,
+                asset,
+                spendable: Amount::checked_sum(spendable.iter().map(|lot| lot.coins))?
+                    .checked_sub(account.overdrawn)?,
+                expired: Amount::checked_sum(expired.iter().map(|lot| lot.coins))?,
+            })
+        })
+        .collect::<Result<Vec<_>, ExpiryError>>()?;
+
+    mut_out.sort_by_key(|balance| (balance.person.to_inner(), balance.asset.clone()));
+
+    Ok(mut_out)
+}
+
+/// Writes off the rest of every lot in the span frame that expired by `today`, all or nothing. Returns the lots that
+/// were written off.
+pub fn sweep_expired(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    today: NaiveDate,
+) -> Result<Vec<Lot>, ExpiryError> {
+    conn.transaction(|conn| {
+        let expired = get_lots(conn, span_frame)?
+            .into_iter()
+            .filter(|lot| lot.is_expired(today))
+            .collect::<Vec<_>>();
+
+        for lot in expired.iter() {
+            let expires_on = lot
+                .opt_expires_on
+                .expect("only lots with an expiry date expire");
+
+            double_entry::insert_coins_for_person(
+                conn,
+                span_frame,
+                &lot.person,
+                lot.coins.checked_neg()?,
+                &lot.asset,
+                &format!(
+                    "expire coins of event {} (expired on {expires_on})",
+                    lot.ev_id
+                ),
+                Some(lot.ev_id),
+            )?;
+        }
+
+        Ok(expired)
+    })
+}
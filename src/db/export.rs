@@ -0,0 +1,462 @@
+//! Export of the coin store to JSON Lines or CSV. See `docs/export.md` for the format of each kind of record.
+//!
+//! Every record carries `format_version` so that readers can tell which shape it has. Diff payloads are exported
+//! upcast to the current schema version of the store.
+
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::VariantArray;
+use thiserror::Error;
+
+use crate::autogen::schema::{EventAction, ObjState};
+use crate::db::exchange;
+use crate::db::models::coin_store;
+use crate::db::scale::{self, ScaleError};
+use crate::macros::diesel_hist_models::{ReadDiffError, SpanFrame};
+
+/// Version 2 added `opt_tx_id` to events. Version 3 added the asset to events, diffs, wallet rows and records. Version 4
+/// added `opt_rate` to events and records. Version 5 added `opt_scale` to events and writes coins as exact decimal text
+/// at the scale of the store. Version 6 added `opt_schedule` to events. Version 7 added `opt_hold` to events. Version 8
+/// added `opt_expires_on` to events. Version 9 added `opt_limit` to events. Version 10 added `opt_credit_line` and
+/// `opt_iou` to events. Version 11 added `opt_made_on` to events.
+pub const EXPORT_FORMAT_VERSION: i32 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    Events,
+    Diffs,
+    Wallet,
+    Records,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Jsonl,
+    Csv,
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Failed to read diffs: {0}")]
+    ReadDiffError(#[from] ReadDiffError),
+
+    #[error("Failed to read the scale: {0}")]
+    ScaleError(#[from] ScaleError),
+
+    #[error("Failed to write: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to write JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Failed to write CSV: {0}")]
+    CsvError(#[from] csv::Error),
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown {0} {1:?}")]
+pub struct ParseExportArgError(&'static str, String);
+
+impl FromStr for ExportKind {
+    type Err = ParseExportArgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "events" => Ok(Self::Events),
+            "diffs" => Ok(Self::Diffs),
+            "wallet" => Ok(Self::Wallet),
+            "records" => Ok(Self::Records),
+            _ => Err(ParseExportArgError("export kind", s.to_owned())),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ParseExportArgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => Err(ParseExportArgError("export format", s.to_owned())),
+        }
+    }
+}
+
+/// An event joined with the diff it refers to, if any
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedEvent {
+    pub format_version: i32,
+    pub id: i32,
+    pub ev_action: String,
+    pub span: i32,
+    pub frame: i32,
+    pub created_on_ts: f32,
+    pub ev_desc: String,
+    pub opt_target_ev_id: Option<i32>,
+    pub opt_ev_hash: Option<String>,
+    pub opt_tx_id: Option<i32>,
+    pub opt_rate: Option<String>,
+    pub opt_scale: Option<i32>,
+    pub opt_schedule: Option<String>,
+    pub opt_hold: Option<String>,
+    pub opt_expires_on: Option<String>,
+    pub opt_limit: Option<String>,
+    pub opt_credit_line: Option<String>,
+    pub opt_iou: Option<String>,
+    pub opt_made_on: Option<String>,
+    pub opt_diff_id: Option<i32>,
+    pub opt_obj_id: Option<i32>,
+    pub opt_person: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_opt_coins")]
+    pub opt_coins: Option<String>,
+    pub opt_asset: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedDiff {
+    pub format_version: i32,
+    pub id: i32,
+    pub obj_id: i32,
+    pub person: String,
+    pub coins: String,
+    pub asset: String,
+    pub schema_version: i32,
+}
+
+/// A row of the hist projection of a span frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedWalletRow {
+    pub format_version: i32,
+    pub span: i32,
+    pub frame: i32,
+    pub obj_id: i32,
+    pub obj_state: String,
+    pub person: String,
+    pub coins: String,
+    pub asset: String,
+}
+
+/// An event as grouped under a span frame, including the ones inherited from lower spans
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedRecord {
+    pub format_version: i32,
+    pub grp_span: i32,
+    pub grp_frame: i32,
+    pub ev_id: i32,
+    pub obj_id: i32,
+    pub ev_action: String,
+    pub span: i32,
+    pub frame: i32,
+    pub created_on_ts: f32,
+    pub person: String,
+    pub coins: String,
+    pub asset: String,
+    pub opt_rate: Option<String>,
+    pub ev_desc: String,
+}
+
+/// Coins as exports write them: exact decimal text since version 5, and whole numbers before it
+fn deserialize_opt_coins<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Coins {
+        Number(i64),
+        Text(String),
+    }
+
+    Ok(
+        Option::<Coins>::deserialize(deserializer)?.map(|coins| match coins {
+            Coins::Number(coins) => coins.to_string(),
+            Coins::Text(coins) => coins,
+        }),
+    )
+}
+
+/// The action in snake case, as stored in the database
+pub fn display_event_action(ev_action: &EventAction) -> String {
+    format!("{ev_action:?}")
+        .chars()
+        .enumerate()
+        .flat_map(|(index, c)| match (index, c.is_ascii_uppercase()) {
+            (0, _) | (_, false) => vec![c.to_ascii_lowercase()],
+            (_, true) => vec!['_', c.to_ascii_lowercase()],
+        })
+        .collect()
+}
+
+pub fn parse_event_action(s: &str) -> Option<EventAction> {
+    EventAction::VARIANTS
+        .iter()
+        .find(|ev_action| display_event_action(ev_action) == s)
+        .cloned()
+}
+
+pub fn display_obj_state(obj_state: &ObjState) -> String {
+    format!("{obj_state:?}").to_lowercase()
+}
+
+pub fn parse_obj_state(s: &str) -> Option<ObjState> {
+    ObjState::VARIANTS
+        .iter()
+        .find(|obj_state| display_obj_state(obj_state) == s)
+        .cloned()
+}
+
+/// Events in id order, optionally only the ones made in the given span frame
+pub fn get_exported_events(
+    conn: &mut SqliteConnection,
+    opt_span_frame: Option<&SpanFrame>,
+) -> Result<Vec<ExportedEvent>, ExportError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let mut mut_query = dsl::coin_store_events.order(dsl::id.asc()).into_boxed();
+
+    if let Some(span_frame) = opt_span_frame {
+        mut_query = mut_query.filter(
+            dsl::span
+                .eq(span_frame.span)
+                .and(dsl::frame.eq(span_frame.frame)),
+        );
+    }
+
+    let events = mut_query
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    let scale = scale::get_scale(conn)?;
+
+    let diffs = coin_store::get_diffs_upcasted(conn)?
+        .into_iter()
+        .map(|(diff, common)| (diff.id, (diff, common)))
+        .collect::<HashMap<_, _>>();
+
+    let out = events
+        .into_iter()
+        .map(|event| {
+            let opt_diff = event.opt_diff_id.and_then(|diff_id| diffs.get(&diff_id));
+
+            ExportedEvent {
+                format_version: EXPORT_FORMAT_VERSION,
+                id: event.id,
+                ev_action: display_event_action(&event.ev_action),
+                span: event.span,
+                frame: event.frame,
+                created_on_ts: event.created_on_ts,
+                ev_desc: event.ev_desc,
+                opt_target_ev_id: event.opt_target_ev_id,
+                opt_ev_hash: event.opt_ev_hash,
+                opt_tx_id: event.opt_tx_id,
+                opt_rate: event.opt_rate,
+                opt_scale: event.opt_scale,
+                opt_schedule: event.opt_schedule,
+                opt_hold: event.opt_hold,
+                opt_expires_on: event.opt_expires_on,
+                opt_limit: event.opt_limit,
+                opt_credit_line: event.opt_credit_line,
+                opt_iou: event.opt_iou,
+                opt_made_on: event.opt_made_on,
+                opt_diff_id: event.opt_diff_id,
+                opt_obj_id: opt_diff.map(|(diff, _)| diff.obj_id),
+                opt_person: opt_diff.map(|(_, common)| common.person.to_inner()),
+                opt_coins: opt_diff.map(|(_, common)| common.coins.to_scaled_string(scale)),
+                opt_asset: opt_diff.map(|(_, common)| common.asset.to_inner()),
+            }
+        })
+        .collect();
+
+    Ok(out)
+}
+
+/// Diffs in id order, optionally only the ones referred to by events made in the given span frame
+pub fn get_exported_diffs(
+    conn: &mut SqliteConnection,
+    opt_span_frame: Option<&SpanFrame>,
+) -> Result<Vec<ExportedDiff>, ExportError> {
+    let opt_diff_ids = match opt_span_frame {
+        Some(span_frame) => Some(
+            get_exported_events(conn, Some(span_frame))?
+                .into_iter()
+                .filter_map(|event| event.opt_diff_id)
+                .collect::<Vec<_>>(),
+        ),
+        None => None,
+    };
+
+    let scale = scale::get_scale(conn)?;
+
+    let out = coin_store::get_diffs_upcasted(conn)?
+        .into_iter()
+        .filter(|(diff, _)| {
+            opt_diff_ids
+                .as_ref()
+                .is_none_or(|diff_ids| diff_ids.contains(&diff.id))
+        })
+        .map(|(diff, common)| ExportedDiff {
+            format_version: EXPORT_FORMAT_VERSION,
+            id: diff.id,
+            obj_id: diff.obj_id,
+            person: common.person.to_inner(),
+            coins: common.coins.to_scaled_string(scale),
+            asset: common.asset.to_inner(),
+            schema_version: crate::db::upcast::COIN_STORE_SCHEMA_VERSION,
+        })
+        .collect();
+
+    Ok(out)
+}
+
+pub fn get_exported_wallet(
+    conn: &mut SqliteConnection,
+    opt_span_frame: Option<&SpanFrame>,
+) -> Result<Vec<ExportedWalletRow>, ExportError> {
+    use crate::autogen::schema::coin_store_hist::dsl;
+
+    let mut mut_query = dsl::coin_store_hist
+        .order((dsl::grp_id.asc(), dsl::obj_id.asc(), dsl::asset.asc()))
+        .into_boxed();
+
+    if let Some(span_frame) = opt_span_frame {
+        mut_query = mut_query.filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        );
+    }
+
+    let scale = scale::get_scale(conn)?;
+
+    let out = mut_query
+        .select(coin_store::Hist::as_select())
+        .get_results(conn)?
+        .into_iter()
+        .map(|row| ExportedWalletRow {
+            format_version: EXPORT_FORMAT_VERSION,
+            span: row.grp_span,
+            frame: row.grp_frame,
+            obj_id: row.obj_id,
+            obj_state: display_obj_state(&row.obj_state),
+            person: row.person.to_inner(),
+            coins: row.coins.to_scaled_string(scale),
+            asset: row.asset.to_inner(),
+        })
+        .collect();
+
+    Ok(out)
+}
+
+pub fn get_exported_records(
+    conn: &mut SqliteConnection,
+    opt_span_frame: Option<&SpanFrame>,
+) -> Result<Vec<ExportedRecord>, ExportError> {
+    use crate::autogen::schema::coin_store_events_grouped::dsl;
+
+    let mut mut_query = dsl::coin_store_events_grouped
+        .order((
+            dsl::created_on_ts.asc(),
+            dsl::ev_id.asc(),
+            dsl::grp_id.asc(),
+        ))
+        .into_boxed();
+
+    if let Some(span_frame) = opt_span_frame {
+        mut_query = mut_query.filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        );
+    }
+
+    let rates = exchange::get_rates_by_ev_id(conn)?;
+    let scale = scale::get_scale(conn)?;
+
+    let out = mut_query
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(conn)?
+        .into_iter()
+        .map(|row| ExportedRecord {
+            format_version: EXPORT_FORMAT_VERSION,
+            grp_span: row.grp_span,
+            grp_frame: row.grp_frame,
+            ev_id: row.ev_id,
+            obj_id: row.obj_id,
+            ev_action: display_event_action(&row.ev_action),
+            span: row.span,
+            frame: row.frame,
+            created_on_ts: row.created_on_ts,
+            person: row.person.to_inner(),
+            coins: row.coins.to_scaled_string(scale),
+            asset: row.asset.to_inner(),
+            opt_rate: rates.get(&row.ev_id).cloned(),
+            ev_desc: row.ev_desc,
+        })
+        .collect();
+
+    Ok(out)
+}
+
+/// Writes the records one per line for JSON Lines, or as rows under a header for CSV
+pub fn write_exported<T: Serialize>(
+    records: &[T],
+    format: ExportFormat,
+    writer: &mut impl Write,
+) -> Result<(), ExportError> {
+    match format {
+        ExportFormat::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut *writer, record)?;
+                writeln!(writer)?;
+            }
+        }
+        ExportFormat::Csv => {
+            let mut mut_csv_writer = csv::Writer::from_writer(&mut *writer);
+
+            for record in records {
+                mut_csv_writer.serialize(record)?;
+            }
+
+            mut_csv_writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Exports one kind of record, optionally only for the given span frame. Returns how many were written.
+pub fn export(
+    conn: &mut SqliteConnection,
+    kind: ExportKind,
+    format: ExportFormat,
+    opt_span_frame: Option<&SpanFrame>,
+    writer: &mut impl Write,
+) -> Result<usize, ExportError> {
+    match kind {
+        ExportKind::Events => {
+            let records = get_exported_events(conn, opt_span_frame)?;
+            write_exported(&records, format, writer)?;
+            Ok(records.len())
+        }
+        ExportKind::Diffs => {
+            let records = get_exported_diffs(conn, opt_span_frame)?;
+            write_exported(&records, format, writer)?;
+            Ok(records.len())
+        }
+        ExportKind::Wallet => {
+            let records = get_exported_wallet(conn, opt_span_frame)?;
+            write_exported(&records, format, writer)?;
+            Ok(records.len())
+        }
+        ExportKind::Records => {
+            let records = get_exported_records(conn, opt_span_frame)?;
+            write_exported(&records, format, writer)?;
+            Ok(records.len())
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Filter expressions over grouped coin store events, used to select events to toggle.
+//!
+//! An expression is a whitespace separated list of `key=value` clauses that must all hold:
+//!
+//! - `person=<name>`
+//! - `asset=<code>`
+//! - `coins=<n>` or `coins=<min>..<max>`, where either bound may be left out, with the decimals of the store's scale
+//! - `date=<from>..<to>` with `YYYY-MM-DD` dates (UTC, both inclusive), where either bound may be left out
+//! - `frame=<span>/<frame>` for the span frame the event was made in
+//! - `action=<insert|update|delete>`, the actions of the events that change an object
+//! - `desc=<regex>` matched against the event description
+
+use std::str::FromStr;
+
+use chrono::{Days, NaiveDate};
+use regex::Regex;
+use strum::VariantArray;
+use thiserror::Error;
+
+use crate::autogen::schema::{EventAction, ObjState};
+use crate::db::export;
+use crate::db::models::coin_store::EventGrouped;
+use crate::db::models::{Amount, Asset, Scale};
+
+#[derive(Debug, Clone)]
+pub enum EventFilterClause {
+    Person(String),
+    Asset(Asset),
+    CoinsRange(Option<Amount>, Option<Amount>),
+    DateRange(Option<NaiveDate>, Option<NaiveDate>),
+    SpanFrame(i32, i32),
+    Action(EventAction),
+    DescRegex(Regex),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub clauses: Vec<EventFilterClause>,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseEventFilterError {
+    #[error("Expected key=value but got {0:?}")]
+    MissingEquals(String),
+
+    #[error("Unknown filter key {0:?}. Expected person, asset, coins, date, frame, action or desc")]
+    UnknownKey(String),
+
+    #[error("Invalid value {1:?} for {0}")]
+    InvalidValue(&'static str, String),
+
+    #[error("Invalid regex: {0}")]
+    RegexError(#[from] regex::Error),
+
+    #[error("Empty filter. At least one clause is needed")]
+    Empty,
+}
+
+fn parse_range<T: Copy>(
+    key: &'static str,
+    value: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<(Option<T>, Option<T>), ParseEventFilterError> {
+    let parse_bound = |bound: &str| -> Result<Option<T>, ParseEventFilterError> {
+        if bound.is_empty() {
+            return Ok(None);
+        }
+
+        parse(bound)
+            .map(Some)
+            .ok_or_else(|| ParseEventFilterError::InvalidValue(key, value.to_owned()))
+    };
+
+    match value.split_once("..") {
+        Some((lo, hi)) => Ok((parse_bound(lo)?, parse_bound(hi)?)),
+        None => match parse_bound(value)? {
+            Some(exact) => Ok((Some(exact), Some(exact))),
+            None => Err(ParseEventFilterError::InvalidValue(key, value.to_owned())),
+        },
+    }
+}
+
+impl FromStr for EventFilterClause {
+    type Err = ParseEventFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_scaled(s, Scale::default())
+    }
+}
+
+impl EventFilterClause {
+    /// Parses the clause, reading coins with the decimals of the given scale
+    pub fn parse_scaled(s: &str, scale: Scale) -> Result<Self, ParseEventFilterError> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| ParseEventFilterError::MissingEquals(s.to_owned()))?;
+
+        match key {
+            "person" => Ok(Self::Person(value.to_owned())),
+            "asset" => value
+                .parse()
+                .map(Self::Asset)
+                .map_err(|_| ParseEventFilterError::InvalidValue("asset", value.to_owned())),
+            "coins" => {
+                let (lo, hi) = parse_range("coins", value, |bound| {
+                    Amount::parse_scaled(bound, scale).ok()
+                })?;
+
+                Ok(Self::CoinsRange(lo, hi))
+            }
+            "date" => {
+                let (lo, hi) = parse_range("date", value, |bound| bound.parse().ok())?;
+
+                Ok(Self::DateRange(lo, hi))
+            }
+            "frame" => {
+                let invalid = || ParseEventFilterError::InvalidValue("frame", value.to_owned());
+
+                let (span, frame) = value.split_once('/').ok_or_else(invalid)?;
+
+                Ok(Self::SpanFrame(
+                    span.parse().map_err(|_| invalid())?,
+                    frame.parse().map_err(|_| invalid())?,
+                ))
+            }
+            "action" => ObjState::VARIANTS
+                .iter()
+                .find(|obj_state| export::display_obj_state(obj_state) == value.to_lowercase())
+                .map(|obj_state| Self::Action(obj_state.clone().into()))
+                .ok_or_else(|| ParseEventFilterError::InvalidValue("action", value.to_owned())),
+            "desc" => Ok(Self::DescRegex(Regex::new(value)?)),
+            _ => Err(ParseEventFilterError::UnknownKey(key.to_owned())),
+        }
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = ParseEventFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_scaled(s, Scale::default())
+    }
+}
+
+impl EventFilter {
+    /// Parses the expression, reading coins with the decimals of the given scale
+    pub fn parse_scaled(s: &str, scale: Scale) -> Result<Self, ParseEventFilterError> {
+        let clauses = s
+            .split_whitespace()
+            .map(|clause| EventFilterClause::parse_scaled(clause, scale))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if clauses.is_empty() {
+            return Err(ParseEventFilterError::Empty);
+        }
+
+        Ok(EventFilter { clauses })
+    }
+}
+
+fn date_to_timestamp_millis(date: NaiveDate) -> i64 {
+    date.and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc()
+        .timestamp_millis()
+}
+
+impl EventFilterClause {
+    pub fn matches(&self, event: &EventGrouped) -> bool {
+        match self {
+            Self::Person(person) => &event.person.to_inner() == person,
+            Self::Asset(asset) => &event.asset == asset,
+            Self::CoinsRange(lo, hi) => {
+                lo.is_none_or(|lo| event.coins >= lo) && hi.is_none_or(|hi| event.coins <= hi)
+            }
+            Self::DateRange(lo, hi) => {
+                let timestamp_millis = event.created_on_ts as i64;
+
+                let after_lo = lo.is_none_or(|lo| timestamp_millis >= date_to_timestamp_millis(lo));
+
+                let before_hi = hi.is_none_or(|hi| {
+                    let day_after_hi = hi.checked_add_days(Days::new(1)).unwrap_or(hi);
+
+                    timestamp_millis < date_to_timestamp_millis(day_after_hi)
+                });
+
+                after_lo && before_hi
+            }
+            Self::SpanFrame(span, frame) => event.span == *span && event.frame == *frame,
+            Self::Action(action) => *action == event.ev_action,
+            Self::DescRegex(regex) => regex.is_match(&event.ev_desc),
+        }
+    }
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &EventGrouped) -> bool {
+        self.clauses.iter().all(|clause| clause.matches(event))
+    }
+}
@@ -0,0 +1,181 @@
+//! Projected balances from the schedules that are not written yet.
+//!
+//! A forecast runs the schedules of the span frame up to each due date in turn, as `coins schedule run` would, and
+//! reads each user's balance back from `coin_store_hist` after every due date. It does so in a transaction that is
+//! always rolled back, so the projections, double-entry mode and the checks of what users may spend included, are
+//! exactly the ones of the store, and nothing is persisted.
+
+use std::convert::Infallible;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::db::double_entry::{self, DoubleEntryError};
+use crate::db::models::{Amount, Asset, Person};
+use crate::db::schedule::{self, RefusedOccurrence, ScheduleError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum ForecastError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Double-entry Error: {0}")]
+    DoubleEntryError(#[from] DoubleEntryError),
+
+    #[error("Schedule Error: {0}")]
+    ScheduleError(#[from] ScheduleError),
+}
+
+/// A user's balance in an asset after the occurrences due on a date
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectedBalance {
+    pub date: NaiveDate,
+    pub person: Person,
+    pub asset: Asset,
+    pub coins: Amount,
+}
+
+#[derive(Debug)]
+pub struct Forecast {
+    pub until: NaiveDate,
+    /// Every user's balance in every asset before the forecast, ordered by name and asset
+    pub balances: Vec<(Person, Asset, Amount)>,
+    /// The balances that the occurrences change, by due date and then name and asset
+    pub projected: Vec<ProjectedBalance>,
+    /// The occurrences that the run on their due date would skip, because the user could not spend the coins
+    pub refused: Vec<RefusedOccurrence>,
+}
+
+impl Forecast {
+    /// The first due date that each user's balance in an asset would be below 0 on, for those it ever would be
+    pub fn get_first_negative_dates(&self) -> Vec<(Person, Asset, NaiveDate)> {
+        let mut mut_out: Vec<(Person, Asset, NaiveDate)> = vec![];
+
+        for projected in self.projected.iter().filter(|p| p.coins.is_negative()) {
+            let seen = mut_out
+                .iter()
+                .any(|(person, asset, _)| *person == projected.person && *asset == projected.asset);
+
+            if !seen {
+                mut_out.push((
+                    projected.person.clone(),
+                    projected.asset.clone(),
+                    projected.date,
+                ));
+            }
+        }
+
+        mut_out
+    }
+}
+
+/// Rolls the transaction back either way, keeping the forecast apart from actual failures
+enum ForecastOutcome {
+    Failed(ForecastError),
+    Simulated(Forecast),
+}
+
+impl From<ForecastError> for ForecastOutcome {
+    fn from(e: ForecastError) -> Self {
+        ForecastOutcome::Failed(e)
+    }
+}
+
+impl From<diesel::result::Error> for ForecastOutcome {
+    fn from(e: diesel::result::Error) -> Self {
+        ForecastOutcome::Failed(e.into())
+    }
+}
+
+impl From<ScheduleError> for ForecastOutcome {
+    fn from(e: ScheduleError) -> Self {
+        ForecastOutcome::Failed(e.into())
+    }
+}
+
+/// The balances of the users in the span frame, leaving out system accounts
+fn get_user_balances(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<(Person, Asset, Amount)>, ForecastError> {
+    Ok(double_entry::get_account_balances(conn, span_frame)?
+        .into_iter()
+        .filter(|(person, _, _)| !person.is_system_account())
+        .collect())
+}
+
+/// Projects the balances of the span frame up to `until` from the occurrences of its schedules that are not written
+/// yet, including any that are overdue. Expenses are only projected if the user would have the coins available at
+/// `now`. Nothing is persisted.
+pub fn forecast(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    until: NaiveDate,
+    now: DateTime<Utc>,
+) -> Result<Forecast, ForecastError> {
+    let res = conn.transaction(|conn| {
+        let balances = get_user_balances(conn, span_frame)?;
+
+        let mut mut_due_dates = vec![];
+
+        for info in schedule::get_running_schedules(conn, span_frame)? {
+            let mut mut_n = info.occurrence_count;
+
+            while let Some(due_date) = info.schedule.nth_due_date(mut_n)
+                && due_date <= until
+            {
+                mut_due_dates.push(due_date);
+                mut_n += 1;
+            }
+        }
+
+        mut_due_dates.sort();
+        mut_due_dates.dedup();
+
+        let mut mut_projected = vec![];
+        let mut mut_refused = vec![];
+
+        for due_date in mut_due_dates {
+            let run = schedule::run_schedules(conn, span_frame, due_date, now)?;
+
+            let changed = run
+                .written
+                .iter()
+                .map(|occurrence| (occurrence.person.clone(), occurrence.asset.clone()))
+                .collect::<Vec<_>>();
+
+            // An occurrence that is refused is tried again on every later due date, but only reported once
+            mut_refused.extend(
+                run.refused
+                    .into_iter()
+                    .filter(|refused| refused.occurrence.due_date == due_date),
+            );
+
+            for (person, asset, coins) in get_user_balances(conn, span_frame)? {
+                if changed.contains(&(person.clone(), asset.clone())) {
+                    mut_projected.push(ProjectedBalance {
+                        date: due_date,
+                        person,
+                        asset,
+                        coins,
+                    });
+                }
+            }
+        }
+
+        Err::<Infallible, _>(ForecastOutcome::Simulated(Forecast {
+            until,
+            balances,
+            projected: mut_projected,
+            refused: mut_refused,
+        }))
+    });
+
+    match res {
+        Ok(never) => match never {},
+        Err(ForecastOutcome::Simulated(forecast)) => Ok(forecast),
+        Err(ForecastOutcome::Failed(e)) => Err(e),
+    }
+}
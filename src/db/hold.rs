@@ -0,0 +1,365 @@
+//! Coins reserved for a later charge, set aside by `hold` events.
+//!
+//! A hold, such as 30 coin of alice's until 2026-11-01T12:00:00Z, keeps the coins in the user's balance, but they are no
+//! longer available to spend. It is settled either by a capture, which is the expense itself pointing at the hold, or
+//! by a `release` event pointing at it. A hold that is not settled before it expires stops counting on its own, so
+//! nothing has to be written for it. Holds, captures and releases are seen in a span frame like its records are, see
+//! `SpanFrameInfo::sees`.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::EventAction;
+use crate::db::credit::{self, CreditError};
+use crate::db::double_entry::{self, DoubleEntryError};
+use crate::db::expiry::{self, ExpiryError};
+use crate::db::limit::{self, LimitError};
+use crate::db::models::{
+    Amount, AmountError, Asset, Hold, Payload, PayloadError, Person, coin_store,
+};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum HoldError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Double-entry Error: {0}")]
+    DoubleEntryError(#[from] DoubleEntryError),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Expiry Error: {0}")]
+    ExpiryError(#[from] ExpiryError),
+
+    #[error("Credit Error: {0}")]
+    CreditError(#[from] CreditError),
+
+    #[error("Limit Error: {0}")]
+    LimitError(#[from] LimitError),
+
+    #[error("Payload Error: {0}")]
+    InvalidHold(#[from] PayloadError),
+
+    #[error("Span frame (span: {}, frame: {}) does not exist", .0.span, .0.frame)]
+    UnknownSpanFrame(SpanFrame),
+
+    #[error("Coins to hold or capture must be positive")]
+    NotPositive,
+
+    #[error("Event {0} is not a hold seen in this span frame")]
+    UnknownHold(i32),
+
+    #[error("Hold {0} is already captured or released")]
+    AlreadySettled(i32),
+
+    #[error("Hold {ev_id} expired on {expires_on}")]
+    Expired {
+        ev_id: i32,
+        expires_on: DateTime<Utc>,
+    },
+
+    #[error("Cannot capture {coins} from hold {ev_id}, which only holds {held}")]
+    ExceedsHold {
+        ev_id: i32,
+        held: Amount,
+        coins: Amount,
+    },
+
+    #[error("{person} only has {available} {asset} available, which is less than {coins}")]
+    InsufficientFunds {
+        person: String,
+        asset: String,
+        available: Amount,
+        coins: Amount,
+    },
+}
+
+/// A hold with what became of it so far
+#[derive(Debug)]
+pub struct HoldInfo {
+    pub event: coin_store::Event,
+    pub hold: Hold,
+    /// The event id of the capture or release that settled the hold, if any
+    pub opt_settled_by: Option<i32>,
+}
+
+impl HoldInfo {
+    /// Whether the hold still reserves its coins at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.opt_settled_by.is_none() && now < self.hold.expires_on
+    }
+}
+
+/// A user's balance in an asset, how much of it is held or expired, and how far below 0 it may go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvailableBalance {
+    pub person: Person,
+    pub asset: Asset,
+    pub balance: Amount,
+    /// Coins the user may spend beyond their balance
+    pub credit_line: Amount,
+    pub held: Amount,
+    /// Coins that expired but are not swept yet
+    pub expired: Amount,
+    pub available: Amount,
+}
+
+/// Every hold the span frame sees in id order, settled and expired ones included
+pub fn get_holds(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<HoldInfo>, HoldError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let info = coin_store::get_span_frame_info(conn, span_frame)?
+        .ok_or_else(|| HoldError::UnknownSpanFrame(span_frame.clone()))?;
+
+    let is_seen =
+        |event: &coin_store::Event| info.sees(event.span, event.frame, event.created_on_ts);
+
+    let events: Vec<coin_store::Event> = dsl::coin_store_events
+        .filter(dsl::ev_action.eq(EventAction::Hold))
+        .order(dsl::id.asc())
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    let events = events.into_iter().filter(is_seen).collect::<Vec<_>>();
+
+    let hold_ev_ids = events.iter().map(|event| event.id).collect::<Vec<_>>();
+
+    // Events that point at a hold are the capture or the release that settled it
+    let settling: Vec<coin_store::Event> = dsl::coin_store_events
+        .filter(dsl::opt_target_ev_id.eq_any(&hold_ev_ids))
+        .order(dsl::id.asc())
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    let settling = settling.into_iter().filter(is_seen).collect::<Vec<_>>();
+
+    events
+        .into_iter()
+        .map(|event| {
+            let hold = Hold::from_payload(event.opt_hold.as_deref().unwrap_or_default())?;
+
+            Ok(HoldInfo {
+                opt_settled_by: settling
+                    .iter()
+                    .find(|other| other.opt_target_ev_id == Some(event.id))
+                    .map(|other| other.id),
+                hold,
+                event,
+            })
+        })
+        .collect()
+}
+
+/// The balance of every user in every asset in the span frame, with the coins of the holds that are active at `now`
+/// and the coins that expired by then set apart and the credit line added, ordered by name and asset
+pub fn get_available_balances(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    now: DateTime<Utc>,
+) -> Result<Vec<AvailableBalance>, HoldError> {
+    let holds = get_holds(conn, span_frame)?;
+    let expiry_balances = expiry::get_expiry_balances(conn, span_frame, now.date_naive())?;
+    let credit_lines = credit::get_credit_lines(conn, span_frame)?;
+
+    double_entry::get_account_balances(conn, span_frame)?
+        .into_iter()
+        .filter(|(person, _, _)| !person.is_system_account())
+        .map(|(person, asset, balance)| {
+            let held = Amount::checked_sum(
+                holds
+                    .iter()
+                    .filter(|info| info.is_active(now))
+                    .filter(|info| info.hold.person == person && info.hold.asset == asset)
+                    .map(|info| info.hold.coins),
+            )?;
+
+            let expired = expiry_balances
+                .iter()
+                .find(|expiry_balance| {
+                    expiry_balance.person == person && expiry_balance.asset == asset
+                })
+                .map_or(Amount::ZERO, |expiry_balance| expiry_balance.expired);
+
+            let credit_line = credit_lines
+                .iter()
+                .find(|credit_line| credit_line.person == person && credit_line.asset == asset)
+                .map_or(Amount::ZERO, |credit_line| credit_line.coins);
+
+            Ok(AvailableBalance {
+                available: balance
+                    .checked_add(credit_line)?
+                    .checked_sub(held)?
+                    .checked_sub(expired)?,
+                person,
+                asset,
+                balance,
+                credit_line,
+                held,
+                expired,
+            })
+        })
+        .collect()
+}
+
+/// Refuses to spend more than the user has available at `now`, which is their balance and credit line less their
+/// active holds and expired coins
+pub fn check_available(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    asset: &Asset,
+    coins: Amount,
+    now: DateTime<Utc>,
+) -> Result<(), HoldError> {
+    let opt_available = get_available_balances(conn, span_frame, now)?
+        .into_iter()
+        .find(|balance| balance.person == *person && balance.asset == *asset)
+        .map(|balance| balance.available);
+
+    // A user may spend on credit in an asset they never had
+    let available = match opt_available {
+        Some(available) => available,
+        None => credit::get_credit_lines(conn, span_frame)?
+            .into_iter()
+            .find(|credit_line| credit_line.person == *person && credit_line.asset == *asset)
+            .map_or(Amount::ZERO, |credit_line| credit_line.coins),
+    };
+
+    if available < coins {
+        return Err(HoldError::InsufficientFunds {
+            person: person.to_inner(),
+            asset: asset.to_inner(),
+            available,
+            coins,
+        });
+    }
+
+    Ok(())
+}
+
+/// Holds the coins of the user until the hold expires, if they have them available at `now`
+pub fn place_hold(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    hold: &Hold,
+    now: DateTime<Utc>,
+    ev_desc: &str,
+) -> Result<coin_store::Event, HoldError> {
+    if !hold.coins.is_positive() {
+        return Err(HoldError::NotPositive);
+    }
+
+    conn.transaction(|conn| {
+        check_available(conn, span_frame, &hold.person, &hold.asset, hold.coins, now)?;
+
+        let new_event = coin_store::NewEvent {
+            opt_hold: Some(hold.to_payload()?),
+            ..coin_store::NewEvent::new(EventAction::Hold, span_frame, ev_desc)
+        };
+
+        Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+    })
+}
+
+/// The hold if it is still active at `now`, or why it is not
+fn get_active_hold(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    hold_ev_id: i32,
+    now: DateTime<Utc>,
+) -> Result<HoldInfo, HoldError> {
+    let info = get_holds(conn, span_frame)?
+        .into_iter()
+        .find(|info| info.event.id == hold_ev_id)
+        .ok_or(HoldError::UnknownHold(hold_ev_id))?;
+
+    if info.opt_settled_by.is_some() {
+        return Err(HoldError::AlreadySettled(hold_ev_id));
+    }
+
+    if !info.is_active(now) {
+        return Err(HoldError::Expired {
+            ev_id: hold_ev_id,
+            expires_on: info.hold.expires_on,
+        });
+    }
+
+    Ok(info)
+}
+
+/// Charges the user the held coins, or only part of them, and settles the hold, if the limits that apply to them allow
+/// it at `now`. The rest is available again. Returns the coins that were charged.
+pub fn capture_hold(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    hold_ev_id: i32,
+    opt_coins: Option<Amount>,
+    now: DateTime<Utc>,
+    ev_desc: &str,
+) -> Result<Amount, HoldError> {
+    conn.transaction(|conn| {
+        let info = get_active_hold(conn, span_frame, hold_ev_id, now)?;
+
+        let coins = opt_coins.unwrap_or(info.hold.coins);
+
+        if !coins.is_positive() {
+            return Err(HoldError::NotPositive);
+        }
+
+        if coins > info.hold.coins {
+            return Err(HoldError::ExceedsHold {
+                ev_id: hold_ev_id,
+                held: info.hold.coins,
+                coins,
+            });
+        }
+
+        // The coins were set aside when the hold was placed, but they are only spent now
+        limit::check_limits(
+            conn,
+            span_frame,
+            &info.hold.person,
+            &info.hold.asset,
+            coins,
+            now,
+        )?;
+
+        double_entry::insert_coins_for_person(
+            conn,
+            span_frame,
+            &info.hold.person,
+            coins.checked_neg()?,
+            &info.hold.asset,
+            ev_desc,
+            Some(hold_ev_id),
+        )?;
+
+        Ok(coins)
+    })
+}
+
+/// Lets go of the hold without charging anything
+pub fn release_hold(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    hold_ev_id: i32,
+    now: DateTime<Utc>,
+    ev_desc: &str,
+) -> Result<coin_store::Event, HoldError> {
+    conn.transaction(|conn| {
+        get_active_hold(conn, span_frame, hold_ev_id, now)?;
+
+        let new_event = coin_store::NewEvent {
+            opt_target_ev_id: Some(hold_ev_id),
+            ..coin_store::NewEvent::new(EventAction::Release, span_frame, ev_desc)
+        };
+
+        Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+    })
+}
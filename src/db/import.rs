@@ -0,0 +1,701 @@
+//! Import of an event stream written by `coins export events` in JSON Lines.
+//!
+//! Every record is validated before anything is written, and then replayed inside a single transaction through
+//! `insert_recorded_event`, the same path the demo's writes end up in. Timestamps are kept, since they decide which
+//! events a span frame inherits. Imported events get new ids and hashes in the target store, while their order and
+//! the events they point at are kept.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+};
+
+use diesel::prelude::*;
+use strum::VariantArray;
+use thiserror::Error;
+
+use crate::autogen::schema::EventAction;
+use crate::db::double_entry;
+use crate::db::export::{self, EXPORT_FORMAT_VERSION, ExportedEvent};
+use crate::db::models::{
+    Amount, AmountError, Asset, AssetFromStrError, CreditLine, Hold, Iou, Limit, Payload,
+    PayloadError, Person, PersonFromStrError, Rate, RateFromStrError, Scale, ScaleFromStrError,
+    Schedule, coin_store,
+};
+use crate::db::scale::{self, ScaleError};
+use crate::macros::diesel_hist_models::{LabelSpanFrameError, SpanFrame, SpanFrameLabelKind};
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Failed to read: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Line {line} is not an exported event: {source}")]
+    JsonError {
+        line: usize,
+        source: serde_json::Error,
+    },
+
+    #[error(
+        "Event {ev_id} has format version {format_version}, but only up to {EXPORT_FORMAT_VERSION} is supported"
+    )]
+    UnsupportedFormatVersion { ev_id: i32, format_version: i32 },
+
+    #[error("Event {ev_id} has unknown action {ev_action:?}")]
+    UnknownEventAction { ev_id: i32, ev_action: String },
+
+    #[error("Event {ev_id} has an invalid person: {source}")]
+    InvalidPerson {
+        ev_id: i32,
+        source: PersonFromStrError,
+    },
+
+    #[error("Event {ev_id} has an invalid asset: {source}")]
+    InvalidAsset {
+        ev_id: i32,
+        source: AssetFromStrError,
+    },
+
+    #[error("Event {ev_id} has an invalid rate: {source}")]
+    InvalidRate {
+        ev_id: i32,
+        source: RateFromStrError,
+    },
+
+    #[error("Event {ev_id} sets a rate, but does not say which")]
+    MissingRate { ev_id: i32 },
+
+    #[error("Event {ev_id} has an invalid scale: {source}")]
+    InvalidScale {
+        ev_id: i32,
+        source: ScaleFromStrError,
+    },
+
+    #[error("Event {ev_id} sets the scale, but does not say which")]
+    MissingScale { ev_id: i32 },
+
+    #[error("Event {ev_id} has an invalid schedule: {source}")]
+    InvalidSchedule { ev_id: i32, source: PayloadError },
+
+    #[error("Event {ev_id} sets up a schedule, but does not say which")]
+    MissingSchedule { ev_id: i32 },
+
+    #[error("Event {ev_id} has an invalid hold: {source}")]
+    InvalidHold { ev_id: i32, source: PayloadError },
+
+    #[error("Event {ev_id} places a hold, but does not say which")]
+    MissingHold { ev_id: i32 },
+
+    #[error("Event {ev_id} has an invalid expiry date {expires_on:?}")]
+    InvalidExpiry { ev_id: i32, expires_on: String },
+
+    #[error("Event {ev_id} has an invalid date {made_on:?} it was made on")]
+    InvalidMadeOn { ev_id: i32, made_on: String },
+
+    #[error("Event {ev_id} has an invalid limit: {source}")]
+    InvalidLimit { ev_id: i32, source: PayloadError },
+
+    #[error("Event {ev_id} sets a limit, but does not say which")]
+    MissingLimit { ev_id: i32 },
+
+    #[error("Event {ev_id} has an invalid credit line: {source}")]
+    InvalidCreditLine { ev_id: i32, source: PayloadError },
+
+    #[error("Event {ev_id} sets a credit line, but does not say which")]
+    MissingCreditLine { ev_id: i32 },
+
+    #[error("Event {ev_id} has an invalid IOU: {source}")]
+    InvalidIou { ev_id: i32, source: PayloadError },
+
+    #[error("Event {ev_id} records an IOU, but does not say which")]
+    MissingIou { ev_id: i32 },
+
+    #[error("Failed to read the scale of the store: {0}")]
+    ScaleError(#[from] ScaleError),
+
+    #[error(
+        "Event {ev_id} must have an object id, person and coins if and only if it is an insert, update or delete"
+    )]
+    InvalidDiff { ev_id: i32 },
+
+    #[error("Event {ev_id} is in span={span} frame={frame}, which is not opened before it")]
+    MissingSpanFrame { ev_id: i32, span: i32, frame: i32 },
+
+    #[error("Event {ev_id} points at event {target_ev_id}, which is not imported before it")]
+    UnknownTarget { ev_id: i32, target_ev_id: i32 },
+
+    #[error("Event {ev_id} toggles or cancels an event, but does not say which")]
+    MissingTarget { ev_id: i32 },
+
+    #[error("Event {ev_id} moves coins outside of a transaction in double-entry mode")]
+    UnbalancedEvent { ev_id: i32 },
+
+    #[error(
+        "Transaction {tx_id} is unbalanced: its postings of {asset} add up to {total} instead of 0"
+    )]
+    UnbalancedTransaction {
+        tx_id: i32,
+        asset: String,
+        total: Amount,
+    },
+
+    #[error("Event {ev_id}: {source}")]
+    AmountError { ev_id: i32, source: AmountError },
+
+    #[error("Event {ev_id} is out of order: {reason}")]
+    OutOfOrder { ev_id: i32, reason: String },
+
+    #[error("Failed to label span frame: {0}")]
+    LabelSpanFrameError(#[from] LabelSpanFrameError),
+}
+
+/// What an import wrote, or would write for a dry run
+#[derive(Debug, Clone)]
+pub struct ImportSummary {
+    pub event_count: usize,
+    /// Counted per action, in the order of `EventAction::VARIANTS`. Actions without events are left out.
+    pub action_counts: Vec<(String, usize)>,
+    pub opened_span_frames: Vec<SpanFrame>,
+    /// Span frames that were already in the target store. Their open events are skipped and the other events are
+    /// appended to them.
+    pub existing_span_frames: Vec<SpanFrame>,
+}
+
+/// Reads exported events, one per non-empty line
+pub fn read_exported_events(reader: impl BufRead) -> Result<Vec<ExportedEvent>, ImportError> {
+    let mut mut_out = vec![];
+
+    for (index, res_line) in reader.lines().enumerate() {
+        let line = res_line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event = serde_json::from_str(&line).map_err(|source| ImportError::JsonError {
+            line: index + 1,
+            source,
+        })?;
+
+        mut_out.push(event);
+    }
+
+    Ok(mut_out)
+}
+
+/// An exported event with its action and diff parsed
+struct ValidatedEvent<'a> {
+    exported: &'a ExportedEvent,
+    ev_action: EventAction,
+    opt_obj: Option<(i32, Person, Amount, Asset)>,
+}
+
+/// Checks every record on its own and then the stream as a whole against the target store: span frames are opened
+/// before they are used, closed before they are reopened, objects are inserted before they are updated or deleted,
+/// events only point at events imported before them, and transactions balance. In double-entry mode, coins can only
+/// move in transactions. Coins are read at the scale in effect, which can only be set before any coins move.
+fn validate<'a>(
+    conn: &mut SqliteConnection,
+    events: &'a [ExportedEvent],
+) -> Result<Vec<ValidatedEvent<'a>>, ImportError> {
+    use crate::autogen::schema::coin_store_diffs::dsl;
+
+    // Whether each known span frame is closed
+    let mut mut_closed = coin_store::get_span_frame_infos(conn)?
+        .into_iter()
+        .map(|info| ((info.span_frame.span, info.span_frame.frame), info.closed))
+        .collect::<HashMap<_, _>>();
+
+    let mut mut_obj_ids = dsl::coin_store_diffs
+        .select(dsl::obj_id)
+        .distinct()
+        .get_results::<i32>(conn)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let target_span_frames = mut_closed.keys().copied().collect::<HashSet<_>>();
+
+    let mut mut_double_entry = double_entry::is_double_entry(conn)?;
+
+    let mut mut_scale = scale::get_scale(conn)?;
+
+    let mut mut_coins_moved = diesel::select(diesel::dsl::exists(
+        dsl::coin_store_diffs.filter(dsl::coins.ne(0)),
+    ))
+    .get_result::<bool>(conn)?;
+
+    // Totals per transaction and asset
+    let mut mut_tx_totals: Vec<((i32, Asset), Amount)> = vec![];
+
+    let mut mut_ev_ids = HashSet::new();
+    let mut mut_opt_prev_ev_id = None;
+    let mut mut_out = vec![];
+
+    for exported in events {
+        let ev_id = exported.id;
+
+        if !(1..=EXPORT_FORMAT_VERSION).contains(&exported.format_version) {
+            return Err(ImportError::UnsupportedFormatVersion {
+                ev_id,
+                format_version: exported.format_version,
+            });
+        }
+
+        let ev_action = export::parse_event_action(&exported.ev_action).ok_or_else(|| {
+            ImportError::UnknownEventAction {
+                ev_id,
+                ev_action: exported.ev_action.clone(),
+            }
+        })?;
+
+        let is_obj_event = matches!(
+            ev_action,
+            EventAction::Insert | EventAction::Update | EventAction::Delete
+        );
+
+        let opt_obj = match (
+            is_obj_event,
+            exported.opt_obj_id,
+            exported.opt_person.as_ref(),
+            exported.opt_coins.as_ref(),
+        ) {
+            (true, Some(obj_id), Some(person), Some(coins)) => {
+                let person = Person::parse_account(person)
+                    .map_err(|source| ImportError::InvalidPerson { ev_id, source })?;
+
+                // Exports from before assets existed have no asset, so their diffs are of the default one
+                let asset = match exported.opt_asset.as_ref() {
+                    Some(asset) => asset
+                        .parse()
+                        .map_err(|source| ImportError::InvalidAsset { ev_id, source })?,
+                    None => Asset::default(),
+                };
+
+                // Exports before version 5 have whole coins, which read the same at any scale
+                let coins = Amount::parse_scaled(coins, mut_scale)
+                    .map_err(|source| ImportError::AmountError { ev_id, source })?;
+
+                Some((obj_id, person, coins, asset))
+            }
+            (false, None, None, None) => None,
+            _ => return Err(ImportError::InvalidDiff { ev_id }),
+        };
+
+        if let Some(rate) = exported.opt_rate.as_ref() {
+            rate.parse::<Rate>()
+                .map_err(|source| ImportError::InvalidRate { ev_id, source })?;
+        } else if matches!(ev_action, EventAction::Rate) {
+            return Err(ImportError::MissingRate { ev_id });
+        }
+
+        let opt_scale = exported
+            .opt_scale
+            .map(Scale::try_from)
+            .transpose()
+            .map_err(|source| ImportError::InvalidScale { ev_id, source })?;
+
+        if matches!(ev_action, EventAction::Scale) && opt_scale.is_none() {
+            return Err(ImportError::MissingScale { ev_id });
+        }
+
+        if let Some(schedule) = exported.opt_schedule.as_ref() {
+            Schedule::from_payload(schedule)
+                .map_err(|source| ImportError::InvalidSchedule { ev_id, source })?;
+        } else if matches!(ev_action, EventAction::Schedule) {
+            return Err(ImportError::MissingSchedule { ev_id });
+        }
+
+        if let Some(hold) = exported.opt_hold.as_ref() {
+            Hold::from_payload(hold)
+                .map_err(|source| ImportError::InvalidHold { ev_id, source })?;
+        } else if matches!(ev_action, EventAction::Hold) {
+            return Err(ImportError::MissingHold { ev_id });
+        }
+
+        if let Some(expires_on) = exported.opt_expires_on.as_ref()
+            && expires_on.parse::<chrono::NaiveDate>().is_err()
+        {
+            return Err(ImportError::InvalidExpiry {
+                ev_id,
+                expires_on: expires_on.clone(),
+            });
+        }
+
+        if let Some(made_on) = exported.opt_made_on.as_ref()
+            && made_on.parse::<chrono::NaiveDate>().is_err()
+        {
+            return Err(ImportError::InvalidMadeOn {
+                ev_id,
+                made_on: made_on.clone(),
+            });
+        }
+
+        if let Some(limit) = exported.opt_limit.as_ref() {
+            Limit::from_payload(limit)
+                .map_err(|source| ImportError::InvalidLimit { ev_id, source })?;
+        } else if matches!(ev_action, EventAction::Limit) {
+            return Err(ImportError::MissingLimit { ev_id });
+        }
+
+        if let Some(credit_line) = exported.opt_credit_line.as_ref() {
+            CreditLine::from_payload(credit_line)
+                .map_err(|source| ImportError::InvalidCreditLine { ev_id, source })?;
+        } else if matches!(ev_action, EventAction::CreditLine) {
+            return Err(ImportError::MissingCreditLine { ev_id });
+        }
+
+        if let Some(iou) = exported.opt_iou.as_ref() {
+            Iou::from_payload(iou).map_err(|source| ImportError::InvalidIou { ev_id, source })?;
+        } else if matches!(ev_action, EventAction::Iou) {
+            return Err(ImportError::MissingIou { ev_id });
+        }
+
+        if mut_opt_prev_ev_id.is_some_and(|prev_ev_id| prev_ev_id >= ev_id) {
+            return Err(ImportError::OutOfOrder {
+                ev_id,
+                reason: "events must be in increasing id order".to_owned(),
+            });
+        }
+
+        match exported.opt_target_ev_id {
+            Some(target_ev_id) if !mut_ev_ids.contains(&target_ev_id) => {
+                return Err(ImportError::UnknownTarget {
+                    ev_id,
+                    target_ev_id,
+                });
+            }
+            None if matches!(
+                ev_action,
+                EventAction::Toggle
+                    | EventAction::Untoggle
+                    | EventAction::Unschedule
+                    | EventAction::Release
+                    | EventAction::Unlimit
+            ) =>
+            {
+                return Err(ImportError::MissingTarget { ev_id });
+            }
+            _ => {}
+        }
+
+        let key = (exported.span, exported.frame);
+
+        match (&ev_action, mut_closed.get(&key).copied()) {
+            (EventAction::Open, None) => {
+                mut_closed.insert(key, false);
+            }
+            (EventAction::Open, Some(_)) if target_span_frames.contains(&key) => {
+                // Already in the target store, so the open event is skipped
+            }
+            (EventAction::Open, Some(_)) => {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: "the span frame is opened twice".to_owned(),
+                });
+            }
+            (_, None) => {
+                return Err(ImportError::MissingSpanFrame {
+                    ev_id,
+                    span: exported.span,
+                    frame: exported.frame,
+                });
+            }
+            (EventAction::Close, Some(true)) => {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: "the span frame is already closed".to_owned(),
+                });
+            }
+            (EventAction::Reopen, Some(false)) => {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: "the span frame is not closed".to_owned(),
+                });
+            }
+            (EventAction::Close, Some(false)) => {
+                mut_closed.insert(key, true);
+            }
+            (EventAction::Reopen, Some(true)) => {
+                mut_closed.insert(key, false);
+            }
+            _ => {}
+        }
+
+        if matches!(ev_action, EventAction::DoubleEntry) {
+            if mut_double_entry {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: "double-entry mode is already enabled".to_owned(),
+                });
+            }
+
+            if mut_coins_moved {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: "coins already moved before double-entry mode is enabled".to_owned(),
+                });
+            }
+
+            mut_double_entry = true;
+        }
+
+        if let Some(scale) = opt_scale
+            && matches!(ev_action, EventAction::Scale)
+        {
+            if mut_coins_moved {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: "coins already moved before the scale is set".to_owned(),
+                });
+            }
+
+            mut_scale = scale;
+        }
+
+        if let Some((_, _, coins, asset)) = opt_obj.as_ref() {
+            match exported.opt_tx_id {
+                Some(tx_id) => match mut_tx_totals
+                    .iter_mut()
+                    .find(|((other, other_asset), _)| *other == tx_id && other_asset == asset)
+                {
+                    Some((_, total)) => {
+                        *total = total
+                            .checked_add(*coins)
+                            .map_err(|source| ImportError::AmountError { ev_id, source })?;
+                    }
+                    None => mut_tx_totals.push(((tx_id, asset.clone()), *coins)),
+                },
+                None if mut_double_entry && !coins.is_zero() => {
+                    return Err(ImportError::UnbalancedEvent { ev_id });
+                }
+                None => {}
+            }
+
+            mut_coins_moved |= !coins.is_zero();
+        }
+
+        if let Some((obj_id, _, _, _)) = opt_obj.as_ref() {
+            if matches!(ev_action, EventAction::Insert) {
+                mut_obj_ids.insert(*obj_id);
+            } else if !mut_obj_ids.contains(obj_id) {
+                return Err(ImportError::OutOfOrder {
+                    ev_id,
+                    reason: format!("object {obj_id} is not inserted before it"),
+                });
+            }
+        }
+
+        mut_ev_ids.insert(ev_id);
+        mut_opt_prev_ev_id = Some(ev_id);
+
+        mut_out.push(ValidatedEvent {
+            exported,
+            ev_action,
+            opt_obj,
+        });
+    }
+
+    if let Some(((tx_id, asset), total)) = mut_tx_totals
+        .into_iter()
+        .find(|(_, total)| !total.is_zero())
+    {
+        return Err(ImportError::UnbalancedTransaction {
+            tx_id,
+            asset: asset.to_inner(),
+            total,
+        });
+    }
+
+    Ok(mut_out)
+}
+
+/// Writes the validated events with their original timestamps. Returns the summary of what was written.
+fn replay(
+    conn: &mut SqliteConnection,
+    validated: &[ValidatedEvent],
+) -> Result<ImportSummary, ImportError> {
+    let span_frames = coin_store::get_created_span_frames(conn)?;
+
+    let existing_span_frames = validated
+        .iter()
+        .filter(|event| matches!(event.ev_action, EventAction::Open))
+        .filter_map(|event| {
+            span_frames
+                .iter()
+                .find(|sf| sf.span == event.exported.span && sf.frame == event.exported.frame)
+                .cloned()
+        })
+        .collect::<Vec<_>>();
+
+    // Exported event and transaction ids to the ids they were written with
+    let mut mut_ev_ids = HashMap::new();
+    let mut mut_tx_ids = HashMap::new();
+    let mut mut_opened_span_frames = vec![];
+    let mut mut_action_counts: Vec<(String, usize)> = EventAction::VARIANTS
+        .iter()
+        .map(|ev_action| (export::display_event_action(ev_action), 0))
+        .collect();
+
+    for event in validated {
+        let exported = event.exported;
+
+        let span_frame = SpanFrame {
+            span: exported.span,
+            frame: exported.frame,
+        };
+
+        match &event.ev_action {
+            EventAction::Open => {
+                let already_exists = existing_span_frames
+                    .iter()
+                    .any(|sf| sf.span == span_frame.span && sf.frame == span_frame.frame);
+
+                if already_exists {
+                    // Frames opened later may still branch off this one
+                    let open_ev_id = coin_store::get_open_event_id(conn, &span_frame)?;
+                    mut_ev_ids.insert(exported.id, open_ev_id);
+                    continue;
+                }
+
+                mut_opened_span_frames.push(span_frame);
+            }
+            EventAction::Name => {
+                coin_store::check_span_frame_label(
+                    conn,
+                    &SpanFrameLabelKind::Name,
+                    &exported.ev_desc,
+                )?;
+            }
+            EventAction::Tag => {
+                coin_store::check_span_frame_label(
+                    conn,
+                    &SpanFrameLabelKind::Tag,
+                    &exported.ev_desc,
+                )?;
+            }
+            _ => {}
+        }
+
+        let opt_target_ev_id = exported
+            .opt_target_ev_id
+            .map(|target_ev_id| {
+                mut_ev_ids
+                    .get(&target_ev_id)
+                    .copied()
+                    .ok_or(ImportError::UnknownTarget {
+                        ev_id: exported.id,
+                        target_ev_id,
+                    })
+            })
+            .transpose()?;
+
+        let opt_tx_id = match exported.opt_tx_id {
+            Some(tx_id) => match mut_tx_ids.get(&tx_id) {
+                Some(new_tx_id) => Some(*new_tx_id),
+                None => {
+                    let new_tx_id = double_entry::get_next_tx_id(conn)?;
+                    mut_tx_ids.insert(tx_id, new_tx_id);
+                    Some(new_tx_id)
+                }
+            },
+            None => None,
+        };
+
+        let new_event = coin_store::NewEvent {
+            opt_diff_id: None,
+            ev_action: event.ev_action.clone(),
+            span: exported.span,
+            frame: exported.frame,
+            created_on_ts: exported.created_on_ts,
+            ev_desc: &exported.ev_desc,
+            opt_target_ev_id,
+            opt_ev_hash: None,
+            opt_tx_id,
+            opt_rate: exported.opt_rate.clone(),
+            opt_scale: exported.opt_scale,
+            opt_schedule: exported.opt_schedule.clone(),
+            opt_hold: exported.opt_hold.clone(),
+            opt_expires_on: exported.opt_expires_on.clone(),
+            opt_limit: exported.opt_limit.clone(),
+            opt_credit_line: exported.opt_credit_line.clone(),
+            opt_iou: exported.opt_iou.clone(),
+            opt_made_on: exported.opt_made_on.clone(),
+        };
+
+        let opt_obj = event
+            .opt_obj
+            .as_ref()
+            .map(|(obj_id, person, coins, asset)| {
+                (
+                    *obj_id,
+                    coin_store::NewCommon {
+                        coins: *coins,
+                        person,
+                        asset,
+                    },
+                )
+            });
+
+        let out = coin_store::insert_recorded_event(conn, new_event, opt_obj)?;
+
+        mut_ev_ids.insert(exported.id, out.id);
+
+        let ev_action = export::display_event_action(&event.ev_action);
+
+        if let Some((_, count)) = mut_action_counts
+            .iter_mut()
+            .find(|(other, _)| *other == ev_action)
+        {
+            *count += 1;
+        }
+    }
+
+    mut_action_counts.retain(|(_, count)| *count > 0);
+
+    Ok(ImportSummary {
+        event_count: mut_action_counts.iter().map(|(_, count)| count).sum(),
+        action_counts: mut_action_counts,
+        opened_span_frames: mut_opened_span_frames,
+        existing_span_frames,
+    })
+}
+
+/// Rolls the transaction back either way, keeping the summary of a dry run apart from actual failures
+enum ImportOutcome {
+    Failed(ImportError),
+    DryRun(ImportSummary),
+}
+
+impl From<diesel::result::Error> for ImportOutcome {
+    fn from(e: diesel::result::Error) -> Self {
+        ImportOutcome::Failed(e.into())
+    }
+}
+
+/// Validates and replays the events into the store, all or nothing. With `dry_run`, everything is written and then
+/// rolled back, so the summary and any error are exactly what a real import would give.
+pub fn import_events(
+    conn: &mut SqliteConnection,
+    events: &[ExportedEvent],
+    dry_run: bool,
+) -> Result<ImportSummary, ImportError> {
+    let res = conn.transaction(|conn| {
+        let validated = validate(conn, events).map_err(ImportOutcome::Failed)?;
+        let summary = replay(conn, &validated).map_err(ImportOutcome::Failed)?;
+
+        if dry_run {
+            Err(ImportOutcome::DryRun(summary))
+        } else {
+            Ok(summary)
+        }
+    });
+
+    match res {
+        Ok(summary) => Ok(summary),
+        Err(ImportOutcome::DryRun(summary)) => Ok(summary),
+        Err(ImportOutcome::Failed(e)) => Err(e),
+    }
+}
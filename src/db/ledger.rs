@@ -0,0 +1,448 @@
+//! Plain text accounting export of a span frame for ledger or beancount.
+//!
+//! Every record seen from the span frame that changes coins becomes a transaction between the person's account under
+//! `Assets:Coins` and `Income:Coins` for income or `Expenses:Coins` for expenses. Records of one double-entry
+//! transaction become a single transaction with a posting each, where the `@issuance` and `@expenses` system accounts
+//! are `Income:Coins` and `Expenses:Coins`, and `@exchange` is `Equity:Exchange`. Transactions are dated by their
+//! timestamp in UTC, narrated by their description, and carry the id of their first event so they can be traced back.
+//! Each asset is a commodity named after its code in uppercase, e.g. `COIN` or `POINTS`. Amounts are written with the
+//! decimals of the store's scale.
+
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use chrono::{DateTime, NaiveDate};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::db::models::{Amount, AmountError, Person, Scale, coin_store};
+use crate::db::scale::{self, ScaleError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerFormat {
+    Ledger,
+    Beancount,
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown ledger format {0:?}. Expected ledger or beancount")]
+pub struct ParseLedgerFormatError(String);
+
+impl FromStr for LedgerFormat {
+    type Err = ParseLedgerFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ledger" => Ok(Self::Ledger),
+            "beancount" => Ok(Self::Beancount),
+            _ => Err(ParseLedgerFormatError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LedgerExportError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Failed to write: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Failed to read the scale: {0}")]
+    ScaleError(#[from] ScaleError),
+}
+
+/// A change of a person's coins in one asset
+#[derive(Debug, Clone)]
+pub struct LedgerPosting {
+    pub person: String,
+    pub coins: Amount,
+    pub asset: String,
+}
+
+/// Changes of coins that belong together. Whatever the postings leave unbalanced is posted against income or expenses
+/// depending on its sign.
+#[derive(Debug, Clone)]
+pub struct LedgerTransaction {
+    pub ev_id: i32,
+    pub date: NaiveDate,
+    pub narration: String,
+    pub postings: Vec<LedgerPosting>,
+}
+
+impl LedgerTransaction {
+    /// What the postings add up to in each asset they leave unbalanced, in the order the assets first appear
+    pub fn get_rests(&self) -> Result<Vec<(String, Amount)>, AmountError> {
+        let mut mut_out: Vec<(String, Amount)> = vec![];
+
+        for posting in self.postings.iter() {
+            match mut_out
+                .iter_mut()
+                .find(|(asset, _)| *asset == posting.asset)
+            {
+                Some((_, rest)) => *rest = rest.checked_add(posting.coins)?,
+                None => mut_out.push((posting.asset.clone(), posting.coins)),
+            }
+        }
+
+        mut_out.retain(|(_, rest)| !rest.is_zero());
+
+        Ok(mut_out)
+    }
+}
+
+/// A person whose balance of an asset in the export differs from `coin_store_hist`
+#[derive(Debug, Clone)]
+pub struct LedgerBalanceMismatch {
+    pub person: String,
+    pub asset: String,
+    pub exported_coins: Amount,
+    pub hist_coins: Amount,
+}
+
+/// The transactions of the records seen from the span frame, in the order the wallet applies them
+pub fn get_ledger_transactions(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<LedgerTransaction>, diesel::result::Error> {
+    use crate::autogen::schema::{coin_store_events, coin_store_events_grouped::dsl};
+
+    let records = dsl::coin_store_events_grouped
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .filter(dsl::coins.ne(0))
+        .order((dsl::created_on_ts.asc(), dsl::ev_id.asc()))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(conn)?;
+
+    let tx_ids: HashMap<i32, Option<i32>> = coin_store_events::table
+        .filter(coin_store_events::id.eq_any(records.iter().map(|record| record.ev_id)))
+        .select((coin_store_events::id, coin_store_events::opt_tx_id))
+        .get_results(conn)?
+        .into_iter()
+        .collect();
+
+    // Where each transaction seen so far is in the output
+    let mut mut_tx_indices: HashMap<i32, usize> = HashMap::new();
+    let mut mut_out: Vec<LedgerTransaction> = vec![];
+
+    for record in records {
+        let posting = LedgerPosting {
+            person: record.person.to_inner(),
+            coins: record.coins,
+            asset: record.asset.to_inner(),
+        };
+
+        let opt_tx_id = tx_ids.get(&record.ev_id).copied().flatten();
+
+        if let Some(tx_id) = opt_tx_id
+            && let Some(index) = mut_tx_indices.get(&tx_id)
+        {
+            mut_out[*index].postings.push(posting);
+            continue;
+        }
+
+        if let Some(tx_id) = opt_tx_id {
+            mut_tx_indices.insert(tx_id, mut_out.len());
+        }
+
+        mut_out.push(LedgerTransaction {
+            ev_id: record.ev_id,
+            date: DateTime::from_timestamp_millis(record.created_on_ts as i64)
+                .unwrap_or_default()
+                .date_naive(),
+            narration: record.ev_desc,
+            postings: vec![posting],
+        });
+    }
+
+    Ok(mut_out)
+}
+
+/// Account names are split on `:` and, for ledger, end at two spaces. Beancount also needs every part to start with
+/// a capital letter or digit and contain only letters, digits and dashes. System accounts map to income, expenses and
+/// equity.
+pub fn get_person_account(format: LedgerFormat, person: &str) -> String {
+    if person == Person::issuance().to_inner() {
+        return "Income:Coins".to_owned();
+    }
+
+    if person == Person::expenses().to_inner() {
+        return "Expenses:Coins".to_owned();
+    }
+
+    if person == Person::exchange().to_inner() {
+        return "Equity:Exchange".to_owned();
+    }
+
+    let part = match format {
+        LedgerFormat::Ledger => person
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace(':', "-"),
+        LedgerFormat::Beancount => {
+            let part = person
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>();
+
+            let mut mut_chars = part.chars();
+
+            match mut_chars.next() {
+                Some(first) if first.is_ascii_alphanumeric() => {
+                    first.to_ascii_uppercase().to_string() + mut_chars.as_str()
+                }
+                _ => format!("X{part}"),
+            }
+        }
+    };
+
+    format!("Assets:Coins:{part}")
+}
+
+/// Asset codes are lowercase letters, digits, dashes and underscores, so in uppercase they are valid commodities for
+/// both formats
+pub fn get_commodity(asset: &str) -> String {
+    asset.to_uppercase()
+}
+
+fn get_counter_account(coins: Amount) -> &'static str {
+    if coins.is_positive() {
+        "Income:Coins"
+    } else {
+        "Expenses:Coins"
+    }
+}
+
+/// Writes the transactions as a ledger journal or a beancount file. Beancount files also open every account on the
+/// date of its first transaction, for the commodities it holds.
+pub fn write_ledger(
+    format: LedgerFormat,
+    scale: Scale,
+    transactions: &[LedgerTransaction],
+    writer: &mut impl Write,
+) -> Result<(), LedgerExportError> {
+    let get_narration = |transaction: &LedgerTransaction| {
+        transaction
+            .narration
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    match format {
+        LedgerFormat::Ledger => {
+            for transaction in transactions {
+                writeln!(
+                    writer,
+                    "{} {}",
+                    transaction.date.format("%Y/%m/%d"),
+                    get_narration(transaction)
+                )?;
+                writeln!(writer, "    ; ev_id: {}", transaction.ev_id)?;
+
+                for posting in transaction.postings.iter() {
+                    writeln!(
+                        writer,
+                        "    {}  {} {}",
+                        get_person_account(format, &posting.person),
+                        posting.coins.to_scaled_string(scale),
+                        get_commodity(&posting.asset)
+                    )?;
+                }
+
+                match transaction.get_rests()?.as_slice() {
+                    [] => {}
+                    // Ledger fills in the amount of a single posting left without one
+                    [(_, rest)] => writeln!(writer, "    {}", get_counter_account(*rest))?,
+                    rests => {
+                        for (asset, rest) in rests {
+                            writeln!(
+                                writer,
+                                "    {}  {} {}",
+                                get_counter_account(*rest),
+                                rest.checked_neg()?.to_scaled_string(scale),
+                                get_commodity(asset)
+                            )?;
+                        }
+                    }
+                }
+
+                writeln!(writer)?;
+            }
+        }
+        LedgerFormat::Beancount => {
+            // Accounts in the order they are first used, with the commodities they hold
+            let mut mut_opened: Vec<(String, NaiveDate, Vec<String>)> = vec![];
+
+            for transaction in transactions {
+                let rests = transaction.get_rests()?;
+
+                let accounts = transaction
+                    .postings
+                    .iter()
+                    .map(|posting| {
+                        (
+                            get_person_account(format, &posting.person),
+                            get_commodity(&posting.asset),
+                        )
+                    })
+                    .chain(rests.into_iter().map(|(asset, rest)| {
+                        (get_counter_account(rest).to_owned(), get_commodity(&asset))
+                    }));
+
+                for (account, commodity) in accounts {
+                    match mut_opened
+                        .iter_mut()
+                        .find(|(other, _, _)| *other == account)
+                    {
+                        Some((_, _, commodities)) if !commodities.contains(&commodity) => {
+                            commodities.push(commodity)
+                        }
+                        Some(_) => {}
+                        None => mut_opened.push((account, transaction.date, vec![commodity])),
+                    }
+                }
+            }
+
+            for (account, date, commodities) in mut_opened.iter() {
+                writeln!(
+                    writer,
+                    "{} open {account} {}",
+                    date.format("%Y-%m-%d"),
+                    commodities.join(",")
+                )?;
+            }
+
+            if !mut_opened.is_empty() {
+                writeln!(writer)?;
+            }
+
+            for transaction in transactions {
+                writeln!(
+                    writer,
+                    "{} * \"{}\"",
+                    transaction.date.format("%Y-%m-%d"),
+                    get_narration(transaction)
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                )?;
+                writeln!(writer, "  ev_id: {}", transaction.ev_id)?;
+
+                for posting in transaction.postings.iter() {
+                    writeln!(
+                        writer,
+                        "  {}  {} {}",
+                        get_person_account(format, &posting.person),
+                        posting.coins.to_scaled_string(scale),
+                        get_commodity(&posting.asset)
+                    )?;
+                }
+
+                for (asset, rest) in transaction.get_rests()? {
+                    writeln!(
+                        writer,
+                        "  {}  {} {}",
+                        get_counter_account(rest),
+                        rest.checked_neg()?.to_scaled_string(scale),
+                        get_commodity(&asset)
+                    )?;
+                }
+
+                writeln!(writer)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares each person's balance of each asset over the transactions with their coins in `coin_store_hist`
+pub fn check_ledger_balances(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    transactions: &[LedgerTransaction],
+) -> Result<Vec<LedgerBalanceMismatch>, LedgerExportError> {
+    use crate::autogen::schema::coin_store_hist::dsl;
+
+    let hist = dsl::coin_store_hist
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .select(coin_store::Hist::as_select())
+        .get_results(conn)?;
+
+    let postings = transactions
+        .iter()
+        .flat_map(|transaction| transaction.postings.iter())
+        .collect::<Vec<_>>();
+
+    let mut mut_accounts = hist
+        .iter()
+        .map(|row| (row.person.to_inner(), row.asset.to_inner()))
+        .chain(
+            postings
+                .iter()
+                .map(|posting| (posting.person.clone(), posting.asset.clone())),
+        )
+        .collect::<Vec<_>>();
+
+    mut_accounts.sort();
+    mut_accounts.dedup();
+
+    let mut mut_out = vec![];
+
+    for (person, asset) in mut_accounts {
+        let exported_coins = Amount::checked_sum(
+            postings
+                .iter()
+                .filter(|posting| posting.person == person && posting.asset == asset)
+                .map(|posting| posting.coins),
+        )?;
+
+        let hist_coins = Amount::checked_sum(
+            hist.iter()
+                .filter(|row| row.person.to_inner() == person && row.asset.to_inner() == asset)
+                .map(|row| row.coins),
+        )?;
+
+        if exported_coins != hist_coins {
+            mut_out.push(LedgerBalanceMismatch {
+                person,
+                asset,
+                exported_coins,
+                hist_coins,
+            });
+        }
+    }
+
+    Ok(mut_out)
+}
+
+/// Writes the span frame's transactions and checks them against `coin_store_hist`. Returns how many transactions were
+/// written and the persons whose balances do not match.
+pub fn export_ledger(
+    conn: &mut SqliteConnection,
+    format: LedgerFormat,
+    span_frame: &SpanFrame,
+    writer: &mut impl Write,
+) -> Result<(usize, Vec<LedgerBalanceMismatch>), LedgerExportError> {
+    let transactions = get_ledger_transactions(conn, span_frame)?;
+
+    let scale = scale::get_scale(conn)?;
+
+    write_ledger(format, scale, &transactions, writer)?;
+
+    let mismatches = check_ledger_balances(conn, span_frame, &transactions)?;
+
+    Ok((transactions.len(), mismatches))
+}
@@ -0,0 +1,343 @@
+//! Spending limits, set by `limit` events.
+//!
+//! A limit, such as 500 coin per day for alice, caps what a user spends of an asset in a calendar window in UTC. A
+//! limit without a user is a default per-user limit: it caps what each user spends on their own, next to any limits
+//! for them, and not what the users of the store spend together. What was spent is read from the records of the span
+//! frame, so it covers its whole ancestry: every coin that left the user's account in the window counts, except expired
+//! coins that were written off. A later limit for the same user, asset and window replaces an earlier one, and an
+//! `unlimit` event lifts the limit it points at. Which of them apply in a span frame follows `SpanFrameInfo::sees`.
+//!
+//! Limits are checked in the same transaction as the coins are spent, by expenses, transfers, splits, IOU repayments,
+//! scheduled expenses and captures of holds alike. Expiry sweeps only write off what is gone, so they are never held
+//! back.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::EventAction;
+use crate::db::models::{
+    Amount, AmountError, Asset, Limit, Payload, PayloadError, Person, Scale, coin_store,
+};
+use crate::db::scale::{self, ScaleError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum LimitError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Payload Error: {0}")]
+    InvalidLimit(#[from] PayloadError),
+
+    #[error("Span frame (span: {}, frame: {}) does not exist", .0.span, .0.frame)]
+    UnknownSpanFrame(SpanFrame),
+
+    #[error("Event {0} is not a limit seen in this span frame")]
+    UnknownLimit(i32),
+
+    #[error("Limit {0} is already lifted or replaced")]
+    AlreadyEnded(i32),
+
+    #[error("Window of limit {0} resets past the dates that can be represented")]
+    WindowOutOfRange(i32),
+
+    #[error("Scale Error: {0}")]
+    ScaleError(#[from] ScaleError),
+
+    #[error(
+        "{person} can only spend {} more {asset} until {resets_on} under limit {limit_ev_id}, which is less than {}",
+        .remaining.to_scaled_string(*.scale),
+        .coins.to_scaled_string(*.scale)
+    )]
+    LimitExceeded {
+        person: String,
+        asset: String,
+        limit_ev_id: i32,
+        remaining: Amount,
+        coins: Amount,
+        resets_on: DateTime<Utc>,
+        /// The scale of the store, to show the coins at
+        scale: Scale,
+    },
+}
+
+/// A limit with what became of it so far
+#[derive(Debug)]
+pub struct LimitInfo {
+    pub event: coin_store::Event,
+    pub limit: Limit,
+    /// The event id of the unlimit event or the later limit that ended the limit, if any
+    pub opt_ended_by: Option<i32>,
+}
+
+impl LimitInfo {
+    pub fn is_active(&self) -> bool {
+        self.opt_ended_by.is_none()
+    }
+}
+
+/// What a user may still spend under a limit in its current window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allowance {
+    pub limit_ev_id: i32,
+    pub limit: Limit,
+    pub spent: Amount,
+    pub remaining: Amount,
+    pub resets_on: DateTime<Utc>,
+}
+
+fn get_midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(chrono::NaiveTime::MIN).and_utc()
+}
+
+/// Every limit the span frame sees in id order, ended ones included
+pub fn get_limits(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<LimitInfo>, LimitError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let info = coin_store::get_span_frame_info(conn, span_frame)?
+        .ok_or_else(|| LimitError::UnknownSpanFrame(span_frame.clone()))?;
+
+    let is_seen =
+        |event: &coin_store::Event| info.sees(event.span, event.frame, event.created_on_ts);
+
+    let events: Vec<coin_store::Event> = dsl::coin_store_events
+        .filter(dsl::ev_action.eq_any([EventAction::Limit, EventAction::Unlimit]))
+        .order(dsl::id.asc())
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    let (limit_events, unlimit_events): (Vec<_>, Vec<_>) = events
+        .into_iter()
+        .filter(is_seen)
+        .partition(|event| matches!(event.ev_action, EventAction::Limit));
+
+    let limits = limit_events
+        .into_iter()
+        .map(|event| {
+            let limit = Limit::from_payload(event.opt_limit.as_deref().unwrap_or_default())?;
+
+            Ok((event, limit))
+        })
+        .collect::<Result<Vec<_>, LimitError>>()?;
+
+    let ended_by = limits
+        .iter()
+        .enumerate()
+        .map(|(index, (event, limit))| {
+            let opt_lifted_by = unlimit_events
+                .iter()
+                .find(|other| other.opt_target_ev_id == Some(event.id))
+                .map(|other| other.id);
+
+            let opt_replaced_by = limits[index + 1..]
+                .iter()
+                .find(|(_, other)| {
+                    other.opt_person == limit.opt_person
+                        && other.asset == limit.asset
+                        && other.window == limit.window
+                })
+                .map(|(other, _)| other.id);
+
+            [opt_lifted_by, opt_replaced_by].into_iter().flatten().min()
+        })
+        .collect::<Vec<_>>();
+
+    Ok(limits
+        .into_iter()
+        .zip(ended_by)
+        .map(|((event, limit), opt_ended_by)| LimitInfo {
+            event,
+            limit,
+            opt_ended_by,
+        })
+        .collect())
+}
+
+/// Records the limit in the span frame, replacing any earlier limit for the same user, asset and window
+pub fn set_limit(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    limit: &Limit,
+    ev_desc: &str,
+) -> Result<coin_store::Event, LimitError> {
+    let new_event = coin_store::NewEvent {
+        opt_limit: Some(limit.to_payload()?),
+        ..coin_store::NewEvent::new(EventAction::Limit, span_frame, ev_desc)
+    };
+
+    Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+}
+
+/// Lifts the limit, so that it no longer restricts what anyone spends
+pub fn lift_limit(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    limit_ev_id: i32,
+    ev_desc: &str,
+) -> Result<coin_store::Event, LimitError> {
+    conn.transaction(|conn| {
+        let info = get_limits(conn, span_frame)?
+            .into_iter()
+            .find(|info| info.event.id == limit_ev_id)
+            .ok_or(LimitError::UnknownLimit(limit_ev_id))?;
+
+        if !info.is_active() {
+            return Err(LimitError::AlreadyEnded(limit_ev_id));
+        }
+
+        let new_event = coin_store::NewEvent {
+            opt_target_ev_id: Some(limit_ev_id),
+            ..coin_store::NewEvent::new(EventAction::Unlimit, span_frame, ev_desc)
+        };
+
+        Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+    })
+}
+
+/// The coins of the asset that left the user's account in the span frame since `since`, leaving out expired coins that
+/// were written off
+pub fn get_spent(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    asset: &Asset,
+    since: DateTime<Utc>,
+) -> Result<Amount, LimitError> {
+    use crate::autogen::schema::coin_store_events::dsl as events_dsl;
+    use crate::autogen::schema::coin_store_events_grouped::dsl;
+
+    let records: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .filter(dsl::person.eq(person))
+        .filter(dsl::asset.eq(asset))
+        .filter(dsl::coins.lt(0))
+        .filter(dsl::created_on_ts.ge((since - TimeDelta::minutes(5)).timestamp_millis() as f32))
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(conn)?;
+
+    // Timestamps are stored as f32, whose steps are about 2 minutes at the millis of today, so `since` is only rounded
+    // to them for the SQL filter above, and compared exactly here
+    let since_millis = since.timestamp_millis() as f64;
+
+    let records = records
+        .into_iter()
+        .filter(|record| f64::from(record.created_on_ts) >= since_millis)
+        .collect::<Vec<_>>();
+
+    let targets: Vec<(i32, i32)> = events_dsl::coin_store_events
+        .filter(events_dsl::id.eq_any(records.iter().map(|record| record.ev_id)))
+        .select((events_dsl::id, events_dsl::opt_target_ev_id))
+        .get_results::<(i32, Option<i32>)>(conn)?
+        .into_iter()
+        .filter_map(|(ev_id, opt_target_ev_id)| Some((ev_id, opt_target_ev_id?)))
+        .collect();
+
+    // A write-off of expired coins points at the income they came from, which has an expiry date
+    let expiring_ev_ids: HashSet<i32> = events_dsl::coin_store_events
+        .filter(events_dsl::id.eq_any(targets.iter().map(|(_, target_ev_id)| *target_ev_id)))
+        .filter(events_dsl::opt_expires_on.is_not_null())
+        .select(events_dsl::id)
+        .get_results(conn)?
+        .into_iter()
+        .collect();
+
+    let write_off_ev_ids: HashSet<i32> = targets
+        .into_iter()
+        .filter(|(_, target_ev_id)| expiring_ev_ids.contains(target_ev_id))
+        .map(|(ev_id, _)| ev_id)
+        .collect();
+
+    Amount::checked_sum(
+        records
+            .iter()
+            .filter(|record| !write_off_ev_ids.contains(&record.ev_id))
+            .map(|record| record.coins),
+    )?
+    .checked_neg()
+    .map_err(LimitError::from)
+}
+
+/// What the user may still spend of the asset at `now` under each active limit that applies to them, in id order
+pub fn get_allowances(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    asset: &Asset,
+    now: DateTime<Utc>,
+) -> Result<Vec<Allowance>, LimitError> {
+    let today = now.date_naive();
+
+    get_limits(conn, span_frame)?
+        .into_iter()
+        .filter(|info| info.is_active())
+        .filter(|info| info.limit.applies_to(person) && info.limit.asset == *asset)
+        .map(|info| {
+            let window = info.limit.window;
+
+            let resets_on = window
+                .get_reset(today)
+                .map(get_midnight)
+                .ok_or(LimitError::WindowOutOfRange(info.event.id))?;
+
+            let spent = get_spent(
+                conn,
+                span_frame,
+                person,
+                asset,
+                get_midnight(window.get_start(today)),
+            )?;
+
+            Ok(Allowance {
+                limit_ev_id: info.event.id,
+                remaining: info.limit.coins.checked_sub(spent)?.max(Amount::ZERO),
+                limit: info.limit,
+                spent,
+                resets_on,
+            })
+        })
+        .collect()
+}
+
+/// Refuses to spend more than the user may still spend at `now` under the limits that apply to them. The error is
+/// about the limit with the least left.
+pub fn check_limits(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    asset: &Asset,
+    coins: Amount,
+    now: DateTime<Utc>,
+) -> Result<(), LimitError> {
+    let opt_tightest = get_allowances(conn, span_frame, person, asset, now)?
+        .into_iter()
+        .min_by_key(|allowance| allowance.remaining);
+
+    if let Some(allowance) = opt_tightest
+        && allowance.remaining < coins
+    {
+        return Err(LimitError::LimitExceeded {
+            person: person.to_inner(),
+            asset: asset.to_inner(),
+            limit_ev_id: allowance.limit_ev_id,
+            remaining: allowance.remaining,
+            coins,
+            resets_on: allowance.resets_on,
+            scale: scale::get_scale(conn)?,
+        });
+    }
+
+    Ok(())
+}
@@ -1,3 +1,19 @@
 pub mod actions;
+pub mod credit;
+pub mod double_entry;
+pub mod exchange;
+pub mod expiry;
+pub mod export;
+pub mod filter;
+pub mod forecast;
+pub mod hold;
+pub mod import;
+pub mod ledger;
+pub mod limit;
 pub mod loader;
 pub mod models;
+pub mod scale;
+pub mod schedule;
+pub mod spend;
+pub mod split;
+pub mod upcast;
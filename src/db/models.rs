@@ -3,15 +3,20 @@
 use std::str::FromStr;
 
 use diesel_derive_newtype::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, DieselNewType)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, DieselNewType, Serialize, Deserialize)]
+#[serde(try_from = "String")]
 pub struct Person(String);
 
 #[derive(Error, Debug)]
 pub enum PersonFromStrError {
     #[error("Person name cannot be admin")]
     AdminNotAllowed,
+
+    #[error("Person name cannot start with @, which is reserved for system accounts")]
+    SystemAccountNotAllowed,
 }
 
 impl FromStr for Person {
@@ -20,16 +25,822 @@ impl FromStr for Person {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.to_lowercase() == "admin" {
             Err(PersonFromStrError::AdminNotAllowed)
+        } else if s.starts_with('@') {
+            Err(PersonFromStrError::SystemAccountNotAllowed)
         } else {
             Ok(Person(s.to_owned()))
         }
     }
 }
 
+impl TryFrom<String> for Person {
+    type Error = PersonFromStrError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 impl Person {
     pub fn to_inner(&self) -> String {
         self.0.clone()
     }
+
+    /// The system account that income is issued from in double-entry mode
+    pub fn issuance() -> Person {
+        Person("@issuance".to_owned())
+    }
+
+    /// The system account that expenses are paid into in double-entry mode
+    pub fn expenses() -> Person {
+        Person("@expenses".to_owned())
+    }
+
+    /// The system account that exchanges between assets are made with
+    pub fn exchange() -> Person {
+        Person("@exchange".to_owned())
+    }
+
+    /// Like parsing a person, but also accepts the system accounts
+    pub fn parse_account(s: &str) -> Result<Person, PersonFromStrError> {
+        [Person::issuance(), Person::expenses(), Person::exchange()]
+            .into_iter()
+            .find(|account| account.0 == s)
+            .map_or_else(|| s.parse(), Ok)
+    }
+
+    pub fn is_system_account(&self) -> bool {
+        self.0.starts_with('@')
+    }
+
+    /// The object id of the person's account, derived from their name so that it is the same in every span frame
+    pub fn get_obj_id(&self) -> i32 {
+        use std::hash::Hash;
+
+        use crc32fast::Hasher;
+        use deterministic_hash::DeterministicHasher;
+
+        let mut hasher = DeterministicHasher::new(Hasher::new());
+        self.hash(&mut hasher);
+
+        hasher.as_inner().clone().finalize() as i32
+    }
+}
+
+/// The code of an asset that balances are held in, such as `coin`, `credits` or `points`
+#[derive(
+    Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, DieselNewType, Serialize, Deserialize,
+)]
+#[serde(try_from = "String")]
+pub struct Asset(String);
+
+/// Diffs written before assets existed are of this asset
+pub const DEFAULT_ASSET: &str = "coin";
+
+#[derive(Error, Debug)]
+#[error(
+    "Invalid asset {0:?}. Assets are at most 24 lowercase letters, digits, - or _, starting with a letter and ending with a letter or digit"
+)]
+pub struct AssetFromStrError(String);
+
+impl FromStr for Asset {
+    type Err = AssetFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.to_lowercase();
+
+        let valid = code.len() <= 24
+            && code.starts_with(|c: char| c.is_ascii_lowercase())
+            && code.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && code
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+        if valid {
+            Ok(Asset(code))
+        } else {
+            Err(AssetFromStrError(s.to_owned()))
+        }
+    }
+}
+
+impl TryFrom<String> for Asset {
+    type Error = AssetFromStrError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Default for Asset {
+    fn default() -> Self {
+        Asset(DEFAULT_ASSET.to_owned())
+    }
+}
+
+impl Asset {
+    pub fn to_inner(&self) -> String {
+        self.0.clone()
+    }
+}
+
+/// An amount of coins, as a whole number of the smallest unit of the store's [`Scale`], e.g. cents at a scale of 2.
+/// Arithmetic on amounts is checked, so that an overflow is an error instead of wrapping around.
+#[derive(
+    Clone,
+    Copy,
+    Default,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+)]
+#[serde(transparent)]
+pub struct Amount(i64);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("Invalid amount {0:?}. Amounts are numbers such as 12 or 12.34")]
+    Invalid(String),
+
+    #[error("Amount {amount:?} has more than the {scale} decimals of the store")]
+    TooPrecise { amount: String, scale: Scale },
+
+    #[error("Amount {0:?} is out of range")]
+    OutOfRange(String),
+
+    #[error("Amount overflowed")]
+    Overflow,
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use std::num::IntErrorKind;
+
+        s.trim()
+            .parse()
+            .map(Amount)
+            .map_err(|e: std::num::ParseIntError| match e.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                    AmountError::OutOfRange(s.to_owned())
+                }
+                _ => AmountError::Invalid(s.to_owned()),
+            })
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The bare number, like the `i32` that coins used to be, which the hashes of earlier events were computed over
+impl std::fmt::Debug for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<i32> for Amount {
+    fn from(coins: i32) -> Self {
+        Amount(i64::from(coins))
+    }
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn new(coins: i64) -> Self {
+        Amount(coins)
+    }
+
+    pub fn to_inner(&self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    pub fn checked_neg(self) -> Result<Amount, AmountError> {
+        self.0
+            .checked_neg()
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Sums the amounts, failing on the first overflow
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Result<Amount, AmountError> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |total, amount| total.checked_add(amount))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    /// Parses an amount written with up to `scale` decimals exactly, e.g. `12.3` is 1230 at a scale of 2
+    pub fn parse_scaled(s: &str, scale: Scale) -> Result<Amount, AmountError> {
+        let trimmed = s.trim();
+
+        let (sign, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+
+        let (whole, fraction) = match unsigned.split_once('.') {
+            Some((whole, fraction)) if !fraction.is_empty() => (whole, fraction),
+            Some(_) => return Err(AmountError::Invalid(s.to_owned())),
+            None => (unsigned, ""),
+        };
+
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+
+        if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+            return Err(AmountError::Invalid(s.to_owned()));
+        }
+
+        let decimals = scale.to_inner() as usize;
+
+        if fraction.len() > decimals {
+            return Err(AmountError::TooPrecise {
+                amount: s.to_owned(),
+                scale,
+            });
+        }
+
+        // Only digits are left, so the only way to fail is to be out of range
+        format!("{sign}{whole}{fraction:0<decimals$}")
+            .parse()
+            .map(Amount)
+            .map_err(|_| AmountError::OutOfRange(s.to_owned()))
+    }
+
+    /// The amount with `scale` decimals, e.g. `-0.05` for -5 at a scale of 2
+    pub fn to_scaled_string(self, scale: Scale) -> String {
+        let decimals = scale.to_inner() as usize;
+
+        if decimals == 0 {
+            return self.0.to_string();
+        }
+
+        let unit = 10_u64.pow(scale.to_inner());
+        let magnitude = self.0.unsigned_abs();
+
+        format!(
+            "{}{}.{:0decimals$}",
+            if self.is_negative() { "-" } else { "" },
+            magnitude / unit,
+            magnitude % unit,
+        )
+    }
+}
+
+/// Number of decimals of the amounts of a store, e.g. 2 to hold cents. Amounts are stored in the smallest unit, so
+/// that sums stay exact.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scale(u32);
+
+pub const MAX_SCALE: u32 = 8;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid scale {0:?}. A scale is a number of decimals from 0 to {MAX_SCALE}")]
+pub struct ScaleFromStrError(String);
+
+impl FromStr for Scale {
+    type Err = ScaleFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse() {
+            Ok(decimals) if decimals <= MAX_SCALE => Ok(Scale(decimals)),
+            _ => Err(ScaleFromStrError(s.to_owned())),
+        }
+    }
+}
+
+impl TryFrom<i32> for Scale {
+    type Error = ScaleFromStrError;
+
+    fn try_from(decimals: i32) -> Result<Self, Self::Error> {
+        decimals.to_string().parse()
+    }
+}
+
+impl std::fmt::Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Scale {
+    pub fn to_inner(&self) -> u32 {
+        self.0
+    }
+}
+
+/// How many coins of one asset are worth how many of another, e.g. `10 points = 1 coin`. A rate holds both ways.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rate {
+    pub from_coins: Amount,
+    pub from_asset: Asset,
+    pub to_coins: Amount,
+    pub to_asset: Asset,
+}
+
+#[derive(Error, Debug)]
+#[error(
+    "Invalid rate {0:?}. Rates are like 10 points = 1 coin, with positive coins and two different assets"
+)]
+pub struct RateFromStrError(String);
+
+impl FromStr for Rate {
+    type Err = RateFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RateFromStrError(s.to_owned());
+
+        let parse_side = |side: &str| -> Result<(Amount, Asset), RateFromStrError> {
+            let parts = side.split_whitespace().collect::<Vec<_>>();
+
+            let [coins, asset] = parts.as_slice() else {
+                return Err(invalid());
+            };
+
+            let coins: Amount = coins.parse().map_err(|_| invalid())?;
+
+            if !coins.is_positive() {
+                return Err(invalid());
+            }
+
+            Ok((coins, asset.parse().map_err(|_| invalid())?))
+        };
+
+        let (from, to) = s.split_once('=').ok_or_else(invalid)?;
+        let (from_coins, from_asset) = parse_side(from)?;
+        let (to_coins, to_asset) = parse_side(to)?;
+
+        if from_asset == to_asset {
+            return Err(invalid());
+        }
+
+        Ok(Rate {
+            from_coins,
+            from_asset,
+            to_coins,
+            to_asset,
+        })
+    }
+}
+
+impl std::fmt::Display for Rate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} = {} {}",
+            self.from_coins, self.from_asset.0, self.to_coins, self.to_asset.0
+        )
+    }
+}
+
+impl Rate {
+    /// Whether the rate is between the two assets, in either direction
+    pub fn is_between(&self, asset: &Asset, other: &Asset) -> bool {
+        (self.from_asset == *asset && self.to_asset == *other)
+            || (self.from_asset == *other && self.to_asset == *asset)
+    }
+
+    /// The coins of the other asset that `coins` of `asset` are worth. This is `None` if they are not worth a whole
+    /// number of coins, or too many.
+    pub fn convert(&self, coins: Amount, asset: &Asset) -> Option<Amount> {
+        let (per, worth) = if *asset == self.from_asset {
+            (self.from_coins, self.to_coins)
+        } else {
+            (self.to_coins, self.from_coins)
+        };
+
+        let scaled = i128::from(coins.0) * i128::from(worth.0);
+
+        if scaled % i128::from(per.0) != 0 {
+            return None;
+        }
+
+        i64::try_from(scaled / i128::from(per.0)).ok().map(Amount)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PayloadError {
+    #[error("Could not encode {kind}: {source}")]
+    Unencodable {
+        kind: &'static str,
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid {kind} {payload:?}: {source}")]
+    Malformed {
+        kind: &'static str,
+        payload: String,
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid {kind} {payload:?}: {reason}")]
+    Invalid {
+        kind: &'static str,
+        payload: String,
+        reason: &'static str,
+    },
+}
+
+/// A value that an event stores as JSON, such as a schedule, a hold or an IOU. Reading it back checks it like it was checked
+/// when it was written.
+pub trait Payload: Serialize + serde::de::DeserializeOwned {
+    /// What the value is called in errors, e.g. `schedule`
+    const KIND: &'static str;
+
+    /// Why the value is invalid, for what its type cannot rule out, such as a hold of 0 coins
+    fn check(&self) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// The JSON that the event stores, once the value is checked
+    fn to_payload(&self) -> Result<String, PayloadError> {
+        let payload = serde_json::to_string(self).map_err(|source| PayloadError::Unencodable {
+            kind: Self::KIND,
+            source,
+        })?;
+
+        self.check().map_err(|reason| PayloadError::Invalid {
+            kind: Self::KIND,
+            payload: payload.clone(),
+            reason,
+        })?;
+
+        Ok(payload)
+    }
+
+    /// Reads the value back from the JSON that an event stores
+    fn from_payload(payload: &str) -> Result<Self, PayloadError> {
+        let value: Self =
+            serde_json::from_str(payload).map_err(|source| PayloadError::Malformed {
+                kind: Self::KIND,
+                payload: payload.to_owned(),
+                source,
+            })?;
+
+        value.check().map_err(|reason| PayloadError::Invalid {
+            kind: Self::KIND,
+            payload: payload.to_owned(),
+            reason,
+        })?;
+
+        Ok(value)
+    }
+}
+
+/// How often a schedule is due, e.g. `month` or `2 weeks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid recurrence {0:?}. Recurrences are like day, 2 weeks or month")]
+pub struct RecurrenceFromStrError(String);
+
+impl FromStr for Recurrence {
+    type Err = RecurrenceFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RecurrenceFromStrError(s.to_owned());
+
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+
+        let (count, unit) = match parts.as_slice() {
+            [unit] => (1, *unit),
+            [count, unit] => (count.parse().map_err(|_| invalid())?, *unit),
+            _ => return Err(invalid()),
+        };
+
+        if count == 0 {
+            return Err(invalid());
+        }
+
+        match unit.strip_suffix('s').unwrap_or(unit) {
+            "day" => Ok(Recurrence::Days(count)),
+            "week" => Ok(Recurrence::Weeks(count)),
+            "month" => Ok(Recurrence::Months(count)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (count, unit) = match self {
+            Recurrence::Days(count) => (*count, "day"),
+            Recurrence::Weeks(count) => (*count, "week"),
+            Recurrence::Months(count) => (*count, "month"),
+        };
+
+        match count {
+            1 => write!(f, "{unit}"),
+            _ => write!(f, "{count} {unit}s"),
+        }
+    }
+}
+
+impl Recurrence {
+    /// Whether it is every 1 or more days, weeks or months, since every 0 would never move past the start date
+    pub fn is_valid(&self) -> bool {
+        !matches!(
+            self,
+            Recurrence::Days(0) | Recurrence::Weeks(0) | Recurrence::Months(0)
+        )
+    }
+
+    /// The `n`th due date after `start`, counted from it so that monthly dates do not drift. A month that is too short
+    /// for the day of `start` is due on its last day instead. This is `None` past the dates that chrono supports.
+    pub fn nth_after(&self, start: chrono::NaiveDate, n: u32) -> Option<chrono::NaiveDate> {
+        match self {
+            Recurrence::Days(count) => {
+                start.checked_add_days(chrono::Days::new(u64::from(n) * u64::from(*count)))
+            }
+            Recurrence::Weeks(count) => {
+                start.checked_add_days(chrono::Days::new(u64::from(n) * u64::from(*count) * 7))
+            }
+            Recurrence::Months(count) => {
+                start.checked_add_months(chrono::Months::new(n.checked_mul(*count)?))
+            }
+        }
+    }
+}
+
+/// Coins that a user is credited, or charged if negative, on every due date. The first due date is the start date.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub person: Person,
+    pub coins: Amount,
+    pub asset: Asset,
+    pub every: Recurrence,
+    pub start: chrono::NaiveDate,
+}
+
+impl Payload for Schedule {
+    const KIND: &'static str = "schedule";
+
+    fn check(&self) -> Result<(), &'static str> {
+        if self.coins.is_zero() {
+            return Err("Schedules are of coins other than 0");
+        }
+
+        if !self.every.is_valid() {
+            return Err("Schedules recur every 1 or more days, weeks or months");
+        }
+
+        Ok(())
+    }
+}
+
+impl Schedule {
+    /// The due date of occurrence `n`, counting the one on the start date as 0
+    pub fn nth_due_date(&self, n: u32) -> Option<chrono::NaiveDate> {
+        self.every.nth_after(self.start, n)
+    }
+}
+
+/// How long a hold lasts before it expires, e.g. `15 minutes`, `2 hours` or `7 days`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HoldDuration(chrono::TimeDelta);
+
+#[derive(Error, Debug)]
+#[error("Invalid hold duration {0:?}. Hold durations are like 15 minutes, 2 hours or 7 days")]
+pub struct HoldDurationFromStrError(String);
+
+impl FromStr for HoldDuration {
+    type Err = HoldDurationFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HoldDurationFromStrError(s.to_owned());
+
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+
+        let [count, unit] = parts.as_slice() else {
+            return Err(invalid());
+        };
+
+        let count: i64 = count.parse().map_err(|_| invalid())?;
+
+        if count <= 0 {
+            return Err(invalid());
+        }
+
+        let opt_delta = match unit.strip_suffix('s').unwrap_or(unit) {
+            "minute" => chrono::TimeDelta::try_minutes(count),
+            "hour" => chrono::TimeDelta::try_hours(count),
+            "day" => chrono::TimeDelta::try_days(count),
+            _ => return Err(invalid()),
+        };
+
+        opt_delta.map(HoldDuration).ok_or_else(invalid)
+    }
+}
+
+impl Default for HoldDuration {
+    fn default() -> Self {
+        HoldDuration(chrono::TimeDelta::minutes(15))
+    }
+}
+
+impl HoldDuration {
+    pub fn to_inner(&self) -> chrono::TimeDelta {
+        self.0
+    }
+}
+
+/// Coins of a user that are reserved until they are captured, released or expire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hold {
+    pub person: Person,
+    pub coins: Amount,
+    pub asset: Asset,
+    pub expires_on: chrono::DateTime<chrono::Utc>,
+}
+
+impl Payload for Hold {
+    const KIND: &'static str = "hold";
+
+    fn check(&self) -> Result<(), &'static str> {
+        if !self.coins.is_positive() {
+            return Err("Holds are of positive coins");
+        }
+
+        Ok(())
+    }
+}
+
+/// The calendar window, in UTC, that a limit counts spending over, e.g. `day`. Weeks start on Monday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitWindow {
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid limit window {0:?}. Limit windows are day, week or month")]
+pub struct LimitWindowFromStrError(String);
+
+impl FromStr for LimitWindow {
+    type Err = LimitWindowFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "day" => Ok(LimitWindow::Day),
+            "week" => Ok(LimitWindow::Week),
+            "month" => Ok(LimitWindow::Month),
+            _ => Err(LimitWindowFromStrError(s.to_owned())),
+        }
+    }
+}
+
+impl std::fmt::Display for LimitWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitWindow::Day => write!(f, "day"),
+            LimitWindow::Week => write!(f, "week"),
+            LimitWindow::Month => write!(f, "month"),
+        }
+    }
+}
+
+impl LimitWindow {
+    /// The first day of the window that `today` is in
+    pub fn get_start(&self, today: chrono::NaiveDate) -> chrono::NaiveDate {
+        use chrono::Datelike;
+
+        match self {
+            LimitWindow::Day => today,
+            LimitWindow::Week => today.week(chrono::Weekday::Mon).first_day(),
+            LimitWindow::Month => today.with_day(1).unwrap_or(today),
+        }
+    }
+
+    /// The first day of the window after the one that `today` is in, which is when the window resets. This is `None`
+    /// past the dates that chrono supports.
+    pub fn get_reset(&self, today: chrono::NaiveDate) -> Option<chrono::NaiveDate> {
+        let start = self.get_start(today);
+
+        match self {
+            LimitWindow::Day => start.checked_add_days(chrono::Days::new(1)),
+            LimitWindow::Week => start.checked_add_days(chrono::Days::new(7)),
+            LimitWindow::Month => start.checked_add_months(chrono::Months::new(1)),
+        }
+    }
+}
+
+/// The most coins of an asset that a user may spend in a window, e.g. 500 coin per day. A limit without a user is a
+/// default per-user limit, which applies to each user on their own rather than to all of them together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limit {
+    /// The user the limit is for, or `None` for each user on their own
+    pub opt_person: Option<Person>,
+    pub coins: Amount,
+    pub asset: Asset,
+    pub window: LimitWindow,
+}
+
+impl Payload for Limit {
+    const KIND: &'static str = "limit";
+
+    fn check(&self) -> Result<(), &'static str> {
+        if self.coins.is_negative() {
+            return Err("Limits are of 0 coins or more");
+        }
+
+        Ok(())
+    }
+}
+
+impl Limit {
+    /// Whether the limit restricts what the user spends
+    pub fn applies_to(&self, person: &Person) -> bool {
+        self.opt_person.as_ref().is_none_or(|other| other == person)
+    }
+}
+
+/// How far below 0 a user's balance of an asset may go, e.g. 200 coin lets the user spend down to -200 coin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreditLine {
+    pub person: Person,
+    pub coins: Amount,
+    pub asset: Asset,
+}
+
+impl Payload for CreditLine {
+    const KIND: &'static str = "credit line";
+
+    fn check(&self) -> Result<(), &'static str> {
+        if self.coins.is_negative() {
+            return Err("Credit lines are of 0 coins or more");
+        }
+
+        Ok(())
+    }
+}
+
+/// Coins that one user owes another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Iou {
+    pub debtor: Person,
+    pub creditor: Person,
+    pub coins: Amount,
+    pub asset: Asset,
+}
+
+impl Payload for Iou {
+    const KIND: &'static str = "IOU";
+
+    fn check(&self) -> Result<(), &'static str> {
+        if self.debtor == self.creditor {
+            return Err("IOUs are between two different users");
+        }
+
+        if !self.coins.is_positive() {
+            return Err("IOUs are of positive coins");
+        }
+
+        Ok(())
+    }
 }
 
 pub mod coin_store {
@@ -42,10 +853,34 @@ pub mod coin_store {
         events_grouped_partial_table: coin_store_events_grouped_partial,
         hist_table: coin_store_hist,
         hist_partial_table: coin_store_hist_partial,
+        sessions_table: coin_store_sessions,
+        snapshots_table: coin_store_snapshots,
+        snapshot_hist_table: coin_store_snapshot_hist,
+        schema_version: crate::db::upcast::COIN_STORE_SCHEMA_VERSION,
+        upcasters: crate::db::upcast::COIN_STORE_UPCASTERS,
+
+        // Event columns of this store, in the order they are hashed
+        event_fields: {
+            opt_tx_id: Option<i32>,
+            opt_rate: Option<String>,
+            opt_scale: Option<i32>,
+            opt_schedule: Option<String>,
+            opt_hold: Option<String>,
+            opt_expires_on: Option<String>,
+            opt_limit: Option<String>,
+            opt_credit_line: Option<String>,
+            opt_iou: Option<String>,
+            opt_made_on: Option<String>,
+        },
+
+        hist_key_fields: {
+            asset: super::Asset,
+        },
 
         fields_read: {
             person: super::Person,
-            coins: i32,
+            coins: super::Amount,
+            asset: super::Asset,
         }
     }
 
@@ -56,13 +891,38 @@ pub mod coin_store {
         events_grouped_partial_table: coin_store_events_grouped_partial,
         hist_table: coin_store_hist,
         hist_partial_table: coin_store_hist_partial,
+        sessions_table: coin_store_sessions,
+        snapshots_table: coin_store_snapshots,
+        snapshot_hist_table: coin_store_snapshot_hist,
+        chain_seals_table: coin_store_chain_seals,
+        snapshot_every_n_events: 50,
+        schema_version: crate::db::upcast::COIN_STORE_SCHEMA_VERSION,
+        fields_added: crate::db::upcast::COIN_STORE_FIELDS_ADDED,
+
+        // Event columns of this store, in the order they are hashed
+        event_fields: {
+            opt_tx_id: Option<i32>,
+            opt_rate: Option<String>,
+            opt_scale: Option<i32>,
+            opt_schedule: Option<String>,
+            opt_hold: Option<String>,
+            opt_expires_on: Option<String>,
+            opt_limit: Option<String>,
+            opt_credit_line: Option<String>,
+            opt_iou: Option<String>,
+            opt_made_on: Option<String>,
+        },
+
+        // Event column that groups postings into transactions, which are toggled as a whole
+        tx_id_field: opt_tx_id,
 
         fields_write: {
-            coins: i32,
+            coins: super::Amount,
         },
 
         fields_write_ref: {
             person: &'a super::Person,
+            asset: &'a super::Asset,
         },
     }
 }
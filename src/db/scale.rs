@@ -0,0 +1,84 @@
+//! The number of decimals of the amounts in a store, set by a `scale` event.
+//!
+//! Amounts are stored as whole numbers of the smallest unit, e.g. cents at a scale of 2, so that the projections sum
+//! them exactly. The scale only decides how amounts are parsed and displayed. Like double-entry mode, it holds for
+//! the whole store and can only be set before any coins have moved or any event with an amount in its payload has been
+//! recorded, since it changes what the stored amounts mean.
+
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::EventAction;
+use crate::db::models::{Scale, ScaleFromStrError, coin_store};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum ScaleError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Stored scale is invalid: {0}")]
+    InvalidScale(#[from] ScaleFromStrError),
+
+    #[error("The scale can only be set before any coins have moved or amounts have been recorded")]
+    CoinsAlreadyMoved,
+}
+
+/// The scale set by the latest scale event, or 0 decimals if none was set
+pub fn get_scale(conn: &mut SqliteConnection) -> Result<Scale, ScaleError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let opt_scale: Option<Option<i32>> = dsl::coin_store_events
+        .filter(dsl::ev_action.eq(EventAction::Scale))
+        .order(dsl::id.desc())
+        .select(dsl::opt_scale)
+        .first(conn)
+        .optional()?;
+
+    match opt_scale.flatten() {
+        Some(decimals) => Ok(Scale::try_from(decimals)?),
+        None => Ok(Scale::default()),
+    }
+}
+
+/// Records the scale of the store from now on
+pub fn set_scale(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    scale: Scale,
+    ev_desc: &str,
+) -> Result<coin_store::Event, ScaleError> {
+    use crate::autogen::schema::{coin_store_diffs, coin_store_events};
+
+    conn.transaction(|conn| {
+        let coins_moved = diesel::select(diesel::dsl::exists(
+            coin_store_diffs::dsl::coin_store_diffs.filter(coin_store_diffs::dsl::coins.ne(0)),
+        ))
+        .get_result::<bool>(conn)?;
+
+        // Schedules, holds, limits, credit lines and IOUs carry amounts in their payloads too
+        let amounts_recorded = diesel::select(diesel::dsl::exists(
+            coin_store_events::dsl::coin_store_events.filter(
+                coin_store_events::dsl::ev_action.eq_any([
+                    EventAction::Schedule,
+                    EventAction::Hold,
+                    EventAction::Limit,
+                    EventAction::CreditLine,
+                    EventAction::Iou,
+                ]),
+            ),
+        ))
+        .get_result::<bool>(conn)?;
+
+        if coins_moved || amounts_recorded {
+            return Err(ScaleError::CoinsAlreadyMoved);
+        }
+
+        let new_event = coin_store::NewEvent {
+            opt_scale: Some(scale.to_inner() as i32),
+            ..coin_store::NewEvent::new(EventAction::Scale, span_frame, ev_desc)
+        };
+
+        Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+    })
+}
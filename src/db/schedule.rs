@@ -0,0 +1,311 @@
+//! Recurring income and expenses, set up by `schedule` events.
+//!
+//! A schedule, such as 100 coin for alice every month from 2026-11-01, is made in a span frame, and its occurrences are
+//! written there as the same updates that income and expenses are, each pointing at the schedule event. Since the
+//! occurrences themselves are the record of what was written, running the schedules again, after a restart or from
+//! another process, only writes the ones that are due and still missing. An `unschedule` event stops a schedule
+//! from writing any more occurrences. Schedules of closed span frames wait until they are reopened.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::autogen::schema::EventAction;
+use crate::db::double_entry::{self, DoubleEntryError};
+use crate::db::hold::HoldError;
+use crate::db::limit::LimitError;
+use crate::db::models::{
+    Amount, AmountError, Asset, Payload, PayloadError, Person, Schedule, coin_store,
+};
+use crate::db::spend::{self, Expense, SpendError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Double-entry Error: {0}")]
+    DoubleEntryError(#[from] DoubleEntryError),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Spend Error: {0}")]
+    SpendError(#[from] SpendError),
+
+    #[error("Payload Error: {0}")]
+    InvalidSchedule(#[from] PayloadError),
+
+    #[error("Event {0} is not a schedule")]
+    UnknownSchedule(i32),
+
+    #[error("Schedule {0} is already cancelled")]
+    AlreadyCancelled(i32),
+}
+
+/// A schedule with what became of it so far
+#[derive(Debug)]
+pub struct ScheduleInfo {
+    pub event: coin_store::Event,
+    pub schedule: Schedule,
+    pub cancelled: bool,
+    /// Occurrences written so far, which are always the first ones
+    pub occurrence_count: u32,
+}
+
+impl ScheduleInfo {
+    pub fn span_frame(&self) -> SpanFrame {
+        SpanFrame {
+            span: self.event.span,
+            frame: self.event.frame,
+        }
+    }
+
+    /// The due date of the first occurrence that is not written yet, unless the schedule is cancelled
+    pub fn next_due_date(&self) -> Option<NaiveDate> {
+        if self.cancelled {
+            return None;
+        }
+
+        self.schedule.nth_due_date(self.occurrence_count)
+    }
+}
+
+/// An occurrence written by `run_schedules`
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub schedule_ev_id: i32,
+    pub span_frame: SpanFrame,
+    pub due_date: NaiveDate,
+    pub person: Person,
+    pub coins: Amount,
+    pub asset: Asset,
+}
+
+/// An occurrence that `run_schedules` did not write, because the user could not spend its coins
+#[derive(Debug)]
+pub struct RefusedOccurrence {
+    pub occurrence: Occurrence,
+    pub error: SpendError,
+}
+
+/// What a run of the schedules wrote, and what it refused to
+#[derive(Debug, Default)]
+pub struct ScheduleRun {
+    pub written: Vec<Occurrence>,
+    pub refused: Vec<RefusedOccurrence>,
+}
+
+/// Records the schedule in the span frame. Nothing is written for it until the schedules are run.
+pub fn add_schedule(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    schedule: &Schedule,
+    ev_desc: &str,
+) -> Result<coin_store::Event, ScheduleError> {
+    let new_event = coin_store::NewEvent {
+        opt_schedule: Some(schedule.to_payload()?),
+        ..coin_store::NewEvent::new(EventAction::Schedule, span_frame, ev_desc)
+    };
+
+    Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+}
+
+/// Every schedule in id order, cancelled ones included
+pub fn get_schedules(conn: &mut SqliteConnection) -> Result<Vec<ScheduleInfo>, ScheduleError> {
+    use crate::autogen::schema::coin_store_events::dsl;
+
+    let events: Vec<coin_store::Event> = dsl::coin_store_events
+        .filter(dsl::ev_action.eq(EventAction::Schedule))
+        .order(dsl::id.asc())
+        .select(coin_store::Event::as_select())
+        .get_results(conn)?;
+
+    let schedule_ev_ids = events.iter().map(|event| event.id).collect::<Vec<_>>();
+
+    // Events that point at a schedule are its occurrences, or the unschedule event that cancelled it
+    let pointing: Vec<(Option<i32>, EventAction)> = dsl::coin_store_events
+        .filter(dsl::opt_target_ev_id.eq_any(&schedule_ev_ids))
+        .select((dsl::opt_target_ev_id, dsl::ev_action))
+        .get_results(conn)?;
+
+    let mut mut_occurrence_counts: HashMap<i32, u32> = HashMap::new();
+    let mut mut_cancelled = vec![];
+
+    for (opt_target_ev_id, ev_action) in pointing {
+        let Some(target_ev_id) = opt_target_ev_id else {
+            continue;
+        };
+
+        match ev_action {
+            EventAction::Unschedule => mut_cancelled.push(target_ev_id),
+            _ => *mut_occurrence_counts.entry(target_ev_id).or_default() += 1,
+        }
+    }
+
+    events
+        .into_iter()
+        .map(|event| {
+            let schedule =
+                Schedule::from_payload(event.opt_schedule.as_deref().unwrap_or_default())?;
+
+            Ok(ScheduleInfo {
+                cancelled: mut_cancelled.contains(&event.id),
+                occurrence_count: mut_occurrence_counts
+                    .get(&event.id)
+                    .copied()
+                    .unwrap_or_default(),
+                schedule,
+                event,
+            })
+        })
+        .collect()
+}
+
+/// Stops the schedule from writing any more occurrences. The cancellation is recorded in the schedule's span frame,
+/// and the occurrences written so far stay.
+pub fn cancel_schedule(
+    conn: &mut SqliteConnection,
+    schedule_ev_id: i32,
+    ev_desc: &str,
+) -> Result<coin_store::Event, ScheduleError> {
+    conn.transaction(|conn| {
+        let info = get_schedules(conn)?
+            .into_iter()
+            .find(|info| info.event.id == schedule_ev_id)
+            .ok_or(ScheduleError::UnknownSchedule(schedule_ev_id))?;
+
+        if info.cancelled {
+            return Err(ScheduleError::AlreadyCancelled(schedule_ev_id));
+        }
+
+        let new_event = coin_store::NewEvent {
+            opt_target_ev_id: Some(schedule_ev_id),
+            ..coin_store::NewEvent::new(EventAction::Unschedule, &info.span_frame(), ev_desc)
+        };
+
+        Ok(coin_store::insert_recorded_event(conn, new_event, None)?)
+    })
+}
+
+/// The schedules that are run in the span frame, which are its own ones that are not cancelled, unless it is closed
+pub fn get_running_schedules(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+) -> Result<Vec<ScheduleInfo>, ScheduleError> {
+    let is_closed =
+        coin_store::get_span_frame_info(conn, span_frame)?.is_some_and(|info| info.closed);
+
+    if is_closed {
+        return Ok(vec![]);
+    }
+
+    Ok(get_schedules(conn)?
+        .into_iter()
+        .filter(|info| {
+            !info.cancelled
+                && info.event.span == span_frame.span
+                && info.event.frame == span_frame.frame
+        })
+        .collect())
+}
+
+/// Whether the error is a refusal to spend the coins, rather than a failure of the store
+fn is_refused(e: &SpendError) -> bool {
+    matches!(
+        e,
+        SpendError::HoldError(HoldError::InsufficientFunds { .. })
+            | SpendError::LimitError(LimitError::LimitExceeded { .. })
+    )
+}
+
+/// Writes the occurrence of the schedule, taking the coins of an expense from the user if they have them available at
+/// `now`
+fn insert_occurrence(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    info: &ScheduleInfo,
+    due_date: NaiveDate,
+    now: DateTime<Utc>,
+) -> Result<(), ScheduleError> {
+    let ev_desc = format!("{} (due {due_date})", info.event.ev_desc);
+
+    if info.schedule.coins.is_negative() {
+        let expense = Expense {
+            person: &info.schedule.person,
+            coins: info.schedule.coins.checked_neg()?,
+            asset: &info.schedule.asset,
+            ev_desc: &ev_desc,
+            opt_target_ev_id: Some(info.event.id),
+        };
+
+        spend::insert_expense(conn, span_frame, &expense, now)?;
+    } else {
+        double_entry::insert_coins_for_person(
+            conn,
+            span_frame,
+            &info.schedule.person,
+            info.schedule.coins,
+            &info.schedule.asset,
+            &ev_desc,
+            Some(info.event.id),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes every occurrence of the span frame's schedules that is due on or before `until` and not written yet. The
+/// occurrences are counted in the same transaction as they are written, so none is ever written twice. Expenses are
+/// only written if the user has the coins available at `now`, and each occurrence is written in a savepoint of its
+/// own, so one that is refused is left out alone. Since occurrences are counted from the first, the later ones of its
+/// schedule wait for the next run. Returns what was written and what was refused, in order of schedule and due date.
+pub fn run_schedules(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    until: NaiveDate,
+    now: DateTime<Utc>,
+) -> Result<ScheduleRun, ScheduleError> {
+    conn.transaction(|conn| {
+        let mut mut_run = ScheduleRun::default();
+
+        for info in get_running_schedules(conn, span_frame)? {
+            let mut mut_n = info.occurrence_count;
+
+            while let Some(due_date) = info.schedule.nth_due_date(mut_n)
+                && due_date <= until
+            {
+                let occurrence = Occurrence {
+                    schedule_ev_id: info.event.id,
+                    span_frame: span_frame.clone(),
+                    due_date,
+                    person: info.schedule.person.clone(),
+                    coins: info.schedule.coins,
+                    asset: info.schedule.asset.clone(),
+                };
+
+                let res = conn
+                    .transaction(|conn| insert_occurrence(conn, span_frame, &info, due_date, now));
+
+                match res {
+                    Ok(()) => mut_run.written.push(occurrence),
+                    Err(ScheduleError::SpendError(error)) if is_refused(&error) => {
+                        mut_run
+                            .refused
+                            .push(RefusedOccurrence { occurrence, error });
+
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+
+                mut_n += 1;
+            }
+        }
+
+        Ok(mut_run)
+    })
+}
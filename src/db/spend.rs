@@ -0,0 +1,137 @@
+//! Spending the coins of users.
+//!
+//! Expenses, transfers, exchanges, splits, IOU repayments and scheduled expenses take coins out of users' accounts,
+//! so they are written here, in the same transaction as the check that every user who pays has the coins available
+//! and is within their limits. What a user has available is their balance and credit line less their active holds and
+//! expired coins. Captures of holds spend coins that were already set aside, so they only check the limits themselves,
+//! and expiry sweeps only write off what is gone, so they write their postings directly.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use thiserror::Error;
+
+use crate::db::double_entry::{self, DoubleEntryError, Posting};
+use crate::db::hold::{self, HoldError};
+use crate::db::limit::{self, LimitError};
+use crate::db::models::{Amount, AmountError, Asset, Person};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum SpendError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Double-entry Error: {0}")]
+    DoubleEntryError(#[from] DoubleEntryError),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Hold Error: {0}")]
+    HoldError(#[from] HoldError),
+
+    #[error("Limit Error: {0}")]
+    LimitError(#[from] LimitError),
+
+    #[error("Coins to spend must be positive")]
+    NotPositive,
+}
+
+/// Refuses to spend more than the user has available at `now`, or than the limits that apply to them allow
+pub fn check_spend(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    person: &Person,
+    asset: &Asset,
+    coins: Amount,
+    now: DateTime<Utc>,
+) -> Result<(), SpendError> {
+    hold::check_available(conn, span_frame, person, asset, coins, now)?;
+    limit::check_limits(conn, span_frame, person, asset, coins, now)?;
+
+    Ok(())
+}
+
+/// Coins of an asset that a user pays
+#[derive(Debug, Clone)]
+pub struct Expense<'a> {
+    pub person: &'a Person,
+    pub coins: Amount,
+    pub asset: &'a Asset,
+    pub ev_desc: &'a str,
+    pub opt_target_ev_id: Option<i32>,
+}
+
+/// Takes the coins of the expense from the user, if they have them available at `now`. In double-entry mode, they are
+/// paid into `@expenses` in the same transaction. Only the user's posting points at `opt_target_ev_id`.
+pub fn insert_expense(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    expense: &Expense,
+    now: DateTime<Utc>,
+) -> Result<(), SpendError> {
+    let Expense {
+        person,
+        coins,
+        asset,
+        ev_desc,
+        opt_target_ev_id,
+    } = *expense;
+
+    if !coins.is_positive() {
+        return Err(SpendError::NotPositive);
+    }
+
+    conn.transaction(|conn| {
+        check_spend(conn, span_frame, person, asset, coins, now)?;
+
+        double_entry::insert_coins_for_person(
+            conn,
+            span_frame,
+            person,
+            coins.checked_neg()?,
+            asset,
+            ev_desc,
+            opt_target_ev_id,
+        )?;
+
+        Ok(())
+    })
+}
+
+/// Writes the postings as one transaction, all or nothing, if every user they take coins from has them available at
+/// `now`. System accounts take the other side and may go below 0, so they are not checked. Returns its transaction id.
+pub fn insert_spending_transaction(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    postings: &[Posting],
+    now: DateTime<Utc>,
+) -> Result<i32, SpendError> {
+    conn.transaction(|conn| {
+        // A user may pay in more than one posting, so what they spend is checked as a whole
+        let mut mut_spent: Vec<(&Person, &Asset, Amount)> = vec![];
+
+        for posting in postings
+            .iter()
+            .filter(|posting| posting.coins.is_negative() && !posting.person.is_system_account())
+        {
+            let coins = posting.coins.checked_neg()?;
+
+            match mut_spent
+                .iter_mut()
+                .find(|(person, asset, _)| *person == posting.person && *asset == posting.asset)
+            {
+                Some((_, _, spent)) => *spent = spent.checked_add(coins)?,
+                None => mut_spent.push((posting.person, posting.asset, coins)),
+            }
+        }
+
+        for (person, asset, coins) in mut_spent {
+            check_spend(conn, span_frame, person, asset, coins, now)?;
+        }
+
+        let tx_id = double_entry::insert_transaction(conn, span_frame, postings)?;
+
+        Ok(tx_id)
+    })
+}
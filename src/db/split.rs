@@ -0,0 +1,294 @@
+//! Splitting a group purchase between the users who took part in it.
+//!
+//! One user pays the whole purchase into `@expenses`, and every other participant pays their share of it back to them.
+//! The shares are worked out equally, by weights or as exact amounts, and the purchase and the repayments are written
+//! as the postings of one transaction, so they go through all or nothing and share its transaction id. The payer ends
+//! up paying their own share, if they took part, and nothing otherwise.
+//!
+//! Shares are worked out in the smallest unit of the asset. Whatever cannot be divided evenly goes one unit at a time to
+//! the participants that lost the most to rounding, and to the one listed first among those that lost the same, so the
+//! same split always gives the same shares.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::autogen::schema::ObjState;
+use crate::db::double_entry::Posting;
+use crate::db::models::{Amount, AmountError, Asset, Person};
+use crate::db::spend::{self, SpendError};
+use crate::macros::diesel_hist_models::SpanFrame;
+
+#[derive(Error, Debug)]
+pub enum SplitError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Amount Error: {0}")]
+    AmountError(#[from] AmountError),
+
+    #[error("Spend Error: {0}")]
+    SpendError(#[from] SpendError),
+
+    #[error("Coins to split must be positive")]
+    NotPositive,
+
+    #[error("A split needs at least one participant")]
+    NoParticipants,
+
+    #[error("{0} takes part in the split more than once")]
+    DuplicateParticipant(String),
+
+    #[error("Expected one weight or amount per participant, {expected} in total, but got {actual}")]
+    WrongCount { expected: usize, actual: usize },
+
+    #[error("Weights must add up to more than 0")]
+    ZeroWeights,
+
+    #[error("Exact amounts must not be negative")]
+    NegativeAmount,
+
+    #[error("Exact amounts add up to {sum} instead of {total}")]
+    ExactMismatch { total: Amount, sum: Amount },
+
+    #[error("Nothing to split, since only the payer has a share")]
+    NothingToSplit,
+}
+
+/// How a total is divided between the participants, in the order they are listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitMode {
+    Equal,
+    /// Shares in proportion to the weights, e.g. `2 1 1` gives the first participant half
+    Weights(Vec<u32>),
+    /// Shares given as they are, which must add up to the total
+    Exact(Vec<Amount>),
+}
+
+/// A purchase that the payer paid for the participants, to be divided between them
+#[derive(Debug, Clone)]
+pub struct SplitOrder<'a> {
+    pub payer: &'a Person,
+    pub total: Amount,
+    pub asset: &'a Asset,
+    pub participants: &'a [Person],
+    pub mode: &'a SplitMode,
+    pub ev_desc: &'a str,
+}
+
+/// What a participant pays of a split
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    pub person: Person,
+    pub coins: Amount,
+}
+
+/// A split that was written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split {
+    /// The transaction that ties the postings of the split together
+    pub tx_id: i32,
+    pub shares: Vec<Share>,
+}
+
+/// Divides the total between the participants in the given mode, in the order they are listed
+pub fn get_shares(
+    total: Amount,
+    participants: &[Person],
+    mode: &SplitMode,
+) -> Result<Vec<Share>, SplitError> {
+    if !total.is_positive() {
+        return Err(SplitError::NotPositive);
+    }
+
+    if participants.is_empty() {
+        return Err(SplitError::NoParticipants);
+    }
+
+    if let Some(person) = participants.iter().duplicates().next() {
+        return Err(SplitError::DuplicateParticipant(person.to_inner()));
+    }
+
+    let check_count = |actual: usize| {
+        if actual == participants.len() {
+            Ok(())
+        } else {
+            Err(SplitError::WrongCount {
+                expected: participants.len(),
+                actual,
+            })
+        }
+    };
+
+    let coins = match mode {
+        SplitMode::Equal => divide_by_weights(total, &vec![1; participants.len()])?,
+        SplitMode::Weights(weights) => {
+            check_count(weights.len())?;
+
+            divide_by_weights(total, weights)?
+        }
+        SplitMode::Exact(amounts) => {
+            check_count(amounts.len())?;
+
+            if amounts.iter().any(|coins| coins.is_negative()) {
+                return Err(SplitError::NegativeAmount);
+            }
+
+            let sum = Amount::checked_sum(amounts.iter().copied())?;
+
+            if sum != total {
+                return Err(SplitError::ExactMismatch { total, sum });
+            }
+
+            amounts.clone()
+        }
+    };
+
+    Ok(participants
+        .iter()
+        .zip(coins)
+        .map(|(person, coins)| Share {
+            person: person.clone(),
+            coins,
+        })
+        .collect())
+}
+
+/// Divides the total in proportion to the weights, rounding down, then hands out what is left one unit at a time by
+/// the largest remainder
+fn divide_by_weights(total: Amount, weights: &[u32]) -> Result<Vec<Amount>, SplitError> {
+    let total = i128::from(total.to_inner());
+    let weight_sum = weights
+        .iter()
+        .map(|weight| i128::from(*weight))
+        .sum::<i128>();
+
+    if weight_sum == 0 {
+        return Err(SplitError::ZeroWeights);
+    }
+
+    // Neither can overflow, since the total is an i64 and the weights are u32
+    let mut mut_shares = weights
+        .iter()
+        .map(|weight| total * i128::from(*weight) / weight_sum)
+        .collect::<Vec<_>>();
+
+    let left = total - mut_shares.iter().sum::<i128>();
+
+    let by_remainder = weights
+        .iter()
+        .enumerate()
+        .sorted_by_key(|(index, weight)| {
+            (
+                std::cmp::Reverse(total * i128::from(**weight) % weight_sum),
+                *index,
+            )
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    // What is left is less than the number of participants
+    for index in by_remainder.into_iter().take(left as usize) {
+        mut_shares[index] += 1;
+    }
+
+    Ok(mut_shares
+        .into_iter()
+        .map(|coins| Amount::new(coins as i64))
+        .collect())
+}
+
+/// Divides the total between the participants and writes a transaction in which the payer pays the total into
+/// `@expenses`, and each of the other participants pays their share back to the payer, if the payer has the total and
+/// the others their share available at `now`
+pub fn insert_split(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    order: &SplitOrder,
+    now: DateTime<Utc>,
+) -> Result<Split, SplitError> {
+    use crate::autogen::schema::coin_store_diffs::dsl;
+
+    let SplitOrder {
+        payer,
+        total,
+        asset,
+        participants,
+        mode,
+        ev_desc,
+    } = *order;
+
+    let shares = get_shares(total, participants, mode)?;
+
+    let repayments = shares
+        .iter()
+        .filter(|share| share.person != *payer && share.coins.is_positive())
+        .collect::<Vec<_>>();
+
+    if repayments.is_empty() {
+        return Err(SplitError::NothingToSplit);
+    }
+
+    let repaid = Amount::checked_sum(repayments.iter().map(|share| share.coins))?;
+
+    let expenses = Person::expenses();
+    let expenses_obj_id = expenses.get_obj_id();
+
+    let tx_id = conn.transaction(|conn| {
+        let expenses_obj_exists = diesel::select(diesel::dsl::exists(
+            dsl::coin_store_diffs.filter(dsl::obj_id.eq(expenses_obj_id)),
+        ))
+        .get_result::<bool>(conn)?;
+
+        let purchase = Posting {
+            obj_id: payer.get_obj_id(),
+            obj_state: ObjState::Update,
+            opt_target_ev_id: None,
+            ev_desc,
+            person: payer,
+            coins: total.checked_neg()?,
+            asset,
+            opt_rate: None,
+            opt_expires_on: None,
+        };
+
+        // The first posting of the system account inserts it
+        let expense = Posting {
+            obj_id: expenses_obj_id,
+            obj_state: if expenses_obj_exists {
+                ObjState::Update
+            } else {
+                ObjState::Insert
+            },
+            person: &expenses,
+            coins: total,
+            ..purchase.clone()
+        };
+
+        let mut mut_postings = vec![purchase.clone(), expense];
+
+        for share in repayments.iter() {
+            mut_postings.push(Posting {
+                obj_id: share.person.get_obj_id(),
+                person: &share.person,
+                coins: share.coins.checked_neg()?,
+                ..purchase.clone()
+            });
+        }
+
+        mut_postings.push(Posting {
+            coins: repaid,
+            ..purchase.clone()
+        });
+
+        Ok::<_, SplitError>(spend::insert_spending_transaction(
+            conn,
+            span_frame,
+            &mut_postings,
+            now,
+        )?)
+    })?;
+
+    Ok(Split { tx_id, shares })
+}
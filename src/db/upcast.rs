@@ -0,0 +1,61 @@
+//! Schema versions of diff payloads and the upcasters that bring old payloads up to the current shape on read.
+//!
+//! When the payload of a store changes, its schema version is bumped by a migration that upcasts the stored diffs, for
+//! example by giving a new column a DEFAULT, since the projections read the payload in SQL. The version a diff was
+//! written with still decides which fields its hash covers. Every version step registers an upcaster here, which runs
+//! whenever a diff is loaded through `get_diffs_upcasted`. A step that its migration made in SQL registers a no-op, so
+//! that a forgotten step fails loudly instead of passing payloads through unchanged.
+
+use thiserror::Error;
+
+use crate::db::models::coin_store;
+
+/// Converts a payload written under `from_version` to the shape of `from_version + 1`
+pub struct Upcaster<T> {
+    pub from_version: i32,
+    pub upcast: fn(T) -> T,
+}
+
+#[derive(Error, Debug)]
+pub enum UpcastError {
+    #[error("Schema version {0} is newer than the current version {1}")]
+    UnknownSchemaVersion(i32, i32),
+
+    #[error("No upcaster registered from schema version {0}")]
+    MissingUpcaster(i32),
+}
+
+/// Upcasts a payload written under `version` to `current_version` by chaining `upcasters`
+pub fn upcast_with<T>(
+    upcasters: &[Upcaster<T>],
+    current_version: i32,
+    version: i32,
+    payload: T,
+) -> Result<T, UpcastError> {
+    if version > current_version {
+        return Err(UpcastError::UnknownSchemaVersion(version, current_version));
+    }
+
+    (version..current_version).try_fold(payload, |payload, from_version| {
+        upcasters
+            .iter()
+            .find(|upcaster| upcaster.from_version == from_version)
+            .map(|upcaster| (upcaster.upcast)(payload))
+            .ok_or(UpcastError::MissingUpcaster(from_version))
+    })
+}
+
+/// Version 2 added `asset`, which the migration gives a DEFAULT of the default asset
+pub const COIN_STORE_SCHEMA_VERSION: i32 = 2;
+
+pub const COIN_STORE_UPCASTERS: &[Upcaster<coin_store::Common>] = &[
+    // The migration to version 2 filled `asset` in SQL
+    Upcaster {
+        from_version: 1,
+        upcast: |common| common,
+    },
+];
+
+/// Payload fields added after the first schema version, with the version that added them. Diffs written under an
+/// earlier version are hashed without them, so that their hashes still verify.
+pub const COIN_STORE_FIELDS_ADDED: &[(&str, i32)] = &[("asset", 2)];
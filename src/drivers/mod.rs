@@ -18,7 +18,23 @@ pub fn read_str_or_quit(item_name: &str) -> Option<String> {
     Some(mut_input)
 }
 
+/// Uses the command argument at `idx` if it was passed, otherwise asks the user for it
+pub fn arg_or_read_str_or_quit(args: &[String], idx: usize, item_name: &str) -> Option<String> {
+    match args.get(idx) {
+        Some(arg) => Some(arg.clone()),
+        None => read_str_or_quit(item_name),
+    }
+}
+
 pub fn read_input_from_user_until_valid_or_quit<T: FromStr>(item_name: &str) -> Option<T> {
+    read_input_from_user_until_parsed_or_quit(item_name, T::from_str)
+}
+
+/// Like `read_input_from_user_until_valid_or_quit`, but with the given parser, e.g. for input that depends on the store
+pub fn read_input_from_user_until_parsed_or_quit<T, E>(
+    item_name: &str,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Option<T> {
     let mut mut_input = String::new();
 
     loop {
@@ -31,7 +47,7 @@ pub fn read_input_from_user_until_valid_or_quit<T: FromStr>(item_name: &str) ->
             break None;
         }
 
-        let parsed = T::from_str(&mut_input);
+        let parsed = parse(&mut_input);
 
         match parsed {
             Ok(item) => break Some(item),
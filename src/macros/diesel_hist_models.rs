@@ -7,6 +7,131 @@ pub struct SpanFrame {
     pub frame: i32,
 }
 
+/// Summary of a created span frame as seen from the events table. See `get_span_frame_infos`.
+///
+/// The parent is the frame targeted by the open event. Open events written before parents were recorded have no target,
+/// so their parent is inferred: a frame at span `s` branches off the latest frame opened at span `s - 1` before it, and
+/// frames at span 1 are roots.
+#[derive(Debug, Clone)]
+pub struct SpanFrameInfo {
+    pub span_frame: SpanFrame,
+    pub opt_parent: Option<SpanFrame>,
+    pub ev_desc: String,
+    pub created_on_ts: f32,
+    pub event_count: usize,
+    pub closed: bool,
+    pub names: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl SpanFrameInfo {
+    /// Whether an event made in the span frame `span`/`frame` at `created_on_ts` is seen in this span frame, the same
+    /// way its records are: it was made in the span frame itself, or in a lower span before the span frame was opened.
+    /// Events that are not records, e.g. rates, holds or limits, are read with it.
+    pub fn sees(&self, span: i32, frame: i32, created_on_ts: f32) -> bool {
+        (span == self.span_frame.span && frame == self.span_frame.frame)
+            || (span < self.span_frame.span && created_on_ts < self.created_on_ts)
+    }
+}
+
+/// Names are movable like branches: the latest name event for a label decides its span frame. Tags are immutable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpanFrameLabelKind {
+    Name,
+    Tag,
+}
+
+/// A human readable label attached to a span frame. See `get_span_frame_labels`.
+#[derive(Debug, Clone)]
+pub struct SpanFrameLabel {
+    pub kind: SpanFrameLabelKind,
+    pub label: String,
+    pub span_frame: SpanFrame,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LabelSpanFrameError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Invalid label {0:?}. Labels must be non-empty and contain no whitespace")]
+    InvalidLabel(String),
+
+    #[error("Tag {0:?} already exists and tags cannot be moved")]
+    DuplicateTag(String),
+
+    #[error("Label {0:?} is already used by a {1:?}")]
+    LabelKindConflict(String, SpanFrameLabelKind),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadDiffError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Upcast Error: {0}")]
+    UpcastError(#[from] crate::db::upcast::UpcastError),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum VerifyEventChainError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("Event {ev_id} has no hash although events before it do")]
+    MissingHash { ev_id: i32 },
+
+    #[error(
+        "Event {ev_id} does not match its hash. It or the event before it was changed, or events were removed"
+    )]
+    HashMismatch { ev_id: i32 },
+
+    #[error("Event {ev_id} refers to diff {diff_id}, which does not exist")]
+    MissingDiff { ev_id: i32, diff_id: i32 },
+}
+
+/// Hex encoded SHA-256 of the previous event's hash followed by the content of an event
+pub fn hash_event_content(opt_prev_ev_hash: Option<&str>, content: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+
+    hasher.update(opt_prev_ev_hash.unwrap_or_default().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(content.as_bytes());
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// How the value of a store's own event column is written into the content that the event hash covers. Numbers are
+/// written as is and text is quoted.
+pub trait EventFieldHashContent {
+    fn to_hash_content(&self) -> String;
+}
+
+impl EventFieldHashContent for i32 {
+    fn to_hash_content(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl EventFieldHashContent for String {
+    fn to_hash_content(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InsertEventForObjError {
+    #[error("Diesel Error: {0:?}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error(
+        "Coins can only move in balanced transactions in double-entry mode, write postings instead"
+    )]
+    DoubleEntry,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CreateSpanFrameError {
     #[error("Diesel Error: {0:?}")]
@@ -24,6 +149,13 @@ macro_rules! create_diesel_hist_structs_read {
         events_grouped_partial_table: $events_grouped_partial_table:ident,
         hist_table: $hist_table:ident,
         hist_partial_table: $hist_partial_table:ident,
+        sessions_table: $sessions_table:ident,
+        snapshots_table: $snapshots_table:ident,
+        snapshot_hist_table: $snapshot_hist_table:ident,
+        schema_version: $schema_version:expr,
+        upcasters: $upcasters:expr,
+        event_fields: {$($event_field:ident: Option<$typ_event_field:ty>),* $(,)?},
+        hist_key_fields: {$($hist_key_field:ident: $typ_hist_key:ty),* $(,)?},
 
         fields_read: {$($field_read:ident: $typ_read:ty),+ $(,)?}$(,)?
     } => {
@@ -50,6 +182,7 @@ macro_rules! create_diesel_hist_structs_read {
             $(
                 pub $field_read: $typ_read,
             )*
+            pub schema_version: i32,
         }
 
         impl GetCommon for Diff {
@@ -62,6 +195,14 @@ macro_rules! create_diesel_hist_structs_read {
             }
         }
 
+        impl Diff {
+            /// The payload in its current shape, however old the schema version it was written with
+            #[allow(dead_code)]
+            pub fn get_common_upcasted(&self) -> Result<Common, crate::db::upcast::UpcastError> {
+                crate::db::upcast::upcast_with($upcasters, $schema_version, self.schema_version, self.get_common())
+            }
+        }
+
         #[derive(Debug, Queryable, Selectable)]
         #[diesel(table_name = crate::autogen::schema::$events_table)]
         #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -74,9 +215,14 @@ macro_rules! create_diesel_hist_structs_read {
             pub frame: i32,
             pub created_on_ts: f32,
             pub ev_desc: String,
+            pub opt_target_ev_id: Option<i32>,
+            pub opt_ev_hash: Option<String>,
+            $(
+                pub $event_field: Option<$typ_event_field>,
+            )*
         }
 
-        #[derive(Debug, Queryable, Selectable)]
+        #[derive(Debug, Clone, Queryable, Selectable)]
         #[diesel(table_name = crate::autogen::schema::$events_grouped_table)]
         #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
         #[allow(dead_code)]
@@ -143,7 +289,7 @@ macro_rules! create_diesel_hist_structs_read {
             }
         }
 
-        #[derive(Debug, Queryable, Selectable)]
+        #[derive(Debug, Queryable, QueryableByName, Selectable)]
         #[diesel(table_name = crate::autogen::schema::$hist_table)]
         #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
         #[allow(dead_code)]
@@ -169,6 +315,17 @@ macro_rules! create_diesel_hist_structs_read {
             }
         }
 
+        /// Hist rows are projected per object and these fields, e.g. one row per asset of an object
+        #[allow(dead_code)]
+        pub type HistKey = (i32, $($typ_hist_key,)*);
+
+        impl Hist {
+            #[allow(dead_code)]
+            pub fn get_key(&self) -> HistKey {
+                (self.obj_id, $(self.$hist_key_field.clone(),)*)
+            }
+        }
+
         #[derive(Debug, Queryable, Selectable)]
         #[diesel(table_name = crate::autogen::schema::$hist_partial_table)]
         #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -194,6 +351,73 @@ macro_rules! create_diesel_hist_structs_read {
                 }
             }
         }
+
+        impl HistPartial {
+            #[allow(dead_code)]
+            pub fn get_key(&self) -> HistKey {
+                (self.obj_id, $(self.$hist_key_field.clone(),)*)
+            }
+        }
+
+        #[derive(Debug, Queryable, Selectable)]
+        #[diesel(table_name = crate::autogen::schema::$sessions_table)]
+        #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+        #[allow(dead_code)]
+        pub struct Session {
+            pub session: String,
+            pub span: i32,
+            pub frame: i32,
+            pub updated_on_ts: f32,
+        }
+
+        #[derive(Debug, Queryable, Selectable)]
+        #[diesel(table_name = crate::autogen::schema::$snapshots_table)]
+        #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+        #[allow(dead_code)]
+        pub struct Snapshot {
+            pub id: i32,
+            pub span: i32,
+            pub frame: i32,
+            pub last_ev_id: i32,
+            pub created_on_ts: f32,
+            pub ev_desc: String,
+        }
+
+        #[derive(Debug, Queryable, Selectable)]
+        #[diesel(table_name = crate::autogen::schema::$snapshot_hist_table)]
+        #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+        #[allow(dead_code)]
+        pub struct SnapshotHist {
+            pub id: i32,
+            pub snapshot_id: i32,
+            pub grp_id: i32,
+            pub grp_span: i32,
+            pub grp_frame: i32,
+            pub obj_id: i32,
+            pub obj_state: crate::autogen::schema::ObjState,
+            $(
+                pub $field_read: $typ_read,
+            )*
+        }
+
+        impl GetCommon for SnapshotHist {
+            fn get_common(&self) -> Common {
+                Common {
+                    $(
+                        $field_read: self.$field_read.clone(),
+                    )*
+                }
+            }
+        }
+
+        /// A hist row whose projection differs from the one replayed from the full event log
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        pub struct HistMismatch {
+            pub key: HistKey,
+            pub opt_projected: Option<Hist>,
+            pub opt_replayed: Option<Hist>,
+        }
     }
 }
 
@@ -206,6 +430,15 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
         events_grouped_partial_table: $events_grouped_partial_table:ident,
         hist_table: $hist_table:ident,
         hist_partial_table: $hist_partial_table:ident,
+        sessions_table: $sessions_table:ident,
+        snapshots_table: $snapshots_table:ident,
+        snapshot_hist_table: $snapshot_hist_table:ident,
+        chain_seals_table: $chain_seals_table:ident,
+        snapshot_every_n_events: $snapshot_every_n_events:expr,
+        schema_version: $schema_version:expr,
+        fields_added: $fields_added:expr,
+        event_fields: {$($event_field:ident: Option<$typ_event_field:ty>),* $(,)?},
+        tx_id_field: $tx_id_field:ident,
 
         fields_write: {$($field_write:ident: $typ_write:ty),+ $(,)?}$(,)?
         fields_write_ref: {$($field_write_ref:ident: $typ_write_ref:ty),+ $(,)?}$(,)?
@@ -233,6 +466,7 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
             $(
                 pub $field_write_ref: $typ_write_ref,
             )*
+            pub schema_version: i32,
         }
 
         impl<'a> NewDiff<'a> {
@@ -245,6 +479,7 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
                     $(
                         $field_write_ref: new_common.$field_write_ref,
                     )*
+                    schema_version: $schema_version,
                 }
             }
         }
@@ -259,30 +494,270 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
             pub frame: i32,
             pub created_on_ts: f32,
             pub ev_desc: &'a str,
+            pub opt_target_ev_id: Option<i32>,
+            pub opt_ev_hash: Option<String>,
+            $(
+                pub $event_field: Option<$typ_event_field>,
+            )*
+        }
+
+        impl Event {
+            /// The event as it was when written, before it got its id
+            fn to_new_event(&self) -> NewEvent<'_> {
+                NewEvent {
+                    opt_diff_id: self.opt_diff_id,
+                    ev_action: self.ev_action.clone(),
+                    span: self.span,
+                    frame: self.frame,
+                    created_on_ts: self.created_on_ts,
+                    ev_desc: &self.ev_desc,
+                    opt_target_ev_id: self.opt_target_ev_id,
+                    opt_ev_hash: None,
+                    $(
+                        $event_field: self.$event_field.clone(),
+                    )*
+                }
+            }
+        }
+
+        impl<'a> NewEvent<'a> {
+            /// An event in the span frame made now, with none of the optional columns set
+            #[allow(dead_code)]
+            pub fn new(
+                ev_action: crate::autogen::schema::EventAction,
+                span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+                ev_desc: &'a str,
+            ) -> NewEvent<'a> {
+                use chrono::prelude::*;
+
+                NewEvent {
+                    opt_diff_id: None,
+                    ev_action,
+                    span: span_frame.span,
+                    frame: span_frame.frame,
+                    created_on_ts: Utc::now().timestamp_millis() as f32,
+                    ev_desc,
+                    opt_target_ev_id: None,
+                    opt_ev_hash: None,
+                    $(
+                        $event_field: None,
+                    )*
+                }
+            }
         }
 
         #[derive(Debug, Insertable, AsChangeset)]
-        #[diesel(table_name = crate::autogen::schema::$events_grouped_partial_table)]
+        #[diesel(table_name = crate::autogen::schema::$sessions_table)]
         #[allow(dead_code)]
-        pub struct NewEventGroupedPartial<'a> {
-            pub grp_id: i32,
-            pub grp_span: i32,
-            pub grp_frame: i32,
-            pub grp_created_on_ts: f32,
-            pub dup: i32,
-            pub ev_id: i32,
-            pub obj_id: i32,
-            pub ev_action: crate::autogen::schema::EventAction,
+        pub struct NewSession<'a> {
+            pub session: &'a str,
+            pub span: i32,
+            pub frame: i32,
+            pub updated_on_ts: f32,
+        }
+
+        #[derive(Debug, Insertable)]
+        #[diesel(table_name = crate::autogen::schema::$snapshots_table)]
+        #[allow(dead_code)]
+        pub struct NewSnapshot<'a> {
             pub span: i32,
             pub frame: i32,
+            pub last_ev_id: i32,
             pub created_on_ts: f32,
+            pub ev_desc: &'a str,
+        }
+
+        /// The content of an event that its hash covers, including the diff it refers to
+        fn get_event_hash_content(new_event: &NewEvent, opt_diff: Option<&Diff>) -> String {
+            let mut mut_content = format!(
+                "opt_diff_id={:?}\nev_action={:?}\nspan={}\nframe={}\ncreated_on_ts={}\nev_desc={:?}\nopt_target_ev_id={:?}\n",
+                new_event.opt_diff_id,
+                new_event.ev_action,
+                new_event.span,
+                new_event.frame,
+                new_event.created_on_ts.to_bits(),
+                new_event.ev_desc,
+                new_event.opt_target_ev_id,
+            );
+
+            // Only hashed when set, so that events from before a column existed keep their hashes
             $(
-                pub $field_write: $typ_write,
-            )*
-            $(
-                pub $field_write_ref: $typ_write_ref,
+                if let Some(value) = new_event.$event_field.as_ref() {
+                    mut_content += &format!(
+                        concat!(stringify!($event_field), "={}\n"),
+                        crate::macros::diesel_hist_models::EventFieldHashContent::to_hash_content(value),
+                    );
+                }
             )*
-            pub ev_desc: &'a str,
+
+            if let Some(diff) = opt_diff {
+                mut_content += &format!("obj_id={}\nschema_version={}\n", diff.obj_id, diff.schema_version);
+
+                // Fields added in a later schema version are left out of diffs written before it
+                let is_hashed = |field: &str| {
+                    let fields_added: &[(&str, i32)] = $fields_added;
+
+                    fields_added
+                        .iter()
+                        .find(|(name, _)| *name == field)
+                        .is_none_or(|(_, version)| diff.schema_version >= *version)
+                };
+
+                $(
+                    if is_hashed(stringify!($field_write)) {
+                        mut_content += &format!(concat!(stringify!($field_write), "={:?}\n"), diff.$field_write);
+                    }
+                )*
+                $(
+                    if is_hashed(stringify!($field_write_ref)) {
+                        mut_content += &format!(concat!(stringify!($field_write_ref), "={:?}\n"), diff.$field_write_ref);
+                    }
+                )*
+            }
+
+            mut_content
+        }
+
+        fn get_diff_by_id(conn: &mut SqliteConnection, diff_id: i32) -> Result<Option<Diff>, diesel::result::Error> {
+            use crate::autogen::schema::$diff_table::dsl;
+
+            dsl::$diff_table
+                .find(diff_id)
+                .select(Diff::as_select())
+                .first(conn)
+                .optional()
+        }
+
+        /// Hashes the events written before hashing existed, in id order. This happens once: the migration that added
+        /// sealing recorded the last of them, and it is cleared here. An event that loses its hash later is left as is,
+        /// so that verifying reports it as missing.
+        #[allow(dead_code)]
+        pub fn seal_event_chain(conn: &mut SqliteConnection) -> Result<usize, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl;
+            use crate::autogen::schema::$chain_seals_table::dsl as dsl_s;
+
+            conn.transaction(|conn| {
+                let last_unsealed_ev_id: i32 = dsl_s::$chain_seals_table
+                    .select(dsl_s::last_unsealed_ev_id)
+                    .first(conn)
+                    .optional()?
+                    .unwrap_or_default();
+
+                if last_unsealed_ev_id == 0 {
+                    return Ok(0);
+                }
+
+                let unsealed: Vec<Event> = dsl::$events_table
+                    .filter(dsl::opt_ev_hash.is_null().and(dsl::id.le(last_unsealed_ev_id)))
+                    .order(dsl::id.asc())
+                    .select(Event::as_select())
+                    .get_results(conn)?;
+
+                for event in unsealed.iter() {
+                    let opt_prev_ev_hash: Option<String> = dsl::$events_table
+                        .filter(dsl::id.lt(event.id))
+                        .order(dsl::id.desc())
+                        .select(dsl::opt_ev_hash)
+                        .first(conn)
+                        .optional()?
+                        .flatten();
+
+                    let opt_diff = match event.opt_diff_id {
+                        Some(diff_id) => get_diff_by_id(conn, diff_id)?,
+                        None => None,
+                    };
+
+                    let content = get_event_hash_content(&event.to_new_event(), opt_diff.as_ref());
+
+                    let ev_hash = crate::macros::diesel_hist_models::hash_event_content(opt_prev_ev_hash.as_deref(), &content);
+
+                    diesel::update(dsl::$events_table.find(event.id))
+                        .set(dsl::opt_ev_hash.eq(ev_hash))
+                        .execute(conn)?;
+                }
+
+                diesel::update(dsl_s::$chain_seals_table)
+                    .set(dsl_s::last_unsealed_ev_id.eq(0))
+                    .execute(conn)?;
+
+                Ok(unsealed.len())
+            })
+        }
+
+        /// Every event is written through here so that it is chained to the hash of the event before it
+        fn insert_event(
+            conn: &mut SqliteConnection,
+            new_event: NewEvent,
+            opt_diff: Option<&Diff>,
+        ) -> Result<Event, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl;
+
+            // Reading the previous hash and inserting in one transaction keeps concurrent writers from both chaining
+            // to the same event
+            conn.transaction(|conn| {
+                seal_event_chain(conn)?;
+
+                let opt_prev_ev_hash: Option<String> = dsl::$events_table
+                    .order(dsl::id.desc())
+                    .select(dsl::opt_ev_hash)
+                    .first(conn)
+                    .optional()?
+                    .flatten();
+
+                let content = get_event_hash_content(&new_event, opt_diff);
+
+                let new_event = NewEvent {
+                    opt_ev_hash: Some(crate::macros::diesel_hist_models::hash_event_content(opt_prev_ev_hash.as_deref(), &content)),
+                    ..new_event
+                };
+
+                diesel::insert_into(dsl::$events_table)
+                    .values(&new_event)
+                    .returning(Event::as_returning())
+                    .get_result(conn)
+            })
+        }
+
+        /// Walks the event log in id order and recomputes every hash, stopping at the first broken link. Returns the
+        /// number of events checked and the hash of the latest one, which can be noted down to also detect events
+        /// being removed from the end.
+        #[allow(dead_code)]
+        pub fn verify_event_chain(
+            conn: &mut SqliteConnection,
+        ) -> Result<(usize, Option<String>), crate::macros::diesel_hist_models::VerifyEventChainError> {
+            use crate::autogen::schema::$events_table::dsl;
+            use crate::macros::diesel_hist_models::VerifyEventChainError;
+
+            let events: Vec<Event> = dsl::$events_table
+                .order(dsl::id.asc())
+                .select(Event::as_select())
+                .get_results(conn)?;
+
+            let mut opt_prev_ev_hash: Option<String> = None;
+
+            for event in events.iter() {
+                let Some(ev_hash) = event.opt_ev_hash.as_ref() else {
+                    return Err(VerifyEventChainError::MissingHash { ev_id: event.id });
+                };
+
+                let opt_diff = match event.opt_diff_id {
+                    Some(diff_id) => Some(
+                        get_diff_by_id(conn, diff_id)?
+                            .ok_or(VerifyEventChainError::MissingDiff { ev_id: event.id, diff_id })?,
+                    ),
+                    None => None,
+                };
+
+                let content = get_event_hash_content(&event.to_new_event(), opt_diff.as_ref());
+
+                if &crate::macros::diesel_hist_models::hash_event_content(opt_prev_ev_hash.as_deref(), &content) != ev_hash {
+                    return Err(VerifyEventChainError::HashMismatch { ev_id: event.id });
+                }
+
+                opt_prev_ev_hash = Some(ev_hash.clone());
+            }
+
+            Ok((events.len(), opt_prev_ev_hash))
         }
 
         pub fn get_created_span_frames(conn: &mut SqliteConnection) -> Result<Vec<crate::macros::diesel_hist_models::SpanFrame>, diesel::result::Error> {
@@ -301,15 +776,269 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
             Ok(span_frames)
         }
 
+        #[allow(dead_code)]
+        pub fn get_span_frame_infos(conn: &mut SqliteConnection) -> Result<Vec<crate::macros::diesel_hist_models::SpanFrameInfo>, diesel::result::Error> {
+            use crate::autogen::schema::EventAction;
+            use crate::macros::diesel_hist_models::{SpanFrame, SpanFrameInfo, SpanFrameLabelKind};
+
+            let labels = get_span_frame_labels(conn)?;
+
+            let events: Vec<Event> = crate::autogen::schema::$events_table::dsl::$events_table
+                .select(Event::as_select())
+                .order(crate::autogen::schema::$events_table::dsl::id.asc())
+                .get_results(conn)?;
+
+            let open_events = events
+                .iter()
+                .filter(|event| matches!(event.ev_action, EventAction::Open))
+                .collect::<Vec<_>>();
+
+            let span_frame_infos = open_events
+                .iter()
+                .map(|open_event| {
+                    let opt_parent = match open_event.opt_target_ev_id {
+                        Some(target_ev_id) => open_events.iter().find(|other| other.id == target_ev_id),
+                        None => open_events
+                            .iter()
+                            .filter(|other| other.span == open_event.span - 1 && other.id < open_event.id)
+                            .max_by_key(|other| other.id),
+                    }
+                    .map(|other| SpanFrame { span: other.span, frame: other.frame });
+
+                    let in_span_frame = |event: &&Event| event.span == open_event.span && event.frame == open_event.frame;
+
+                    let event_count = events
+                        .iter()
+                        .filter(in_span_frame)
+                        .filter(|event| event.opt_diff_id.is_some())
+                        .count();
+
+                    let closed = events
+                        .iter()
+                        .filter(in_span_frame)
+                        .filter(|event| matches!(event.ev_action, EventAction::Close | EventAction::Reopen))
+                        .last()
+                        .is_some_and(|event| matches!(event.ev_action, EventAction::Close));
+
+                    let labels_of_kind = |kind: SpanFrameLabelKind| {
+                        labels
+                            .iter()
+                            .filter(|label| label.kind == kind)
+                            .filter(|label| label.span_frame.span == open_event.span && label.span_frame.frame == open_event.frame)
+                            .map(|label| label.label.clone())
+                            .collect::<Vec<_>>()
+                    };
+
+                    SpanFrameInfo {
+                        span_frame: SpanFrame { span: open_event.span, frame: open_event.frame },
+                        opt_parent,
+                        ev_desc: open_event.ev_desc.clone(),
+                        created_on_ts: open_event.created_on_ts,
+                        event_count,
+                        closed,
+                        names: labels_of_kind(SpanFrameLabelKind::Name),
+                        tags: labels_of_kind(SpanFrameLabelKind::Tag),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            Ok(span_frame_infos)
+        }
+
+        /// The summary of the span frame, unless it was never created
+        #[allow(dead_code)]
+        pub fn get_span_frame_info(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+        ) -> Result<Option<crate::macros::diesel_hist_models::SpanFrameInfo>, diesel::result::Error> {
+            Ok(get_span_frame_infos(conn)?
+                .into_iter()
+                .find(|info| info.span_frame.span == span_frame.span && info.span_frame.frame == span_frame.frame))
+        }
+
+        /// Resolves the current labels from the name and tag events. A name that was given again points at the span
+        /// frame of its latest event.
+        #[allow(dead_code)]
+        pub fn get_span_frame_labels(conn: &mut SqliteConnection) -> Result<Vec<crate::macros::diesel_hist_models::SpanFrameLabel>, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl::*;
+            use crate::autogen::schema::EventAction;
+            use crate::macros::diesel_hist_models::{SpanFrame, SpanFrameLabel, SpanFrameLabelKind};
+
+            let label_events: Vec<Event> = $events_table
+                .filter(ev_action.eq(EventAction::Name).or(ev_action.eq(EventAction::Tag)))
+                .select(Event::as_select())
+                .order(id.asc())
+                .get_results(conn)?;
+
+            let mut mut_labels: Vec<SpanFrameLabel> = vec![];
+
+            for event in label_events {
+                let kind = match event.ev_action {
+                    EventAction::Name => SpanFrameLabelKind::Name,
+                    _ => SpanFrameLabelKind::Tag,
+                };
+
+                mut_labels.retain(|label| label.label != event.ev_desc);
+
+                mut_labels.push(SpanFrameLabel {
+                    kind,
+                    label: event.ev_desc,
+                    span_frame: SpanFrame { span: event.span, frame: event.frame },
+                });
+            }
+
+            Ok(mut_labels)
+        }
+
+        #[allow(dead_code)]
+        pub fn find_span_frame_by_label(conn: &mut SqliteConnection, label: &str) -> Result<Option<crate::macros::diesel_hist_models::SpanFrame>, diesel::result::Error> {
+            let labels = get_span_frame_labels(conn)?;
+
+            Ok(labels.into_iter().find(|other| other.label == label).map(|other| other.span_frame))
+        }
+
+        /// Checks that a label of the kind can be given now: it is well formed, and neither an existing tag nor a label
+        /// of the other kind
+        #[allow(dead_code)]
+        pub fn check_span_frame_label(
+            conn: &mut SqliteConnection,
+            kind: &crate::macros::diesel_hist_models::SpanFrameLabelKind,
+            label: &str,
+        ) -> Result<(), crate::macros::diesel_hist_models::LabelSpanFrameError> {
+            use crate::macros::diesel_hist_models::{LabelSpanFrameError, SpanFrameLabelKind};
+
+            if label.is_empty() || label.chars().any(char::is_whitespace) {
+                return Err(LabelSpanFrameError::InvalidLabel(label.to_owned()));
+            }
+
+            let labels = get_span_frame_labels(conn)?;
+
+            if let Some(existing) = labels.iter().find(|other| other.label == label) {
+                if existing.kind != *kind {
+                    return Err(LabelSpanFrameError::LabelKindConflict(label.to_owned(), existing.kind.clone()));
+                }
+
+                if existing.kind == SpanFrameLabelKind::Tag {
+                    return Err(LabelSpanFrameError::DuplicateTag(label.to_owned()));
+                }
+            }
+
+            Ok(())
+        }
+
+        fn insert_label_event(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            kind: crate::macros::diesel_hist_models::SpanFrameLabelKind,
+            label: &str,
+        ) -> Result<(), crate::macros::diesel_hist_models::LabelSpanFrameError> {
+            use crate::macros::diesel_hist_models::SpanFrameLabelKind;
+
+            check_span_frame_label(conn, &kind, label)?;
+
+            let new_event = NewEvent::new(
+                match kind {
+                    SpanFrameLabelKind::Name => crate::autogen::schema::EventAction::Name,
+                    SpanFrameLabelKind::Tag => crate::autogen::schema::EventAction::Tag,
+                },
+                span_frame,
+                label,
+            );
+
+            insert_event(conn, new_event, None)?;
+
+            Ok(())
+        }
+
+        /// Points a name at the span frame. If the name is already in use, it moves over to this span frame.
+        #[allow(dead_code)]
+        pub fn name_span_frame(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            name: &str,
+        ) -> Result<(), crate::macros::diesel_hist_models::LabelSpanFrameError> {
+            insert_label_event(conn, span_frame, crate::macros::diesel_hist_models::SpanFrameLabelKind::Name, name)
+        }
+
+        /// Tags the span frame. Tags cannot be moved once created.
+        #[allow(dead_code)]
+        pub fn tag_span_frame(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            tag: &str,
+        ) -> Result<(), crate::macros::diesel_hist_models::LabelSpanFrameError> {
+            insert_label_event(conn, span_frame, crate::macros::diesel_hist_models::SpanFrameLabelKind::Tag, tag)
+        }
+
+        /// Gets the span frame a session was last in. This is `None` for new sessions or if the span frame no longer
+        /// exists.
+        #[allow(dead_code)]
+        pub fn get_session_span_frame(conn: &mut SqliteConnection, session_name: &str) -> Result<Option<crate::macros::diesel_hist_models::SpanFrame>, diesel::result::Error> {
+            use crate::autogen::schema::$sessions_table::dsl::*;
+
+            let opt_session: Option<Session> = $sessions_table
+                .filter(session.eq(session_name))
+                .select(Session::as_select())
+                .get_result(conn)
+                .optional()?;
+
+            let Some(found_session) = opt_session else {
+                return Ok(None);
+            };
+
+            let span_frames = get_created_span_frames(conn)?;
+
+            let opt_span_frame = span_frames
+                .into_iter()
+                .find(|span_frame| span_frame.span == found_session.span && span_frame.frame == found_session.frame);
+
+            Ok(opt_span_frame)
+        }
+
+        #[allow(dead_code)]
+        pub fn set_session_span_frame(
+            conn: &mut SqliteConnection,
+            session_name: &str,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+        ) -> Result<(), diesel::result::Error> {
+            use chrono::prelude::*;
+
+            let new_session = NewSession {
+                session: session_name,
+                span: span_frame.span,
+                frame: span_frame.frame,
+                updated_on_ts: Utc::now().timestamp_millis() as f32,
+            };
+
+            diesel::replace_into(crate::autogen::schema::$sessions_table::dsl::$sessions_table)
+                .values(&new_session)
+                .execute(conn)?;
+
+            Ok(())
+        }
+
+        /// Returns the id of the event that opened the span frame
+        #[allow(dead_code)]
+        pub fn get_open_event_id(conn: &mut SqliteConnection, span_frame: &crate::macros::diesel_hist_models::SpanFrame) -> Result<i32, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl;
+
+            dsl::$events_table
+                .filter(dsl::ev_action.eq(crate::autogen::schema::EventAction::Open))
+                .filter(dsl::span.eq(span_frame.span))
+                .filter(dsl::frame.eq(span_frame.frame))
+                .select(dsl::id)
+                .first(conn)
+        }
+
+        /// The parent is recorded as the target of the new open event, see `SpanFrameInfo`
         #[allow(dead_code)]
         pub fn create_span_frame(
             conn: &mut SqliteConnection,
             span: i32,
             frame: i32,
+            opt_parent: Option<&crate::macros::diesel_hist_models::SpanFrame>,
             ev_desc: &str
         ) -> Result<crate::macros::diesel_hist_models::SpanFrame, crate::macros::diesel_hist_models::CreateSpanFrameError> {
-            use chrono::prelude::*;
-
             let span_frames = get_created_span_frames(conn)?;
 
             let duplicate = span_frames
@@ -320,61 +1049,38 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
                 return Err(crate::macros::diesel_hist_models::CreateSpanFrameError::DuplicateSpanFrame { span, frame });
             }
 
+            let opt_target_ev_id = opt_parent
+                .map(|parent| get_open_event_id(conn, parent))
+                .transpose()?;
+
             let new_event = NewEvent {
-                opt_diff_id: None,
-                ev_action: crate::autogen::schema::EventAction::Open,
-                span,
-                frame,
-                created_on_ts: Utc::now().timestamp_millis() as f32,
-                ev_desc,
+                opt_target_ev_id,
+                ..NewEvent::new(
+                    crate::autogen::schema::EventAction::Open,
+                    &crate::macros::diesel_hist_models::SpanFrame { span, frame },
+                    ev_desc,
+                )
             };
 
-            let out = diesel::insert_into(crate::autogen::schema::$events_table::dsl::$events_table)
-                .values(&new_event)
-                .returning(Event::as_returning())
-                .get_result(conn)?;
+            let out = insert_event(conn, new_event, None)?;
 
             Ok(crate::macros::diesel_hist_models::SpanFrame { span: out.span, frame: out.frame })
         }
 
         #[allow(dead_code)]
         pub fn close_span_frame(conn: &mut SqliteConnection, span_frame: crate::macros::diesel_hist_models::SpanFrame, ev_desc: &str) -> Result<(), diesel::result::Error> {
-            use chrono::prelude::*;
+            let new_event = NewEvent::new(crate::autogen::schema::EventAction::Close, &span_frame, ev_desc);
 
-            let new_event = NewEvent {
-                opt_diff_id: None,
-                ev_action: crate::autogen::schema::EventAction::Close,
-                span: span_frame.span,
-                frame: span_frame.frame,
-                created_on_ts: Utc::now().timestamp_millis() as f32,
-                ev_desc,
-            };
-
-            diesel::insert_into(crate::autogen::schema::$events_table::dsl::$events_table)
-                .values(&new_event)
-                .returning(Event::as_returning())
-                .execute(conn)?;
+            insert_event(conn, new_event, None)?;
 
             Ok(())
         }
 
         #[allow(dead_code)]
         pub fn reopen_span_frame(conn: &mut SqliteConnection, span_frame: crate::macros::diesel_hist_models::SpanFrame, ev_desc: &str) -> Result<(), diesel::result::Error> {
-            use chrono::prelude::*;
+            let new_event = NewEvent::new(crate::autogen::schema::EventAction::Reopen, &span_frame, ev_desc);
 
-            let new_event = NewEvent {
-                opt_diff_id: None,
-                ev_action: crate::autogen::schema::EventAction::Reopen,
-                span: span_frame.span,
-                frame: span_frame.frame,
-                created_on_ts: Utc::now().timestamp_millis() as f32,
-                ev_desc,
-            };
-
-            diesel::insert_into(crate::autogen::schema::$events_table::dsl::$events_table)
-                .values(&new_event)
-                .returning(Event::as_returning())
-                .execute(conn)?;
+            insert_event(conn, new_event, None)?;
 
             Ok(())
         }
@@ -390,6 +1096,31 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
             Ok(out)
         }
 
+        /// All diffs with their payloads upcast to the current schema version
+        #[allow(dead_code)]
+        pub fn get_diffs_upcasted(
+            conn: &mut SqliteConnection,
+        ) -> Result<Vec<(Diff, Common)>, crate::macros::diesel_hist_models::ReadDiffError> {
+            use crate::autogen::schema::$diff_table::dsl;
+
+            let diffs = dsl::$diff_table
+                .order(dsl::id.asc())
+                .select(Diff::as_select())
+                .get_results(conn)?;
+
+            let out = diffs
+                .into_iter()
+                .map(|diff| {
+                    let common = diff.get_common_upcasted()?;
+
+                    Ok((diff, common))
+                })
+                .collect::<Result<Vec<_>, crate::db::upcast::UpcastError>>()?;
+
+            Ok(out)
+        }
+
+        /// Writes a single event outside of any transaction, which is refused once the store is in double-entry mode
         #[allow(dead_code)]
         pub fn insert_event_for_obj<'a>(
             conn: &mut SqliteConnection,
@@ -398,80 +1129,407 @@ macro_rules! create_diesel_hist_structs_write_one_lifetime {
             obj_state: crate::autogen::schema::ObjState,
             ev_desc: &'a str,
             new_common: NewCommon<'a>
-        ) -> Result<Event, diesel::result::Error> {
-            use chrono::prelude::*;
+        ) -> Result<Event, crate::macros::diesel_hist_models::InsertEventForObjError> {
+            insert_event_for_obj_targeting(conn, obj_id, span_frame, obj_state, None, ev_desc, new_common)
+        }
+
+        /// Like `insert_event_for_obj`, but also records the event this one refers to. For example, the disabled event
+        /// that it compensates for.
+        #[allow(dead_code)]
+        pub fn insert_event_for_obj_targeting<'a>(
+            conn: &mut SqliteConnection,
+            obj_id: i32,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            obj_state: crate::autogen::schema::ObjState,
+            opt_target_ev_id: Option<i32>,
+            ev_desc: &'a str,
+            new_common: NewCommon<'a>
+        ) -> Result<Event, crate::macros::diesel_hist_models::InsertEventForObjError> {
+            use crate::autogen::schema::$events_table::dsl;
+
+            let double_entry = diesel::select(diesel::dsl::exists(
+                dsl::$events_table.filter(dsl::ev_action.eq(crate::autogen::schema::EventAction::DoubleEntry)),
+            ))
+            .get_result::<bool>(conn)?;
+
+            if double_entry {
+                return Err(crate::macros::diesel_hist_models::InsertEventForObjError::DoubleEntry);
+            }
 
             // Check that the frame exists
 
 
-            let diff = insert_diff(conn, obj_id, new_common)?;
+            let new_event = NewEvent {
+                opt_target_ev_id,
+                ..NewEvent::new(obj_state.into(), span_frame, ev_desc)
+            };
+
+            Ok(insert_recorded_event(conn, new_event, Some((obj_id, new_common)))?)
+        }
+
+        /// Writes an event that was recorded elsewhere as is, keeping its timestamp, e.g. when importing. Its diff is
+        /// written from `opt_obj`, and it is chained and snapshotted like any other event. The other checks of the
+        /// functions above, such as for duplicate span frames or labels, are up to the caller.
+        #[allow(dead_code)]
+        pub fn insert_recorded_event<'a>(
+            conn: &mut SqliteConnection,
+            new_event: NewEvent<'a>,
+            opt_obj: Option<(i32, NewCommon<'a>)>,
+        ) -> Result<Event, diesel::result::Error> {
+            let opt_diff = match opt_obj {
+                Some((obj_id, new_common)) => Some(insert_diff(conn, obj_id, new_common)?),
+                None => None,
+            };
 
             let new_event = NewEvent {
-                opt_diff_id: Some(diff.id),
-                ev_action: obj_state.into(),
-                span: span_frame.span,
-                frame: span_frame.frame,
-                created_on_ts: Utc::now().timestamp_millis() as f32,
-                ev_desc,
+                opt_diff_id: opt_diff.as_ref().map(|diff| diff.id),
+                ..new_event
             };
 
-            let out = diesel::insert_into(crate::autogen::schema::$events_table::dsl::$events_table)
-                .values(&new_event)
-                .returning(Event::as_returning())
-                .get_result(conn)?;
+            let out = insert_event(conn, new_event, opt_diff.as_ref())?;
+
+            if opt_diff.is_some() {
+                let span_frame = crate::macros::diesel_hist_models::SpanFrame { span: out.span, frame: out.frame };
+
+                create_span_frame_snapshot_if_due(conn, &span_frame)?;
+            }
 
             Ok(out)
         }
 
+        /// Records that the event is disabled (`Toggle`) or enabled again (`Untoggle`) within the span frame's partial
+        /// view. The partial tables are projected from these events.
         #[allow(dead_code)]
-        pub fn set_events_grouped_partial(
+        pub fn insert_toggle_event(
             conn: &mut SqliteConnection,
-            events_grouped: &[EventGrouped]
-        ) -> Result<(), diesel::result::Error> {
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            target_ev_id: i32,
+            enabled: bool,
+            ev_desc: &str,
+        ) -> Result<Event, diesel::result::Error> {
 
-            diesel::delete(crate::autogen::schema::$events_grouped_partial_table::dsl::$events_grouped_partial_table)
-                .execute(conn)?;
+            let new_event = NewEvent {
+                opt_target_ev_id: Some(target_ev_id),
+                ..NewEvent::new(
+                    if enabled {
+                        crate::autogen::schema::EventAction::Untoggle
+                    } else {
+                        crate::autogen::schema::EventAction::Toggle
+                    },
+                    span_frame,
+                    ev_desc,
+                )
+            };
+
+            let out = insert_event(conn, new_event, None)?;
+
+            Ok(out)
+        }
+
+        #[allow(dead_code)]
+        pub fn get_toggle_events(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+        ) -> Result<Vec<Event>, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl::*;
+            use crate::autogen::schema::EventAction;
 
-            let new_events_grouped_partial = events_grouped
+            $events_table
+                .filter(span.eq(span_frame.span).and(frame.eq(span_frame.frame)))
+                .filter(ev_action.eq(EventAction::Toggle).or(ev_action.eq(EventAction::Untoggle)))
+                .select(Event::as_select())
+                .order(id.asc())
+                .get_results(conn)
+        }
+
+        /// The events of the span frame that its partial view would have enabled if the given ones were asked for.
+        /// Postings of one transaction are enabled or disabled together, so an event whose state changes takes the
+        /// other postings of its transaction with it, and the partial view never shows half of a transaction.
+        #[allow(dead_code)]
+        pub fn get_events_grouped_partial_next(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            events_grouped: &[EventGrouped],
+        ) -> Result<Vec<EventGrouped>, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl as dsl_e;
+            use crate::autogen::schema::$events_grouped_table::dsl as dsl_g;
+            use crate::autogen::schema::$events_grouped_partial_table::dsl as dsl_p;
+
+            let objects: Vec<EventGrouped> = dsl_g::$events_grouped_table
+                .filter(dsl_g::grp_span.eq(span_frame.span).and(dsl_g::grp_frame.eq(span_frame.frame)))
+                .order((dsl_g::created_on_ts.asc(), dsl_g::ev_id.asc()))
+                .select(EventGrouped::as_select())
+                .get_results(conn)?;
+
+            let enabled_ev_ids: Vec<i32> = dsl_p::$events_grouped_partial_table
+                .filter(dsl_p::grp_span.eq(span_frame.span).and(dsl_p::grp_frame.eq(span_frame.frame)))
+                .select(dsl_p::ev_id)
+                .get_results(conn)?;
+
+            let tx_ids: Vec<(i32, Option<i32>)> = dsl_e::$events_table
+                .filter(dsl_e::id.eq_any(objects.iter().map(|object| object.ev_id).collect::<Vec<_>>()))
+                .select((dsl_e::id, dsl_e::$tx_id_field))
+                .get_results(conn)?;
+
+            let get_opt_tx_id = |ev_id: i32| {
+                tx_ids
+                    .iter()
+                    .find(|(other, _)| *other == ev_id)
+                    .and_then(|(_, opt_tx_id)| *opt_tx_id)
+            };
+
+            let is_asked_for = |object: &EventGrouped| {
+                events_grouped
+                    .iter()
+                    .filter(|e| e.grp_span == span_frame.span && e.grp_frame == span_frame.frame)
+                    .any(|e| e.ev_id == object.ev_id)
+            };
+
+            // The first posting of a transaction that changes decides what becomes of the whole transaction
+            let mut mut_tx_states: Vec<(i32, bool)> = vec![];
+
+            for object in &objects {
+                let enabled_next = is_asked_for(object);
+
+                if enabled_ev_ids.contains(&object.ev_id) != enabled_next
+                    && let Some(tx_id) = get_opt_tx_id(object.ev_id)
+                    && !mut_tx_states.iter().any(|(other, _)| *other == tx_id)
+                {
+                    mut_tx_states.push((tx_id, enabled_next));
+                }
+            }
+
+            Ok(objects
                 .into_iter()
-                .map(|e| {
-                    let new_common = NewCommon {
+                .filter(|object| {
+                    let opt_tx_state = get_opt_tx_id(object.ev_id).and_then(|tx_id| {
+                        mut_tx_states
+                            .iter()
+                            .find(|(other, _)| *other == tx_id)
+                            .map(|(_, enabled)| *enabled)
+                    });
+
+                    opt_tx_state.unwrap_or_else(|| is_asked_for(object))
+                })
+                .collect())
+        }
+
+        /// Makes the span frame's partial view have exactly the given events enabled, with the rest of the
+        /// transactions they are postings of, by recording toggle events for the ones that change. Events grouped
+        /// under other span frames are ignored, so each span frame keeps its own selection.
+        #[allow(dead_code)]
+        pub fn set_events_grouped_partial(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            events_grouped: &[EventGrouped],
+            ev_desc: &str,
+        ) -> Result<Vec<Event>, diesel::result::Error> {
+            use crate::autogen::schema::$events_grouped_table::dsl as dsl_g;
+            use crate::autogen::schema::$events_grouped_partial_table::dsl as dsl_p;
+
+            conn.transaction(|conn| {
+                let objects: Vec<EventGrouped> = dsl_g::$events_grouped_table
+                    .filter(dsl_g::grp_span.eq(span_frame.span).and(dsl_g::grp_frame.eq(span_frame.frame)))
+                    .select(EventGrouped::as_select())
+                    .get_results(conn)?;
+
+                let objects_p: Vec<EventGroupedPartial> = dsl_p::$events_grouped_partial_table
+                    .filter(dsl_p::grp_span.eq(span_frame.span).and(dsl_p::grp_frame.eq(span_frame.frame)))
+                    .select(EventGroupedPartial::as_select())
+                    .get_results(conn)?;
+
+                let objects_next = get_events_grouped_partial_next(conn, span_frame, events_grouped)?;
+
+                let mut mut_toggle_events = vec![];
+
+                for object in objects {
+                    let enabled_now = objects_p.iter().any(|object_p| object_p.ev_id == object.ev_id);
+                    let enabled_next = objects_next.iter().any(|e| e.ev_id == object.ev_id);
+
+                    if enabled_now != enabled_next {
+                        mut_toggle_events.push(insert_toggle_event(conn, span_frame, object.ev_id, enabled_next, ev_desc)?);
+                    }
+                }
+
+                Ok(mut_toggle_events)
+            })
+        }
+
+        /// Snapshots the span frame's hist as of the latest event. Later rebuilds of the span frame's hist start from the
+        /// latest snapshot and only fold in the events after it.
+        #[allow(dead_code)]
+        pub fn create_span_frame_snapshot(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+            ev_desc: &str,
+        ) -> Result<Snapshot, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl as dsl_e;
+            use crate::autogen::schema::$hist_table::dsl as dsl_h;
+            use crate::autogen::schema::$snapshot_hist_table::dsl as dsl_sh;
+            use chrono::prelude::*;
+
+            conn.transaction(|conn| {
+                let opt_last_ev_id: Option<i32> = dsl_e::$events_table
+                    .select(diesel::dsl::max(dsl_e::id))
+                    .get_result(conn)?;
+
+                let new_snapshot = NewSnapshot {
+                    span: span_frame.span,
+                    frame: span_frame.frame,
+                    last_ev_id: opt_last_ev_id.unwrap_or(0),
+                    created_on_ts: Utc::now().timestamp_millis() as f32,
+                    ev_desc,
+                };
+
+                let snapshot = diesel::insert_into(crate::autogen::schema::$snapshots_table::dsl::$snapshots_table)
+                    .values(&new_snapshot)
+                    .returning(Snapshot::as_returning())
+                    .get_result(conn)?;
+
+                let rows = dsl_h::$hist_table
+                    .filter(dsl_h::grp_span.eq(span_frame.span).and(dsl_h::grp_frame.eq(span_frame.frame)))
+                    .select((
+                        snapshot.id.into_sql::<diesel::sql_types::Integer>(),
+                        dsl_h::grp_id,
+                        dsl_h::grp_span,
+                        dsl_h::grp_frame,
+                        dsl_h::obj_id,
+                        dsl_h::obj_state,
                         $(
-                            $field_write: e.$field_write,
+                            dsl_h::$field_write,
                         )*
                         $(
-                            $field_write_ref: &e.$field_write_ref,
+                            dsl_h::$field_write_ref,
                         )*
-                    };
+                    ));
 
-                    NewEventGroupedPartial {
-                        grp_id: e.grp_id,
-                        grp_span: e.grp_span,
-                        grp_frame: e.grp_frame,
-                        grp_created_on_ts: e.grp_created_on_ts,
-                        dup: e.dup,
-                        ev_id: e.ev_id,
-                        obj_id: e.obj_id,
-                        ev_action: e.ev_action.clone(),
-                        span: e.span,
-                        frame: e.frame,
-                        created_on_ts: e.created_on_ts,
+                diesel::insert_into(dsl_sh::$snapshot_hist_table)
+                    .values(rows)
+                    .into_columns((
+                        dsl_sh::snapshot_id,
+                        dsl_sh::grp_id,
+                        dsl_sh::grp_span,
+                        dsl_sh::grp_frame,
+                        dsl_sh::obj_id,
+                        dsl_sh::obj_state,
                         $(
-                            $field_write: new_common.$field_write,
+                            dsl_sh::$field_write,
                         )*
                         $(
-                            $field_write_ref: new_common.$field_write_ref,
+                            dsl_sh::$field_write_ref,
                         )*
-                        ev_desc: &e.ev_desc
-                    }
-                })
+                    ))
+                    .execute(conn)?;
+
+                Ok(snapshot)
+            })
+        }
+
+        /// Snapshots the span frame once it has had enough diff events since its latest snapshot
+        #[allow(dead_code)]
+        pub fn create_span_frame_snapshot_if_due(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+        ) -> Result<Option<Snapshot>, diesel::result::Error> {
+            use crate::autogen::schema::$events_table::dsl as dsl_e;
+            use crate::autogen::schema::$snapshots_table::dsl as dsl_s;
+
+            let opt_last_ev_id: Option<i32> = dsl_s::$snapshots_table
+                .filter(dsl_s::span.eq(span_frame.span).and(dsl_s::frame.eq(span_frame.frame)))
+                .select(diesel::dsl::max(dsl_s::last_ev_id))
+                .get_result(conn)?;
+
+            let events_since: i64 = dsl_e::$events_table
+                .filter(
+                    dsl_e::span
+                        .eq(span_frame.span)
+                        .and(dsl_e::frame.eq(span_frame.frame))
+                        .and(dsl_e::opt_diff_id.is_not_null())
+                        .and(dsl_e::id.gt(opt_last_ev_id.unwrap_or(0))),
+                )
+                .count()
+                .get_result(conn)?;
+
+            if events_since < $snapshot_every_n_events {
+                return Ok(None);
+            }
+
+            let snapshot = create_span_frame_snapshot(
+                conn,
+                span_frame,
+                &format!("auto snapshot after {events_since} events"),
+            )?;
+
+            Ok(Some(snapshot))
+        }
+
+        #[allow(dead_code)]
+        pub fn get_snapshots(conn: &mut SqliteConnection) -> Result<Vec<Snapshot>, diesel::result::Error> {
+            use crate::autogen::schema::$snapshots_table::dsl;
+
+            dsl::$snapshots_table
+                .order(dsl::id.asc())
+                .select(Snapshot::as_select())
+                .get_results(conn)
+        }
+
+        /// Compares the span frame's hist, which is updated event by event and rebuilt from its latest snapshot, against
+        /// a replay of the full event log. Returns the rows that differ, so an empty result means the snapshots can be
+        /// trusted.
+        #[allow(dead_code)]
+        pub fn verify_span_frame_snapshot(
+            conn: &mut SqliteConnection,
+            span_frame: &crate::macros::diesel_hist_models::SpanFrame,
+        ) -> Result<Vec<HistMismatch>, diesel::result::Error> {
+            use crate::autogen::schema::$hist_table::dsl as dsl_h;
+            use itertools::Itertools;
+
+            let projected: Vec<Hist> = dsl_h::$hist_table
+                .filter(dsl_h::grp_span.eq(span_frame.span).and(dsl_h::grp_frame.eq(span_frame.frame)))
+                .select(Hist::as_select())
+                .get_results(conn)?;
+
+            let replayed: Vec<Hist> = diesel::sql_query(concat!(
+                "SELECT 0 AS id, t1.* FROM v_",
+                stringify!($hist_table),
+                " AS t1 WHERE t1.grp_span = ? AND t1.grp_frame = ?"
+            ))
+            .bind::<diesel::sql_types::Integer, _>(span_frame.span)
+            .bind::<diesel::sql_types::Integer, _>(span_frame.frame)
+            .load(conn)?;
+
+            let keys = projected
+                .iter()
+                .chain(replayed.iter())
+                .map(|row| row.get_key())
+                .unique()
                 .collect::<Vec<_>>();
 
-            diesel::insert_into(crate::autogen::schema::$events_grouped_partial_table::dsl::$events_grouped_partial_table)
-                .values(new_events_grouped_partial)
-                .execute(conn)?;
+            let describe = |opt_row: Option<&Hist>| {
+                opt_row.map(|row| format!("{:?} {:?}", row.obj_state, row.get_common()))
+            };
 
-            Ok(())
+            let mut mut_projected = projected;
+            let mut mut_replayed = replayed;
+            let mut mut_mismatches = vec![];
+
+            for key in keys {
+                let opt_projected_idx = mut_projected.iter().position(|row| row.get_key() == key);
+                let opt_replayed_idx = mut_replayed.iter().position(|row| row.get_key() == key);
+
+                let same = describe(opt_projected_idx.map(|idx| &mut_projected[idx]))
+                    == describe(opt_replayed_idx.map(|idx| &mut_replayed[idx]));
+
+                if !same {
+                    mut_mismatches.push(HistMismatch {
+                        key,
+                        opt_projected: opt_projected_idx.map(|idx| mut_projected.swap_remove(idx)),
+                        opt_replayed: opt_replayed_idx.map(|idx| mut_replayed.swap_remove(idx)),
+                    });
+                }
+            }
+
+            Ok(mut_mismatches)
         }
     }
 }
@@ -0,0 +1,90 @@
+mod common;
+
+use std::str::FromStr;
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::models::{Amount, AmountError, Asset, Person, coin_store};
+use diesel::prelude::*;
+
+use common::establish_migrated;
+
+#[test]
+fn test_amounts_are_parsed_checked() {
+    assert_eq!(Amount::from_str(" 42 ").unwrap(), Amount::from(42));
+    assert_eq!(
+        Amount::from_str("9223372036854775807").unwrap(),
+        Amount::new(i64::MAX)
+    );
+
+    assert!(matches!(
+        Amount::from_str("9223372036854775808"),
+        Err(AmountError::OutOfRange(_))
+    ));
+    assert!(matches!(
+        Amount::from_str("1.5"),
+        Err(AmountError::Invalid(_))
+    ));
+}
+
+#[test]
+fn test_amount_arithmetic_is_checked() {
+    let max = Amount::new(i64::MAX);
+
+    assert_eq!(max.checked_add(Amount::from(1)), Err(AmountError::Overflow));
+    assert_eq!(
+        Amount::new(i64::MIN).checked_neg(),
+        Err(AmountError::Overflow)
+    );
+    assert_eq!(
+        Amount::checked_sum([max, Amount::from(-1), Amount::from(1)]),
+        Ok(max)
+    );
+    assert_eq!(
+        Amount::checked_sum([max, Amount::from(1), Amount::from(-1)]),
+        Err(AmountError::Overflow)
+    );
+}
+
+#[test]
+fn test_large_amounts_are_projected_exactly() {
+    use credit_store_demo::autogen::schema::coin_store_hist::dsl;
+
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+    let alice = Person::from_str("alice").unwrap();
+
+    // Well beyond what an i32 holds
+    let large = Amount::new(5_000_000_000);
+
+    for (obj_state, coins) in [
+        (ObjState::Insert, Amount::ZERO),
+        (ObjState::Update, large),
+        (ObjState::Update, large),
+    ] {
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            1,
+            &span_frame,
+            obj_state,
+            "test",
+            coin_store::NewCommon {
+                coins,
+                person: &alice,
+                asset: &Asset::default(),
+            },
+        )
+        .unwrap();
+    }
+
+    let hist = dsl::coin_store_hist
+        .filter(dsl::grp_span.eq(1).and(dsl::grp_frame.eq(1)))
+        .select(coin_store::Hist::as_select())
+        .get_results(&mut conn)
+        .unwrap();
+
+    assert_eq!(
+        hist.iter().map(|row| row.coins).collect::<Vec<_>>(),
+        vec![Amount::new(10_000_000_000)]
+    );
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::str::FromStr;
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::models::{Asset, Person, coin_store};
+use diesel::prelude::*;
+
+use common::{establish_migrated, load_fixture};
+
+fn get_wallet(conn: &mut SqliteConnection, span: i32, frame: i32) -> Vec<(String, String, i64)> {
+    use credit_store_demo::autogen::schema::coin_store_hist::dsl;
+
+    dsl::coin_store_hist
+        .filter(dsl::grp_span.eq(span).and(dsl::grp_frame.eq(frame)))
+        .order((dsl::obj_id.asc(), dsl::asset.asc()))
+        .select(coin_store::Hist::as_select())
+        .get_results(conn)
+        .unwrap()
+        .into_iter()
+        .map(|row| {
+            (
+                row.person.to_inner(),
+                row.asset.to_inner(),
+                row.coins.to_inner(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_wallet_holds_one_balance_per_asset() {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    let alice = Person::from_str("alice").unwrap();
+    let coin = Asset::default();
+    let points = Asset::from_str("Points").unwrap();
+
+    for (obj_state, coins, asset) in [
+        (ObjState::Insert, 0, &coin),
+        (ObjState::Update, 10, &coin),
+        (ObjState::Update, 25, &points),
+        (ObjState::Update, -5, &points),
+        (ObjState::Update, -3, &coin),
+    ] {
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            1,
+            &span_frame,
+            obj_state,
+            "test",
+            coin_store::NewCommon {
+                coins: coins.into(),
+                person: &alice,
+                asset,
+            },
+        )
+        .unwrap();
+    }
+
+    assert_eq!(
+        get_wallet(&mut conn, 1, 1),
+        vec![
+            ("alice".to_owned(), "coin".to_owned(), 7),
+            ("alice".to_owned(), "points".to_owned(), 20),
+        ]
+    );
+    assert!(
+        coin_store::verify_span_frame_snapshot(&mut conn, &span_frame)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_existing_diffs_read_as_default_asset() {
+    let mut conn = load_fixture("coin_store_at_2026-10-18-000500.sql");
+
+    let diffs = coin_store::get_diffs_upcasted(&mut conn).unwrap();
+
+    assert!(!diffs.is_empty());
+    assert!(
+        diffs
+            .iter()
+            .all(|(_, common)| common.asset == Asset::default())
+    );
+    assert!(
+        get_wallet(&mut conn, 2, 1)
+            .iter()
+            .all(|(_, asset, _)| asset == "coin")
+    );
+}
+
+#[test]
+fn test_asset_codes_are_validated() {
+    assert_eq!(Asset::from_str("Vouchers").unwrap().to_inner(), "vouchers");
+
+    for code in [
+        "",
+        "1st",
+        "points-",
+        "with space",
+        "a_very_long_asset_code_indeed",
+    ] {
+        assert!(
+            Asset::from_str(code).is_err(),
+            "{code:?} should be rejected"
+        );
+    }
+}
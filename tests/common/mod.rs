@@ -0,0 +1,113 @@
+//! Helpers shared by the integration tests that need a database.
+//!
+//! Fixtures are coin stores dumped with `sqlite3 <db> .dump` into `tests/fixtures`, named after the latest migration
+//! they were made at.
+#![allow(dead_code)]
+
+use std::{fs, path::Path, str::FromStr};
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::models::{Amount, Asset, Person, coin_store};
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::{connection::SimpleConnection, prelude::*, sql_types::Text};
+
+#[derive(QueryableByName)]
+struct AppliedMigration {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+/// Applies the migrations not recorded in `__diesel_schema_migrations` yet, like `diesel migration run`
+pub fn run_pending_migrations(conn: &mut SqliteConnection, migrations_dir: &Path) {
+    let applied = diesel::sql_query("SELECT version FROM __diesel_schema_migrations")
+        .load::<AppliedMigration>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect::<Vec<_>>();
+
+    let mut mut_dirs = fs::read_dir(migrations_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("up.sql").exists())
+        .collect::<Vec<_>>();
+
+    mut_dirs.sort();
+
+    for dir in mut_dirs {
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let version = name[..17].replace('-', "");
+
+        if applied.contains(&version) {
+            continue;
+        }
+
+        let up_sql = fs::read_to_string(dir.join("up.sql")).unwrap();
+
+        conn.batch_execute(&format!(
+            "BEGIN;\n{up_sql}\nINSERT INTO __diesel_schema_migrations (version) VALUES ('{version}');\nCOMMIT;"
+        ))
+        .unwrap_or_else(|e| panic!("Failed to run migration {name}: {e}"));
+    }
+}
+
+pub fn load_fixture(file_name: &str) -> SqliteConnection {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+
+    let dump = fs::read_to_string(manifest_dir.join("tests/fixtures").join(file_name)).unwrap();
+
+    conn.batch_execute(&dump).unwrap();
+
+    run_pending_migrations(&mut conn, &manifest_dir.join("migrations"));
+
+    conn
+}
+
+/// An empty in-memory coin store with every migration applied
+pub fn establish_migrated() -> SqliteConnection {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+
+    conn.batch_execute(
+        "CREATE TABLE __diesel_schema_migrations (version VARCHAR(50) PRIMARY KEY NOT NULL, run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP);",
+    )
+    .unwrap();
+
+    run_pending_migrations(&mut conn, &manifest_dir.join("migrations"));
+
+    conn
+}
+
+/// A migrated coin store with its first span frame, where each user is added with the coins given next to them
+pub fn establish_with_users<const N: usize>(
+    users: [(&str, i64); N],
+) -> (SqliteConnection, SpanFrame, [Person; N]) {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    let persons = users.map(|(name, coins)| {
+        let person = Person::from_str(name).unwrap();
+
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            person.get_obj_id(),
+            &span_frame,
+            ObjState::Insert,
+            "add user",
+            coin_store::NewCommon {
+                coins: Amount::new(coins),
+                person: &person,
+                asset: &Asset::default(),
+            },
+        )
+        .unwrap();
+
+        person
+    });
+
+    (conn, span_frame, persons)
+}
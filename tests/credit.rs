@@ -0,0 +1,255 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::Utc;
+use credit_store_demo::db::credit::{self, CreditError, Debt, NetPosition};
+use credit_store_demo::db::hold::{self, HoldError};
+use credit_store_demo::db::models::{Amount, Asset, CreditLine, Iou, Payload, Person};
+use credit_store_demo::db::spend::SpendError;
+
+use common::establish_with_users;
+
+fn new_credit_line(person: &str, coins: i64, asset: &str) -> CreditLine {
+    CreditLine {
+        person: Person::from_str(person).unwrap(),
+        coins: Amount::new(coins),
+        asset: Asset::from_str(asset).unwrap(),
+    }
+}
+
+fn new_iou(debtor: &str, creditor: &str, coins: i64) -> Iou {
+    Iou {
+        debtor: Person::from_str(debtor).unwrap(),
+        creditor: Person::from_str(creditor).unwrap(),
+        coins: Amount::new(coins),
+        asset: Asset::default(),
+    }
+}
+
+#[test]
+fn test_credit_lines_and_ious_are_checked() {
+    assert!(new_credit_line("alice", -5, "coin").to_payload().is_err());
+
+    for invalid in [new_iou("bob", "alice", 0), new_iou("bob", "bob", 5)] {
+        assert!(
+            invalid.to_payload().is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+
+    assert!(
+        CreditLine::from_payload(r#"{"person":"@expenses","coins":5,"asset":"coin"}"#).is_err()
+    );
+    assert!(
+        Iou::from_payload(r#"{"debtor":"bob","creditor":"@expenses","coins":5,"asset":"coin"}"#)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_credit_lines_let_balances_go_below_zero() {
+    let (mut conn, span_frame, [_, bob]) = establish_with_users([("alice", 100), ("bob", 20)]);
+    let now = Utc::now();
+
+    assert!(matches!(
+        hold::check_available(&mut conn, &span_frame, &bob, &Asset::default(), Amount::new(21), now),
+        Err(HoldError::InsufficientFunds { available, .. }) if available == Amount::new(20)
+    ));
+
+    credit::set_credit_line(
+        &mut conn,
+        &span_frame,
+        &new_credit_line("bob", 50, "coin"),
+        "credit line",
+    )
+    .unwrap();
+
+    let available = hold::get_available_balances(&mut conn, &span_frame, now)
+        .unwrap()
+        .into_iter()
+        .find(|balance| balance.person == bob)
+        .unwrap();
+
+    assert_eq!(
+        (
+            available.balance,
+            available.credit_line,
+            available.available
+        ),
+        (Amount::new(20), Amount::new(50), Amount::new(70))
+    );
+
+    hold::check_available(
+        &mut conn,
+        &span_frame,
+        &bob,
+        &Asset::default(),
+        Amount::new(70),
+        now,
+    )
+    .unwrap();
+    assert!(matches!(
+        hold::check_available(&mut conn, &span_frame, &bob, &Asset::default(), Amount::new(71), now),
+        Err(HoldError::InsufficientFunds { available, .. }) if available == Amount::new(70)
+    ));
+
+    // A credit line in an asset the user never had is all they can spend of it
+    let points = Asset::from_str("points").unwrap();
+
+    credit::set_credit_line(
+        &mut conn,
+        &span_frame,
+        &new_credit_line("bob", 10, "points"),
+        "credit line",
+    )
+    .unwrap();
+
+    hold::check_available(&mut conn, &span_frame, &bob, &points, Amount::new(10), now).unwrap();
+
+    // Setting a credit line again replaces it, and 0 takes it back
+    credit::set_credit_line(
+        &mut conn,
+        &span_frame,
+        &new_credit_line("bob", 0, "coin"),
+        "no more credit",
+    )
+    .unwrap();
+
+    assert_eq!(
+        credit::get_credit_lines(&mut conn, &span_frame).unwrap(),
+        [new_credit_line("bob", 10, "points")]
+    );
+}
+
+#[test]
+fn test_repayments_settle_ious() {
+    let (mut conn, span_frame, [alice, bob]) = establish_with_users([("alice", 100), ("bob", 20)]);
+
+    let dinner = credit::record_iou(
+        &mut conn,
+        &span_frame,
+        &new_iou("bob", "alice", 50),
+        "dinner",
+    )
+    .unwrap();
+
+    credit::record_iou(&mut conn, &span_frame, &new_iou("alice", "bob", 10), "taxi").unwrap();
+
+    // Recording an IOU moves no coins
+    assert_eq!(
+        hold::get_available_balances(&mut conn, &span_frame, Utc::now())
+            .unwrap()
+            .into_iter()
+            .map(|balance| balance.balance)
+            .collect::<Vec<_>>(),
+        [Amount::new(100), Amount::new(20)]
+    );
+
+    assert_eq!(
+        credit::repay_iou(
+            &mut conn,
+            &span_frame,
+            dinner.id,
+            Some(Amount::new(20)),
+            Utc::now(),
+            "repay"
+        )
+        .unwrap(),
+        Amount::new(20)
+    );
+    assert!(matches!(
+        credit::repay_iou(
+            &mut conn,
+            &span_frame,
+            dinner.id,
+            Some(Amount::new(31)),
+            Utc::now(),
+            "repay"
+        ),
+        Err(CreditError::ExceedsOutstanding { outstanding, .. }) if outstanding == Amount::new(30)
+    ));
+
+    assert_eq!(
+        credit::get_debts(&mut conn, &span_frame).unwrap(),
+        [
+            Debt {
+                debtor: alice.clone(),
+                creditor: bob.clone(),
+                asset: Asset::default(),
+                outstanding: Amount::new(10),
+            },
+            Debt {
+                debtor: bob.clone(),
+                creditor: alice.clone(),
+                asset: Asset::default(),
+                outstanding: Amount::new(30),
+            },
+        ]
+    );
+
+    assert_eq!(
+        credit::get_net_positions(&mut conn, &span_frame).unwrap(),
+        [
+            NetPosition {
+                person: alice.clone(),
+                asset: Asset::default(),
+                owed: Amount::new(30),
+                owing: Amount::new(10),
+                net: Amount::new(20),
+            },
+            NetPosition {
+                person: bob.clone(),
+                asset: Asset::default(),
+                owed: Amount::new(10),
+                owing: Amount::new(30),
+                net: Amount::new(-20),
+            },
+        ]
+    );
+
+    // Bob has no coins left, so he repays the rest on credit
+    assert!(matches!(
+        credit::repay_iou(&mut conn, &span_frame, dinner.id, None, Utc::now(), "repay"),
+        Err(CreditError::SpendError(e))
+            if matches!(*e, SpendError::HoldError(HoldError::InsufficientFunds { .. }))
+    ));
+
+    credit::set_credit_line(
+        &mut conn,
+        &span_frame,
+        &new_credit_line("bob", 30, "coin"),
+        "credit line",
+    )
+    .unwrap();
+
+    // The rest is repaid when no coins are given
+    assert_eq!(
+        credit::repay_iou(&mut conn, &span_frame, dinner.id, None, Utc::now(), "repay").unwrap(),
+        Amount::new(30)
+    );
+    assert!(matches!(
+        credit::repay_iou(&mut conn, &span_frame, dinner.id, None, Utc::now(), "repay"),
+        Err(CreditError::AlreadyRepaid(_))
+    ));
+    assert!(matches!(
+        credit::repay_iou(&mut conn, &span_frame, 9999, None, Utc::now(), "repay"),
+        Err(CreditError::UnknownIou(9999))
+    ));
+
+    let ious = credit::get_ious(&mut conn, &span_frame).unwrap();
+
+    assert_eq!(
+        (ious[0].repaid, ious[0].outstanding),
+        (Amount::new(50), Amount::ZERO)
+    );
+
+    assert_eq!(
+        hold::get_available_balances(&mut conn, &span_frame, Utc::now())
+            .unwrap()
+            .into_iter()
+            .map(|balance| balance.balance)
+            .collect::<Vec<_>>(),
+        [Amount::new(150), Amount::new(-30)]
+    );
+}
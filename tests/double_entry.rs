@@ -0,0 +1,314 @@
+mod common;
+
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::double_entry::{self, DoubleEntryError, Posting};
+use credit_store_demo::db::ledger::{self, LedgerFormat};
+use credit_store_demo::db::models::{Amount, Asset, Person, Scale, coin_store};
+use credit_store_demo::macros::diesel_hist_models::{InsertEventForObjError, SpanFrame};
+use diesel::prelude::*;
+
+use common::establish_migrated;
+
+/// A store with alice and bob added with 0 coins
+fn establish_with_users() -> (SqliteConnection, SpanFrame, Person, Person) {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    let alice = Person::from_str("alice").unwrap();
+    let bob = Person::from_str("bob").unwrap();
+
+    for (obj_id, person) in [(1, &alice), (2, &bob)] {
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            obj_id,
+            &span_frame,
+            ObjState::Insert,
+            "add user",
+            coin_store::NewCommon {
+                coins: Amount::ZERO,
+                person,
+                asset: &Asset::default(),
+            },
+        )
+        .unwrap();
+    }
+
+    (conn, span_frame, alice, bob)
+}
+
+static COIN: LazyLock<Asset> = LazyLock::new(Asset::default);
+
+fn posting<'a>(obj_id: i32, obj_state: ObjState, person: &'a Person, coins: i32) -> Posting<'a> {
+    Posting {
+        obj_id,
+        obj_state,
+        opt_target_ev_id: None,
+        ev_desc: "test",
+        person,
+        coins: coins.into(),
+        asset: &COIN,
+        opt_rate: None,
+        opt_expires_on: None,
+    }
+}
+
+#[test]
+fn test_double_entry_accounts_add_up_to_zero() {
+    let (mut conn, span_frame, alice, bob) = establish_with_users();
+
+    double_entry::enable_double_entry(&mut conn, &span_frame, "double-entry").unwrap();
+    assert!(double_entry::is_double_entry(&mut conn).unwrap());
+
+    let issuance = Person::issuance();
+    let expenses = Person::expenses();
+
+    double_entry::insert_transaction(
+        &mut conn,
+        &span_frame,
+        &[
+            posting(1, ObjState::Update, &alice, 10),
+            posting(3, ObjState::Insert, &issuance, -10),
+        ],
+    )
+    .unwrap();
+
+    let tx_id = double_entry::insert_transaction(
+        &mut conn,
+        &span_frame,
+        &[
+            posting(1, ObjState::Update, &alice, -4),
+            posting(2, ObjState::Update, &bob, 4),
+        ],
+    )
+    .unwrap();
+
+    double_entry::insert_transaction(
+        &mut conn,
+        &span_frame,
+        &[
+            posting(2, ObjState::Update, &bob, -1),
+            posting(4, ObjState::Insert, &expenses, 1),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(tx_id, 2);
+
+    let balances = double_entry::get_account_balances(&mut conn, &span_frame).unwrap();
+
+    assert_eq!(
+        balances,
+        vec![
+            (expenses, Asset::default(), 1.into()),
+            (issuance, Asset::default(), (-10).into()),
+            (alice, Asset::default(), 6.into()),
+            (bob, Asset::default(), 3.into()),
+        ]
+    );
+    assert_eq!(
+        Amount::checked_sum(balances.iter().map(|(_, _, coins)| *coins)).unwrap(),
+        Amount::ZERO
+    );
+
+    // Each transaction is exported as one, without a counter posting for the transfer
+    let transactions = ledger::get_ledger_transactions(&mut conn, &span_frame).unwrap();
+
+    assert_eq!(transactions.len(), 3);
+    assert!(
+        transactions
+            .iter()
+            .all(|transaction| transaction.postings.len() == 2)
+    );
+
+    let mut out = vec![];
+    ledger::write_ledger(
+        LedgerFormat::Ledger,
+        Scale::default(),
+        &transactions,
+        &mut out,
+    )
+    .unwrap();
+
+    assert!(
+        String::from_utf8(out)
+            .unwrap()
+            .contains("    Income:Coins  -10 COIN\n\n")
+    );
+    assert!(
+        ledger::check_ledger_balances(&mut conn, &span_frame, &transactions)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_double_entry_refuses_unbalanced_coins() {
+    let (mut conn, span_frame, alice, bob) = establish_with_users();
+
+    double_entry::enable_double_entry(&mut conn, &span_frame, "double-entry").unwrap();
+
+    assert!(matches!(
+        double_entry::insert_transaction(
+            &mut conn,
+            &span_frame,
+            &[
+                posting(1, ObjState::Update, &alice, -4),
+                posting(2, ObjState::Update, &bob, 3),
+            ],
+        ),
+        Err(DoubleEntryError::Unbalanced { total, .. }) if total == Amount::from(-1)
+    ));
+
+    assert!(matches!(
+        double_entry::insert_transaction(
+            &mut conn,
+            &span_frame,
+            &[posting(1, ObjState::Update, &alice, 0)],
+        ),
+        Err(DoubleEntryError::TooFewPostings)
+    ));
+
+    // Outside of a transaction, events are refused, and the database refuses any coins
+    assert!(matches!(
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            1,
+            &span_frame,
+            ObjState::Update,
+            "income",
+            coin_store::NewCommon {
+                coins: 5.into(),
+                person: &alice,
+                asset: &Asset::default(),
+            },
+        ),
+        Err(InsertEventForObjError::DoubleEntry)
+    ));
+    assert!(
+        double_entry::insert_posting(
+            &mut conn,
+            &span_frame,
+            None,
+            &posting(1, ObjState::Update, &alice, 5)
+        )
+        .is_err()
+    );
+
+    assert!(
+        double_entry::get_account_balances(&mut conn, &span_frame)
+            .unwrap()
+            .iter()
+            .all(|(_, _, coins)| coins.is_zero())
+    );
+}
+
+#[test]
+fn test_double_entry_only_enabled_before_coins_move() {
+    let (mut conn, span_frame, alice, _) = establish_with_users();
+
+    coin_store::insert_event_for_obj(
+        &mut conn,
+        1,
+        &span_frame,
+        ObjState::Update,
+        "income",
+        coin_store::NewCommon {
+            coins: 5.into(),
+            person: &alice,
+            asset: &Asset::default(),
+        },
+    )
+    .unwrap();
+
+    assert!(matches!(
+        double_entry::enable_double_entry(&mut conn, &span_frame, "double-entry"),
+        Err(DoubleEntryError::CoinsAlreadyMoved)
+    ));
+    assert!(!double_entry::is_double_entry(&mut conn).unwrap());
+
+    assert!(Person::from_str("@issuance").is_err());
+}
+
+fn get_partial_coins(conn: &mut SqliteConnection, span_frame: &SpanFrame) -> Vec<(String, i64)> {
+    use credit_store_demo::autogen::schema::coin_store_hist_partial::dsl;
+
+    dsl::coin_store_hist_partial
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .order(dsl::obj_id.asc())
+        .select(coin_store::HistPartial::as_select())
+        .get_results(conn)
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.person.to_inner(), row.coins.to_inner()))
+        .collect()
+}
+
+#[test]
+fn test_transactions_are_toggled_as_a_whole() {
+    use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+
+    let (mut conn, span_frame, alice, bob) = establish_with_users();
+
+    double_entry::insert_transaction(
+        &mut conn,
+        &span_frame,
+        &[
+            posting(1, ObjState::Update, &alice, -4),
+            posting(2, ObjState::Update, &bob, 4),
+        ],
+    )
+    .unwrap();
+
+    let events: Vec<coin_store::EventGrouped> = dsl::coin_store_events_grouped
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .select(coin_store::EventGrouped::as_select())
+        .get_results(&mut conn)
+        .unwrap();
+
+    // Like a filter that only matches alice's posting of the transfer
+    let without_alice_posting = events
+        .iter()
+        .filter(|event| !(event.person == alice && event.coins == Amount::from(-4)))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let toggle_events = coin_store::set_events_grouped_partial(
+        &mut conn,
+        &span_frame,
+        &without_alice_posting,
+        "toggle",
+    )
+    .unwrap();
+
+    assert_eq!(toggle_events.len(), 2);
+    assert_eq!(
+        get_partial_coins(&mut conn, &span_frame),
+        [("alice".to_owned(), 0), ("bob".to_owned(), 0)]
+    );
+
+    // Enabling bob's posting again brings alice's back with it
+    coin_store::set_events_grouped_partial(
+        &mut conn,
+        &span_frame,
+        &without_alice_posting,
+        "untoggle",
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_partial_coins(&mut conn, &span_frame),
+        [("alice".to_owned(), -4), ("bob".to_owned(), 4)]
+    );
+}
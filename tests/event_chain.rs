@@ -0,0 +1,124 @@
+mod common;
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::models::{Asset, Person, coin_store};
+use credit_store_demo::macros::diesel_hist_models::VerifyEventChainError;
+use diesel::{connection::SimpleConnection, prelude::*};
+
+use common::{establish_migrated, load_fixture};
+
+/// Opens a frame with alice's account and two transactions. Returns the id of the second transaction.
+fn insert_events(conn: &mut SqliteConnection) -> i32 {
+    let span_frame = coin_store::create_span_frame(conn, 1, 1, None, "First frame").unwrap();
+    let person: Person = "alice".parse().unwrap();
+
+    let ev_ids = [
+        (ObjState::Insert, 0),
+        (ObjState::Update, 10),
+        (ObjState::Update, -3),
+    ]
+    .into_iter()
+    .map(|(obj_state, coins)| {
+        coin_store::insert_event_for_obj(
+            conn,
+            101,
+            &span_frame,
+            obj_state,
+            "transaction",
+            coin_store::NewCommon {
+                coins: coins.into(),
+                person: &person,
+                asset: &Asset::default(),
+            },
+        )
+        .unwrap()
+        .id
+    })
+    .collect::<Vec<_>>();
+
+    ev_ids[1]
+}
+
+#[test]
+fn test_chain_detects_edited_diff() {
+    let mut conn = establish_migrated();
+    let ev_id = insert_events(&mut conn);
+
+    let (event_count, opt_head_ev_hash) = coin_store::verify_event_chain(&mut conn).unwrap();
+
+    assert_eq!(event_count, 4);
+    assert!(opt_head_ev_hash.is_some());
+
+    conn.batch_execute(&format!(
+        "UPDATE coin_store_diffs SET coins = 1000 WHERE id = (SELECT opt_diff_id FROM coin_store_events WHERE id = {ev_id})"
+    ))
+    .unwrap();
+
+    assert!(matches!(
+        coin_store::verify_event_chain(&mut conn),
+        Err(VerifyEventChainError::HashMismatch { ev_id: broken_ev_id }) if broken_ev_id == ev_id
+    ));
+}
+
+#[test]
+fn test_chain_detects_removed_event() {
+    let mut conn = establish_migrated();
+    let ev_id = insert_events(&mut conn);
+
+    conn.batch_execute(&format!("DELETE FROM coin_store_events WHERE id = {ev_id}"))
+        .unwrap();
+
+    assert!(matches!(
+        coin_store::verify_event_chain(&mut conn),
+        Err(VerifyEventChainError::HashMismatch { ev_id: broken_ev_id }) if broken_ev_id == ev_id + 1
+    ));
+}
+
+#[test]
+fn test_chain_seals_events_from_before_hashing() {
+    let mut conn = load_fixture("coin_store_at_2025-09-25-225000.sql");
+
+    assert!(matches!(
+        coin_store::verify_event_chain(&mut conn),
+        Err(VerifyEventChainError::MissingHash { ev_id: 1 })
+    ));
+
+    let sealed_count = coin_store::seal_event_chain(&mut conn).unwrap();
+
+    let (event_count, _) = coin_store::verify_event_chain(&mut conn).unwrap();
+
+    assert_eq!(sealed_count, event_count);
+
+    // Sealing only happens once
+    conn.batch_execute("UPDATE coin_store_events SET opt_ev_hash = NULL WHERE id = 1")
+        .unwrap();
+
+    assert_eq!(coin_store::seal_event_chain(&mut conn).unwrap(), 0);
+    assert!(matches!(
+        coin_store::verify_event_chain(&mut conn),
+        Err(VerifyEventChainError::MissingHash { ev_id: 1 })
+    ));
+}
+
+#[test]
+fn test_chain_does_not_reseal_removed_hashes() {
+    let mut conn = establish_migrated();
+    let ev_id = insert_events(&mut conn);
+
+    // Editing a diff and removing the hashes from there on must not get the edit chained again
+    conn.batch_execute(&format!(
+        "UPDATE coin_store_diffs SET coins = 1000 WHERE id = (SELECT opt_diff_id FROM coin_store_events WHERE id = {ev_id});
+         UPDATE coin_store_events SET opt_ev_hash = NULL WHERE id >= {ev_id};"
+    ))
+    .unwrap();
+
+    assert_eq!(coin_store::seal_event_chain(&mut conn).unwrap(), 0);
+
+    // Writing another event does not seal them either
+    coin_store::create_span_frame(&mut conn, 1, 2, None, "After tampering").unwrap();
+
+    assert!(matches!(
+        coin_store::verify_event_chain(&mut conn),
+        Err(VerifyEventChainError::MissingHash { ev_id: missing_ev_id }) if missing_ev_id == ev_id
+    ));
+}
@@ -0,0 +1,93 @@
+use credit_store_demo::autogen::schema::EventAction;
+use credit_store_demo::db::filter::{EventFilter, ParseEventFilterError};
+use credit_store_demo::db::models::coin_store::EventGrouped;
+
+fn make_event(person: &str, coins: i32, created_on: &str, ev_desc: &str) -> EventGrouped {
+    let created_on_ts = chrono::NaiveDate::parse_from_str(created_on, "%Y-%m-%d")
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp_millis() as f32;
+
+    EventGrouped {
+        id: 1,
+        grp_id: 1,
+        grp_span: 2,
+        grp_frame: 1,
+        grp_created_on_ts: created_on_ts,
+        dup: 1,
+        ev_id: 1,
+        obj_id: 1,
+        ev_action: EventAction::Update,
+        span: 1,
+        frame: 1,
+        created_on_ts,
+        person: person.parse().unwrap(),
+        coins: coins.into(),
+        ev_desc: ev_desc.to_owned(),
+        asset: Default::default(),
+    }
+}
+
+#[test]
+fn test_filter_matches_all_clauses() {
+    let event = make_event("alice", 50, "2025-03-10", "rent for march");
+
+    let filter: EventFilter =
+        "person=alice asset=coin coins=10..100 date=2025-03-01..2025-03-10 frame=1/1 action=update desc=^rent"
+            .parse()
+            .unwrap();
+
+    assert!(filter.matches(&event));
+}
+
+#[test]
+fn test_filter_rejects_on_any_clause() {
+    let event = make_event("alice", 50, "2025-03-10", "rent for march");
+
+    for filter_str in [
+        "person=bob",
+        "asset=points",
+        "coins=51..",
+        "coins=..49",
+        "coins=49",
+        "date=2025-03-11..",
+        "date=..2025-03-09",
+        "frame=2/1",
+        "action=insert",
+        "desc=^march",
+    ] {
+        let filter: EventFilter = filter_str.parse().unwrap();
+
+        assert!(!filter.matches(&event), "{filter_str} should not match");
+    }
+}
+
+#[test]
+fn test_filter_parse_errors() {
+    assert!(matches!(
+        "".parse::<EventFilter>(),
+        Err(ParseEventFilterError::Empty)
+    ));
+    assert!(matches!(
+        "person".parse::<EventFilter>(),
+        Err(ParseEventFilterError::MissingEquals(_))
+    ));
+    assert!(matches!(
+        "amount=1".parse::<EventFilter>(),
+        Err(ParseEventFilterError::UnknownKey(_))
+    ));
+    assert!(matches!(
+        "coins=a..b".parse::<EventFilter>(),
+        Err(ParseEventFilterError::InvalidValue("coins", _))
+    ));
+    assert!(matches!(
+        "action=launch".parse::<EventFilter>(),
+        Err(ParseEventFilterError::InvalidValue("action", _))
+    ));
+    assert!(matches!(
+        "desc=(".parse::<EventFilter>(),
+        Err(ParseEventFilterError::RegexError(_))
+    ));
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::{TimeDelta, Utc};
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::double_entry;
+use credit_store_demo::db::exchange::{self, ExchangeError, ExchangeOrder};
+use credit_store_demo::db::export;
+use credit_store_demo::db::hold::{self, HoldError};
+use credit_store_demo::db::limit::{self, LimitError};
+use credit_store_demo::db::models::{
+    Amount, Asset, Hold, Limit, LimitWindow, Person, Rate, coin_store,
+};
+use credit_store_demo::db::spend::SpendError;
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::prelude::*;
+
+use common::establish_migrated;
+
+const EXCHANGE_OBJ_ID: i32 = 99;
+
+/// A store with alice added with 100 points
+fn establish_with_points() -> (SqliteConnection, SpanFrame, Person, Asset) {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    let alice = Person::from_str("alice").unwrap();
+    let points = Asset::from_str("points").unwrap();
+
+    for (obj_state, coins) in [(ObjState::Insert, 0), (ObjState::Update, 100)] {
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            1,
+            &span_frame,
+            obj_state,
+            "test",
+            coin_store::NewCommon {
+                coins: coins.into(),
+                person: &alice,
+                asset: &points,
+            },
+        )
+        .unwrap();
+    }
+
+    (conn, span_frame, alice, points)
+}
+
+#[test]
+fn test_exchange_at_rate_of_span_frame() {
+    let (mut conn, span_frame, alice, points) = establish_with_points();
+    let coin = Asset::default();
+
+    let order = ExchangeOrder {
+        obj_id: 1,
+        person: &alice,
+        coins: 30.into(),
+        from_asset: &points,
+        to_asset: &coin,
+        ev_desc: "exchange",
+    };
+
+    assert!(matches!(
+        exchange::insert_exchange(&mut conn, &span_frame, &order, EXCHANGE_OBJ_ID, Utc::now()),
+        Err(ExchangeError::MissingRate { .. })
+    ));
+
+    let rate = Rate::from_str("10 points = 1 coin").unwrap();
+    exchange::set_rate(&mut conn, &span_frame, &rate, "rate").unwrap();
+
+    let exchanged =
+        exchange::insert_exchange(&mut conn, &span_frame, &order, EXCHANGE_OBJ_ID, Utc::now())
+            .unwrap();
+
+    assert_eq!(exchanged.to_coins, 3.into());
+    assert_eq!(exchanged.rate, rate);
+
+    // The rate holds both ways, but only for whole amounts
+    let back = ExchangeOrder {
+        coins: 1.into(),
+        from_asset: &coin,
+        to_asset: &points,
+        ..order.clone()
+    };
+
+    assert_eq!(
+        exchange::insert_exchange(&mut conn, &span_frame, &back, EXCHANGE_OBJ_ID, Utc::now())
+            .unwrap()
+            .to_coins,
+        10.into()
+    );
+    assert!(matches!(
+        exchange::insert_exchange(
+            &mut conn,
+            &span_frame,
+            &ExchangeOrder {
+                coins: 5.into(),
+                ..order.clone()
+            },
+            EXCHANGE_OBJ_ID,
+            Utc::now()
+        ),
+        Err(ExchangeError::InexactAmount { .. })
+    ));
+
+    let balances = double_entry::get_account_balances(&mut conn, &span_frame).unwrap();
+
+    assert_eq!(
+        balances,
+        vec![
+            (Person::exchange(), coin.clone(), (-2).into()),
+            (Person::exchange(), points.clone(), 20.into()),
+            (alice.clone(), coin.clone(), 2.into()),
+            (alice.clone(), points.clone(), 80.into()),
+        ]
+    );
+
+    // The user's records report the rate they were made at
+    let records = export::get_exported_records(&mut conn, Some(&span_frame)).unwrap();
+
+    assert_eq!(
+        records
+            .iter()
+            .filter(|record| record.person == "alice")
+            .filter_map(|record| record.opt_rate.as_deref())
+            .collect::<Vec<_>>(),
+        vec!["10 points = 1 coin"; 4]
+    );
+}
+
+#[test]
+fn test_exchange_only_spends_what_is_available() {
+    let (mut conn, span_frame, alice, points) = establish_with_points();
+    let coin = Asset::default();
+    let now = Utc::now();
+
+    exchange::set_rate(
+        &mut conn,
+        &span_frame,
+        &Rate::from_str("10 points = 1 coin").unwrap(),
+        "rate",
+    )
+    .unwrap();
+
+    let order = |coins: i64| ExchangeOrder {
+        obj_id: 1,
+        person: &alice,
+        coins: Amount::new(coins),
+        from_asset: &points,
+        to_asset: &coin,
+        ev_desc: "exchange",
+    };
+
+    hold::place_hold(
+        &mut conn,
+        &span_frame,
+        &Hold {
+            person: alice.clone(),
+            coins: Amount::new(80),
+            asset: points.clone(),
+            expires_on: now + TimeDelta::days(1),
+        },
+        now,
+        "hold",
+    )
+    .unwrap();
+
+    // The @exchange account takes the other side, so only alice's points are checked
+    assert!(matches!(
+        exchange::insert_exchange(&mut conn, &span_frame, &order(30), EXCHANGE_OBJ_ID, now),
+        Err(ExchangeError::SpendError(SpendError::HoldError(HoldError::InsufficientFunds { available, .. })))
+            if available == Amount::new(20)
+    ));
+
+    limit::set_limit(
+        &mut conn,
+        &span_frame,
+        &Limit {
+            opt_person: Some(alice.clone()),
+            coins: Amount::new(10),
+            asset: points.clone(),
+            window: LimitWindow::Day,
+        },
+        "limit",
+    )
+    .unwrap();
+
+    assert!(matches!(
+        exchange::insert_exchange(&mut conn, &span_frame, &order(20), EXCHANGE_OBJ_ID, now),
+        Err(ExchangeError::SpendError(SpendError::LimitError(
+            LimitError::LimitExceeded { .. }
+        )))
+    ));
+
+    exchange::insert_exchange(&mut conn, &span_frame, &order(10), EXCHANGE_OBJ_ID, now).unwrap();
+
+    assert_eq!(
+        double_entry::get_account_balances(&mut conn, &span_frame).unwrap(),
+        [
+            (Person::exchange(), coin.clone(), Amount::new(-1)),
+            (Person::exchange(), points.clone(), Amount::new(10)),
+            (alice.clone(), coin, Amount::new(1)),
+            (alice, points, Amount::new(90)),
+        ]
+    );
+}
+
+#[test]
+fn test_rates_are_kept_per_span_frame() {
+    let (mut conn, span_frame, _, points) = establish_with_points();
+    let coin = Asset::default();
+
+    exchange::set_rate(
+        &mut conn,
+        &span_frame,
+        &Rate::from_str("10 points = 1 coin").unwrap(),
+        "rate",
+    )
+    .unwrap();
+
+    let what_if = coin_store::create_span_frame(&mut conn, 1, 2, None, "what if").unwrap();
+
+    exchange::set_rate(
+        &mut conn,
+        &what_if,
+        &Rate::from_str("1 coin = 5 points").unwrap(),
+        "rate",
+    )
+    .unwrap();
+
+    let get_rate = |conn: &mut SqliteConnection, span_frame: &SpanFrame| {
+        exchange::get_rate(conn, span_frame, &coin, &points)
+            .unwrap()
+            .map(|rate| rate.to_string())
+    };
+
+    // The what-if frame's rate does not leak into the frame next to it
+    assert_eq!(
+        get_rate(&mut conn, &span_frame).as_deref(),
+        Some("10 points = 1 coin")
+    );
+    assert_eq!(
+        get_rate(&mut conn, &what_if).as_deref(),
+        Some("1 coin = 5 points")
+    );
+
+    for invalid in [
+        "10 points",
+        "0 points = 1 coin",
+        "1 coin = 2 coin",
+        "a points = 1 coin",
+    ] {
+        assert!(
+            Rate::from_str(invalid).is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+}
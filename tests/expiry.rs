@@ -0,0 +1,230 @@
+mod common;
+
+use chrono::{NaiveDate, TimeDelta, Utc};
+use credit_store_demo::db::double_entry;
+use credit_store_demo::db::expiry::{self, ExpiryBalance, ExpiryError};
+use credit_store_demo::db::hold;
+use credit_store_demo::db::models::{Amount, Asset, Person};
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::prelude::*;
+
+use common::establish_with_users;
+
+/// A store with alice added with no coins, then given 30 coins expiring in 10 days, 50 coins expiring in 20 days and 5
+/// coins that never expire
+fn establish_with_alice() -> (SqliteConnection, SpanFrame, Person, NaiveDate) {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 0)]);
+    let today = Utc::now().date_naive();
+
+    for (coins, days) in [(30, 10), (50, 20)] {
+        expiry::insert_expiring_income(
+            &mut conn,
+            &span_frame,
+            &alice,
+            Amount::new(coins),
+            &Asset::default(),
+            today + TimeDelta::days(days),
+            "promotion",
+        )
+        .unwrap();
+    }
+
+    double_entry::insert_coins_for_person(
+        &mut conn,
+        &span_frame,
+        &alice,
+        Amount::new(5),
+        &Asset::default(),
+        "salary",
+        None,
+    )
+    .unwrap();
+
+    (conn, span_frame, alice, today)
+}
+
+fn get_alice_expiry_balance(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    today: NaiveDate,
+) -> (Amount, Amount) {
+    let ExpiryBalance {
+        spendable, expired, ..
+    } = expiry::get_expiry_balances(conn, span_frame, today)
+        .unwrap()
+        .into_iter()
+        .find(|balance| balance.person.to_inner() == "alice")
+        .unwrap();
+
+    (spendable, expired)
+}
+
+#[test]
+fn test_expenses_consume_the_oldest_unexpired_coins_first() {
+    let (mut conn, span_frame, alice, today) = establish_with_alice();
+
+    assert!(matches!(
+        expiry::insert_expiring_income(
+            &mut conn,
+            &span_frame,
+            &alice,
+            Amount::ZERO,
+            &Asset::default(),
+            today,
+            "promotion"
+        ),
+        Err(ExpiryError::NotPositive)
+    ));
+
+    double_entry::insert_coins_for_person(
+        &mut conn,
+        &span_frame,
+        &alice,
+        Amount::new(-40),
+        &Asset::default(),
+        "groceries",
+        None,
+    )
+    .unwrap();
+
+    // The 30 coins expiring first are used up, then 10 of the 50
+    let lots = expiry::get_lots(&mut conn, &span_frame).unwrap();
+
+    assert_eq!(
+        lots.iter()
+            .map(|lot| (lot.coins, lot.opt_expires_on))
+            .collect::<Vec<_>>(),
+        [
+            (Amount::new(40), Some(today + TimeDelta::days(20))),
+            (Amount::new(5), None),
+        ]
+    );
+
+    assert_eq!(
+        get_alice_expiry_balance(&mut conn, &span_frame, today + TimeDelta::days(10)),
+        (Amount::new(45), Amount::ZERO)
+    );
+    assert_eq!(
+        get_alice_expiry_balance(&mut conn, &span_frame, today + TimeDelta::days(20)),
+        (Amount::new(5), Amount::new(40))
+    );
+}
+
+#[test]
+fn test_sweep_writes_off_expired_coins_once() {
+    let (mut conn, span_frame, _, today) = establish_with_alice();
+    let later = today + TimeDelta::days(10);
+
+    // Expired coins are still in the balance until swept, but cannot be spent
+    let now = Utc::now() + TimeDelta::days(10);
+    let available = hold::get_available_balances(&mut conn, &span_frame, now)
+        .unwrap()
+        .into_iter()
+        .find(|balance| balance.person.to_inner() == "alice")
+        .unwrap();
+
+    assert_eq!(
+        (available.balance, available.expired, available.available),
+        (Amount::new(85), Amount::new(30), Amount::new(55))
+    );
+
+    let swept = expiry::sweep_expired(&mut conn, &span_frame, later).unwrap();
+
+    assert_eq!(
+        swept
+            .iter()
+            .map(|lot| (lot.coins, lot.opt_expires_on))
+            .collect::<Vec<_>>(),
+        [(Amount::new(30), Some(later))]
+    );
+
+    assert_eq!(
+        double_entry::get_account_balances(&mut conn, &span_frame).unwrap()[0].2,
+        Amount::new(55)
+    );
+    assert_eq!(
+        get_alice_expiry_balance(&mut conn, &span_frame, later),
+        (Amount::new(55), Amount::ZERO)
+    );
+    assert!(
+        expiry::sweep_expired(&mut conn, &span_frame, later)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_expenses_spend_the_lots_of_the_date_they_were_made_on() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 0)]);
+    let today = Utc::now().date_naive();
+    let expires_on = today + TimeDelta::days(10);
+
+    expiry::insert_expiring_income(
+        &mut conn,
+        &span_frame,
+        &alice,
+        Amount::new(30),
+        &Asset::default(),
+        expires_on,
+        "promotion",
+    )
+    .unwrap();
+
+    double_entry::insert_coins_for_person(
+        &mut conn,
+        &span_frame,
+        &alice,
+        Amount::new(-10),
+        &Asset::default(),
+        "groceries",
+        None,
+    )
+    .unwrap();
+
+    let ev_id: i32 = {
+        use credit_store_demo::autogen::schema::coin_store_events::dsl;
+
+        let (ev_id, opt_made_on) = dsl::coin_store_events
+            .filter(dsl::ev_desc.eq("groceries"))
+            .select((dsl::id, dsl::opt_made_on))
+            .first::<(i32, Option<String>)>(&mut conn)
+            .unwrap();
+
+        assert_eq!(opt_made_on, Some(today.to_string()));
+
+        ev_id
+    };
+
+    // Made just before midnight of the day before the lot expires, with a timestamp that rounds past midnight
+    let created_on_ts = expires_on
+        .and_time(chrono::NaiveTime::MIN)
+        .and_utc()
+        .timestamp_millis() as f32;
+
+    {
+        use credit_store_demo::autogen::schema::coin_store_events::dsl;
+
+        diesel::update(dsl::coin_store_events.filter(dsl::id.eq(ev_id)))
+            .set((
+                dsl::created_on_ts.eq(created_on_ts),
+                dsl::opt_made_on.eq((expires_on - TimeDelta::days(1)).to_string()),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    {
+        use credit_store_demo::autogen::schema::coin_store_events_grouped::dsl;
+
+        diesel::update(dsl::coin_store_events_grouped.filter(dsl::ev_id.eq(ev_id)))
+            .set(dsl::created_on_ts.eq(created_on_ts))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    // The groceries were paid from the lot before it expired, so only the rest of it expires
+    assert_eq!(
+        get_alice_expiry_balance(&mut conn, &span_frame, expires_on).1,
+        Amount::new(20)
+    );
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use credit_store_demo::db::export::{self, ExportFormat, ExportKind, ExportedEvent};
+
+use common::load_fixture;
+
+#[test]
+fn test_export_events_round_trips() {
+    let mut conn = load_fixture("coin_store_at_2026-10-18-000500.sql");
+
+    let events = export::get_exported_events(&mut conn, None).unwrap();
+
+    let mut jsonl = vec![];
+    let count = export::export(
+        &mut conn,
+        ExportKind::Events,
+        ExportFormat::Jsonl,
+        None,
+        &mut jsonl,
+    )
+    .unwrap();
+
+    assert_eq!(count, events.len());
+
+    let from_jsonl = String::from_utf8(jsonl)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<ExportedEvent>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(from_jsonl, events);
+
+    let mut csv = vec![];
+    export::export(
+        &mut conn,
+        ExportKind::Events,
+        ExportFormat::Csv,
+        None,
+        &mut csv,
+    )
+    .unwrap();
+
+    let from_csv = csv::Reader::from_reader(csv.as_slice())
+        .deserialize::<ExportedEvent>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(from_csv, events);
+}
+
+#[test]
+fn test_export_wallet_of_frame() {
+    let mut conn = load_fixture("coin_store_at_2025-09-25-225000.sql");
+
+    let span_frame = credit_store_demo::db::models::coin_store::get_created_span_frames(&mut conn)
+        .unwrap()
+        .into_iter()
+        .find(|sf| sf.span == 2)
+        .unwrap();
+
+    let mut csv = vec![];
+    export::export(
+        &mut conn,
+        ExportKind::Wallet,
+        ExportFormat::Csv,
+        Some(&span_frame),
+        &mut csv,
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "format_version,span,frame,obj_id,obj_state,person,coins,asset\n\
+         11,2,1,101,update,alice,14,coin\n\
+         11,2,1,202,update,bob,5,coin\n"
+    );
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::{NaiveDate, Utc};
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::credit;
+use credit_store_demo::db::double_entry;
+use credit_store_demo::db::forecast::{self, ProjectedBalance};
+use credit_store_demo::db::models::{Amount, Asset, CreditLine, Person, Schedule, coin_store};
+use credit_store_demo::db::schedule;
+use diesel::prelude::*;
+
+use common::{establish_migrated, establish_with_users};
+
+fn date(s: &str) -> NaiveDate {
+    s.parse().unwrap()
+}
+
+fn new_alice_schedule(coins: i64, every: &str, start: &str) -> Schedule {
+    Schedule {
+        person: Person::from_str("alice").unwrap(),
+        coins: Amount::new(coins),
+        asset: Asset::default(),
+        every: every.parse().unwrap(),
+        start: date(start),
+    }
+}
+
+fn count_events(conn: &mut SqliteConnection) -> i64 {
+    use credit_store_demo::autogen::schema::coin_store_events::dsl;
+
+    dsl::coin_store_events.count().get_result(conn).unwrap()
+}
+
+#[test]
+fn test_forecast_projects_balances_without_persisting() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 20)]);
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_alice_schedule(10, "month", "2026-01-15"),
+        "allowance",
+    )
+    .unwrap();
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_alice_schedule(-25, "month", "2026-01-01"),
+        "rent",
+    )
+    .unwrap();
+
+    // Rent takes alice below 0, which her credit line allows
+    credit::set_credit_line(
+        &mut conn,
+        &span_frame,
+        &CreditLine {
+            person: alice.clone(),
+            coins: Amount::new(50),
+            asset: Asset::default(),
+        },
+        "credit line",
+    )
+    .unwrap();
+
+    // The first rent is already written, so only the later occurrences are forecast
+    schedule::run_schedules(&mut conn, &span_frame, date("2026-01-01"), Utc::now()).unwrap();
+
+    let event_count = count_events(&mut conn);
+
+    let forecast =
+        forecast::forecast(&mut conn, &span_frame, date("2026-02-20"), Utc::now()).unwrap();
+
+    assert_eq!(
+        forecast.balances,
+        [(alice.clone(), Asset::default(), Amount::new(-5))]
+    );
+
+    let projected = |s: &str, coins: i64| ProjectedBalance {
+        date: date(s),
+        person: alice.clone(),
+        asset: Asset::default(),
+        coins: Amount::new(coins),
+    };
+
+    assert_eq!(
+        forecast.projected,
+        [
+            projected("2026-01-15", 5),
+            projected("2026-02-01", -20),
+            projected("2026-02-15", -10),
+        ]
+    );
+
+    // Alice is below 0 already, but the allowance lifts her above it before the rent pulls her back down
+    assert_eq!(
+        forecast.get_first_negative_dates(),
+        [(alice, Asset::default(), date("2026-02-01"))]
+    );
+
+    assert_eq!(count_events(&mut conn), event_count);
+    assert_eq!(
+        schedule::get_schedules(&mut conn).unwrap()[1].occurrence_count,
+        1
+    );
+}
+
+#[test]
+fn test_forecast_leaves_out_cancelled_schedules_and_system_accounts() {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+    let alice = Person::from_str("alice").unwrap();
+
+    coin_store::insert_event_for_obj(
+        &mut conn,
+        alice.get_obj_id(),
+        &span_frame,
+        ObjState::Insert,
+        "add user",
+        coin_store::NewCommon {
+            coins: Amount::ZERO,
+            person: &alice,
+            asset: &Asset::default(),
+        },
+    )
+    .unwrap();
+
+    double_entry::enable_double_entry(&mut conn, &span_frame, "double-entry").unwrap();
+
+    let cancelled = schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_alice_schedule(-100, "day", "2026-01-01"),
+        "fine",
+    )
+    .unwrap();
+
+    schedule::cancel_schedule(&mut conn, cancelled.id, "cancel").unwrap();
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_alice_schedule(5, "week", "2026-01-01"),
+        "pocket money",
+    )
+    .unwrap();
+
+    let forecast =
+        forecast::forecast(&mut conn, &span_frame, date("2026-01-08"), Utc::now()).unwrap();
+
+    assert_eq!(
+        forecast
+            .projected
+            .iter()
+            .map(|p| (p.date, p.person.clone(), p.coins))
+            .collect::<Vec<_>>(),
+        [
+            (date("2026-01-01"), alice.clone(), Amount::new(5)),
+            (date("2026-01-08"), alice, Amount::new(10)),
+        ]
+    );
+    assert!(forecast.get_first_negative_dates().is_empty());
+}
+
+#[test]
+fn test_forecast_runs_the_schedules_as_a_run_would() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 10)]);
+
+    let fee = schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_alice_schedule(-5, "day", "2026-01-01"),
+        "fee",
+    )
+    .unwrap();
+
+    // The third fee is more than alice has left
+    let forecast =
+        forecast::forecast(&mut conn, &span_frame, date("2026-01-04"), Utc::now()).unwrap();
+
+    assert_eq!(
+        forecast
+            .projected
+            .iter()
+            .map(|p| (p.date, p.person.clone(), p.coins))
+            .collect::<Vec<_>>(),
+        [
+            (date("2026-01-01"), alice.clone(), Amount::new(5)),
+            (date("2026-01-02"), alice, Amount::new(0)),
+        ]
+    );
+    assert_eq!(
+        forecast
+            .refused
+            .iter()
+            .map(|refused| (
+                refused.occurrence.schedule_ev_id,
+                refused.occurrence.due_date
+            ))
+            .collect::<Vec<_>>(),
+        [(fee.id, date("2026-01-03"))]
+    );
+
+    // Schedules are run in their own span frame only, so a pushed span frame has nothing to forecast
+    let pushed = coin_store::create_span_frame(&mut conn, 2, 1, Some(&span_frame), "push").unwrap();
+
+    let forecast = forecast::forecast(&mut conn, &pushed, date("2026-01-04"), Utc::now()).unwrap();
+
+    assert!(forecast.projected.is_empty());
+    assert!(forecast.refused.is_empty());
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use credit_store_demo::db::hold::{self, AvailableBalance, HoldError};
+use credit_store_demo::db::models::{Amount, Asset, Hold, HoldDuration, Payload, Person};
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::prelude::*;
+
+use common::establish_with_users;
+
+fn new_hold(person: &Person, coins: i64, now: DateTime<Utc>) -> Hold {
+    Hold {
+        person: person.clone(),
+        coins: Amount::new(coins),
+        asset: Asset::default(),
+        expires_on: now + HoldDuration::default().to_inner(),
+    }
+}
+
+fn get_alice_balance(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    now: DateTime<Utc>,
+) -> (Amount, Amount, Amount) {
+    let AvailableBalance {
+        balance,
+        held,
+        available,
+        ..
+    } = hold::get_available_balances(conn, span_frame, now)
+        .unwrap()
+        .into_iter()
+        .find(|balance| balance.person.to_inner() == "alice")
+        .unwrap();
+
+    (balance, held, available)
+}
+
+#[test]
+fn test_holds_are_checked_and_durations_parsed() {
+    let now = Utc::now();
+    let alice = Person::from_str("alice").unwrap();
+
+    for coins in [0, -5] {
+        assert!(new_hold(&alice, coins, now).to_payload().is_err());
+    }
+
+    for invalid in [
+        r#"{"person":"@exchange","coins":5,"asset":"coin","expires_on":"2026-11-01T12:00:00Z"}"#,
+        r#"{"person":"alice","coins":5,"asset":"coin","expires_on":"tomorrow"}"#,
+    ] {
+        assert!(
+            Hold::from_payload(invalid).is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+
+    assert_eq!(
+        HoldDuration::from_str("2 hours").unwrap().to_inner(),
+        TimeDelta::hours(2)
+    );
+    assert_eq!(
+        HoldDuration::from_str("1 day").unwrap().to_inner(),
+        TimeDelta::days(1)
+    );
+    assert!(HoldDuration::from_str("0 minutes").is_err());
+    assert!(HoldDuration::from_str("3 fortnights").is_err());
+}
+
+#[test]
+fn test_holds_reserve_coins_until_captured() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 100)]);
+    let now = Utc::now();
+
+    let event = hold::place_hold(
+        &mut conn,
+        &span_frame,
+        &new_hold(&alice, 30, now),
+        now,
+        "checkout",
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_alice_balance(&mut conn, &span_frame, now),
+        (Amount::new(100), Amount::new(30), Amount::new(70))
+    );
+
+    // Neither spending nor holding can dip into the held coins
+    assert!(matches!(
+        hold::check_available(
+            &mut conn,
+            &span_frame,
+            &alice,
+            &Asset::default(),
+            Amount::new(71),
+            now
+        ),
+        Err(HoldError::InsufficientFunds { available, .. }) if available == Amount::new(70)
+    ));
+    assert!(matches!(
+        hold::place_hold(
+            &mut conn,
+            &span_frame,
+            &new_hold(&alice, 71, now),
+            now,
+            "checkout"
+        ),
+        Err(HoldError::InsufficientFunds { .. })
+    ));
+
+    assert!(matches!(
+        hold::capture_hold(
+            &mut conn,
+            &span_frame,
+            event.id,
+            Some(Amount::new(31)),
+            now,
+            "charge"
+        ),
+        Err(HoldError::ExceedsHold { .. })
+    ));
+
+    // Capturing less than was held frees up the rest
+    assert_eq!(
+        hold::capture_hold(
+            &mut conn,
+            &span_frame,
+            event.id,
+            Some(Amount::new(20)),
+            now,
+            "charge"
+        )
+        .unwrap(),
+        Amount::new(20)
+    );
+
+    assert_eq!(
+        get_alice_balance(&mut conn, &span_frame, now),
+        (Amount::new(80), Amount::ZERO, Amount::new(80))
+    );
+
+    assert!(matches!(
+        hold::capture_hold(&mut conn, &span_frame, event.id, None, now, "charge"),
+        Err(HoldError::AlreadySettled(_))
+    ));
+}
+
+#[test]
+fn test_holds_are_released_or_expire() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 100)]);
+    let now = Utc::now();
+
+    let released = hold::place_hold(
+        &mut conn,
+        &span_frame,
+        &new_hold(&alice, 40, now),
+        now,
+        "checkout",
+    )
+    .unwrap();
+
+    let expiring = hold::place_hold(
+        &mut conn,
+        &span_frame,
+        &new_hold(&alice, 50, now),
+        now,
+        "checkout",
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_alice_balance(&mut conn, &span_frame, now),
+        (Amount::new(100), Amount::new(90), Amount::new(10))
+    );
+
+    hold::release_hold(&mut conn, &span_frame, released.id, now, "release").unwrap();
+
+    assert!(matches!(
+        hold::release_hold(&mut conn, &span_frame, released.id, now, "release"),
+        Err(HoldError::AlreadySettled(_))
+    ));
+    assert!(matches!(
+        hold::release_hold(&mut conn, &span_frame, 1, now, "release"),
+        Err(HoldError::UnknownHold(1))
+    ));
+
+    assert_eq!(
+        get_alice_balance(&mut conn, &span_frame, now),
+        (Amount::new(100), Amount::new(50), Amount::new(50))
+    );
+
+    // Nothing is written when a hold expires, it just stops counting
+    let later = now + TimeDelta::minutes(16);
+
+    assert_eq!(
+        get_alice_balance(&mut conn, &span_frame, later),
+        (Amount::new(100), Amount::ZERO, Amount::new(100))
+    );
+    assert!(matches!(
+        hold::capture_hold(&mut conn, &span_frame, expiring.id, None, later, "charge"),
+        Err(HoldError::Expired { .. })
+    ));
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use credit_store_demo::db::export::{self, ExportFormat, ExportKind, ExportedEvent};
+use credit_store_demo::db::import::{self, ImportError};
+use diesel::prelude::*;
+
+use common::{establish_migrated, load_fixture};
+
+fn export_events(conn: &mut SqliteConnection) -> Vec<ExportedEvent> {
+    let mut jsonl = vec![];
+    export::export(
+        conn,
+        ExportKind::Events,
+        ExportFormat::Jsonl,
+        None,
+        &mut jsonl,
+    )
+    .unwrap();
+
+    import::read_exported_events(jsonl.as_slice()).unwrap()
+}
+
+/// The wallets of every span frame, which should survive an import unchanged
+fn export_wallets(conn: &mut SqliteConnection) -> Vec<(i32, i32, String, String)> {
+    export::get_exported_wallet(conn, None)
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.span, row.frame, row.person, row.coins))
+        .collect()
+}
+
+fn count_events(conn: &mut SqliteConnection) -> i64 {
+    use credit_store_demo::autogen::schema::coin_store_events::dsl;
+
+    dsl::coin_store_events.count().get_result(conn).unwrap()
+}
+
+#[test]
+fn test_import_replays_exported_store() {
+    let mut source_conn = load_fixture("coin_store_at_2026-10-18-000500.sql");
+    let events = export_events(&mut source_conn);
+
+    let mut conn = establish_migrated();
+
+    let summary = import::import_events(&mut conn, &events, true).unwrap();
+
+    assert_eq!(summary.event_count, events.len());
+    assert_eq!(count_events(&mut conn), 0);
+
+    import::import_events(&mut conn, &events, false).unwrap();
+
+    assert_eq!(count_events(&mut conn), events.len() as i64);
+    assert_eq!(export_wallets(&mut conn), export_wallets(&mut source_conn));
+    assert!(credit_store_demo::db::models::coin_store::verify_event_chain(&mut conn).is_ok());
+
+    let imported = export_events(&mut conn);
+
+    assert_eq!(
+        imported
+            .iter()
+            .map(|event| (
+                &event.ev_action,
+                &event.ev_desc,
+                &event.opt_person,
+                &event.opt_coins
+            ))
+            .collect::<Vec<_>>(),
+        events
+            .iter()
+            .map(|event| (
+                &event.ev_action,
+                &event.ev_desc,
+                &event.opt_person,
+                &event.opt_coins
+            ))
+            .collect::<Vec<_>>(),
+    );
+}
+
+#[test]
+fn test_import_is_all_or_nothing() {
+    let mut source_conn = load_fixture("coin_store_at_2025-09-25-225000.sql");
+    let mut events = export_events(&mut source_conn);
+
+    let mut conn = establish_migrated();
+
+    // An update of an object that is only inserted after it
+    let insert_index = events
+        .iter()
+        .position(|event| event.ev_action == "insert")
+        .unwrap();
+    let insert = events.remove(insert_index);
+    events.push(ExportedEvent {
+        id: events.last().unwrap().id + 1,
+        ..insert
+    });
+
+    assert!(matches!(
+        import::import_events(&mut conn, &events, false),
+        Err(ImportError::OutOfOrder { .. })
+    ));
+    assert_eq!(count_events(&mut conn), 0);
+
+    let mut events = export_events(&mut source_conn);
+    events
+        .iter_mut()
+        .rfind(|event| event.opt_person.is_some())
+        .unwrap()
+        .opt_person = Some("admin".to_owned());
+
+    assert!(matches!(
+        import::import_events(&mut conn, &events, false),
+        Err(ImportError::InvalidPerson { .. })
+    ));
+    assert_eq!(count_events(&mut conn), 0);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use credit_store_demo::db::ledger::{self, LedgerFormat};
+use credit_store_demo::db::models::coin_store;
+
+use common::load_fixture;
+
+#[test]
+fn test_ledger_export_balances_match_wallet() {
+    let mut conn = load_fixture("coin_store_at_2026-10-18-000500.sql");
+
+    let span_frame = coin_store::find_span_frame_by_label(&mut conn, "main")
+        .unwrap()
+        .unwrap();
+
+    for format in [LedgerFormat::Ledger, LedgerFormat::Beancount] {
+        let mut out = vec![];
+
+        let (count, mismatches) =
+            ledger::export_ledger(&mut conn, format, &span_frame, &mut out).unwrap();
+
+        assert!(count > 0);
+        assert!(mismatches.is_empty());
+
+        let out = String::from_utf8(out).unwrap();
+
+        match format {
+            LedgerFormat::Ledger => {
+                assert!(out.contains("    Assets:Coins:bob  -2 COIN\n    Expenses:Coins\n"));
+            }
+            LedgerFormat::Beancount => {
+                assert!(out.contains(" open Assets:Coins:Alice COIN\n"));
+                assert!(out.contains(" * \"lunch\"\n"));
+                assert!(out.contains("  Assets:Coins:Bob  -2 COIN\n  Expenses:Coins  2 COIN\n"));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ledger_balance_check_detects_missing_transaction() {
+    let mut conn = load_fixture("coin_store_at_2026-10-18-000500.sql");
+
+    let span_frame = coin_store::find_span_frame_by_label(&mut conn, "main")
+        .unwrap()
+        .unwrap();
+
+    let mut transactions = ledger::get_ledger_transactions(&mut conn, &span_frame).unwrap();
+    let removed = transactions.pop().unwrap();
+
+    let mismatches = ledger::check_ledger_balances(&mut conn, &span_frame, &transactions).unwrap();
+
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].person, removed.postings[0].person);
+    assert_eq!(
+        mismatches[0]
+            .hist_coins
+            .checked_sub(mismatches[0].exported_coins)
+            .unwrap(),
+        removed.postings[0].coins
+    );
+}
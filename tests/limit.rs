@@ -0,0 +1,362 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::double_entry;
+use credit_store_demo::db::hold::{self, HoldError};
+use credit_store_demo::db::limit::{self, LimitError};
+use credit_store_demo::db::models::{
+    Amount, Asset, Hold, Limit, LimitWindow, Payload, Person, Recurrence, Scale, Schedule,
+    coin_store,
+};
+use credit_store_demo::db::scale;
+use credit_store_demo::db::schedule;
+use credit_store_demo::db::spend::SpendError;
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::prelude::*;
+
+use common::establish_with_users;
+
+/// A store with alice added with 100 coins, who then spent 30 of them
+fn establish_with_alice() -> (SqliteConnection, SpanFrame, Person) {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 100)]);
+
+    double_entry::insert_coins_for_person(
+        &mut conn,
+        &span_frame,
+        &alice,
+        Amount::new(-30),
+        &Asset::default(),
+        "groceries",
+        None,
+    )
+    .unwrap();
+
+    (conn, span_frame, alice)
+}
+
+fn date(s: &str) -> NaiveDate {
+    s.parse().unwrap()
+}
+
+/// A limit in the default asset, for each user on their own if `opt_person` is `None`
+fn new_limit(opt_person: Option<&str>, coins: i64, window: LimitWindow) -> Limit {
+    Limit {
+        opt_person: opt_person.map(|person| Person::from_str(person).unwrap()),
+        coins: Amount::new(coins),
+        asset: Asset::default(),
+        window,
+    }
+}
+
+#[test]
+fn test_limits_are_checked_and_windows_reset() {
+    let limit = new_limit(None, 0, LimitWindow::Month);
+
+    assert!(limit.applies_to(&Person::from_str("bob").unwrap()));
+
+    // A default per-user limit is stored as such, not as a user with a special name
+    assert!(limit.to_payload().unwrap().contains(r#""opt_person":null"#));
+    assert!(
+        new_limit(Some("alice"), -5, LimitWindow::Day)
+            .to_payload()
+            .is_err()
+    );
+
+    for invalid in [
+        r#"{"opt_person":"@expenses","coins":5,"asset":"coin","window":"day"}"#,
+        r#"{"opt_person":"alice","coins":5,"asset":"coin","window":"fortnight"}"#,
+        r#"{"opt_person":"alice","coins":5,"asset":"coin"}"#,
+    ] {
+        assert!(
+            Limit::from_payload(invalid).is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+
+    // 2026-10-21 is a Wednesday
+    let today = date("2026-10-21");
+
+    assert_eq!(LimitWindow::Week.get_start(today), date("2026-10-19"));
+    assert_eq!(LimitWindow::Week.get_reset(today), Some(date("2026-10-26")));
+    assert_eq!(
+        LimitWindow::Month.get_reset(today),
+        Some(date("2026-11-01"))
+    );
+    assert_eq!(LimitWindow::Day.get_reset(today), Some(date("2026-10-22")));
+}
+
+#[test]
+fn test_limits_cap_spending_in_the_window() {
+    let (mut conn, span_frame, alice) = establish_with_alice();
+    let now = Utc::now();
+
+    let alice_limit = limit::set_limit(
+        &mut conn,
+        &span_frame,
+        &new_limit(Some("alice"), 50, LimitWindow::Day),
+        "daily limit",
+    )
+    .unwrap();
+
+    // Income does not make up for what was spent
+    double_entry::insert_coins_for_person(
+        &mut conn,
+        &span_frame,
+        &alice,
+        Amount::new(10),
+        &Asset::default(),
+        "salary",
+        None,
+    )
+    .unwrap();
+
+    let tomorrow = (now + TimeDelta::days(1))
+        .date_naive()
+        .and_time(chrono::NaiveTime::MIN)
+        .and_utc();
+
+    assert!(matches!(
+        limit::check_limits(&mut conn, &span_frame, &alice, &Asset::default(), Amount::new(21), now),
+        Err(LimitError::LimitExceeded { remaining, resets_on, limit_ev_id, .. })
+            if remaining == Amount::new(20) && resets_on == tomorrow && limit_ev_id == alice_limit.id
+    ));
+    limit::check_limits(
+        &mut conn,
+        &span_frame,
+        &alice,
+        &Asset::default(),
+        Amount::new(20),
+        now,
+    )
+    .unwrap();
+
+    // Other assets are not limited
+    limit::check_limits(
+        &mut conn,
+        &span_frame,
+        &alice,
+        &Asset::from_str("points").unwrap(),
+        Amount::new(1000),
+        now,
+    )
+    .unwrap();
+
+    // The limit with the least left is the one reported
+    let everyone_limit = limit::set_limit(
+        &mut conn,
+        &span_frame,
+        &new_limit(None, 10, LimitWindow::Day),
+        "daily limit for everyone",
+    )
+    .unwrap();
+
+    assert!(matches!(
+        limit::check_limits(&mut conn, &span_frame, &alice, &Asset::default(), Amount::new(1), now),
+        Err(LimitError::LimitExceeded { remaining, limit_ev_id, .. })
+            if remaining == Amount::ZERO && limit_ev_id == everyone_limit.id
+    ));
+
+    let lifting = limit::lift_limit(&mut conn, &span_frame, everyone_limit.id, "lift").unwrap();
+
+    assert!(matches!(
+        limit::lift_limit(&mut conn, &span_frame, everyone_limit.id, "lift"),
+        Err(LimitError::AlreadyEnded(_))
+    ));
+
+    // Setting the limit again replaces it
+    let replacing = limit::set_limit(
+        &mut conn,
+        &span_frame,
+        &new_limit(Some("alice"), 100, LimitWindow::Day),
+        "higher daily limit",
+    )
+    .unwrap();
+
+    let limits = limit::get_limits(&mut conn, &span_frame).unwrap();
+
+    assert_eq!(
+        limits
+            .iter()
+            .map(|info| (info.event.id, info.opt_ended_by))
+            .collect::<Vec<_>>(),
+        [
+            (alice_limit.id, Some(replacing.id)),
+            (everyone_limit.id, Some(lifting.id)),
+            (replacing.id, None),
+        ]
+    );
+
+    let allowances =
+        limit::get_allowances(&mut conn, &span_frame, &alice, &Asset::default(), now).unwrap();
+
+    assert_eq!(
+        allowances
+            .iter()
+            .map(|allowance| (allowance.limit_ev_id, allowance.spent, allowance.remaining))
+            .collect::<Vec<_>>(),
+        [(replacing.id, Amount::new(30), Amount::new(70))]
+    );
+}
+
+#[test]
+fn test_limits_hold_back_scheduled_expenses_and_captures() {
+    let (mut conn, span_frame, alice) = establish_with_alice();
+    let now = Utc::now();
+
+    limit::set_limit(
+        &mut conn,
+        &span_frame,
+        &new_limit(Some("alice"), 50, LimitWindow::Day),
+        "daily limit",
+    )
+    .unwrap();
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &Schedule {
+            person: alice.clone(),
+            coins: Amount::new(-25),
+            asset: Asset::default(),
+            every: Recurrence::Days(1),
+            start: now.date_naive(),
+        },
+        "rent",
+    )
+    .unwrap();
+
+    // 30 of the 50 were spent today already
+    let run = schedule::run_schedules(&mut conn, &span_frame, now.date_naive(), now).unwrap();
+
+    assert!(run.written.is_empty());
+    assert!(matches!(
+        run.refused[0].error,
+        SpendError::LimitError(LimitError::LimitExceeded { remaining, .. })
+            if remaining == Amount::new(20)
+    ));
+
+    // Placing a hold spends nothing, but capturing it does
+    let hold_event = hold::place_hold(
+        &mut conn,
+        &span_frame,
+        &Hold {
+            person: alice,
+            coins: Amount::new(25),
+            asset: Asset::default(),
+            expires_on: now + TimeDelta::days(1),
+        },
+        now,
+        "hold",
+    )
+    .unwrap();
+
+    assert!(matches!(
+        hold::capture_hold(&mut conn, &span_frame, hold_event.id, None, now, "capture"),
+        Err(HoldError::LimitError(LimitError::LimitExceeded { .. }))
+    ));
+    assert_eq!(
+        hold::capture_hold(
+            &mut conn,
+            &span_frame,
+            hold_event.id,
+            Some(Amount::new(20)),
+            now,
+            "capture"
+        )
+        .unwrap(),
+        Amount::new(20)
+    );
+}
+
+#[test]
+fn test_spent_is_read_from_the_exact_start_of_the_window() {
+    let (mut conn, span_frame, alice) = establish_with_alice();
+
+    let created_on_ts: f32 = {
+        use credit_store_demo::autogen::schema::coin_store_events::dsl;
+
+        dsl::coin_store_events
+            .filter(dsl::ev_desc.eq("groceries"))
+            .select(dsl::created_on_ts)
+            .first(&mut conn)
+            .unwrap()
+    };
+
+    let written_on = DateTime::from_timestamp_millis(created_on_ts as i64).unwrap();
+
+    assert_eq!(
+        limit::get_spent(
+            &mut conn,
+            &span_frame,
+            &alice,
+            &Asset::default(),
+            written_on
+        )
+        .unwrap(),
+        Amount::new(30)
+    );
+
+    // A millisecond later rounds to the same f32, but the groceries were bought before it
+    assert_eq!(
+        limit::get_spent(
+            &mut conn,
+            &span_frame,
+            &alice,
+            &Asset::default(),
+            written_on + TimeDelta::milliseconds(1)
+        )
+        .unwrap(),
+        Amount::ZERO
+    );
+}
+
+#[test]
+fn test_limit_errors_show_coins_at_the_scale_of_the_store() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 0)]);
+
+    scale::set_scale(
+        &mut conn,
+        &span_frame,
+        Scale::from_str("2").unwrap(),
+        "scale",
+    )
+    .unwrap();
+
+    coin_store::insert_event_for_obj(
+        &mut conn,
+        alice.get_obj_id(),
+        &span_frame,
+        ObjState::Update,
+        "salary",
+        coin_store::NewCommon {
+            coins: Amount::new(10000),
+            person: &alice,
+            asset: &Asset::default(),
+        },
+    )
+    .unwrap();
+
+    limit::set_limit(
+        &mut conn,
+        &span_frame,
+        &new_limit(Some("alice"), 5000, LimitWindow::Day),
+        "daily limit",
+    )
+    .unwrap();
+
+    let e = limit::check_limits(
+        &mut conn,
+        &span_frame,
+        &alice,
+        &Asset::default(),
+        Amount::new(5001),
+        Utc::now(),
+    )
+    .unwrap_err();
+
+    assert!(e.to_string().contains("can only spend 50.00 more coin"));
+    assert!(e.to_string().ends_with("which is less than 50.01"));
+}
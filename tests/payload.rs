@@ -0,0 +1,74 @@
+use std::str::FromStr;
+
+use credit_store_demo::db::models::{
+    Amount, Asset, CreditLine, Hold, Iou, Limit, LimitWindow, Payload, Person, Recurrence, Schedule,
+};
+
+#[test]
+fn test_payloads_read_back_names_with_whitespace() {
+    let alice_smith = Person::from_str("alice \"the\" smith").unwrap();
+
+    let schedule = Schedule {
+        person: alice_smith.clone(),
+        coins: Amount::new(-50),
+        asset: Asset::default(),
+        every: Recurrence::Months(1),
+        start: "2026-11-01".parse().unwrap(),
+    };
+
+    assert_eq!(
+        Schedule::from_payload(&schedule.to_payload().unwrap()).unwrap(),
+        schedule
+    );
+
+    let hold = Hold {
+        person: alice_smith.clone(),
+        coins: Amount::new(30),
+        asset: Asset::default(),
+        expires_on: "2026-11-01T12:00:00Z".parse().unwrap(),
+    };
+
+    assert_eq!(
+        Hold::from_payload(&hold.to_payload().unwrap()).unwrap(),
+        hold
+    );
+
+    // Only a limit without a user is for every user, so a user named `*` is not mistaken for everyone
+    for opt_person in [
+        Some(alice_smith.clone()),
+        Some(Person::from_str("*").unwrap()),
+        None,
+    ] {
+        let limit = Limit {
+            opt_person,
+            coins: Amount::new(5),
+            asset: Asset::default(),
+            window: LimitWindow::Week,
+        };
+
+        assert_eq!(
+            Limit::from_payload(&limit.to_payload().unwrap()).unwrap(),
+            limit
+        );
+    }
+
+    let credit_line = CreditLine {
+        person: alice_smith.clone(),
+        coins: Amount::new(200),
+        asset: Asset::default(),
+    };
+
+    assert_eq!(
+        CreditLine::from_payload(&credit_line.to_payload().unwrap()).unwrap(),
+        credit_line
+    );
+
+    let iou = Iou {
+        debtor: Person::from_str("bob jones").unwrap(),
+        creditor: alice_smith,
+        coins: Amount::new(50),
+        asset: Asset::default(),
+    };
+
+    assert_eq!(Iou::from_payload(&iou.to_payload().unwrap()).unwrap(), iou);
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use std::str::FromStr;
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::export;
+use credit_store_demo::db::import::{self, ImportError};
+use credit_store_demo::db::ledger::{self, LedgerFormat};
+use credit_store_demo::db::limit;
+use credit_store_demo::db::models::{
+    Amount, AmountError, Asset, Limit, LimitWindow, Person, Scale, coin_store,
+};
+use credit_store_demo::db::scale::{self, ScaleError};
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::prelude::*;
+
+use common::establish_migrated;
+
+/// A store with cents, where alice has 12.34 coins
+fn establish_with_cents() -> (SqliteConnection, SpanFrame, Scale) {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+    let cents = Scale::from_str("2").unwrap();
+
+    scale::set_scale(&mut conn, &span_frame, cents, "scale").unwrap();
+
+    let alice = Person::from_str("alice").unwrap();
+
+    for (obj_state, coins) in [(ObjState::Insert, "0"), (ObjState::Update, "12.34")] {
+        coin_store::insert_event_for_obj(
+            &mut conn,
+            1,
+            &span_frame,
+            obj_state,
+            "test",
+            coin_store::NewCommon {
+                coins: Amount::parse_scaled(coins, cents).unwrap(),
+                person: &alice,
+                asset: &Asset::default(),
+            },
+        )
+        .unwrap();
+    }
+
+    (conn, span_frame, cents)
+}
+
+#[test]
+fn test_amounts_are_parsed_and_displayed_at_scale() {
+    let cents = Scale::from_str("2").unwrap();
+
+    for (input, expected, displayed) in [
+        ("12.34", 1234, "12.34"),
+        ("12.3", 1230, "12.30"),
+        ("12", 1200, "12.00"),
+        ("-0.05", -5, "-0.05"),
+        ("+7.5", 750, "7.50"),
+    ] {
+        let amount = Amount::parse_scaled(input, cents).unwrap();
+
+        assert_eq!(amount, Amount::new(expected));
+        assert_eq!(amount.to_scaled_string(cents), displayed);
+    }
+
+    assert!(matches!(
+        Amount::parse_scaled("1.234", cents),
+        Err(AmountError::TooPrecise { .. })
+    ));
+    assert!(matches!(
+        Amount::parse_scaled("1.5", Scale::default()),
+        Err(AmountError::TooPrecise { .. })
+    ));
+
+    for invalid in ["", "1.", ".5", "1,50", "1.5e2", "--1"] {
+        assert!(
+            matches!(
+                Amount::parse_scaled(invalid, cents),
+                Err(AmountError::Invalid(_))
+            ),
+            "{invalid:?} should be rejected"
+        );
+    }
+
+    assert!(matches!(
+        Amount::parse_scaled("92233720368547758.08", cents),
+        Err(AmountError::OutOfRange(_))
+    ));
+    assert!(Scale::from_str("9").is_err());
+}
+
+#[test]
+fn test_scale_is_set_before_coins_move() {
+    let (mut conn, span_frame, cents) = establish_with_cents();
+
+    assert_eq!(scale::get_scale(&mut conn).unwrap(), cents);
+    assert!(matches!(
+        scale::set_scale(&mut conn, &span_frame, Scale::default(), "scale"),
+        Err(ScaleError::CoinsAlreadyMoved)
+    ));
+
+    let wallet = export::get_exported_wallet(&mut conn, Some(&span_frame)).unwrap();
+
+    assert_eq!(wallet[0].coins, "12.34");
+
+    let mut out = vec![];
+    ledger::export_ledger(&mut conn, LedgerFormat::Ledger, &span_frame, &mut out).unwrap();
+
+    assert!(String::from_utf8(out).unwrap().contains("12.34 COIN"));
+}
+
+#[test]
+fn test_scale_is_set_before_amounts_are_recorded() {
+    let mut conn = establish_migrated();
+
+    let span_frame = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    let limit = Limit {
+        opt_person: None,
+        coins: Amount::new(5),
+        asset: Asset::default(),
+        window: LimitWindow::Week,
+    };
+
+    limit::set_limit(&mut conn, &span_frame, &limit, "limit").unwrap();
+
+    // No coins have moved, but the limit's amount would change meaning
+    assert!(matches!(
+        scale::set_scale(
+            &mut conn,
+            &span_frame,
+            Scale::from_str("2").unwrap(),
+            "scale"
+        ),
+        Err(ScaleError::CoinsAlreadyMoved)
+    ));
+}
+
+#[test]
+fn test_import_reads_coins_at_scale() {
+    let (mut source_conn, _, cents) = establish_with_cents();
+
+    let mut jsonl = vec![];
+    export::export(
+        &mut source_conn,
+        export::ExportKind::Events,
+        export::ExportFormat::Jsonl,
+        None,
+        &mut jsonl,
+    )
+    .unwrap();
+
+    let events = import::read_exported_events(jsonl.as_slice()).unwrap();
+
+    let mut conn = establish_migrated();
+    import::import_events(&mut conn, &events, false).unwrap();
+
+    assert_eq!(scale::get_scale(&mut conn).unwrap(), cents);
+    assert_eq!(
+        export::get_exported_wallet(&mut conn, None).unwrap(),
+        export::get_exported_wallet(&mut source_conn, None).unwrap()
+    );
+
+    // Importing into a store with whole coins fails on the first amount with decimals, once the scale event is gone
+    let mut conn = establish_migrated();
+    let without_scale = events
+        .iter()
+        .filter(|event| event.ev_action != "scale")
+        .cloned()
+        .collect::<Vec<_>>();
+
+    assert!(matches!(
+        import::import_events(&mut conn, &without_scale, false),
+        Err(ImportError::AmountError {
+            source: AmountError::TooPrecise { .. },
+            ..
+        })
+    ));
+}
@@ -0,0 +1,321 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::{NaiveDate, Utc};
+use credit_store_demo::db::double_entry;
+use credit_store_demo::db::export;
+use credit_store_demo::db::hold::HoldError;
+use credit_store_demo::db::import;
+use credit_store_demo::db::models::{
+    Amount, Asset, Payload, Person, Recurrence, Schedule, coin_store,
+};
+use credit_store_demo::db::schedule::{self, ScheduleError};
+use credit_store_demo::db::spend::SpendError;
+use diesel::prelude::*;
+
+use common::{establish_migrated, establish_with_users};
+
+fn date(s: &str) -> NaiveDate {
+    s.parse().unwrap()
+}
+
+fn new_schedule(person: &str, coins: i64, every: &str, start: &str) -> Schedule {
+    Schedule {
+        person: Person::from_str(person).unwrap(),
+        coins: Amount::new(coins),
+        asset: Asset::default(),
+        every: every.parse().unwrap(),
+        start: date(start),
+    }
+}
+
+fn get_coins(conn: &mut SqliteConnection, person: &str) -> String {
+    export::get_exported_wallet(conn, None)
+        .unwrap()
+        .into_iter()
+        .find(|row| row.person == person)
+        .unwrap()
+        .coins
+}
+
+fn get_alice_coins(conn: &mut SqliteConnection) -> String {
+    get_coins(conn, "alice")
+}
+
+#[test]
+fn test_schedules_are_due_without_drift() {
+    let schedule = new_schedule("alice", 100, "month", "2026-01-31");
+
+    assert_eq!(schedule.every, Recurrence::Months(1));
+
+    // Short months are due on their last day, and the month after is back on the 31st
+    assert_eq!(
+        (0..4)
+            .map(|n| schedule.nth_due_date(n).unwrap())
+            .collect::<Vec<_>>(),
+        [
+            date("2026-01-31"),
+            date("2026-02-28"),
+            date("2026-03-31"),
+            date("2026-04-30")
+        ]
+    );
+
+    let charge = new_schedule("bob", -5, "2 weeks", "2026-11-01");
+
+    assert_eq!(charge.nth_due_date(2), Some(date("2026-11-29")));
+
+    for invalid in [
+        new_schedule("alice", 0, "month", "2026-11-01"),
+        Schedule {
+            every: Recurrence::Days(0),
+            ..schedule.clone()
+        },
+    ] {
+        assert!(
+            invalid.to_payload().is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+
+    for invalid in [
+        r#"{"person":"@issuance","coins":100,"asset":"coin","every":{"months":1},"start":"2026-11-01"}"#,
+        r#"{"person":"alice","coins":100,"asset":"coin","every":{"fortnights":1},"start":"2026-11-01"}"#,
+        r#"{"person":"alice","coins":100,"asset":"coin","every":{"months":1},"start":"2026-13-01"}"#,
+        r#"{"person":"alice","coins":100,"asset":"coin","every":{"months":1}}"#,
+    ] {
+        assert!(
+            Schedule::from_payload(invalid).is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+}
+
+#[test]
+fn test_run_schedules_writes_due_occurrences_exactly_once() {
+    let (mut conn, span_frame, _) = establish_with_users([("alice", 0)]);
+
+    let monthly = schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_schedule("alice", 100, "month", "2026-01-01"),
+        "salary",
+    )
+    .unwrap();
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_schedule("alice", -5, "2 weeks", "2026-01-01"),
+        "fee",
+    )
+    .unwrap();
+
+    // Nothing moves until the schedules are run
+    assert_eq!(get_alice_coins(&mut conn), "0");
+
+    // Schedules are only run in their own span frame
+    let pushed = coin_store::create_span_frame(&mut conn, 2, 1, Some(&span_frame), "push").unwrap();
+
+    assert!(
+        schedule::run_schedules(&mut conn, &pushed, date("2026-03-15"), Utc::now())
+            .unwrap()
+            .written
+            .is_empty()
+    );
+
+    // Jan 1, Feb 1, Mar 1 and Jan 1, 15, 29, Feb 12, 26, Mar 12
+    let occurrences =
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-03-15"), Utc::now())
+            .unwrap()
+            .written;
+
+    assert_eq!(occurrences.len(), 9);
+    assert_eq!(occurrences[1].due_date, date("2026-02-01"));
+    assert_eq!(get_alice_coins(&mut conn), "270");
+
+    assert!(
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-03-15"), Utc::now())
+            .unwrap()
+            .written
+            .is_empty()
+    );
+
+    // Mar 26 and Apr 1
+    assert_eq!(
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-04-01"), Utc::now())
+            .unwrap()
+            .written
+            .len(),
+        2
+    );
+    assert_eq!(get_alice_coins(&mut conn), "365");
+
+    schedule::cancel_schedule(&mut conn, monthly.id, "cancel").unwrap();
+
+    assert!(matches!(
+        schedule::cancel_schedule(&mut conn, monthly.id, "cancel"),
+        Err(ScheduleError::AlreadyCancelled(_))
+    ));
+    assert!(matches!(
+        schedule::cancel_schedule(&mut conn, 1, "cancel"),
+        Err(ScheduleError::UnknownSchedule(1))
+    ));
+
+    // Only the fee is still due, on Apr 9, Apr 23, May 7 and May 21
+    assert_eq!(
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-06-01"), Utc::now())
+            .unwrap()
+            .written
+            .len(),
+        4
+    );
+    assert_eq!(get_alice_coins(&mut conn), "345");
+
+    let infos = schedule::get_schedules(&mut conn).unwrap();
+
+    assert_eq!(
+        infos
+            .iter()
+            .map(|info| (info.cancelled, info.occurrence_count, info.next_due_date()))
+            .collect::<Vec<_>>(),
+        [(true, 4, None), (false, 11, Some(date("2026-06-04")))]
+    );
+}
+
+#[test]
+fn test_occurrences_balance_in_double_entry() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 0)]);
+
+    double_entry::enable_double_entry(&mut conn, &span_frame, "double-entry").unwrap();
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_schedule("alice", 100, "day", "2026-01-01"),
+        "allowance",
+    )
+    .unwrap();
+
+    assert_eq!(
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-01-03"), Utc::now())
+            .unwrap()
+            .written
+            .len(),
+        3
+    );
+
+    let balances = double_entry::get_account_balances(&mut conn, &span_frame).unwrap();
+
+    assert!(balances.contains(&(alice, Asset::default(), Amount::new(300))));
+    assert!(balances.contains(&(Person::issuance(), Asset::default(), Amount::new(-300))));
+
+    // Only alice's postings are occurrences, so the count is not thrown off by the other side
+    assert_eq!(
+        schedule::get_schedules(&mut conn).unwrap()[0].occurrence_count,
+        3
+    );
+}
+
+#[test]
+fn test_run_schedules_skips_expenses_that_are_not_available() {
+    let (mut conn, span_frame, _) = establish_with_users([("alice", 10), ("bob", 0)]);
+
+    let fee = schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_schedule("alice", -5, "day", "2026-01-01"),
+        "fee",
+    )
+    .unwrap();
+
+    schedule::add_schedule(
+        &mut conn,
+        &span_frame,
+        &new_schedule("bob", 20, "day", "2026-01-01"),
+        "allowance",
+    )
+    .unwrap();
+
+    // The third fee is more than alice has left, so it is skipped alone, and bob's allowance is written all the same
+    let run =
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-01-03"), Utc::now()).unwrap();
+
+    assert_eq!(run.written.len(), 5);
+    assert_eq!(
+        run.refused
+            .iter()
+            .map(|refused| (
+                refused.occurrence.schedule_ev_id,
+                refused.occurrence.due_date
+            ))
+            .collect::<Vec<_>>(),
+        [(fee.id, date("2026-01-03"))]
+    );
+    assert!(matches!(
+        run.refused[0].error,
+        SpendError::HoldError(HoldError::InsufficientFunds { .. })
+    ));
+    assert_eq!(get_alice_coins(&mut conn), "0");
+    assert_eq!(get_coins(&mut conn, "bob"), "60");
+
+    // The fee waits for alice to have the coins, and later ones wait for it
+    let run =
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-01-05"), Utc::now()).unwrap();
+
+    assert_eq!(run.written.len(), 2);
+    assert_eq!(run.refused[0].occurrence.due_date, date("2026-01-03"));
+    assert_eq!(
+        schedule::get_schedules(&mut conn).unwrap()[0].occurrence_count,
+        2
+    );
+}
+
+#[test]
+fn test_imported_schedules_keep_their_occurrences() {
+    let (mut source_conn, span_frame, _) = establish_with_users([("alice", 0)]);
+
+    schedule::add_schedule(
+        &mut source_conn,
+        &span_frame,
+        &new_schedule("alice", 100, "week", "2026-01-01"),
+        "pocket money",
+    )
+    .unwrap();
+
+    schedule::run_schedules(
+        &mut source_conn,
+        &span_frame,
+        date("2026-01-10"),
+        Utc::now(),
+    )
+    .unwrap();
+
+    let mut jsonl = vec![];
+    export::export(
+        &mut source_conn,
+        export::ExportKind::Events,
+        export::ExportFormat::Jsonl,
+        None,
+        &mut jsonl,
+    )
+    .unwrap();
+
+    let events = import::read_exported_events(jsonl.as_slice()).unwrap();
+
+    let mut conn = establish_migrated();
+    import::import_events(&mut conn, &events, false).unwrap();
+
+    assert_eq!(
+        schedule::get_schedules(&mut conn).unwrap()[0].occurrence_count,
+        2
+    );
+    assert!(
+        schedule::run_schedules(&mut conn, &span_frame, date("2026-01-10"), Utc::now())
+            .unwrap()
+            .written
+            .is_empty()
+    );
+    assert_eq!(get_alice_coins(&mut conn), "200");
+}
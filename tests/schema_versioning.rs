@@ -0,0 +1,187 @@
+//! Loads coin stores dumped at earlier migrations, runs the migrations since, and checks that their events still read
+//! the same.
+mod common;
+
+use std::{fs, path::Path};
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::models::{Asset, Person, coin_store};
+use credit_store_demo::db::upcast::{self, UpcastError, Upcaster};
+use diesel::{connection::SimpleConnection, prelude::*};
+
+use common::{load_fixture, run_pending_migrations};
+
+fn get_wallet(conn: &mut SqliteConnection, span: i32, frame: i32) -> Vec<(String, i64)> {
+    use credit_store_demo::autogen::schema::coin_store_hist::dsl;
+
+    dsl::coin_store_hist
+        .filter(dsl::grp_span.eq(span).and(dsl::grp_frame.eq(frame)))
+        .order(dsl::obj_id.asc())
+        .select(coin_store::Hist::as_select())
+        .get_results(conn)
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.person.to_inner(), row.coins.to_inner()))
+        .collect()
+}
+
+fn get_partial_wallet(conn: &mut SqliteConnection, span: i32, frame: i32) -> Vec<(String, i64)> {
+    use credit_store_demo::autogen::schema::coin_store_hist_partial::dsl;
+
+    dsl::coin_store_hist_partial
+        .filter(dsl::grp_span.eq(span).and(dsl::grp_frame.eq(frame)))
+        .order(dsl::obj_id.asc())
+        .select(coin_store::HistPartial::as_select())
+        .get_results(conn)
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.person.to_inner(), row.coins.to_inner()))
+        .collect()
+}
+
+/// Writes an event through the library so every projection is rebuilt, and checks it gets the current version
+fn insert_event_and_check_version(conn: &mut SqliteConnection) {
+    let span_frames = coin_store::get_created_span_frames(conn).unwrap();
+    let span_frame = span_frames.last().unwrap();
+
+    let person: Person = "alice".parse().unwrap();
+
+    coin_store::insert_event_for_obj(
+        conn,
+        101,
+        span_frame,
+        ObjState::Update,
+        "after migrating",
+        coin_store::NewCommon {
+            coins: 1.into(),
+            person: &person,
+            asset: &Asset::default(),
+        },
+    )
+    .unwrap();
+
+    let diffs = coin_store::get_diffs_upcasted(conn).unwrap();
+    let (last_diff, _) = diffs.last().unwrap();
+
+    assert_eq!(last_diff.schema_version, upcast::COIN_STORE_SCHEMA_VERSION);
+}
+
+#[test]
+fn test_fixture_from_first_release() {
+    let mut conn = load_fixture("coin_store_at_2025-09-25-225000.sql");
+
+    let diffs = coin_store::get_diffs_upcasted(&mut conn).unwrap();
+
+    assert!(diffs.iter().all(|(diff, _)| diff.schema_version == 1));
+    assert_eq!(
+        diffs
+            .iter()
+            .map(|(_, common)| (common.person.to_inner(), common.coins.to_inner()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("alice".to_owned(), 0),
+            ("alice".to_owned(), 10),
+            ("bob".to_owned(), 0),
+            ("bob".to_owned(), 5),
+            ("alice".to_owned(), -3),
+            ("alice".to_owned(), 7),
+        ]
+    );
+
+    insert_event_and_check_version(&mut conn);
+
+    assert_eq!(
+        get_wallet(&mut conn, 2, 1),
+        vec![("alice".to_owned(), 15), ("bob".to_owned(), 5)]
+    );
+
+    // Bob's gift was toggled off in the old partial table, which carries over as a toggle event
+    assert_eq!(
+        get_partial_wallet(&mut conn, 1, 1),
+        vec![("alice".to_owned(), 7), ("bob".to_owned(), 0)]
+    );
+}
+
+#[test]
+fn test_fixture_without_a_partial_view_toggles_nothing_off() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+
+    let dump =
+        fs::read_to_string(manifest_dir.join("tests/fixtures/coin_store_at_2025-09-25-225000.sql"))
+            .unwrap();
+
+    conn.batch_execute(&dump).unwrap();
+    conn.batch_execute("DELETE FROM coin_store_events_grouped_partial;")
+        .unwrap();
+
+    run_pending_migrations(&mut conn, &manifest_dir.join("migrations"));
+
+    // An empty old partial table means no partial view was ever set, not that everything was toggled off
+    assert_eq!(
+        get_partial_wallet(&mut conn, 1, 1),
+        vec![("alice".to_owned(), 7), ("bob".to_owned(), 5)]
+    );
+}
+
+#[test]
+fn test_fixture_before_schema_versions() {
+    let mut conn = load_fixture("coin_store_at_2026-10-18-000500.sql");
+
+    let diffs = coin_store::get_diffs_upcasted(&mut conn).unwrap();
+
+    assert_eq!(diffs.len(), 7);
+    assert!(diffs.iter().all(|(diff, _)| diff.schema_version == 1));
+
+    insert_event_and_check_version(&mut conn);
+
+    let span_frame = coin_store::find_span_frame_by_label(&mut conn, "main")
+        .unwrap()
+        .unwrap();
+
+    assert_eq!((span_frame.span, span_frame.frame), (2, 1));
+
+    // Projected from the fixture's snapshot plus the events after it
+    assert_eq!(
+        get_wallet(&mut conn, 2, 1),
+        vec![("alice".to_owned(), 15), ("bob".to_owned(), 3)]
+    );
+    assert!(
+        coin_store::verify_span_frame_snapshot(&mut conn, &span_frame)
+            .unwrap()
+            .is_empty()
+    );
+
+    let session_span_frame = coin_store::get_session_span_frame(&mut conn, "default")
+        .unwrap()
+        .unwrap();
+
+    assert_eq!((session_span_frame.span, session_span_frame.frame), (2, 1));
+}
+
+#[test]
+fn test_upcast_chain() {
+    let upcasters: &[Upcaster<i32>] = &[
+        Upcaster {
+            from_version: 1,
+            upcast: |coins| coins * 10,
+        },
+        Upcaster {
+            from_version: 2,
+            upcast: |coins| coins + 1,
+        },
+    ];
+
+    assert_eq!(upcast::upcast_with(upcasters, 3, 1, 5).unwrap(), 51);
+    assert_eq!(upcast::upcast_with(upcasters, 3, 2, 5).unwrap(), 6);
+    assert_eq!(upcast::upcast_with(upcasters, 3, 3, 5).unwrap(), 5);
+    assert!(matches!(
+        upcast::upcast_with(upcasters, 3, 4, 5),
+        Err(UpcastError::UnknownSchemaVersion(4, 3))
+    ));
+    assert!(matches!(
+        upcast::upcast_with(&upcasters[..1], 3, 1, 5),
+        Err(UpcastError::MissingUpcaster(2))
+    ));
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use std::str::FromStr;
+
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::models::{Asset, Person, coin_store};
+use credit_store_demo::macros::diesel_hist_models::SpanFrame;
+use diesel::prelude::*;
+
+use common::establish_migrated;
+
+#[test]
+fn test_span_frame_parent_is_the_frame_it_was_pushed_from() {
+    let mut conn = establish_migrated();
+
+    let root = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+    coin_store::create_span_frame(&mut conn, 1, 2, None, "hard reset").unwrap();
+
+    // Pushed from the first root after the second root was opened
+    coin_store::create_span_frame(&mut conn, 2, 1, Some(&root), "push new spanframe").unwrap();
+
+    let infos = coin_store::get_span_frame_infos(&mut conn).unwrap();
+
+    let pushed = infos
+        .iter()
+        .find(|info| info.span_frame.span == 2 && info.span_frame.frame == 1)
+        .unwrap();
+
+    let parent = pushed.opt_parent.as_ref().unwrap();
+
+    assert_eq!((parent.span, parent.frame), (1, 1));
+}
+
+#[test]
+fn test_span_frame_sees_its_own_events_and_those_from_before_it_was_opened() {
+    let mut conn = establish_migrated();
+
+    let root = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+    let pushed = coin_store::create_span_frame(&mut conn, 2, 1, Some(&root), "push").unwrap();
+
+    let info = coin_store::get_span_frame_info(&mut conn, &pushed)
+        .unwrap()
+        .unwrap();
+
+    // Timestamps are f32 millis, which only tell apart steps of about 2 minutes
+    let before = info.created_on_ts - 1_000_000.0;
+    let after = info.created_on_ts + 1_000_000.0;
+
+    assert!(info.sees(2, 1, after));
+    assert!(info.sees(1, 1, before));
+    assert!(!info.sees(1, 1, after));
+    assert!(!info.sees(2, 2, before));
+    assert!(!info.sees(3, 1, before));
+
+    assert!(
+        coin_store::get_span_frame_info(&mut conn, &SpanFrame { span: 3, frame: 1 })
+            .unwrap()
+            .is_none()
+    );
+}
+
+fn insert_coins(
+    conn: &mut SqliteConnection,
+    span_frame: &SpanFrame,
+    obj_state: ObjState,
+    coins: i64,
+) {
+    coin_store::insert_event_for_obj(
+        conn,
+        1,
+        span_frame,
+        obj_state,
+        "test",
+        coin_store::NewCommon {
+            coins: coins.into(),
+            person: &Person::from_str("alice").unwrap(),
+            asset: &Asset::default(),
+        },
+    )
+    .unwrap();
+}
+
+fn get_coins(conn: &mut SqliteConnection, span_frame: &SpanFrame) -> i64 {
+    use credit_store_demo::autogen::schema::coin_store_hist::dsl;
+
+    dsl::coin_store_hist
+        .filter(
+            dsl::grp_span
+                .eq(span_frame.span)
+                .and(dsl::grp_frame.eq(span_frame.frame)),
+        )
+        .select(coin_store::Hist::as_select())
+        .get_result(conn)
+        .unwrap()
+        .coins
+        .to_inner()
+}
+
+#[test]
+fn test_hist_is_updated_per_event_like_a_replay() {
+    let mut conn = establish_migrated();
+
+    let root = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    insert_coins(&mut conn, &root, ObjState::Insert, 0);
+    insert_coins(&mut conn, &root, ObjState::Update, 10);
+    coin_store::create_span_frame_snapshot(&mut conn, &root, "test").unwrap();
+
+    let pushed = coin_store::create_span_frame(&mut conn, 2, 1, Some(&root), "push").unwrap();
+
+    insert_coins(&mut conn, &root, ObjState::Update, 5);
+    insert_coins(&mut conn, &pushed, ObjState::Update, -3);
+
+    assert_eq!(get_coins(&mut conn, &root), 15);
+
+    for span_frame in [&root, &pushed] {
+        assert!(
+            coin_store::verify_span_frame_snapshot(&mut conn, span_frame)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
+
+fn count_marked(conn: &mut SqliteConnection, table: &str) -> i64 {
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    diesel::sql_query(format!(
+        "SELECT COUNT(*) AS count FROM {table} WHERE ev_desc = 'marked'"
+    ))
+    .get_result::<Count>(conn)
+    .unwrap()
+    .count
+}
+
+#[test]
+fn test_grouped_projections_are_updated_per_event() {
+    use credit_store_demo::autogen::schema::{coin_store_events_grouped, coin_store_hist_partial};
+
+    let mut conn = establish_migrated();
+
+    let root = coin_store::create_span_frame(&mut conn, 1, 1, None, "init").unwrap();
+
+    insert_coins(&mut conn, &root, ObjState::Insert, 0);
+    insert_coins(&mut conn, &root, ObjState::Update, 10);
+
+    // A rebuild from the event log would undo the marks, so they show that a write leaves the existing rows alone
+    for table in [
+        "coin_store_events_grouped",
+        "coin_store_events_grouped_partial",
+    ] {
+        diesel::sql_query(format!("UPDATE {table} SET ev_desc = 'marked'"))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    insert_coins(&mut conn, &root, ObjState::Update, 5);
+    let pushed = coin_store::create_span_frame(&mut conn, 2, 1, Some(&root), "push").unwrap();
+
+    assert_eq!(count_marked(&mut conn, "coin_store_events_grouped"), 2);
+    assert_eq!(
+        count_marked(&mut conn, "coin_store_events_grouped_partial"),
+        2
+    );
+
+    // Toggling off the 10 coins only changes the partial view of the span frame it was toggled in
+    let ten_ev_id = coin_store_events_grouped::dsl::coin_store_events_grouped
+        .filter(coin_store_events_grouped::dsl::coins.eq(10_i64))
+        .select(coin_store_events_grouped::dsl::ev_id)
+        .first::<i32>(&mut conn)
+        .unwrap();
+
+    coin_store::insert_toggle_event(&mut conn, &root, ten_ev_id, false, "toggle").unwrap();
+
+    let get_partial_coins = |conn: &mut SqliteConnection, span_frame: &SpanFrame| {
+        coin_store_hist_partial::dsl::coin_store_hist_partial
+            .filter(
+                coin_store_hist_partial::dsl::grp_span
+                    .eq(span_frame.span)
+                    .and(coin_store_hist_partial::dsl::grp_frame.eq(span_frame.frame)),
+            )
+            .select(coin_store::HistPartial::as_select())
+            .get_result(conn)
+            .unwrap()
+            .coins
+            .to_inner()
+    };
+
+    assert_eq!(get_partial_coins(&mut conn, &root), 5);
+    assert_eq!(get_partial_coins(&mut conn, &pushed), 15);
+
+    coin_store::insert_toggle_event(&mut conn, &root, ten_ev_id, true, "untoggle").unwrap();
+
+    assert_eq!(get_partial_coins(&mut conn, &root), 15);
+}
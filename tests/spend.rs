@@ -0,0 +1,96 @@
+mod common;
+
+use chrono::{TimeDelta, Utc};
+use credit_store_demo::autogen::schema::ObjState;
+use credit_store_demo::db::double_entry::{self, Posting};
+use credit_store_demo::db::hold::{self, HoldError};
+use credit_store_demo::db::models::{Amount, Asset, Hold, Person};
+use credit_store_demo::db::spend::{self, Expense, SpendError};
+
+use common::establish_with_users;
+
+#[test]
+fn test_expenses_only_spend_what_is_available() {
+    let (mut conn, span_frame, [alice]) = establish_with_users([("alice", 100)]);
+    let now = Utc::now();
+
+    let hold = Hold {
+        person: alice.clone(),
+        coins: Amount::new(30),
+        asset: Asset::default(),
+        expires_on: now + TimeDelta::days(1),
+    };
+
+    hold::place_hold(&mut conn, &span_frame, &hold, now, "hold").unwrap();
+
+    let expense = |coins: i64| Expense {
+        person: &alice,
+        coins: Amount::new(coins),
+        asset: &Asset::default(),
+        ev_desc: "groceries",
+        opt_target_ev_id: None,
+    };
+
+    // The held coins are still in the balance, but not available
+    assert!(matches!(
+        spend::insert_expense(&mut conn, &span_frame, &expense(80), now),
+        Err(SpendError::HoldError(HoldError::InsufficientFunds { available, .. }))
+            if available == Amount::new(70)
+    ));
+    assert!(matches!(
+        spend::insert_expense(&mut conn, &span_frame, &expense(0), now),
+        Err(SpendError::NotPositive)
+    ));
+
+    spend::insert_expense(&mut conn, &span_frame, &expense(70), now).unwrap();
+
+    assert_eq!(
+        double_entry::get_account_balances(&mut conn, &span_frame).unwrap(),
+        [(alice, Asset::default(), Amount::new(30))]
+    );
+}
+
+fn posting<'a>(person: &'a Person, coins: i64, asset: &'a Asset) -> Posting<'a> {
+    Posting {
+        obj_id: person.get_obj_id(),
+        obj_state: ObjState::Update,
+        opt_target_ev_id: None,
+        ev_desc: "test",
+        person,
+        coins: Amount::new(coins),
+        asset,
+        opt_rate: None,
+        opt_expires_on: None,
+    }
+}
+
+#[test]
+fn test_spending_transactions_check_what_each_user_pays_in_total() {
+    let (mut conn, span_frame, [alice, bob]) = establish_with_users([("alice", 50), ("bob", 0)]);
+    let coin = Asset::default();
+
+    // Each posting of alice's is covered on its own, but not both together
+    let postings = [
+        posting(&alice, -30, &coin),
+        posting(&alice, -30, &coin),
+        posting(&bob, 60, &coin),
+    ];
+
+    assert!(matches!(
+        spend::insert_spending_transaction(&mut conn, &span_frame, &postings, Utc::now()),
+        Err(SpendError::HoldError(HoldError::InsufficientFunds { available, .. }))
+            if available == Amount::new(50)
+    ));
+
+    let postings = [posting(&alice, -50, &coin), posting(&bob, 50, &coin)];
+
+    spend::insert_spending_transaction(&mut conn, &span_frame, &postings, Utc::now()).unwrap();
+
+    assert_eq!(
+        double_entry::get_account_balances(&mut conn, &span_frame).unwrap(),
+        [
+            (alice, coin.clone(), Amount::ZERO),
+            (bob, coin, Amount::new(50))
+        ]
+    );
+}
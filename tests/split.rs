@@ -0,0 +1,191 @@
+mod common;
+
+use std::str::FromStr;
+
+use chrono::Utc;
+use credit_store_demo::db::double_entry;
+use credit_store_demo::db::hold::HoldError;
+use credit_store_demo::db::models::{Amount, Asset, Person};
+use credit_store_demo::db::spend::SpendError;
+use credit_store_demo::db::split::{self, SplitError, SplitMode, SplitOrder};
+use diesel::prelude::*;
+
+use common::establish_with_users;
+
+fn get_share_coins(total: i64, participants: &[Person], mode: SplitMode) -> Vec<i64> {
+    split::get_shares(Amount::new(total), participants, &mode)
+        .unwrap()
+        .into_iter()
+        .map(|share| share.coins.to_inner())
+        .collect()
+}
+
+#[test]
+fn test_shares_are_rounded_deterministically() {
+    let people = ["alice", "bob", "carol"]
+        .into_iter()
+        .map(|name| Person::from_str(name).unwrap())
+        .collect::<Vec<_>>();
+
+    // Units left over go to the participants listed first
+    assert_eq!(get_share_coins(10, &people, SplitMode::Equal), [4, 3, 3]);
+    assert_eq!(get_share_coins(11, &people, SplitMode::Equal), [4, 4, 3]);
+
+    // 10 * 1 / 4 = 2.5 and 10 * 2 / 4 = 5, so the unit left over goes to the first of the two that lost a half
+    assert_eq!(
+        get_share_coins(10, &people, SplitMode::Weights(vec![1, 2, 1])),
+        [3, 5, 2]
+    );
+
+    // 7 * 3 / 6 = 3.5, 7 * 2 / 6 = 2.33 and 7 * 1 / 6 = 1.17, so the unit left over goes to the largest remainder
+    assert_eq!(
+        get_share_coins(7, &people, SplitMode::Weights(vec![3, 2, 1])),
+        [4, 2, 1]
+    );
+
+    assert_eq!(
+        get_share_coins(
+            10,
+            &people,
+            SplitMode::Exact(vec![Amount::new(5), Amount::ZERO, Amount::new(5)])
+        ),
+        [5, 0, 5]
+    );
+
+    assert!(matches!(
+        split::get_shares(
+            Amount::new(10),
+            &people,
+            &SplitMode::Exact(vec![Amount::new(5), Amount::new(4), Amount::ZERO])
+        ),
+        Err(SplitError::ExactMismatch { sum, .. }) if sum == Amount::new(9)
+    ));
+    assert!(matches!(
+        split::get_shares(Amount::new(10), &people, &SplitMode::Weights(vec![1, 1])),
+        Err(SplitError::WrongCount {
+            expected: 3,
+            actual: 2
+        })
+    ));
+    assert!(matches!(
+        split::get_shares(Amount::new(10), &people, &SplitMode::Weights(vec![0, 0, 0])),
+        Err(SplitError::ZeroWeights)
+    ));
+    assert!(matches!(
+        split::get_shares(Amount::ZERO, &people, &SplitMode::Equal),
+        Err(SplitError::NotPositive)
+    ));
+    assert!(matches!(
+        split::get_shares(
+            Amount::new(10),
+            &[people[0].clone(), people[0].clone()],
+            &SplitMode::Equal
+        ),
+        Err(SplitError::DuplicateParticipant(_))
+    ));
+}
+
+#[test]
+fn test_split_is_one_transaction_paid_back_to_the_payer() {
+    let (mut conn, span_frame, people) =
+        establish_with_users([("alice", 100), ("bob", 100), ("carol", 100)]);
+
+    let pizza = split::insert_split(
+        &mut conn,
+        &span_frame,
+        &SplitOrder {
+            payer: &people[0],
+            total: Amount::new(10),
+            asset: &Asset::default(),
+            participants: &people,
+            mode: &SplitMode::Equal,
+            ev_desc: "pizza",
+        },
+        Utc::now(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        pizza
+            .shares
+            .iter()
+            .map(|share| share.coins.to_inner())
+            .collect::<Vec<_>>(),
+        [4, 3, 3]
+    );
+
+    // The payer pays the whole purchase and gets the others' shares back, so they end up paying their own
+    assert_eq!(
+        double_entry::get_account_balances(&mut conn, &span_frame)
+            .unwrap()
+            .into_iter()
+            .map(|(person, _, coins)| (person.to_inner(), coins.to_inner()))
+            .collect::<Vec<_>>(),
+        [
+            ("@expenses".to_owned(), 10),
+            ("alice".to_owned(), 96),
+            ("bob".to_owned(), 97),
+            ("carol".to_owned(), 97),
+        ]
+    );
+
+    let tx_ids: Vec<Option<i32>> = {
+        use credit_store_demo::autogen::schema::coin_store_events::dsl;
+
+        dsl::coin_store_events
+            .filter(dsl::ev_desc.eq("pizza"))
+            .select(dsl::opt_tx_id)
+            .get_results(&mut conn)
+            .unwrap()
+    };
+
+    assert_eq!(tx_ids, [Some(pizza.tx_id); 6]);
+
+    // A split where only the payer has a share writes nothing
+    assert!(matches!(
+        split::insert_split(
+            &mut conn,
+            &span_frame,
+            &SplitOrder {
+                payer: &people[0],
+                total: Amount::new(10),
+                asset: &Asset::default(),
+                participants: &people,
+                mode: &SplitMode::Exact(vec![Amount::new(10), Amount::ZERO, Amount::ZERO]),
+                ev_desc: "coffee",
+            },
+            Utc::now(),
+        ),
+        Err(SplitError::NothingToSplit)
+    ));
+}
+
+#[test]
+fn test_split_needs_the_payer_to_have_the_total_available() {
+    let (mut conn, span_frame, people) = establish_with_users([("alice", 5), ("bob", 100)]);
+
+    let order = SplitOrder {
+        payer: &people[0],
+        total: Amount::new(10),
+        asset: &Asset::default(),
+        participants: &people,
+        mode: &SplitMode::Equal,
+        ev_desc: "pizza",
+    };
+
+    // Alice gets 5 back from bob, but has to pay all 10 first
+    assert!(matches!(
+        split::insert_split(&mut conn, &span_frame, &order, Utc::now()),
+        Err(SplitError::SpendError(SpendError::HoldError(
+            HoldError::InsufficientFunds { .. }
+        )))
+    ));
+    assert_eq!(
+        double_entry::get_account_balances(&mut conn, &span_frame)
+            .unwrap()
+            .into_iter()
+            .map(|(person, _, coins)| (person.to_inner(), coins.to_inner()))
+            .collect::<Vec<_>>(),
+        [("alice".to_owned(), 5), ("bob".to_owned(), 100)]
+    );
+}